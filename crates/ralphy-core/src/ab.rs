@@ -0,0 +1,152 @@
+use crate::ai::{AiEngine, AiExecutor, AiResponse};
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::prd::{PrdManager, Task};
+use crate::pricing::Pricing;
+use crate::{git, progress, prompt, text, RunOutcome};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// One side of an A/B run: an engine working on its own branch and worktree.
+struct Side {
+    engine: AiEngine,
+    branch: String,
+    dir: PathBuf,
+}
+
+impl Side {
+    fn new(engine: AiEngine, task: &str) -> Self {
+        let engine_slug = git::slugify(&engine.to_string());
+        Self {
+            engine,
+            branch: format!("ralphy/ab/{}-{}", git::slugify(task), engine_slug),
+            dir: PathBuf::from(progress::STATE_DIR)
+                .join("ab")
+                .join(engine_slug),
+        }
+    }
+}
+
+/// Run the next task with the primary engine and `comparison` side by side,
+/// each in its own worktree, and show both results so the user can pick one.
+///
+/// The PRD is left untouched: keeping a result means merging its branch.
+pub async fn run_ab(
+    config: &RunConfig,
+    prd_manager: &PrdManager,
+    comparison: AiEngine,
+) -> Result<RunOutcome> {
+    let snapshot = prd_manager.refresh().await?;
+    let Some(task) = snapshot.next_task().map(Task::name) else {
+        events::success("All tasks complete!");
+        return Ok(RunOutcome::Complete);
+    };
+    let task = task.as_str();
+
+    progress::ensure_state_dir().await?;
+    let base = git::head_commit()?;
+    let sides = [
+        Side::new(config.ai_engine, task),
+        Side::new(comparison, task),
+    ];
+    for side in &sides {
+        if side.dir.exists() {
+            anyhow::bail!(
+                "{} already exists from a previous A/B run. Remove it with: git worktree remove --force {}",
+                side.dir.display(),
+                side.dir.display()
+            );
+        }
+        git::add_worktree(&side.dir, &side.branch, &base)?;
+    }
+
+    events::info(format!(
+        "A/B: {} vs {} on {}",
+        config.ai_engine,
+        comparison,
+        text::truncate(task, 40)
+    ));
+
+    let prompt = prompt::build_prompt(config, Some(task));
+    let run = |side: &Side| {
+        // --model names a model of the primary engine
+        let model = config
+            .model
+            .as_deref()
+            .filter(|_| side.engine == config.ai_engine);
+        let executor = AiExecutor::new(side.engine)
+            .with_backend(config.backend)
+            .with_rate_limit(config.rate_limit)
+            .with_model(model)
+            .with_extra_args(config.engine_args(side.engine))
+            .with_completion_marker(&config.completion_marker)
+            .with_task(task)
+            .with_dir(&side.dir);
+        let prompt = prompt.clone();
+        async move { crate::execute_with_contract(&executor, &prompt).await }
+    };
+    let (primary, other) = tokio::join!(run(&sides[0]), run(&sides[1]));
+
+    let mut any_succeeded = false;
+    for (side, result) in sides.iter().zip([primary, other]) {
+        any_succeeded |= result.is_ok();
+        show_side(&config.pricing, side, &result, &base, task);
+    }
+
+    let mut help = format!(
+        "\n{}\nKeep a result by merging its branch, e.g.:\n    git merge {}\nThen clean up both worktrees:",
+        "─".repeat(60),
+        sides[0].branch
+    );
+    for side in &sides {
+        help.push_str(&format!(
+            "\n    git worktree remove --force {} && git branch -D {}",
+            side.dir.display(),
+            side.branch
+        ));
+    }
+    events::notice(Level::Plain, help);
+
+    Ok(if any_succeeded {
+        RunOutcome::Complete
+    } else {
+        RunOutcome::WorkRemaining
+    })
+}
+
+fn show_side(pricing: &Pricing, side: &Side, result: &Result<AiResponse>, base: &str, task: &str) {
+    events::notice(
+        Level::Plain,
+        format!(
+            "\n{}\n>>> {} ({})",
+            "─".repeat(60),
+            side.engine,
+            side.branch
+        ),
+    );
+
+    match result {
+        Ok(response) => {
+            events::notice(
+                Level::Passed,
+                format!(
+                    "Done │ {} in │ {} out │ ${:.4}",
+                    response.input_tokens,
+                    response.output_tokens,
+                    pricing.response_cost(side.engine, response)
+                ),
+            );
+        }
+        Err(e) => events::notice(Level::Failed, format!("Failed: {}", e)),
+    }
+
+    let message = format!("{} ({})", task, side.engine);
+    if let Err(e) = git::commit_all_in(&side.dir, &message) {
+        events::warn(e.to_string());
+    }
+    match git::diff_stat_in(&side.dir, base) {
+        Ok(stat) if stat.is_empty() => events::notice(Level::Plain, "  No changes".to_string()),
+        Ok(stat) => events::notice(Level::Plain, stat),
+        Err(e) => events::warn(e.to_string()),
+    }
+}
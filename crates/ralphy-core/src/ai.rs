@@ -0,0 +1,1591 @@
+use crate::anthropic;
+use crate::backend::{self, Backend};
+use crate::contract;
+use crate::log;
+use crate::ollama;
+use crate::openai;
+use crate::preflight::ToolCheck;
+use crate::process::{self, EngineChild};
+use crate::progress;
+use crate::session::{EngineSession, SessionSlot, Turn};
+use crate::text;
+use crate::tools::Workspace;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum AiEngine {
+    Claude,
+    OpenCode,
+    Cursor,
+    Codex,
+    Qwen,
+    Aider,
+    /// Claude through the Messages API, without the CLI
+    AnthropicApi,
+    /// OpenAI models through the Chat Completions API
+    #[cfg_attr(feature = "clap", value(name = "openai-api"))]
+    #[serde(rename = "openai-api")]
+    OpenAiApi,
+    /// A local model served by Ollama
+    Ollama,
+    Mock,
+}
+
+impl AiEngine {
+    /// The engine's name in flags and ralphy.toml, such as `open-code`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AiEngine::Claude => "claude",
+            AiEngine::OpenCode => "open-code",
+            AiEngine::Cursor => "cursor",
+            AiEngine::Codex => "codex",
+            AiEngine::Qwen => "qwen",
+            AiEngine::Aider => "aider",
+            AiEngine::AnthropicApi => "anthropic-api",
+            AiEngine::OpenAiApi => "openai-api",
+            AiEngine::Ollama => "ollama",
+            AiEngine::Mock => "mock",
+        }
+    }
+}
+
+impl std::fmt::Display for AiEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiEngine::Claude => write!(f, "Claude Code"),
+            AiEngine::OpenCode => write!(f, "OpenCode"),
+            AiEngine::Cursor => write!(f, "Cursor"),
+            AiEngine::Codex => write!(f, "Codex"),
+            AiEngine::Qwen => write!(f, "Qwen-Code"),
+            AiEngine::Aider => write!(f, "Aider"),
+            AiEngine::AnthropicApi => write!(f, "Anthropic API"),
+            AiEngine::OpenAiApi => write!(f, "OpenAI API"),
+            AiEngine::Ollama => write!(f, "Ollama"),
+            AiEngine::Mock => write!(f, "Mock"),
+        }
+    }
+}
+
+/// Sender side of the current-step channel an executor reports through.
+pub type StepSender = watch::Sender<String>;
+
+/// Create a step channel, starting at the generic "Processing" step.
+pub fn step_channel() -> (StepSender, watch::Receiver<String>) {
+    watch::channel("Processing".to_string())
+}
+
+/// Tokens a running engine has used so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenCount {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
+/// Sender side of the channel an executor reports its token usage through
+/// while the engine runs.
+pub type UsageSender = watch::Sender<TokenCount>;
+
+/// Create a usage channel, starting at zero.
+pub fn usage_channel() -> (UsageSender, watch::Receiver<TokenCount>) {
+    watch::channel(TokenCount::default())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AiResponse {
+    pub text: String,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub actual_cost: Option<f64>,
+    pub duration_ms: Option<u64>,
+    /// Model the engine reported using, if it says
+    pub model: Option<String>,
+}
+
+impl AiResponse {
+    /// Whether the response used any tokens or cost anything
+    pub fn has_usage(&self) -> bool {
+        self.input_tokens + self.output_tokens > 0 || self.actual_cost.is_some()
+    }
+
+    /// Add another response's usage to this one, e.g. after a re-prompt
+    pub fn absorb_usage(&mut self, other: &AiResponse) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.actual_cost = match (self.actual_cost, other.actual_cost) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+        };
+        self.duration_ms = match (self.duration_ms, other.duration_ms) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        if self.model.is_none() {
+            self.model = other.model.clone();
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Usage of every response the current [`tally`] call has seen
+    static TALLY: Arc<std::sync::Mutex<AiResponse>>;
+}
+
+/// Run `future`, adding up the usage of every response an engine gives while
+/// it runs. That includes attempts the future then fails or is cut short
+/// after, whose usage would otherwise never reach the run's totals.
+pub async fn tally<F: Future>(future: F) -> (F::Output, AiResponse) {
+    let spent = Arc::new(std::sync::Mutex::new(AiResponse::default()));
+    let output = TALLY.scope(spent.clone(), future).await;
+    let spent = spent.lock().unwrap().clone();
+    (output, spent)
+}
+
+pub struct AiExecutor {
+    engine: AiEngine,
+    steps: Option<StepSender>,
+    usage: Option<UsageSender>,
+    task: Option<String>,
+    dir: Option<PathBuf>,
+    backend: Backend,
+    session: Option<SessionSlot>,
+    model: Option<String>,
+    extra_args: Vec<String>,
+    limiter: Arc<RateLimiter>,
+    completion_marker: String,
+}
+
+impl AiExecutor {
+    pub fn new(engine: AiEngine) -> Self {
+        Self {
+            engine,
+            steps: None,
+            usage: None,
+            task: None,
+            dir: None,
+            backend: Backend::Local,
+            session: None,
+            model: None,
+            extra_args: Vec::new(),
+            limiter: RateLimiter::shared(RateLimit::default()),
+            completion_marker: contract::COMPLETION_PROMISE.to_string(),
+        }
+    }
+
+    /// Take `marker`, rather than the default completion promise, as the
+    /// engine saying every task in the PRD is done. Empty turns it off.
+    pub fn with_completion_marker(mut self, marker: &str) -> Self {
+        self.completion_marker = marker.to_string();
+        self
+    }
+
+    /// What the engine outputs once every task in the PRD is done
+    pub fn completion_marker(&self) -> &str {
+        &self.completion_marker
+    }
+
+    /// Start engines, or send API requests, no faster than `limit` allows
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.limiter = RateLimiter::shared(limit);
+        self
+    }
+
+    /// Run the engine on `backend` instead of directly on this machine
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Run the engine in `dir` instead of the current directory
+    pub fn with_dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Ask the engine for `model` rather than its default, when given
+    pub fn with_model(mut self, model: Option<&str>) -> Self {
+        self.model = model.map(str::to_string);
+        self
+    }
+
+    /// Pass `args` on to the engine's CLI as they are. Engines without a
+    /// CLI ignore them.
+    pub fn with_extra_args(mut self, args: &[String]) -> Self {
+        self.extra_args = args.to_vec();
+        self
+    }
+
+    /// `--model` for engines run as a command, when a model was asked for
+    fn model_args(&self) -> Vec<&str> {
+        match self.model {
+            Some(ref model) => vec!["--model", model],
+            None => Vec::new(),
+        }
+    }
+
+    /// Directory the engine runs in
+    pub fn dir(&self) -> &Path {
+        self.dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    fn command(&self, program: &str, envs: &[(&str, &str)]) -> Command {
+        let mut cmd = backend::engine_command(self.backend, program, envs);
+        if let Some(ref dir) = self.dir {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+
+    /// The task being worked on, for engines that don't read it from the prompt
+    pub fn with_task(mut self, task: &str) -> Self {
+        self.task = Some(task.to_string());
+        self
+    }
+
+    /// Send prompts to the long-lived session in `slot` rather than starting
+    /// the engine for each one. Only Claude keeps sessions; other engines
+    /// ignore this.
+    pub fn with_session(mut self, slot: SessionSlot) -> Self {
+        self.session = Some(slot);
+        self
+    }
+
+    /// Report what the engine is currently doing to a progress monitor
+    pub fn with_steps(mut self, steps: StepSender) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    fn report_step(&self, step: &str) {
+        if let Some(ref steps) = self.steps {
+            steps.send_replace(step.to_string());
+        }
+    }
+
+    /// Report token usage as the engine streams it. Engines that only say
+    /// at the end never report.
+    pub fn with_usage(mut self, usage: UsageSender) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    fn report_usage(&self, input_tokens: usize, output_tokens: usize) {
+        if let Some(ref usage) = self.usage {
+            usage.send_replace(TokenCount {
+                input_tokens,
+                output_tokens,
+            });
+        }
+    }
+
+    pub async fn execute(&self, prompt: &str) -> Result<AiResponse> {
+        let mut response = match self.engine {
+            // These wait their turn and retry for each request they send
+            AiEngine::AnthropicApi | AiEngine::OpenAiApi | AiEngine::Ollama => {
+                self.dispatch(prompt).await?
+            }
+            _ => {
+                let rate_limited = |err: &anyhow::Error| is_rate_limited(&format!("{:#}", err));
+                self.limiter
+                    .run(rate_limited, || self.dispatch(prompt))
+                    .await?
+            }
+        };
+        // Engines that don't say which model answered used the one asked for
+        if response.model.is_none() {
+            response.model = self.model.clone();
+        }
+        TALLY
+            .try_with(|spent| spent.lock().unwrap().absorb_usage(&response))
+            .ok();
+        Ok(response)
+    }
+
+    async fn dispatch(&self, prompt: &str) -> Result<AiResponse> {
+        match self.engine {
+            AiEngine::Claude => self.execute_claude(prompt).await,
+            AiEngine::OpenCode => self.execute_opencode(prompt).await,
+            AiEngine::Cursor => self.execute_cursor(prompt).await,
+            AiEngine::Codex => self.execute_codex(prompt).await,
+            AiEngine::Qwen => self.execute_qwen(prompt).await,
+            AiEngine::Aider => self.execute_aider(prompt).await,
+            AiEngine::AnthropicApi => self.execute_anthropic_api(prompt).await,
+            AiEngine::OpenAiApi => self.execute_openai_api(prompt).await,
+            AiEngine::Ollama => self.execute_ollama(prompt).await,
+            AiEngine::Mock => self.execute_mock(prompt).await,
+        }
+    }
+
+    async fn execute_claude(&self, prompt: &str) -> Result<AiResponse> {
+        if let Some(ref slot) = self.session {
+            return self.execute_claude_session(slot, prompt).await;
+        }
+
+        let mut child = EngineChild::spawn(
+            self.command("claude", &[])
+                .arg("--dangerously-skip-permissions")
+                .arg("--verbose")
+                .arg("--output-format")
+                .arg("stream-json")
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg("-p")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn claude command")?;
+        send_prompt(&mut child, prompt)?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut response_text = String::new();
+        let mut input_tokens = 0;
+        let mut output_tokens = 0;
+        let mut actual_cost = None;
+        let mut duration_ms = None;
+        let mut model = None;
+
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(json) = serde_json::from_str::<Value>(&line) {
+                // Parse stream-json format
+                if json["type"].as_str() == Some("system") {
+                    model = json["model"].as_str().map(str::to_string);
+                }
+                if json["type"].as_str() == Some("assistant") {
+                    // Per-message usage until the result gives the total
+                    if let Some((input, output)) = parse_usage(&json["message"]["usage"]) {
+                        input_tokens += input;
+                        output_tokens += output;
+                        self.report_usage(input_tokens, output_tokens);
+                    }
+                    if let Some(step) = claude_tool_step(&json) {
+                        self.report_step(step);
+                    }
+                }
+                if json["type"].as_str() == Some("result") {
+                    if let Some(result) = json["result"].as_str() {
+                        response_text = result.to_string();
+                    }
+                    if let Some(usage) = json["usage"].as_object() {
+                        input_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as usize;
+                        output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as usize;
+                    }
+                    // Prefer the cost Claude reports over our per-token estimate
+                    if let Some(cost) = json["total_cost_usd"].as_f64() {
+                        actual_cost = Some(cost);
+                    }
+                    if let Some(dur) = json["duration_ms"].as_u64() {
+                        duration_ms = Some(dur);
+                    }
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(exit_error(&mut child, "Claude", status).await);
+        }
+
+        Ok(AiResponse {
+            text: response_text,
+            input_tokens,
+            output_tokens,
+            actual_cost,
+            duration_ms,
+            model,
+        })
+    }
+
+    async fn execute_claude_session(&self, slot: &SessionSlot, prompt: &str) -> Result<AiResponse> {
+        let mut turn = Turn::begin(slot).await;
+        let slot = turn.slot();
+        if slot.as_ref().is_some_and(|s| s.dir() != self.dir()) {
+            *slot = None;
+        }
+        if slot.is_none() {
+            *slot = Some(EngineSession::start(
+                self.command("claude", &[])
+                    .arg("--dangerously-skip-permissions")
+                    .arg("--verbose")
+                    .arg("--input-format")
+                    .arg("stream-json")
+                    .arg("--output-format")
+                    .arg("stream-json")
+                    .args(self.model_args())
+                    .args(&self.extra_args)
+                    .arg("-p"),
+                self.dir(),
+            )?);
+        }
+        turn.send(prompt).await
+    }
+
+    async fn execute_opencode(&self, prompt: &str) -> Result<AiResponse> {
+        check_prompt_arg_len(self.engine, prompt)?;
+
+        let mut child = EngineChild::spawn(
+            self.command("opencode", &[("OPENCODE_PERMISSION", r#"{"*":"allow"}"#)])
+                .arg("run")
+                .arg("--format")
+                .arg("json")
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg(prompt)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn opencode command")?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut response_text = String::new();
+        let mut input_tokens = 0;
+        let mut output_tokens = 0;
+        let mut actual_cost = None;
+
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(json) = serde_json::from_str::<Value>(&line) {
+                if let Some(msg_type) = json["type"].as_str() {
+                    match msg_type {
+                        "text" => {
+                            if let Some(text) = json["part"]["text"].as_str() {
+                                response_text.push_str(text);
+                            }
+                        }
+                        "step_finish" => {
+                            if let Some(tokens) = json["part"]["tokens"].as_object() {
+                                input_tokens = tokens["input"].as_u64().unwrap_or(0) as usize;
+                                output_tokens = tokens["output"].as_u64().unwrap_or(0) as usize;
+                                self.report_usage(input_tokens, output_tokens);
+                            }
+                            if let Some(cost) = json["part"]["cost"].as_f64() {
+                                actual_cost = Some(cost);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(exit_error(&mut child, "OpenCode", status).await);
+        }
+
+        Ok(AiResponse {
+            text: if response_text.is_empty() {
+                "Task completed".to_string()
+            } else {
+                response_text
+            },
+            input_tokens,
+            output_tokens,
+            actual_cost,
+            duration_ms: None,
+            model: None,
+        })
+    }
+
+    async fn execute_cursor(&self, prompt: &str) -> Result<AiResponse> {
+        check_prompt_arg_len(self.engine, prompt)?;
+
+        let mut child = EngineChild::spawn(
+            self.command("agent", &[])
+                .arg("--print")
+                .arg("--force")
+                .arg("--output-format")
+                .arg("stream-json")
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg(prompt)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn agent command")?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut response_text = String::new();
+        let mut duration_ms = None;
+        let mut input_tokens = 0;
+        let mut output_tokens = 0;
+        let mut result_usage = None;
+        let mut model = None;
+
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(json) = serde_json::from_str::<Value>(&line) {
+                if let Some(msg_type) = json["type"].as_str() {
+                    match msg_type {
+                        "system" => {
+                            model = json["model"].as_str().map(str::to_string);
+                        }
+                        "result" => {
+                            if let Some(result) = json["result"].as_str() {
+                                response_text = result.to_string();
+                            }
+                            if let Some(dur) = json["duration_ms"].as_u64() {
+                                duration_ms = Some(dur);
+                            }
+                            result_usage = parse_usage(&json["usage"]);
+                        }
+                        "assistant" => {
+                            // Per-message usage, used if the result event has none
+                            if let Some((input, output)) = parse_usage(&json["message"]["usage"]) {
+                                input_tokens += input;
+                                output_tokens += output;
+                                self.report_usage(input_tokens, output_tokens);
+                            }
+                            if response_text.is_empty() || response_text == "Task completed" {
+                                if let Some(text) = json["message"]["content"]
+                                    .as_array()
+                                    .and_then(|content| content.first())
+                                    .and_then(|first| first["text"].as_str())
+                                {
+                                    response_text = text.to_string();
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if let Some((input, output)) = result_usage {
+            input_tokens = input;
+            output_tokens = output;
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(exit_error(&mut child, "Cursor agent", status).await);
+        }
+
+        Ok(AiResponse {
+            text: if response_text.is_empty() {
+                "Task completed".to_string()
+            } else {
+                response_text
+            },
+            input_tokens,
+            output_tokens,
+            actual_cost: None,
+            duration_ms,
+            model,
+        })
+    }
+
+    async fn execute_codex(&self, prompt: &str) -> Result<AiResponse> {
+        use tempfile::NamedTempFile;
+
+        // In a devcontainer codex only sees the workspace, so the message is
+        // written to the ignored state directory inside it
+        let (temp_file, output_arg) = match self.backend {
+            Backend::Devcontainer => {
+                let state_dir = progress::ensure_state_dir_in(self.dir()).await?;
+                let file = NamedTempFile::new_in(state_dir)?;
+                let name = file.path().file_name().context("Temp file has no name")?;
+                let arg = Path::new(progress::STATE_DIR).join(name);
+                (file, arg)
+            }
+            Backend::Local | Backend::Kubernetes => {
+                let file = NamedTempFile::new()?;
+                let arg = file.path().to_path_buf();
+                (file, arg)
+            }
+        };
+
+        let mut child = EngineChild::spawn(
+            self.command("codex", &[])
+                .arg("exec")
+                .arg("--full-auto")
+                .arg("--json")
+                .arg("--output-last-message")
+                .arg(&output_arg)
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg("-")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn codex command")?;
+        send_prompt(&mut child, prompt)?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut stream = CodexStream::default();
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(json) = serde_json::from_str::<Value>(&line) {
+                if let Some(step) = stream.handle(&json) {
+                    self.report_step(step);
+                }
+                self.report_usage(stream.input_tokens, stream.output_tokens);
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            if stream.errors.is_empty() {
+                return Err(exit_error(&mut child, "Codex", status).await);
+            }
+            anyhow::bail!(
+                "Codex command failed with status: {}: {}",
+                status,
+                stream.errors.join("; ")
+            );
+        }
+
+        let response_text = tokio::fs::read_to_string(temp_file.path())
+            .await
+            .ok()
+            .filter(|text| !text.trim().is_empty())
+            .unwrap_or(stream.last_message);
+
+        Ok(AiResponse {
+            text: response_text,
+            input_tokens: stream.input_tokens,
+            output_tokens: stream.output_tokens,
+            actual_cost: None,
+            duration_ms: None,
+            model: None,
+        })
+    }
+
+    async fn execute_anthropic_api(&self, prompt: &str) -> Result<AiResponse> {
+        let mut settings = anthropic::Settings::from_env()?;
+        if let Some(ref model) = self.model {
+            settings.model = model.clone();
+        }
+        let workspace = Workspace::new(self.dir(), self.backend)?;
+        anthropic::run(&settings, &workspace, &self.limiter, prompt, |step| {
+            self.report_step(step)
+        })
+        .await
+    }
+
+    async fn execute_openai_api(&self, prompt: &str) -> Result<AiResponse> {
+        let mut settings = openai::Settings::from_env()?;
+        if let Some(ref model) = self.model {
+            settings.model = model.clone();
+        }
+        let workspace = Workspace::new(self.dir(), self.backend)?;
+        openai::run(&settings, &workspace, &self.limiter, prompt, |step| {
+            self.report_step(step)
+        })
+        .await
+    }
+
+    async fn execute_ollama(&self, prompt: &str) -> Result<AiResponse> {
+        let mut settings = ollama::Settings::from_env()?;
+        if let Some(ref model) = self.model {
+            settings.model = model.clone();
+        }
+        let workspace = Workspace::new(self.dir(), self.backend)?;
+        ollama::run(&settings, &workspace, &self.limiter, prompt, |step| {
+            self.report_step(step)
+        })
+        .await
+    }
+
+    async fn execute_mock(&self, prompt: &str) -> Result<AiResponse> {
+        let settings = MockSettings::from_env();
+        let start = std::time::Instant::now();
+
+        self.report_step("Thinking");
+        tokio::time::sleep(settings.delay / 2).await;
+        self.report_step("Editing files");
+        tokio::time::sleep(settings.delay / 2).await;
+
+        let task = self.task.as_deref().unwrap_or(prompt);
+        if let Some(pattern) = settings.fail_on.iter().find(|p| task.contains(p.as_str())) {
+            anyhow::bail!("Mock engine simulated a failure (matched '{}')", pattern);
+        }
+
+        // Roughly four characters per token
+        let input_tokens = prompt.len() / 4;
+        let output_tokens = settings.response.len() / 4;
+
+        Ok(AiResponse {
+            text: settings.response,
+            input_tokens,
+            output_tokens,
+            actual_cost: None,
+            duration_ms: Some(start.elapsed().as_millis() as u64),
+            model: None,
+        })
+    }
+
+    async fn execute_qwen(&self, prompt: &str) -> Result<AiResponse> {
+        check_prompt_arg_len(self.engine, prompt)?;
+
+        let mut child = EngineChild::spawn(
+            self.command("qwen", &[])
+                .arg("--output-format")
+                .arg("stream-json")
+                .arg("--approval-mode")
+                .arg("yolo")
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg("-p")
+                .arg(prompt)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn qwen command")?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut response_text = String::new();
+        let mut input_tokens = 0;
+        let mut output_tokens = 0;
+
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(json) = serde_json::from_str::<Value>(&line) {
+                if let Some(msg_type) = json["type"].as_str() {
+                    if msg_type == "result" {
+                        if let Some(result) = json["result"].as_str() {
+                            response_text = result.to_string();
+                        }
+                        if let Some(usage) = json["usage"].as_object() {
+                            input_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as usize;
+                            output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as usize;
+                        }
+                    }
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(exit_error(&mut child, "Qwen", status).await);
+        }
+
+        Ok(AiResponse {
+            text: if response_text.is_empty() {
+                "Task completed".to_string()
+            } else {
+                response_text
+            },
+            input_tokens,
+            output_tokens,
+            actual_cost: None,
+            duration_ms: None,
+            model: None,
+        })
+    }
+
+    async fn execute_aider(&self, prompt: &str) -> Result<AiResponse> {
+        check_prompt_arg_len(self.engine, prompt)?;
+
+        let mut child = EngineChild::spawn(
+            self.command("aider", &[])
+                .arg("--yes-always")
+                .arg("--no-pretty")
+                .arg("--no-stream")
+                .arg("--no-check-update")
+                .arg("--no-show-release-notes")
+                .args(self.model_args())
+                .args(&self.extra_args)
+                .arg("--message")
+                .arg(prompt)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .context("Failed to spawn aider command")?;
+
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+
+        let mut output = AiderOutput::default();
+        while let Some(line) = lines.next_line().await? {
+            if let Some(step) = output.handle(&line) {
+                self.report_step(step);
+            }
+            self.report_usage(output.input_tokens, output.output_tokens);
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(exit_error(&mut child, "Aider", status).await);
+        }
+
+        Ok(AiResponse {
+            text: output.lines.join("\n"),
+            input_tokens: output.input_tokens,
+            output_tokens: output.output_tokens,
+            actual_cost: output.cost,
+            duration_ms: None,
+            model: output.model,
+        })
+    }
+}
+
+/// State accumulated while reading aider's plain-text output.
+#[derive(Debug, Default)]
+struct AiderOutput {
+    /// Everything aider printed apart from its usage reports
+    lines: Vec<String>,
+    input_tokens: usize,
+    output_tokens: usize,
+    cost: Option<f64>,
+    model: Option<String>,
+}
+
+impl AiderOutput {
+    /// Consume one line, returning a monitor step if it starts one
+    fn handle(&mut self, line: &str) -> Option<&'static str> {
+        if let Some(report) = line.strip_prefix("Tokens: ") {
+            self.add_usage(report);
+            return None;
+        }
+        self.lines.push(line.to_string());
+        if let Some(model) = line.strip_prefix("Main model: ") {
+            self.model = model.split_whitespace().next().map(str::to_string);
+        }
+        if line.starts_with("Applied edit to ") {
+            Some("Editing files")
+        } else if line.starts_with("Commit ") {
+            Some("Committing")
+        } else if line.starts_with("Running ") {
+            Some("Running command")
+        } else {
+            None
+        }
+    }
+
+    /// Add one message's usage report, e.g. `2.3k sent, 1.2k cache hit,
+    /// 120 received. Cost: $0.0082 message, $0.0150 session.`
+    fn add_usage(&mut self, report: &str) {
+        let (tokens, cost) = report.split_once(". Cost: ").unwrap_or((report, ""));
+        for part in tokens.trim_end_matches('.').split(", ") {
+            let Some((count, kind)) = part.split_once(' ') else {
+                continue;
+            };
+            let Some(count) = parse_token_count(count) else {
+                continue;
+            };
+            // Cached prompt tokens are billed input too
+            match kind {
+                "sent" | "cache write" | "cache hit" => self.input_tokens += count,
+                "received" => self.output_tokens += count,
+                _ => {}
+            }
+        }
+        let message_cost = cost
+            .split(", ")
+            .find_map(|part| part.strip_suffix(" message"))
+            .and_then(|amount| amount.trim_start_matches('$').parse::<f64>().ok());
+        if let Some(message_cost) = message_cost {
+            self.cost = Some(self.cost.unwrap_or(0.0) + message_cost);
+        }
+    }
+}
+
+/// A token count as aider abbreviates it: `850`, `2.3k`, `12k` or `1.2M`.
+fn parse_token_count(count: &str) -> Option<usize> {
+    let (number, scale) = match count.chars().last()? {
+        'k' => (&count[..count.len() - 1], 1_000.0),
+        'M' => (&count[..count.len() - 1], 1_000_000.0),
+        _ => (count, 1.0),
+    };
+    let number: f64 = number.replace(',', "").parse().ok()?;
+    Some((number * scale).round() as usize)
+}
+
+/// Behaviour of the mock engine, read from the environment:
+///
+/// - `RALPHY_MOCK_DELAY_MS`: simulated run time (default 200)
+/// - `RALPHY_MOCK_FAIL`: comma-separated substrings; tasks whose title contains any fail
+/// - `RALPHY_MOCK_RESPONSE`: response text (default reports the task as done)
+#[derive(Debug, Clone)]
+pub struct MockSettings {
+    pub delay: Duration,
+    pub fail_on: Vec<String>,
+    pub response: String,
+}
+
+impl Default for MockSettings {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(200),
+            fail_on: Vec::new(),
+            response: "Mock engine completed the task.\n<status>DONE</status>".to_string(),
+        }
+    }
+}
+
+impl MockSettings {
+    pub fn from_env() -> Self {
+        let mut settings = Self::default();
+        if let Some(ms) = std::env::var("RALPHY_MOCK_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            settings.delay = Duration::from_millis(ms);
+        }
+        if let Ok(fail) = std::env::var("RALPHY_MOCK_FAIL") {
+            settings.fail_on = fail
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(response) = std::env::var("RALPHY_MOCK_RESPONSE") {
+            settings.response = response;
+        }
+        settings
+    }
+}
+
+/// Limits on how often engines are started, or for the API engines how
+/// often requests are sent. Executors under the same limits share them, so
+/// parallel agents stay under them together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    /// Most engine runs started in any one minute
+    pub rpm: Option<u32>,
+    /// Least time between the starts of two engine runs
+    pub min_delay: Duration,
+}
+
+/// Tries at an engine run, or an API request, before a rate-limit error is
+/// given up on.
+const RATE_LIMIT_ATTEMPTS: u32 = 5;
+
+/// Wait after the first rate-limit error; doubled on each one after it.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(15);
+
+/// Error fragments engine CLIs use for being rate limited or overloaded.
+const RATE_LIMIT_PATTERNS: &[&str] = &[
+    "rate limit exceeded",
+    "rate limit reached",
+    "rate_limit_error",
+    "rate_limit_exceeded",
+    "too many requests",
+    "status 429",
+    "status: 429",
+    "http 429",
+    "error 429",
+    "error: 429",
+    "(429)",
+    "resource_exhausted",
+    "overloaded_error",
+    "error: 529",
+];
+
+/// Whether an engine CLI's error says it was rate limited. Only the last
+/// line counts: that is what the CLI failed with, while the lines before it
+/// can be anything the engine ran into on the way.
+pub fn is_rate_limited(error: &str) -> bool {
+    let last = error.lines().rfind(|line| !line.trim().is_empty());
+    let last = last.unwrap_or_default().to_lowercase();
+    RATE_LIMIT_PATTERNS.iter().any(|p| last.contains(p))
+}
+
+/// An API request turned away for going over a rate limit, or while the API
+/// was overloaded. Sending it again later can work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited(pub String);
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Hands out start times for engine runs under a [`RateLimit`], and backs
+/// every run off together once one of them is rate limited.
+pub struct RateLimiter {
+    limit: RateLimit,
+    backoff: Duration,
+    state: tokio::sync::Mutex<LimiterState>,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    /// Start times handed out, oldest first, some possibly still to come
+    starts: VecDeque<Instant>,
+    /// No run starts before this, after a rate-limit error
+    paused_until: Option<Instant>,
+}
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit, backoff: Duration) -> Self {
+        Self {
+            limit,
+            backoff,
+            state: Default::default(),
+        }
+    }
+
+    /// The limiter of every executor under `limit`.
+    fn shared(limit: RateLimit) -> Arc<Self> {
+        static LIMITERS: OnceLock<Mutex<HashMap<RateLimit, Arc<RateLimiter>>>> = OnceLock::new();
+        LIMITERS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry(limit)
+            .or_insert_with(|| Arc::new(Self::new(limit, RATE_LIMIT_BACKOFF)))
+            .clone()
+    }
+
+    /// Take the next free start time and wait for it. The wait doesn't count
+    /// against a task's timeout.
+    async fn acquire(&self) {
+        let start = {
+            let mut state = self.state.lock().await;
+            let now = Instant::now();
+            let window = MINUTE.max(self.limit.min_delay);
+            while state.starts.front().is_some_and(|&t| t + window <= now) {
+                state.starts.pop_front();
+            }
+
+            let mut start = now.max(state.paused_until.unwrap_or(now));
+            if let Some(&last) = state.starts.back() {
+                start = start.max(last + self.limit.min_delay);
+            }
+            if let Some(rpm) = self
+                .limit
+                .rpm
+                .map(|rpm| rpm as usize)
+                .filter(|&rpm| rpm > 0)
+            {
+                if state.starts.len() >= rpm {
+                    start = start.max(state.starts[state.starts.len() - rpm] + MINUTE);
+                }
+            }
+            state.starts.push_back(start);
+            start
+        };
+        process::off_the_clock(tokio::time::sleep_until(start.into())).await;
+    }
+
+    /// Hold off every run for `wait`.
+    async fn pause(&self, wait: Duration) {
+        let mut state = self.state.lock().await;
+        let until = Instant::now() + wait;
+        state.paused_until = Some(state.paused_until.map_or(until, |t| t.max(until)));
+    }
+
+    /// Run `attempt` once a start time is free, retrying with exponential
+    /// backoff while it fails with an error `rate_limited` accepts.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        rate_limited: impl Fn(&anyhow::Error) -> bool,
+        mut attempt: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut tries = 1;
+        loop {
+            self.acquire().await;
+            match attempt().await {
+                Err(err) if tries < RATE_LIMIT_ATTEMPTS && rate_limited(&err) => {
+                    let wait = self.backoff * 2u32.pow(tries - 1);
+                    log::warn(format!(
+                        "Rate limited (attempt {}/{}), retrying in {}s: {}",
+                        tries,
+                        RATE_LIMIT_ATTEMPTS,
+                        wait.as_secs(),
+                        err.to_string().lines().last().unwrap_or_default()
+                    ));
+                    self.pause(wait).await;
+                    tries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Largest prompt passed as a single argv entry. Linux caps one argument at
+/// 128 KiB and Windows caps the whole command line at 32 KiB, so stay under both.
+const MAX_PROMPT_ARG_BYTES: usize = 30 * 1024;
+
+/// Lines of a failed engine CLI's stderr kept in its error.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Write the prompt to the child's stdin and close it, in the background so
+/// a large prompt can't deadlock against the child's stdout.
+fn send_prompt(child: &mut EngineChild, prompt: &str) -> Result<()> {
+    let mut stdin = child.stdin.take().context("Failed to capture stdin")?;
+    let prompt = prompt.to_string();
+    tokio::spawn(async move {
+        stdin.write_all(prompt.as_bytes()).await.ok();
+        stdin.shutdown().await.ok();
+    });
+    Ok(())
+}
+
+/// Error for an engine CLI that exited unsuccessfully, ending with the last
+/// of what it wrote to stderr so the failure can be acted on.
+async fn exit_error(
+    child: &mut EngineChild,
+    engine: &str,
+    status: std::process::ExitStatus,
+) -> anyhow::Error {
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        pipe.read_to_string(&mut stderr).await.ok();
+    }
+    let stderr = text::tail_lines(&stderr, STDERR_TAIL_LINES);
+    if stderr.is_empty() {
+        anyhow::anyhow!("{} command failed with status: {}", engine, status)
+    } else {
+        anyhow::anyhow!(
+            "{} command failed with status: {}\n{}",
+            engine,
+            status,
+            stderr
+        )
+    }
+}
+
+/// An engine failure that another attempt can't fix, such as a missing API
+/// key or rejected credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fatal(pub String);
+
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Fatal {}
+
+/// Fail clearly, instead of with a spawn error, when a prompt is too large to
+/// pass on the command line to an engine that can't read it from stdin.
+fn check_prompt_arg_len(engine: AiEngine, prompt: &str) -> Result<()> {
+    if prompt.len() > MAX_PROMPT_ARG_BYTES {
+        return Err(Fatal(format!(
+            "Prompt is {} bytes, too large to pass to {} as an argument (limit {} bytes). \
+             Reduce the injected context or use an engine that reads prompts from stdin (Claude, Codex).",
+            prompt.len(),
+            engine,
+            MAX_PROMPT_ARG_BYTES
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// State accumulated while reading `codex exec --json` output.
+#[derive(Debug, Default)]
+struct CodexStream {
+    input_tokens: usize,
+    output_tokens: usize,
+    last_message: String,
+    errors: Vec<String>,
+}
+
+impl CodexStream {
+    /// Consume one JSONL event, returning a monitor step if it starts one
+    fn handle(&mut self, json: &Value) -> Option<&'static str> {
+        match json["type"].as_str() {
+            Some("turn.completed") => {
+                // Usage is reported per turn
+                if let Some((input, output)) = parse_usage(&json["usage"]) {
+                    self.input_tokens += input;
+                    self.output_tokens += output;
+                }
+                None
+            }
+            Some("item.started") | Some("item.updated") => codex_item_step(&json["item"]),
+            Some("item.completed") => {
+                if json["item"]["type"].as_str() == Some("agent_message") {
+                    if let Some(text) = json["item"]["text"].as_str() {
+                        self.last_message = text.to_string();
+                    }
+                }
+                None
+            }
+            Some("turn.failed") => {
+                self.push_error(&json["error"]["message"]);
+                None
+            }
+            Some("error") => {
+                self.push_error(&json["message"]);
+                None
+            }
+            _ => {
+                // Older Codex versions wrap events in a `msg` object
+                let msg = &json["msg"];
+                match msg["type"].as_str() {
+                    Some("token_count") => {
+                        if let Some((input, output)) = parse_usage(msg) {
+                            self.input_tokens = input;
+                            self.output_tokens = output;
+                        }
+                        None
+                    }
+                    Some("exec_command_begin") => Some("Running command"),
+                    Some("patch_apply_begin") => Some("Editing files"),
+                    Some("agent_reasoning") => Some("Thinking"),
+                    Some("error") => {
+                        self.push_error(&msg["message"]);
+                        None
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn push_error(&mut self, message: &Value) {
+        if let Some(message) = message.as_str() {
+            self.errors.push(message.to_string());
+        }
+    }
+}
+
+/// Monitor step for the last tool a Claude `assistant` event calls.
+fn claude_tool_step(json: &Value) -> Option<&'static str> {
+    let tool = json["message"]["content"]
+        .as_array()?
+        .iter()
+        .rev()
+        .find(|item| item["type"] == "tool_use")?;
+    Some(match tool["name"].as_str()? {
+        "Bash" => "Running command",
+        "Edit" | "MultiEdit" | "Write" | "NotebookEdit" => "Editing files",
+        "Read" => "Reading files",
+        "Grep" | "Glob" | "WebSearch" | "WebFetch" => "Searching",
+        "TodoWrite" => "Planning",
+        _ => "Calling tool",
+    })
+}
+
+fn codex_item_step(item: &Value) -> Option<&'static str> {
+    match item["type"].as_str()? {
+        "command_execution" => Some("Running command"),
+        "file_change" => Some("Editing files"),
+        "reasoning" => Some("Thinking"),
+        "web_search" => Some("Searching"),
+        "mcp_tool_call" => Some("Calling tool"),
+        "todo_list" => Some("Planning"),
+        "agent_message" => Some("Responding"),
+        _ => None,
+    }
+}
+
+/// Read input/output token counts from a usage object, accepting the
+/// snake_case, camelCase and OpenAI-style field names engines emit.
+fn parse_usage(usage: &Value) -> Option<(usize, usize)> {
+    let field = |names: &[&str]| names.iter().find_map(|name| usage[*name].as_u64());
+
+    let input = field(&["input_tokens", "inputTokens", "prompt_tokens"]);
+    let output = field(&["output_tokens", "outputTokens", "completion_tokens"]);
+    if input.is_none() && output.is_none() {
+        return None;
+    }
+    Some((input.unwrap_or(0) as usize, output.unwrap_or(0) as usize))
+}
+
+/// Name of the CLI binary each engine is invoked through, if any.
+pub fn engine_binary(engine: AiEngine) -> Option<&'static str> {
+    match engine {
+        AiEngine::Claude => Some("claude"),
+        AiEngine::OpenCode => Some("opencode"),
+        AiEngine::Cursor => Some("agent"),
+        AiEngine::Codex => Some("codex"),
+        AiEngine::Qwen => Some("qwen"),
+        AiEngine::Aider => Some("aider"),
+        AiEngine::AnthropicApi | AiEngine::OpenAiApi | AiEngine::Ollama | AiEngine::Mock => None,
+    }
+}
+
+/// Installation hint shown when an engine's CLI is missing.
+pub fn install_hint(engine: AiEngine) -> &'static str {
+    match engine {
+        AiEngine::Claude => "Install Claude Code from https://github.com/anthropics/claude-code",
+        AiEngine::OpenCode => "Install OpenCode from https://opencode.ai/docs/",
+        AiEngine::Cursor => "Install Cursor and ensure 'agent' is in your PATH",
+        AiEngine::Codex => "Install Codex CLI",
+        AiEngine::Qwen => "Install Qwen-Code",
+        AiEngine::Aider => {
+            "Install aider with 'python -m pip install aider-install && aider-install'"
+        }
+        AiEngine::AnthropicApi | AiEngine::OpenAiApi | AiEngine::Ollama => "",
+        AiEngine::Mock => "",
+    }
+}
+
+/// Whether Ralphy talks to `engine` over HTTP and runs its tool calls
+/// itself, rather than handing the task to a CLI.
+pub fn is_http_engine(engine: AiEngine) -> bool {
+    matches!(
+        engine,
+        AiEngine::AnthropicApi | AiEngine::OpenAiApi | AiEngine::Ollama
+    )
+}
+
+/// Environment variable holding the API key an engine reached over HTTP
+/// needs, if any.
+pub fn api_key_var(engine: AiEngine) -> Option<&'static str> {
+    match engine {
+        AiEngine::AnthropicApi => Some(anthropic::API_KEY_VAR),
+        AiEngine::OpenAiApi => Some(openai::API_KEY_VAR),
+        _ => None,
+    }
+}
+
+/// Fail unless the API key `engine` needs is set.
+pub fn check_api_key(engine: AiEngine) -> Result<()> {
+    if let Some(var) = api_key_var(engine) {
+        if std::env::var(var).map_or(true, |key| key.is_empty()) {
+            anyhow::bail!("{} needs an API key: set {}", engine, var);
+        }
+    }
+    Ok(())
+}
+
+pub fn check_ai_availability(engine: AiEngine) -> Result<()> {
+    let mut check = ToolCheck::new();
+    if let Some(binary) = engine_binary(engine) {
+        check.require(binary, install_hint(engine));
+    }
+    check.finish()?;
+    check_api_key(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_engine_name_matches_serde() {
+        for engine in [
+            AiEngine::OpenCode,
+            AiEngine::AnthropicApi,
+            AiEngine::OpenAiApi,
+        ] {
+            assert_eq!(json!(engine), json!(engine.name()));
+        }
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        assert_eq!(
+            parse_usage(&json!({"input_tokens": 10, "output_tokens": 4})),
+            Some((10, 4))
+        );
+        assert_eq!(
+            parse_usage(&json!({"inputTokens": 7, "outputTokens": 2})),
+            Some((7, 2))
+        );
+        assert_eq!(parse_usage(&json!({"prompt_tokens": 3})), Some((3, 0)));
+        assert_eq!(parse_usage(&json!(null)), None);
+    }
+
+    #[test]
+    fn test_is_rate_limited() {
+        assert!(is_rate_limited(
+            "Anthropic API returned 429 Too Many Requests: Number of request tokens has exceeded your rate limit"
+        ));
+        assert!(is_rate_limited(
+            "Codex command failed with status: exit status: 1: stream error: exceeded retry limit, last status: 429"
+        ));
+        assert!(is_rate_limited(
+            "Claude command failed with status: exit status: 1\nAPI Error: 529 {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\"}}"
+        ));
+        assert!(!is_rate_limited(
+            "Claude command failed with status: exit status: 1\nerror: tests failed"
+        ));
+        // What the engine ran into before failing for another reason
+        assert!(!is_rate_limited(
+            "Codex command failed with status: exit status: 1\ntest rate_limit::too_many_requests ... FAILED\nerror: tests failed"
+        ));
+        assert!(!is_rate_limited("The build server is overloaded"));
+    }
+
+    fn mock_response() -> AiResponse {
+        AiResponse {
+            text: String::new(),
+            input_tokens: 0,
+            output_tokens: 0,
+            actual_cost: None,
+            duration_ms: None,
+            model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tally_counts_work_that_then_fails() {
+        let executor = AiExecutor::new(AiEngine::Mock);
+        let (result, spent) = tally(async {
+            executor.execute("Add a login page").await?;
+            executor.execute("Add a login page, again").await?;
+            Err::<(), _>(anyhow::anyhow!("verification failed"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(spent.input_tokens, "Add a login page".len() / 4 + 5);
+
+        // Outside a tally nothing is counted, and nothing breaks
+        executor.execute("Add a login page").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_starts() {
+        let limit = RateLimit {
+            rpm: Some(10),
+            min_delay: Duration::from_millis(50),
+        };
+        let limiter = RateLimiter::new(limit, Duration::ZERO);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_backs_off() {
+        let limiter = RateLimiter::new(RateLimit::default(), Duration::from_millis(20));
+        let attempts = std::cell::Cell::new(0);
+        let start = Instant::now();
+        let rate_limited = |err: &anyhow::Error| err.is::<RateLimited>();
+        let response = limiter
+            .run(rate_limited, || {
+                attempts.set(attempts.get() + 1);
+                async {
+                    if attempts.get() < 3 {
+                        let error = "Anthropic API returned 429 Too Many Requests";
+                        return Err(RateLimited(error.to_string()).into());
+                    }
+                    Ok(mock_response())
+                }
+            })
+            .await;
+        assert!(response.is_ok());
+        assert_eq!(attempts.get(), 3);
+        // 20ms, then 40ms
+        assert!(start.elapsed() >= Duration::from_millis(60));
+
+        attempts.set(0);
+        let response: Result<AiResponse> = limiter
+            .run(rate_limited, || {
+                attempts.set(attempts.get() + 1);
+                async { anyhow::bail!("Anthropic API returned 429 Too Many Requests") }
+            })
+            .await;
+        assert!(response.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exit_error_includes_stderr() {
+        let mut child = EngineChild::spawn(
+            Command::new("sh")
+                .args(["-c", "echo 'rate limited' >&2; exit 3"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .unwrap();
+        let status = child.wait().await.unwrap();
+        let err = exit_error(&mut child, "Claude", status).await;
+        assert_eq!(
+            err.to_string(),
+            "Claude command failed with status: exit status: 3\nrate limited"
+        );
+    }
+
+    #[test]
+    fn test_codex_stream() {
+        let mut stream = CodexStream::default();
+        let events = [
+            json!({"type": "thread.started", "thread_id": "t1"}),
+            json!({"type": "item.started", "item": {"type": "command_execution", "command": "ls"}}),
+            json!({"type": "item.completed", "item": {"type": "agent_message", "text": "Done"}}),
+            json!({"type": "turn.completed", "usage": {"input_tokens": 120, "cached_input_tokens": 20, "output_tokens": 30}}),
+            json!({"type": "error", "message": "stream disconnected"}),
+        ];
+        let steps: Vec<_> = events.iter().filter_map(|e| stream.handle(e)).collect();
+
+        assert_eq!(steps, vec!["Running command"]);
+        assert_eq!(stream.input_tokens, 120);
+        assert_eq!(stream.output_tokens, 30);
+        assert_eq!(stream.last_message, "Done");
+        assert_eq!(stream.errors, vec!["stream disconnected"]);
+    }
+
+    #[test]
+    fn test_claude_tool_step() {
+        let event = json!({"type": "assistant", "message": {"content": [
+            {"type": "text", "text": "Let me look"},
+            {"type": "tool_use", "name": "Grep", "input": {"pattern": "fn main"}},
+        ]}});
+        assert_eq!(claude_tool_step(&event), Some("Searching"));
+        let event = json!({"type": "assistant", "message": {"content": [
+            {"type": "tool_use", "name": "mcp__db__query", "input": {}},
+        ]}});
+        assert_eq!(claude_tool_step(&event), Some("Calling tool"));
+        let event = json!({"type": "assistant", "message": {"content": [
+            {"type": "text", "text": "Done"},
+        ]}});
+        assert_eq!(claude_tool_step(&event), None);
+    }
+
+    #[test]
+    fn test_aider_output() {
+        let mut output = AiderOutput::default();
+        let lines = [
+            "Main model: claude-3-5-sonnet-20241022 with diff edit format, infinite output",
+            "I'll add the greeting.",
+            "Tokens: 2.3k sent, 1.2k cache hit, 120 received. Cost: $0.0082 message, $0.0082 session.",
+            "Applied edit to hello.py",
+            "Commit 1a2b3c4 feat: Add greeting",
+            "Tokens: 850 sent, 12k cache write, 1,024 received. Cost: $0.05 message, $0.0582 session.",
+            "<status>DONE</status>",
+        ];
+        let steps: Vec<_> = lines.iter().filter_map(|l| output.handle(l)).collect();
+
+        assert_eq!(steps, vec!["Editing files", "Committing"]);
+        assert_eq!(output.input_tokens, 2300 + 1200 + 850 + 12_000);
+        assert_eq!(output.output_tokens, 120 + 1024);
+        assert!((output.cost.unwrap() - 0.0582).abs() < 1e-9);
+        assert_eq!(output.model.as_deref(), Some("claude-3-5-sonnet-20241022"));
+        assert!(!output.lines.iter().any(|l| l.starts_with("Tokens:")));
+        assert_eq!(output.lines.last().unwrap(), "<status>DONE</status>");
+
+        assert_eq!(parse_token_count("1.2M"), Some(1_200_000));
+        assert_eq!(parse_token_count("lots"), None);
+    }
+}
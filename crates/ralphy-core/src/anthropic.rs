@@ -0,0 +1,353 @@
+use crate::ai::{AiResponse, Fatal, RateLimited, RateLimiter};
+use crate::tools::{self, ChatApi, Reply, ToolCall, Workspace};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+/// Where requests go unless `ANTHROPIC_BASE_URL` says otherwise.
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+/// Model used unless `ANTHROPIC_MODEL` names another.
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-5";
+
+/// Environment variable holding the API key.
+pub const API_KEY_VAR: &str = "ANTHROPIC_API_KEY";
+
+const API_VERSION: &str = "2023-06-01";
+
+/// Most tokens the model may write in one turn.
+const MAX_TOKENS: u32 = 16_384;
+
+/// How to reach the Messages API, from the environment.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let api_key = std::env::var(API_KEY_VAR)
+            .ok()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| Fatal(format!("{} is not set", API_KEY_VAR)))?;
+        let base_url = std::env::var("ANTHROPIC_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        let model = std::env::var("ANTHROPIC_MODEL")
+            .ok()
+            .filter(|model| !model.is_empty())
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+        Ok(Self {
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+        })
+    }
+}
+
+/// Work on `prompt` through the Messages API, carrying out the model's bash
+/// and editor tool calls in `workspace` until it stops asking for them.
+/// Requests wait their turn under `limiter`, which retries them when the API
+/// is rate limited or overloaded.
+/// `report_step` hears what the model is doing as it goes.
+pub async fn run(
+    settings: &Settings,
+    workspace: &Workspace,
+    limiter: &RateLimiter,
+    prompt: &str,
+    report_step: impl Fn(&str),
+) -> Result<AiResponse> {
+    tools::run_agent(settings, workspace, limiter, prompt, report_step).await
+}
+
+impl ChatApi for Settings {
+    fn name(&self) -> &'static str {
+        "Anthropic API"
+    }
+
+    /// Send one Messages request and read its event stream to the end.
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        system: &str,
+        messages: &[Value],
+    ) -> Result<Reply> {
+        let body = json!({
+            "model": self.model,
+            "max_tokens": MAX_TOKENS,
+            "system": system,
+            "stream": true,
+            "tools": [
+                {"type": "bash_20250124", "name": tools::BASH_TOOL},
+                {"type": "text_editor_20250728", "name": tools::EDITOR_TOOL},
+            ],
+            "messages": messages,
+        });
+        let mut http = client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to reach the Anthropic API")?;
+
+        let status = http.status();
+        if !status.is_success() {
+            let body = http.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            let error = format!("Anthropic API returned {}: {}", status, message);
+            match status.as_u16() {
+                // A bad key stays bad however often the request is retried
+                401 | 403 => return Err(Fatal(error).into()),
+                429 | 529 => return Err(RateLimited(error).into()),
+                _ => anyhow::bail!(error),
+            }
+        }
+
+        let mut events = SseReader::default();
+        let mut turn = Turn::default();
+        while let Some(chunk) = http.chunk().await? {
+            for data in events.push(&chunk) {
+                if let Ok(event) = serde_json::from_str::<Value>(&data) {
+                    turn.handle(&event)?;
+                }
+            }
+        }
+        Ok(turn.into_reply())
+    }
+
+    fn tool_results(&self, results: Vec<(&ToolCall, Result<String>)>) -> Vec<Value> {
+        let results: Vec<Value> = results
+            .into_iter()
+            .map(|(call, result)| {
+                let is_error = result.is_err();
+                json!({
+                    "type": "tool_result",
+                    "tool_use_id": call.id,
+                    "content": result.unwrap_or_else(|e| format!("{:#}", e)),
+                    "is_error": is_error,
+                })
+            })
+            .collect();
+        vec![json!({"role": "user", "content": results})]
+    }
+}
+
+/// Splits a `text/event-stream` body into the data of each event.
+#[derive(Debug, Default)]
+struct SseReader {
+    /// Bytes of the event in progress, kept whole so characters split
+    /// across chunks decode correctly
+    buffer: Vec<u8>,
+}
+
+impl SseReader {
+    /// Add a chunk of the body, returning the data of every event it completed.
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r'));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let bytes: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&bytes);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+/// One assistant message, assembled from its stream events.
+#[derive(Debug, Default)]
+struct Turn {
+    content: Vec<Value>,
+    /// Tool inputs arrive as JSON fragments, one buffer per content block
+    partial_inputs: Vec<String>,
+    stop_reason: Option<String>,
+    input_tokens: usize,
+    output_tokens: usize,
+    model: Option<String>,
+}
+
+impl Turn {
+    fn handle(&mut self, event: &Value) -> Result<()> {
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.model = message["model"].as_str().map(str::to_string);
+                let usage = &message["usage"];
+                // Cached prompt tokens are billed input too
+                self.input_tokens = [
+                    "input_tokens",
+                    "cache_creation_input_tokens",
+                    "cache_read_input_tokens",
+                ]
+                .iter()
+                .filter_map(|field| usage[*field].as_u64())
+                .sum::<u64>() as usize;
+                self.output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as usize;
+            }
+            Some("content_block_start") => {
+                self.content.push(event["content_block"].clone());
+                self.partial_inputs.push(String::new());
+            }
+            Some("content_block_delta") => {
+                let index = event["index"].as_u64().unwrap_or(0) as usize;
+                let delta = &event["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        if let Some(block) = self.content.get_mut(index) {
+                            let text = block["text"].as_str().unwrap_or("").to_string();
+                            block["text"] = json!(text + delta["text"].as_str().unwrap_or(""));
+                        }
+                    }
+                    Some("input_json_delta") => {
+                        if let Some(partial) = self.partial_inputs.get_mut(index) {
+                            partial.push_str(delta["partial_json"].as_str().unwrap_or(""));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Some("content_block_stop") => {
+                let index = event["index"].as_u64().unwrap_or(0) as usize;
+                if let (Some(block), Some(partial)) =
+                    (self.content.get_mut(index), self.partial_inputs.get(index))
+                {
+                    if block["type"] == "tool_use" && !partial.is_empty() {
+                        block["input"] = serde_json::from_str(partial)
+                            .context("Anthropic API sent malformed tool input")?;
+                    }
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let Some(output) = event["usage"]["output_tokens"].as_u64() {
+                    self.output_tokens = output as usize;
+                }
+            }
+            Some("error") => {
+                anyhow::bail!(
+                    "Anthropic API stream failed: {}",
+                    event["error"]["message"]
+                        .as_str()
+                        .unwrap_or("unknown error")
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The finished message, with the tools it asks for if it stopped to
+    /// use them.
+    fn into_reply(self) -> Reply {
+        let text = self.text();
+        let calls = match self.stop_reason.as_deref() {
+            Some("tool_use") => self
+                .content
+                .iter()
+                .filter(|block| block["type"] == "tool_use")
+                .map(|block| ToolCall {
+                    id: block["id"].clone(),
+                    name: block["name"].as_str().unwrap_or("").to_string(),
+                    input: Ok(block["input"].clone()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        // The API rejects empty text blocks sent back to it
+        let content: Vec<Value> = self
+            .content
+            .into_iter()
+            .filter(|block| block["type"] != "text" || block["text"] != "")
+            .collect();
+        Reply {
+            message: json!({"role": "assistant", "content": content}),
+            text,
+            calls,
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            model: self.model,
+        }
+    }
+
+    /// Text the model wrote this turn
+    fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_reader_splits_across_chunks() {
+        let mut reader = SseReader::default();
+        assert!(reader.push(b"event: ping\ndata: {\"type\"").is_empty());
+        let events = reader.push(b": \"ping\"}\n\nevent: message_stop\r\ndata: {}\r\n\r\n");
+        assert_eq!(events, ["{\"type\": \"ping\"}", "{}"]);
+        // A character split between chunks survives
+        let snowman = "data: \u{2603}\n\n".as_bytes();
+        assert!(reader.push(&snowman[..7]).is_empty());
+        assert_eq!(reader.push(&snowman[7..]), ["\u{2603}"]);
+    }
+
+    #[test]
+    fn test_turn_assembles_text_tool_calls_and_usage() {
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-sonnet-4-5", "usage": {"input_tokens": 100, "cache_read_input_tokens": 20, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Listing "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "files"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "t1", "name": "bash", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"command\": "}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"ls\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 42}}),
+            json!({"type": "message_stop"}),
+        ];
+        let mut turn = Turn::default();
+        for event in &events {
+            turn.handle(event).unwrap();
+        }
+
+        assert_eq!(turn.text(), "Listing files");
+        assert_eq!(turn.content[1]["input"], json!({"command": "ls"}));
+        assert_eq!(turn.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!((turn.input_tokens, turn.output_tokens), (120, 42));
+        assert_eq!(turn.model.as_deref(), Some("claude-sonnet-4-5"));
+
+        let overloaded = json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}});
+        assert!(turn
+            .handle(&overloaded)
+            .unwrap_err()
+            .to_string()
+            .contains("Overloaded"));
+
+        let reply = turn.into_reply();
+        assert_eq!(reply.calls.len(), 1);
+        assert_eq!(reply.calls[0].id, "t1");
+        assert_eq!(reply.message["content"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::pricing::Price;
+use crate::shutdown;
+use anyhow::{Context, Result};
+use std::io::Write;
+use tokio::io::AsyncReadExt;
+
+/// Rough size of a token in bytes, for estimating a prompt's input tokens.
+const BYTES_PER_TOKEN: usize = 4;
+
+/// The user turned a task down at the `--interactive` question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Declined {
+    /// Leave this task for another run and go on to the next one
+    Skip,
+    /// Stop the run here
+    Stop,
+}
+
+impl std::fmt::Display for Declined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Declined::Skip => write!(f, "Skipped by the user"),
+            Declined::Stop => write!(f, "Stopped by the user"),
+        }
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// An answer to the question asked before each task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    Skip,
+    Edit,
+}
+
+fn parse_answer(line: &str) -> Option<Answer> {
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => Some(Answer::Yes),
+        "n" | "no" => Some(Answer::No),
+        "s" | "skip" => Some(Answer::Skip),
+        "e" | "edit" => Some(Answer::Edit),
+        _ => None,
+    }
+}
+
+/// What running `prompt` is likely to cost: the average of the tasks run so
+/// far when there are any, otherwise the prompt's own input tokens, which is
+/// a floor rather than an estimate of the whole task, at `price`.
+pub fn estimate(prompt: &str, typical_cost: Option<f64>, price: Price) -> String {
+    let tokens = prompt.len() / BYTES_PER_TOKEN;
+    match typical_cost {
+        Some(cost) => format!("~${:.4} (average so far; prompt ~{} tokens)", cost, tokens),
+        None => format!(
+            "at least ${:.4} (prompt ~{} tokens, before the engine reads or writes anything)",
+            price.cost(tokens, 0),
+            tokens
+        ),
+    }
+}
+
+/// Show `task`, its prompt and what it may cost at `price`, and wait for
+/// the user to approve it, possibly after editing the prompt.
+///
+/// Returns the prompt to send, or a [`Declined`] error when the user skips
+/// the task or stops the run.
+pub async fn confirm(
+    task: &str,
+    prompt: String,
+    typical_cost: Option<f64>,
+    price: Price,
+) -> Result<String> {
+    let mut prompt = prompt;
+    loop {
+        println!("\nTask: {}", task);
+        println!("{}", prompt);
+        println!("Estimated cost: {}", estimate(&prompt, typical_cost, price));
+        print!("Run it? [y]es / [n]o, stop / [s]kip / [e]dit prompt: ");
+        std::io::stdout().flush()?;
+
+        let line = tokio::select! {
+            line = read_line() => line.context("Failed to read the answer")?,
+            _ = shutdown::wait() => anyhow::bail!("Interrupted while waiting for an answer"),
+        };
+        // Nobody to ask, e.g. stdin closed
+        let Some(line) = line else {
+            println!();
+            return Err(Declined::Stop.into());
+        };
+
+        match parse_answer(&line) {
+            Some(Answer::Yes) => return Ok(prompt),
+            Some(Answer::No) => return Err(Declined::Stop.into()),
+            Some(Answer::Skip) => return Err(Declined::Skip.into()),
+            Some(Answer::Edit) => prompt = edit(&prompt)?,
+            None => println!("[WARN] Answer y, n, s or e"),
+        }
+    }
+}
+
+/// One line from stdin, or `None` at its end. Read a byte at a time so
+/// nothing typed ahead for the next question is buffered and lost.
+async fn read_line() -> std::io::Result<Option<String>> {
+    let mut stdin = tokio::io::stdin();
+    let mut line = Vec::new();
+    loop {
+        let mut byte = [0u8; 1];
+        if stdin.read(&mut byte).await? == 0 {
+            return Ok((!line.is_empty()).then(|| String::from_utf8_lossy(&line).into_owned()));
+        }
+        if byte[0] == b'\n' {
+            return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+        }
+        line.push(byte[0]);
+    }
+}
+
+/// Open `prompt` in `$VISUAL` or `$EDITOR` (vi when neither is set) and
+/// return what was saved.
+fn edit(prompt: &str) -> Result<String> {
+    let mut file = tempfile::Builder::new()
+        .prefix("ralphy-prompt-")
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create a file to edit the prompt in")?;
+    file.write_all(prompt.as_bytes())?;
+    file.flush()?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", editor, status);
+    }
+
+    let edited = std::fs::read_to_string(file.path())?;
+    if edited.trim().is_empty() {
+        println!("[WARN] The edited prompt is empty; keeping the previous one");
+        return Ok(prompt.to_string());
+    }
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("y\n"), Some(Answer::Yes));
+        assert_eq!(parse_answer(" Skip "), Some(Answer::Skip));
+        assert_eq!(parse_answer("E"), Some(Answer::Edit));
+        assert_eq!(parse_answer("no"), Some(Answer::No));
+        assert_eq!(parse_answer("maybe"), None);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let prompt = "x".repeat(4000);
+        let price = Price {
+            input: 3.0,
+            output: 15.0,
+        };
+        assert_eq!(
+            estimate(&prompt, Some(0.25), price),
+            "~$0.2500 (average so far; prompt ~1000 tokens)"
+        );
+        assert!(estimate(&prompt, None, price).starts_with("at least $0.0030 (prompt ~1000 tokens"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{events, process};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Where engines and verification commands run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Directly on this machine
+    #[default]
+    Local,
+    /// Inside the project's devcontainer, via the devcontainer CLI
+    Devcontainer,
+    /// Each parallel task as a Kubernetes Job, configured in ralphy.toml
+    Kubernetes,
+}
+
+/// Where a devcontainer configuration may live, in the order the
+/// devcontainer CLI looks for one.
+pub const DEVCONTAINER_CONFIGS: [&str; 2] =
+    [".devcontainer/devcontainer.json", ".devcontainer.json"];
+
+/// The project's devcontainer configuration, if it has one.
+pub fn find_devcontainer_config() -> Option<PathBuf> {
+    DEVCONTAINER_CONFIGS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+}
+
+/// Bring the backend up before the first task runs.
+pub fn start(backend: Backend) -> Result<()> {
+    match backend {
+        Backend::Local => {
+            if let Some(config) = find_devcontainer_config() {
+                events::info(format!(
+                    "Found {}; use --backend devcontainer to run tasks inside it",
+                    config.display()
+                ));
+            }
+            Ok(())
+        }
+        Backend::Kubernetes => crate::kubernetes::check_repo(),
+        Backend::Devcontainer => {
+            let config = find_devcontainer_config().with_context(|| {
+                format!(
+                    "--backend devcontainer needs a devcontainer configuration ({})",
+                    DEVCONTAINER_CONFIGS.join(" or ")
+                )
+            })?;
+            events::info(format!(
+                "Starting devcontainer from {}...",
+                config.display()
+            ));
+
+            let output = std::process::Command::new("devcontainer")
+                .args(["up", "--workspace-folder", "."])
+                .output()
+                .context("Failed to run devcontainer up")?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "devcontainer up failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Command that runs an engine CLI on `backend` with extra environment
+/// variables.
+pub fn engine_command(
+    backend: Backend,
+    program: &str,
+    envs: &[(&str, &str)],
+) -> tokio::process::Command {
+    match backend {
+        // Kubernetes tasks run as Jobs; anything else runs here
+        Backend::Local | Backend::Kubernetes => {
+            let mut cmd = process::engine_command(program);
+            cmd.envs(envs.iter().copied());
+            cmd
+        }
+        Backend::Devcontainer => {
+            let mut cmd = process::engine_command("devcontainer");
+            cmd.args(["exec", "--workspace-folder", "."]);
+            for (key, value) in envs {
+                cmd.arg("--remote-env").arg(format!("{}={}", key, value));
+            }
+            cmd.arg(program);
+            cmd
+        }
+    }
+}
+
+/// Command that runs a shell command line on `backend`.
+pub fn shell_command(backend: Backend, line: &str) -> std::process::Command {
+    let mut cmd = match backend {
+        Backend::Local | Backend::Kubernetes if cfg!(windows) => {
+            let mut cmd = std::process::Command::new("cmd");
+            cmd.arg("/C");
+            cmd
+        }
+        Backend::Local | Backend::Kubernetes => {
+            let mut cmd = std::process::Command::new("sh");
+            cmd.arg("-c");
+            cmd
+        }
+        Backend::Devcontainer => {
+            let mut cmd = std::process::Command::new("devcontainer");
+            cmd.args(["exec", "--workspace-folder", ".", "sh", "-c"]);
+            cmd
+        }
+    };
+    cmd.arg(line);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &tokio::process::Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_devcontainer_engine_command() {
+        let cmd = engine_command(Backend::Devcontainer, "opencode", &[("KEY", "value")]);
+        assert_eq!(cmd.as_std().get_program(), "devcontainer");
+        assert_eq!(
+            args(&cmd),
+            [
+                "exec",
+                "--workspace-folder",
+                ".",
+                "--remote-env",
+                "KEY=value",
+                "opencode"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_engine_command() {
+        let cmd = engine_command(Backend::Local, "claude", &[]);
+        assert_eq!(cmd.as_std().get_program(), "claude");
+        assert!(args(&cmd).is_empty());
+    }
+}
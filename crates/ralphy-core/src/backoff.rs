@@ -0,0 +1,112 @@
+use crate::ai::Fatal;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::{Duration, SystemTime};
+
+/// How long to wait before each retry of a failed task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// Wait before the first retry
+    pub base: Duration,
+    /// What each wait is multiplied by for the next one
+    pub multiplier: f64,
+    /// Longest any one wait gets
+    pub max: Duration,
+    /// Fraction of each wait taken off at random, from 0 to 1, so agents
+    /// that failed together don't all retry together
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(5),
+            multiplier: 2.0,
+            max: Duration::from_secs(300),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// The wait before retry number `retry`, counting from 1, without jitter.
+    pub fn delay(&self, retry: usize) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let secs = self.base.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(secs.min(self.max.as_secs_f64()))
+    }
+
+    /// The wait before retry number `retry`, with jitter.
+    pub fn jittered(&self, retry: usize) -> Duration {
+        self.delay(retry)
+            .mul_f64(1.0 - self.jitter * random_fraction())
+    }
+}
+
+/// A number from 0 up to 1 that differs from call to call.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    hasher.write_u128(nanos);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether another attempt at a task could go differently. An engine that
+/// isn't installed, can't be started or reported a [`Fatal`] error fails
+/// the same way every time.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    !err.chain().any(|cause| {
+        cause.is::<Fatal>()
+            || cause.downcast_ref::<io::Error>().is_some_and(|e| {
+                matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                )
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_delay() {
+        let backoff = Backoff {
+            base: Duration::from_secs(2),
+            multiplier: 3.0,
+            max: Duration::from_secs(30),
+            jitter: 0.0,
+        };
+        let delays: Vec<u64> = (1..=4).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [2, 6, 18, 30]);
+        assert_eq!(backoff.jittered(2), Duration::from_secs(6));
+
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..backoff
+        };
+        for _ in 0..20 {
+            let wait = backoff.jittered(2);
+            assert!(wait > Duration::from_secs(3) && wait <= Duration::from_secs(6));
+        }
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&anyhow::anyhow!("Task timed out after 60s")));
+        assert!(!is_retryable(
+            &Fatal("ANTHROPIC_API_KEY is not set".into()).into()
+        ));
+
+        let missing = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .context("Failed to spawn claude command")
+            .unwrap_err();
+        assert!(!is_retryable(&missing));
+    }
+}
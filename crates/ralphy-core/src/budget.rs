@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+/// Exit code used when a run stops at `--max-cost` or `--max-tokens`.
+pub const EXIT_LIMIT_REACHED: i32 = 3;
+
+/// Which of the whole-run limits, if any, a run that has spent `cost` and
+/// used `tokens` has reached.
+pub fn run_limit_reached(
+    max_cost: Option<f64>,
+    max_tokens: Option<usize>,
+    cost: f64,
+    tokens: usize,
+) -> Option<String> {
+    if let Some(max) = max_cost.filter(|max| cost >= *max) {
+        return Some(format!("spent ${:.4} of the ${:.2} --max-cost", cost, max));
+    }
+    if let Some(max) = max_tokens.filter(|max| tokens >= *max) {
+        return Some(format!("used {} of the {} --max-tokens", tokens, max));
+    }
+    None
+}
+
+/// Parse a `--budget LABEL=USD` argument.
+pub fn parse_budget(arg: &str) -> Result<(String, f64), String> {
+    let (label, amount) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected LABEL=USD, got '{}'", arg))?;
+
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(format!("missing label in '{}'", arg));
+    }
+
+    let amount = parse_usd(amount).map_err(|e| format!("{} in '{}'", e, arg))?;
+    Ok((label.to_string(), amount))
+}
+
+/// Parse a dollar amount such as `2`, `0.5` or `$10`.
+pub fn parse_usd(arg: &str) -> Result<f64, String> {
+    let amount: f64 = arg
+        .trim()
+        .trim_start_matches('$')
+        .parse()
+        .map_err(|_| "invalid amount".to_string())?;
+    if !amount.is_finite() || amount < 0.0 {
+        return Err("amount must be a non-negative number".to_string());
+    }
+    Ok(amount)
+}
+
+/// Spending limits for tasks sharing a tag or parallel group.
+#[derive(Debug, Clone, Default)]
+pub struct Budgets {
+    limits: HashMap<String, f64>,
+    spent: HashMap<String, f64>,
+}
+
+impl Budgets {
+    pub fn new(limits: &[(String, f64)]) -> Self {
+        Self {
+            limits: limits.iter().cloned().collect(),
+            spent: HashMap::new(),
+        }
+    }
+
+    /// Count a task's cost against every budget it belongs to
+    pub fn charge(&mut self, labels: &[String], cost: f64) {
+        for label in labels {
+            if self.limits.contains_key(label) {
+                *self.spent.entry(label.clone()).or_insert(0.0) += cost;
+            }
+        }
+    }
+
+    /// The first budget among `labels` that has been used up, if any
+    pub fn exhausted<'a>(&self, labels: &'a [String]) -> Option<&'a str> {
+        labels
+            .iter()
+            .find(|label| {
+                self.limits
+                    .get(label.as_str())
+                    .is_some_and(|limit| self.spent(label) >= *limit)
+            })
+            .map(String::as_str)
+    }
+
+    pub fn spent(&self, label: &str) -> f64 {
+        self.spent.get(label).copied().unwrap_or(0.0)
+    }
+
+    pub fn limit(&self, label: &str) -> Option<f64> {
+        self.limits.get(label).copied()
+    }
+
+    /// Spending so far against each label, for a checkpoint
+    pub fn spending(&self) -> HashMap<String, f64> {
+        self.spent.clone()
+    }
+
+    /// Pick up spending recorded by an interrupted run
+    pub fn restore(&mut self, spent: HashMap<String, f64>) {
+        self.spent = spent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_budget() {
+        assert_eq!(
+            parse_budget("experimental=2"),
+            Ok(("experimental".to_string(), 2.0))
+        );
+        assert_eq!(
+            parse_budget("group:1=$0.5"),
+            Ok(("group:1".to_string(), 0.5))
+        );
+        assert!(parse_budget("experimental").is_err());
+        assert!(parse_budget("=2").is_err());
+        assert!(parse_budget("x=-1").is_err());
+    }
+
+    #[test]
+    fn test_run_limit_reached() {
+        assert_eq!(run_limit_reached(None, None, 100.0, 1_000_000), None);
+        assert_eq!(
+            run_limit_reached(Some(5.0), Some(10_000), 4.99, 9_999),
+            None
+        );
+        assert_eq!(
+            run_limit_reached(Some(5.0), Some(10_000), 5.25, 200),
+            Some("spent $5.2500 of the $5.00 --max-cost".to_string())
+        );
+        assert_eq!(
+            run_limit_reached(Some(5.0), Some(10_000), 1.0, 12_000),
+            Some("used 12000 of the 10000 --max-tokens".to_string())
+        );
+    }
+
+    #[test]
+    fn test_exhausted_after_spending_limit() {
+        let mut budgets = Budgets::new(&[("experimental".to_string(), 1.0)]);
+        let labels = vec!["experimental".to_string(), "ui".to_string()];
+
+        budgets.charge(&labels, 0.6);
+        assert_eq!(budgets.exhausted(&labels), None);
+
+        budgets.charge(&labels, 0.6);
+        assert_eq!(budgets.exhausted(&labels), Some("experimental"));
+        assert_eq!(budgets.exhausted(&["ui".to_string()]), None);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::budget::Budgets;
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::progress;
+use crate::stats::RunStats;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Where the run in progress is, under [`progress::STATE_DIR`].
+pub const STATE_FILE: &str = "state.json";
+
+/// A task that was running when the checkpoint was taken.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InProgress {
+    pub task: String,
+    /// Branch the task's work goes to, when it has its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+/// Everything a run knows that isn't in the PRD, rewritten as each task
+/// starts and finishes so a killed run can be picked up with `--resume`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// RFC 3339 timestamp
+    pub saved_at: String,
+    pub iteration: usize,
+    pub stats: RunStats,
+    /// Spending so far against each `--budget` label
+    #[serde(default)]
+    pub budget_spent: HashMap<String, f64>,
+    #[serde(default)]
+    pub in_progress: Vec<InProgress>,
+}
+
+fn path() -> PathBuf {
+    PathBuf::from(progress::STATE_DIR).join(STATE_FILE)
+}
+
+impl Checkpoint {
+    /// Take a checkpoint of the run as it is now.
+    pub fn new(
+        iteration: usize,
+        stats: &RunStats,
+        budgets: &Budgets,
+        in_progress: Vec<InProgress>,
+    ) -> Self {
+        Self {
+            saved_at: chrono::Utc::now().to_rfc3339(),
+            iteration,
+            stats: stats.clone(),
+            budget_spent: budgets.spending(),
+            in_progress,
+        }
+    }
+
+    /// Write the checkpoint, replacing the last one in a single rename so a
+    /// crash mid-write can't leave half a file behind.
+    pub async fn save(&self) -> Result<()> {
+        let dir = progress::ensure_state_dir().await?;
+        let staged = dir.join(format!("{}.tmp", STATE_FILE));
+        tokio::fs::write(&staged, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", staged.display()))?;
+        tokio::fs::rename(&staged, path())
+            .await
+            .with_context(|| format!("Failed to write {}", path().display()))
+    }
+
+    pub fn load() -> Result<Option<Self>> {
+        let path = path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Forget the checkpoint once a run has finished.
+    pub fn clear() -> Result<()> {
+        match std::fs::remove_file(path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path().display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The checkpoint to carry on from with `--resume`. Without it, a leftover
+/// checkpoint is only mentioned; the new run replaces it.
+pub fn resume(config: &RunConfig) -> Result<Option<Checkpoint>> {
+    let checkpoint = Checkpoint::load()?;
+    if !config.resume {
+        if checkpoint.is_some() {
+            events::info(
+                "Found an interrupted run; starting a new one (pass --resume to carry on from it)",
+            );
+        }
+        return Ok(None);
+    }
+
+    let mut checkpoint = checkpoint.with_context(|| {
+        format!(
+            "No interrupted run to resume: {} does not exist",
+            path().display()
+        )
+    })?;
+    // Tasks that were cut short run again, so they don't count yet
+    checkpoint.iteration = checkpoint
+        .iteration
+        .saturating_sub(checkpoint.in_progress.len());
+    events::notice(
+        Level::Plain,
+        format!(
+            ">>> Resuming the run interrupted at {}: {} task(s) done, {} failed, ${:.4} spent",
+            checkpoint.saved_at,
+            checkpoint.stats.agents.len(),
+            checkpoint.stats.failed.len(),
+            checkpoint.stats.actual_cost
+        ),
+    );
+    for task in &checkpoint.in_progress {
+        match task.branch {
+            Some(ref branch) => events::notice(
+                Level::Plain,
+                format!(
+                    "    Retrying {} (its earlier work is on {})",
+                    task.task, branch
+                ),
+            ),
+            None => events::notice(Level::Plain, format!("    Retrying {}", task.task)),
+        }
+    }
+    Ok(Some(checkpoint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AiEngine, AiResponse};
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut stats = RunStats::new();
+        let response = AiResponse {
+            text: String::new(),
+            input_tokens: 120,
+            output_tokens: 30,
+            actual_cost: Some(0.25),
+            duration_ms: None,
+            model: None,
+        };
+        stats.record(
+            "Add login",
+            AiEngine::Claude,
+            &response,
+            std::time::Duration::from_secs(90),
+        );
+        stats.record_failure("Add billing");
+        stats.record_error(&anyhow::anyhow!("disk full"));
+        let mut budgets = Budgets::new(&[("experimental".to_string(), 2.0)]);
+        budgets.charge(&["experimental".to_string()], 0.5);
+
+        let in_progress = vec![InProgress {
+            task: "Add search".to_string(),
+            branch: Some("ralphy/add-search".to_string()),
+        }];
+        let checkpoint = Checkpoint::new(2, &stats, &budgets, in_progress.clone());
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let restored: Checkpoint = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.iteration, 2);
+        assert_eq!(restored.stats.completed_tasks(), ["Add login"]);
+        assert_eq!(restored.stats.failed, ["Add billing"]);
+        assert_eq!(restored.stats.error_categories, ["other"]);
+        assert_eq!(restored.stats.input_tokens, 120);
+        assert_eq!(restored.stats.agents[0].engine, AiEngine::Claude);
+        assert_eq!(restored.budget_spent["experimental"], 0.5);
+        assert_eq!(restored.in_progress, in_progress);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::config::RunConfig;
+use crate::events;
+use crate::{git, text};
+use anyhow::Result;
+use regex::Regex;
+
+/// Diffs are cut to this size so the prompt fits every engine.
+const MAX_COMMIT_DIFF_BYTES: usize = 20_000;
+
+/// Longest header commitlint's conventional config accepts.
+const MAX_HEADER_CHARS: usize = 100;
+
+/// Conventional Commits type for a task: the first tag that names one, such
+/// as `bug` or `docs`, else the title's first word, else `feat`.
+pub fn commit_type(task: &str, tags: &[String]) -> &'static str {
+    let type_of = |word: &str| match word.to_lowercase().as_str() {
+        "fix" | "fixes" | "bug" | "bugfix" | "hotfix" | "resolve" | "correct" => Some("fix"),
+        "docs" | "doc" | "document" | "documentation" => Some("docs"),
+        "test" | "tests" | "testing" => Some("test"),
+        "refactor" | "cleanup" | "clean" | "rename" | "simplify" | "extract" => Some("refactor"),
+        "perf" | "performance" | "optimize" | "optimise" | "speed" => Some("perf"),
+        "chore" | "bump" | "upgrade" | "deps" | "ci" | "build" => Some("chore"),
+        "feat" | "feature" | "enhancement" => Some("feat"),
+        _ => None,
+    };
+    let first_word = task
+        .split_whitespace()
+        .next()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .unwrap_or_default();
+    tags.iter()
+        .find_map(|tag| type_of(tag))
+        .or_else(|| type_of(first_word))
+        .unwrap_or("feat")
+}
+
+/// `type(scope): subject` for a task, within commitlint's conventional
+/// rules: the subject starts lowercase, has no trailing period, and the
+/// whole header fits in 100 characters.
+pub fn conventional_header(task: &str, tags: &[String], scope: Option<&str>) -> String {
+    let prefix = match scope {
+        Some(scope) => format!("{}({}): ", commit_type(task, tags), scope),
+        None => format!("{}: ", commit_type(task, tags)),
+    };
+    let subject = task.lines().next().unwrap_or_default().trim();
+    let subject = subject.trim_end_matches('.');
+    // Acronyms such as "API" keep their case
+    let mut chars = subject.chars();
+    let subject = match (chars.next(), chars.next()) {
+        (Some(first), second) if !second.is_some_and(char::is_uppercase) => first
+            .to_lowercase()
+            .chain(subject.chars().skip(1))
+            .collect(),
+        _ => subject.to_string(),
+    };
+    let room = MAX_HEADER_CHARS.saturating_sub(prefix.chars().count());
+    format!("{}{}", prefix, text::truncate(&subject, room))
+}
+
+/// Message to commit a task's leftover changes with when the engine didn't
+/// write one: a conventional header under `--conventional-commits`,
+/// otherwise `fallback`.
+pub fn fallback_message(
+    config: &RunConfig,
+    task: &str,
+    tags: &[String],
+    fallback: String,
+) -> String {
+    if config.conventional_commits {
+        conventional_header(task, tags, config.commit_scope.as_deref())
+    } else {
+        fallback
+    }
+}
+
+/// Prompt asking the engine to describe `diff` as a conventional commit,
+/// under `scope` if one is set.
+pub fn commit_message_prompt(task: &str, diff: &str, scope: Option<&str>) -> String {
+    let example = match scope {
+        Some(scope) => format!("feat({}): add login page", scope),
+        None => "feat: add login page".to_string(),
+    };
+    format!(
+        "Write a git commit message for the change below. Do not edit any files or run any commands.\n\n\
+         Task:\n{}\n\n\
+         Diff:\n```diff\n{}\n```\n\n\
+         Use the Conventional Commits format: a subject line like `{}` \
+         (type one of feat, fix, refactor, test, docs, chore; at most 72 characters), \
+         a blank line, then a short body summarising what changed and why.\n\n\
+         Put the whole message between <commit-message> and </commit-message>.",
+        task,
+        git::truncate_diff(diff, MAX_COMMIT_DIFF_BYTES),
+        example
+    )
+}
+
+/// Pull the commit message out of the engine's response; the last block wins.
+pub fn parse_commit_message(text: &str) -> Option<String> {
+    let message_re = Regex::new(r"(?s)<commit-message>(.*?)</commit-message>").unwrap();
+    let cap = message_re.captures_iter(text).last()?;
+    let message = cap[1].trim();
+    if message.is_empty() {
+        return None;
+    }
+    Some(message.to_string())
+}
+
+/// Squash everything the task changed since `base` into one commit whose
+/// message the engine writes from the diff, falling back to the task title.
+///
+/// The engine's usage is added to `response`.
+pub async fn commit_task(
+    config: &RunConfig,
+    executor: &AiExecutor,
+    task: &str,
+    tags: &[String],
+    base: &str,
+    response: &mut AiResponse,
+) -> Result<()> {
+    let diff = git::diff_since(executor.dir(), base)?;
+    if diff.trim().is_empty() {
+        return Ok(());
+    }
+
+    let reply = executor
+        .execute(&commit_message_prompt(
+            task,
+            &diff,
+            config.commit_scope.as_deref(),
+        ))
+        .await?;
+    response.absorb_usage(&reply);
+
+    let message = parse_commit_message(&reply.text).unwrap_or_else(|| {
+        events::warn("No commit message in the response, using the task title");
+        fallback_message(config, task, tags, task.to_string())
+    });
+
+    git::squash_since(executor.dir(), base, &message)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commit_message() {
+        let text = "Here you go:\n<commit-message>\nfeat: add login page\n\nAdds a form.\n</commit-message>";
+        assert_eq!(
+            parse_commit_message(text),
+            Some("feat: add login page\n\nAdds a form.".to_string())
+        );
+        assert_eq!(
+            parse_commit_message("<commit-message> </commit-message>"),
+            None
+        );
+        assert_eq!(parse_commit_message("feat: no tags"), None);
+    }
+
+    #[test]
+    fn test_commit_type() {
+        assert_eq!(commit_type("Fix the login redirect", &[]), "fix");
+        assert_eq!(commit_type("Add a login page", &[]), "feat");
+        assert_eq!(commit_type("Document the API", &[]), "docs");
+        assert_eq!(
+            commit_type("Login page crashes", &["frontend".into(), "bug".into()]),
+            "fix"
+        );
+    }
+
+    #[test]
+    fn test_conventional_header() {
+        assert_eq!(
+            conventional_header("Fix the login redirect.", &[], Some("auth")),
+            "fix(auth): fix the login redirect"
+        );
+        assert_eq!(
+            conventional_header("API endpoints for users", &[], None),
+            "feat: API endpoints for users"
+        );
+        let header = conventional_header(&"Add a very long title ".repeat(10), &[], None);
+        assert_eq!(header.chars().count(), 100);
+        assert!(header.starts_with("feat: add a very long title"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::ai::{AiEngine, RateLimit};
+use crate::backend::Backend;
+use crate::backoff::Backoff;
+use crate::prd::PrdSource;
+use crate::pricing::Pricing;
+use crate::settings::{
+    DiffScanSettings, EmailSettings, KubernetesSettings, NotificationSettings, PullRequestSettings,
+    ReportingSettings, SecuritySettings, TriageSettings,
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Everything a run needs to know, however it was asked for. The CLI builds
+/// one from its flags and ralphy.toml; embedders fill one in themselves.
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub ai_engine: AiEngine,
+    /// Model asked of the engine; its default when unset
+    pub model: Option<String>,
+    /// Prices to estimate cost with, ralphy.toml's `[pricing]` included
+    pub pricing: Pricing,
+    pub prd_source: PrdSource,
+    pub skip_tests: bool,
+    pub skip_lint: bool,
+    pub skip_commits: bool,
+    pub repo_map: RepoMapMode,
+    /// Token budget of the repository map
+    pub repo_map_tokens: usize,
+    /// Token budget of each prompt, counting the files it references
+    pub max_prompt_tokens: Option<usize>,
+    pub context_files: usize,
+    /// Files from `--context`, included in every prompt
+    pub context: Vec<PathBuf>,
+    pub progress_limit_kb: u64,
+    pub max_iterations: usize,
+    pub max_retries: usize,
+    /// Waits between attempts at a failed task
+    pub retry_backoff: Backoff,
+    /// Seconds a task attempt may run before its engine is stopped
+    pub task_timeout: Option<u64>,
+    /// Seconds between SIGTERM and SIGKILL for a timed-out engine
+    pub timeout_grace: u64,
+    /// How often engines may be started, from `--rpm` and `--min-delay`
+    pub rate_limit: RateLimit,
+    pub max_replans: usize,
+    /// Ends the run when the engine outputs it; empty when off
+    pub completion_marker: String,
+    /// Contents of `--prompt-template`, used instead of the built-in prompt
+    pub prompt_template: Option<String>,
+    /// `[prompt_vars]` from ralphy.toml
+    pub prompt_vars: BTreeMap<String, String>,
+    /// Arguments for each engine's CLI, from `[engine.<name>]` or, for the
+    /// selected engine, `--engine-arg`
+    pub engine_args: HashMap<AiEngine, Vec<String>>,
+    pub reuse_session: bool,
+    pub resume: bool,
+    pub dry_run: bool,
+    pub interactive: bool,
+    pub backend: Backend,
+    pub kubernetes: Option<KubernetesSettings>,
+    pub budgets: Vec<(String, f64)>,
+    /// Dollars the whole run may spend before it stops
+    pub max_cost: Option<f64>,
+    /// Tokens the whole run may use before it stops
+    pub max_tokens: Option<usize>,
+    pub parallel: bool,
+    pub max_parallel: usize,
+    pub merge_queue: bool,
+    pub push_branches: bool,
+    /// Draw parallel agents as a live table
+    pub tui: bool,
+    /// Run after each task and each merge, in order
+    pub verify_cmd: Vec<String>,
+    pub ab: Option<AiEngine>,
+    pub review: bool,
+    pub review_engine: Option<AiEngine>,
+    pub review_mode: ReviewMode,
+    pub gate_script: Option<PathBuf>,
+    pub security: Option<SecuritySettings>,
+    pub diff_scan: Option<DiffScanSettings>,
+    pub rewrite_commit_messages: bool,
+    pub auto_commit: bool,
+    pub conventional_commits: bool,
+    /// Scope of conventional commit headers
+    pub commit_scope: Option<String>,
+    pub branch_per_task: bool,
+    pub base_branch: Option<String>,
+    pub merge_strategy: MergeStrategy,
+    /// Branch task branches are brought into, when not the base branch
+    pub merge_into: Option<String>,
+    pub create_pr: bool,
+    pub draft_pr: bool,
+    pub pull_request: PullRequestSettings,
+    pub file_followups: bool,
+    pub verbose: u8,
+    pub no_color: bool,
+    pub no_notify: bool,
+    /// Where run log events go, `-` meaning stdout
+    pub log_json: Option<PathBuf>,
+    /// Where run log events are POSTed
+    pub webhook: Option<String>,
+    pub reporting: Option<ReportingSettings>,
+    pub triage: Option<TriageSettings>,
+    pub email: Option<EmailSettings>,
+    pub notifications: NotificationSettings,
+}
+
+impl RunConfig {
+    /// Arguments to add to every run of `engine`'s CLI
+    pub fn engine_args(&self, engine: AiEngine) -> &[String] {
+        self.engine_args.get(&engine).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// When prompts include a repository map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoMapMode {
+    /// For engines that explore the repository least on their own
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// What `--review` does with the reviewer's verdict.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewMode {
+    /// Hold the task until the reviewer approves, sending its feedback back
+    /// to the agent for a bounded number of repair rounds
+    #[default]
+    Fix,
+    /// Review once and post the review as a comment on the task's PR
+    Comment,
+}
+
+/// How task branches are brought back into the base branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Leave the branches for you
+    #[default]
+    None,
+    /// A merge commit per branch
+    Merge,
+    /// One commit per branch with all of its changes
+    Squash,
+    /// The branch's commits replayed on top
+    Rebase,
+}
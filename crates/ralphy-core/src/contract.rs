@@ -0,0 +1,121 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Instructions appended to every prompt describing how the agent must end
+/// its response.
+pub const STATUS_INSTRUCTIONS: &str = "\n\nEnd your response with exactly one status line: \
+<status>DONE</status> if you finished the task, or <status>BLOCKED: reason</status> if you could not.";
+
+/// What the agent outputs when every task in the PRD is finished, unless
+/// another marker is configured.
+pub const COMPLETION_PROMISE: &str = "<promise>COMPLETE</promise>";
+
+/// What the agent reported at the end of its response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentStatus {
+    /// The task was finished
+    Done,
+    /// The agent could not finish the task
+    Blocked(String),
+    /// Every task in the PRD is finished
+    AllComplete,
+}
+
+/// Find the status the agent reported, if it followed the contract.
+/// `marker` is what it was told to output once the whole PRD is done; an
+/// empty one never matches.
+///
+/// The last status block wins, so quoting the instructions back doesn't count.
+pub fn parse_status(text: &str, marker: &str) -> Option<AgentStatus> {
+    static STATUS_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)<status>\s*(.*?)\s*</status>").unwrap());
+
+    if let Some(cap) = STATUS_RE.captures_iter(text).last() {
+        let body = cap[1].trim();
+        if body.eq_ignore_ascii_case("done") {
+            return Some(AgentStatus::Done);
+        }
+        if body
+            .get(..7)
+            .is_some_and(|head| head.eq_ignore_ascii_case("blocked"))
+        {
+            let reason = body[7..].trim_start_matches(':').trim();
+            return Some(AgentStatus::Blocked(reason.to_string()));
+        }
+    }
+
+    if declares_complete(text, marker) {
+        return Some(AgentStatus::AllComplete);
+    }
+
+    None
+}
+
+/// Whether the agent declared every task in the PRD complete by writing
+/// `marker`. An empty marker never matches.
+pub fn declares_complete(text: &str, marker: &str) -> bool {
+    !marker.is_empty() && text.contains(marker)
+}
+
+/// Prompt used to re-run a task whose response broke the contract.
+pub fn correction_prompt(original: &str) -> String {
+    format!(
+        "{}\n\nNOTE: A previous attempt at this task ended without the required status line. \
+         Check the current state of the repository, finish anything that is missing, and make sure \
+         your response ends with <status>DONE</status> or <status>BLOCKED: reason</status>.",
+        original
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let parse = |text| parse_status(text, COMPLETION_PROMISE);
+        assert_eq!(
+            parse("All good.\n<status>DONE</status>"),
+            Some(AgentStatus::Done)
+        );
+        assert_eq!(
+            parse("<status>BLOCKED: missing API key</status>"),
+            Some(AgentStatus::Blocked("missing API key".to_string()))
+        );
+        assert_eq!(
+            parse("<promise>COMPLETE</promise>"),
+            Some(AgentStatus::AllComplete)
+        );
+        assert_eq!(parse("I made some changes."), None);
+
+        // A configured marker stands in for the default one
+        assert_eq!(
+            parse_status("ALL_TASKS_DONE", "ALL_TASKS_DONE"),
+            Some(AgentStatus::AllComplete)
+        );
+        assert_eq!(
+            parse_status("<promise>COMPLETE</promise>", "ALL_TASKS_DONE"),
+            None
+        );
+        assert_eq!(parse_status("<promise>COMPLETE</promise>", ""), None);
+    }
+
+    #[test]
+    fn test_declares_complete() {
+        let text = "Checked off the last task.\n<promise>COMPLETE</promise>\n<status>DONE</status>";
+        assert!(declares_complete(text, COMPLETION_PROMISE));
+        assert!(!declares_complete(text, "ALL_DONE"));
+        assert!(declares_complete("ALL_DONE", "ALL_DONE"));
+        assert!(!declares_complete(text, ""));
+    }
+
+    #[test]
+    fn test_last_status_wins() {
+        let text = "You asked for <status>DONE</status> or <status>BLOCKED: reason</status>.\n\
+                    <status>DONE</status>";
+        assert_eq!(
+            parse_status(text, COMPLETION_PROMISE),
+            Some(AgentStatus::Done)
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use crate::ai::{AiEngine, AiResponse};
+use crate::pricing::Pricing;
+use crate::stats::{RunStats, Timing};
+use crate::RunOutcome;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Something that happened during a run. The loops [`publish`] these, and
+/// the console, notifications, the run log and webhooks each [`subscribe`]
+/// to the ones they care about. Serialized as one JSON object with its kind
+/// under `"event"`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RunEvent {
+    RunStarted {
+        engine: AiEngine,
+        source: String,
+        parallel: bool,
+    },
+    TaskStarted {
+        task: String,
+        iteration: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+        /// Where the PRD stood, for tasks run one at a time
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prd: Option<PrdProgress>,
+    },
+    /// A task's branch was checked out, or its worktree added
+    BranchCreated { task: String, branch: String },
+    TaskCompleted {
+        task: String,
+        input_tokens: usize,
+        output_tokens: usize,
+        /// Dollars, as reported by the engine or else estimated
+        cost: f64,
+        cost_estimated: bool,
+        /// As reported by the engine
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+        /// From starting the task to finishing it, retries included
+        wall_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+    TaskFailed {
+        task: String,
+        error: String,
+        attempts: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+    PullRequestOpened {
+        task: String,
+        branch: String,
+        url: String,
+    },
+    /// A parallel batch, merges included, is done
+    BatchFinished {
+        batch: usize,
+        completed: usize,
+        failed: usize,
+    },
+    RunFinished {
+        outcome: RunOutcome,
+        completed: usize,
+        failed: usize,
+        over_budget: usize,
+        input_tokens: usize,
+        output_tokens: usize,
+        cost: f64,
+        wall_ms: u64,
+        /// Spread of the completed tasks' wall-clock times
+        #[serde(skip_serializing_if = "Option::is_none")]
+        task_timing: Option<Timing>,
+        /// Everything the run recorded, for subscribers that summarize it
+        #[serde(skip)]
+        stats: Box<RunStats>,
+        /// A run stopped by a signal doesn't get a summary
+        #[serde(skip)]
+        interrupted: bool,
+    },
+    /// An error stopped the run
+    RunError { error: String },
+    /// Anything else the run has to say along the way, for the console.
+    /// Left out of the run log and webhooks.
+    Notice { level: Level, message: String },
+}
+
+/// Tasks checked off and left when a task started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PrdProgress {
+    pub completed: usize,
+    pub remaining: usize,
+}
+
+/// How a [`RunEvent::Notice`] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    /// Printed as it is, such as an engine's response
+    Plain,
+    Info,
+    Success,
+    Warn,
+    Error,
+    /// One finished step of a batch, such as an agent completing
+    Passed,
+    /// One failed step of a batch
+    Failed,
+}
+
+impl RunEvent {
+    /// The event's `"event"` value, e.g. `task_completed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunEvent::RunStarted { .. } => "run_started",
+            RunEvent::TaskStarted { .. } => "task_started",
+            RunEvent::BranchCreated { .. } => "branch_created",
+            RunEvent::TaskCompleted { .. } => "task_completed",
+            RunEvent::TaskFailed { .. } => "task_failed",
+            RunEvent::PullRequestOpened { .. } => "pull_request_opened",
+            RunEvent::BatchFinished { .. } => "batch_finished",
+            RunEvent::RunFinished { .. } => "run_finished",
+            RunEvent::RunError { .. } => "run_error",
+            RunEvent::Notice { .. } => "notice",
+        }
+    }
+
+    pub fn task_completed(
+        task: &str,
+        response: &AiResponse,
+        cost: f64,
+        wall: Duration,
+        branch: Option<String>,
+    ) -> Self {
+        RunEvent::TaskCompleted {
+            task: task.to_string(),
+            input_tokens: response.input_tokens,
+            output_tokens: response.output_tokens,
+            cost,
+            cost_estimated: response.actual_cost.is_none(),
+            duration_ms: response.duration_ms,
+            wall_ms: wall.as_millis() as u64,
+            model: response.model.clone(),
+            branch,
+        }
+    }
+
+    pub fn task_failed(
+        task: &str,
+        error: &anyhow::Error,
+        attempts: usize,
+        branch: Option<String>,
+    ) -> Self {
+        RunEvent::TaskFailed {
+            task: task.to_string(),
+            error: format!("{:#}", error),
+            attempts,
+            branch,
+        }
+    }
+
+    pub fn run_finished(stats: &RunStats, pricing: &Pricing, outcome: RunOutcome) -> Self {
+        Self::finished(stats, pricing, outcome, false)
+    }
+
+    /// The end of a run stopped by a signal, with work left.
+    pub fn run_interrupted(stats: &RunStats, pricing: &Pricing) -> Self {
+        Self::finished(stats, pricing, RunOutcome::WorkRemaining, true)
+    }
+
+    fn finished(
+        stats: &RunStats,
+        pricing: &Pricing,
+        outcome: RunOutcome,
+        interrupted: bool,
+    ) -> Self {
+        RunEvent::RunFinished {
+            outcome,
+            completed: stats.agents.len(),
+            failed: stats.failed.len(),
+            over_budget: stats.over_budget.len(),
+            input_tokens: stats.input_tokens,
+            output_tokens: stats.output_tokens,
+            cost: crate::run_cost(pricing, stats),
+            wall_ms: stats.wall_ms,
+            task_timing: stats.task_timing(),
+            stats: Box::new(stats.clone()),
+            interrupted,
+        }
+    }
+
+    pub fn run_error(error: &anyhow::Error) -> Self {
+        RunEvent::RunError {
+            error: format!("{:#}", error),
+        }
+    }
+}
+
+/// Publish a [`RunEvent::Notice`].
+pub fn notice(level: Level, message: impl Into<String>) {
+    publish(RunEvent::Notice {
+        level,
+        message: message.into(),
+    });
+}
+
+pub fn info(message: impl Into<String>) {
+    notice(Level::Info, message)
+}
+
+pub fn success(message: impl Into<String>) {
+    notice(Level::Success, message)
+}
+
+pub fn warn(message: impl Into<String>) {
+    notice(Level::Warn, message)
+}
+
+pub fn error(message: impl Into<String>) {
+    notice(Level::Error, message)
+}
+
+type Handler = Arc<dyn Fn(&RunEvent) + Send + Sync>;
+
+/// The handlers following one run's events. A `RalphyRunner` publishes to
+/// a bus of its own, so runners sharing a process don't see each other's
+/// events; the CLI uses the process's.
+#[derive(Default)]
+pub struct Bus {
+    subscribers: Mutex<Vec<(u64, Handler)>>,
+}
+
+tokio::task_local! {
+    static BUS: Arc<Bus>;
+}
+
+static NEXT_SUBSCRIPTION: AtomicU64 = AtomicU64::new(0);
+
+/// The bus of the process, for code outside any [`scope`].
+fn process_bus() -> &'static Arc<Bus> {
+    static BUS: OnceLock<Arc<Bus>> = OnceLock::new();
+    BUS.get_or_init(Arc::default)
+}
+
+/// The bus events go to from here: the [`scope`]d one, or else the
+/// process's.
+pub fn current() -> Arc<Bus> {
+    BUS.try_with(Arc::clone)
+        .unwrap_or_else(|_| process_bus().clone())
+}
+
+/// Run `future` with events published and subscribed in it going to `bus`.
+pub async fn scope<F: Future>(bus: Arc<Bus>, future: F) -> F::Output {
+    BUS.scope(bus, future).await
+}
+
+/// [`tokio::spawn`] `future`, keeping the bus of the task spawning it.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(BUS.scope(current(), future))
+}
+
+/// Keeps a [`subscribe`]d handler called until it is dropped.
+#[must_use = "the handler is unsubscribed when this is dropped"]
+pub struct Subscription {
+    bus: Arc<Bus>,
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Ok(mut subscribers) = self.bus.subscribers.lock() {
+            subscribers.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+/// Call `handler` with every event published from now on. Handlers run on
+/// the publishing task, so anything slow belongs on a queue of its own.
+pub fn subscribe(handler: impl Fn(&RunEvent) + Send + Sync + 'static) -> Subscription {
+    let bus = current();
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut subscribers) = bus.subscribers.lock() {
+        subscribers.push((id, Arc::new(handler)));
+    }
+    Subscription { bus, id }
+}
+
+/// Hand `event` to every subscriber, in the order they subscribed.
+pub fn publish(event: RunEvent) {
+    // Cloned out so a handler can subscribe or unsubscribe without deadlock
+    let handlers: Vec<Handler> = match current().subscribers.lock() {
+        Ok(subscribers) => subscribers.iter().map(|(_, h)| h.clone()).collect(),
+        Err(_) => Vec::new(),
+    };
+    for handler in handlers {
+        handler(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branch(task: &str) -> RunEvent {
+        RunEvent::BranchCreated {
+            task: task.to_string(),
+            branch: format!("ralphy/{}", task),
+        }
+    }
+
+    #[test]
+    fn test_subscribers_see_events_until_dropped() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let subscription = subscribe(move |event| {
+            if let RunEvent::BranchCreated { task, .. } = event {
+                recorder.lock().unwrap().push(task.clone());
+            }
+        });
+
+        publish(branch("first"));
+        drop(subscription);
+        publish(branch("second"));
+        assert_eq!(*seen.lock().unwrap(), ["first"]);
+    }
+
+    /// Publish two events on a bus of its own, one from a spawned task, and
+    /// return what its subscriber saw.
+    async fn run_on_own_bus(name: &'static str) -> Vec<String> {
+        scope(Arc::default(), async move {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let recorder = seen.clone();
+            let _subscription = subscribe(move |event| {
+                if let RunEvent::BranchCreated { task, .. } = event {
+                    recorder.lock().unwrap().push(task.clone());
+                }
+            });
+            publish(branch(name));
+            tokio::task::yield_now().await;
+            spawn(async move { publish(branch(&format!("{} spawned", name))) })
+                .await
+                .unwrap();
+            let seen = seen.lock().unwrap().clone();
+            seen
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_scoped_buses_keep_their_events_apart() {
+        let (first, second) = tokio::join!(run_on_own_bus("first"), run_on_own_bus("second"));
+        assert_eq!(first, ["first", "first spawned"]);
+        assert_eq!(second, ["second", "second spawned"]);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::prd::PrdSource;
+use crate::retry::output_with_retry;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::process::Command;
+use std::time::Duration;
+
+/// Label every follow-up issue gets, so they are easy to find and dedupe.
+pub const FOLLOWUP_LABEL: &str = "ralphy-followup";
+
+/// Most issues filed in one run, so a chatty agent can't flood the tracker.
+pub const MAX_FOLLOWUPS_PER_RUN: usize = 10;
+
+/// Pause between issue creations, to stay clear of GitHub's secondary rate
+/// limits on content creation.
+const FILING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Issue titles are cut to this many characters.
+const MAX_TITLE_CHARS: usize = 100;
+
+/// Prompt line asking the agent to mark work it leaves for later.
+pub const FOLLOWUP_INSTRUCTIONS: &str = "\n\nIf you leave work for later (TODOs, deferred items, \
+things outside this task), list each on its own line as <followup>one-line description</followup>.";
+
+/// Deferred work in an agent's response: `<followup>` blocks, plus lines
+/// that start with `TODO:`.
+pub fn extract(text: &str) -> Vec<String> {
+    let tag_re = Regex::new(r"(?is)<followup>(.*?)</followup>").unwrap();
+    let todo_re = Regex::new(r"(?m)^\s*(?:[-*]\s*)?TODO:\s*(.+?)\s*$").unwrap();
+
+    let mut items: Vec<String> = Vec::new();
+    let found = tag_re
+        .captures_iter(text)
+        .chain(todo_re.captures_iter(text))
+        .map(|cap| cap[1].split_whitespace().collect::<Vec<_>>().join(" "));
+    for item in found {
+        if !item.is_empty() && !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    items
+}
+
+/// A follow-up issue waiting to be filed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Followup {
+    pub title: String,
+    pub body: String,
+}
+
+/// Follow-ups gathered during a run and filed together at the end.
+#[derive(Debug, Default)]
+pub struct Followups {
+    pending: Vec<Followup>,
+}
+
+impl Followups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the deferred items an agent mentioned while doing `task`.
+    pub fn collect(&mut self, task: &str, response: &str) {
+        for item in extract(response) {
+            self.push(Followup {
+                title: crate::text::truncate(&item, MAX_TITLE_CHARS),
+                body: format!(
+                    "Deferred while working on: {}\n\n{}\n\n_Filed by Ralphy._",
+                    task, item
+                ),
+            });
+        }
+    }
+
+    /// Queue a task that was skipped on purpose, e.g. over budget.
+    pub fn skipped(&mut self, task: &str, reason: &str) {
+        self.push(Followup {
+            title: crate::text::truncate(&format!("Skipped: {}", task), MAX_TITLE_CHARS),
+            body: format!(
+                "Ralphy skipped this task: {}.\n\nTask: {}\n\n_Filed by Ralphy._",
+                reason, task
+            ),
+        });
+    }
+
+    fn push(&mut self, followup: Followup) {
+        if !self.pending.iter().any(|f| f.title == followup.title) {
+            self.pending.push(followup);
+        }
+    }
+
+    pub fn pending(&self) -> &[Followup] {
+        &self.pending
+    }
+
+    /// File the queued follow-ups as GitHub issues, skipping titles already
+    /// open under [`FOLLOWUP_LABEL`] and stopping at
+    /// [`MAX_FOLLOWUPS_PER_RUN`]. Failures are only warned about.
+    pub async fn file(&self, config: &RunConfig) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if config.dry_run {
+            for followup in &self.pending {
+                events::info(format!(
+                    "DRY RUN - Would file follow-up: {}",
+                    followup.title
+                ));
+            }
+            return;
+        }
+        if let Err(e) = self.file_issues(config).await {
+            events::warn(format!("Could not file follow-up issues: {:#}", e));
+        }
+    }
+
+    async fn file_issues(&self, config: &RunConfig) -> Result<()> {
+        let repo = issue_repo(config);
+        ensure_label(
+            repo,
+            FOLLOWUP_LABEL,
+            "C5DEF5",
+            "Work deferred by a Ralphy task",
+        )?;
+        let open = open_issue_titles(repo, FOLLOWUP_LABEL)?;
+
+        let new: Vec<&Followup> = self
+            .pending
+            .iter()
+            .filter(|f| !open.contains(&f.title))
+            .collect();
+        if new.len() > MAX_FOLLOWUPS_PER_RUN {
+            events::warn(format!(
+                "Filing only {} of {} follow-ups",
+                MAX_FOLLOWUPS_PER_RUN,
+                new.len()
+            ));
+        }
+
+        events::notice(Level::Plain, "\n>>> Filing follow-up issues...".to_string());
+        for (i, followup) in new.into_iter().take(MAX_FOLLOWUPS_PER_RUN).enumerate() {
+            if i > 0 {
+                tokio::time::sleep(FILING_INTERVAL).await;
+            }
+            let url = create_issue(repo, followup)?;
+            events::notice(Level::Passed, format!("{} {}", followup.title, url));
+        }
+        Ok(())
+    }
+}
+
+/// Repository issues go to: the PRD's when it comes from GitHub, otherwise
+/// whatever `gh` picks for the working directory.
+pub(crate) fn issue_repo(config: &RunConfig) -> Option<&str> {
+    match &config.prd_source {
+        PrdSource::GitHub { repo, .. } => Some(repo.as_str()),
+        _ => None,
+    }
+}
+
+pub(crate) fn gh(repo: Option<&str>, args: &[&str]) -> Command {
+    let mut cmd = Command::new("gh");
+    cmd.args(args);
+    if let Some(repo) = repo {
+        cmd.args(["--repo", repo]);
+    }
+    cmd
+}
+
+/// Create `label`, or leave it as it is if it already exists.
+pub(crate) fn ensure_label(
+    repo: Option<&str>,
+    label: &str,
+    color: &str,
+    description: &str,
+) -> Result<()> {
+    let output = output_with_retry(&mut gh(
+        repo,
+        &[
+            "label",
+            "create",
+            label,
+            "--color",
+            color,
+            "--description",
+            description,
+        ],
+    ))
+    .context("Failed to run gh")?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("already exists") {
+        anyhow::bail!("Failed to create label {}: {}", label, stderr.trim());
+    }
+    Ok(())
+}
+
+/// Titles of the open issues labelled `label`.
+pub(crate) fn open_issue_titles(repo: Option<&str>, label: &str) -> Result<Vec<String>> {
+    let output = output_with_retry(&mut gh(
+        repo,
+        &[
+            "issue", "list", "--label", label, "--state", "open", "--limit", "500", "--json",
+            "title",
+        ],
+    ))
+    .context("Failed to run gh")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list {} issues: {}",
+            label,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let issues: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).context("Failed to parse gh issue list output")?;
+    Ok(issues
+        .iter()
+        .filter_map(|issue| issue["title"].as_str().map(str::to_string))
+        .collect())
+}
+
+fn create_issue(repo: Option<&str>, followup: &Followup) -> Result<String> {
+    let output = gh(
+        repo,
+        &[
+            "issue",
+            "create",
+            "--title",
+            &followup.title,
+            "--body",
+            &followup.body,
+            "--label",
+            FOLLOWUP_LABEL,
+        ],
+    )
+    .output()
+    .context("Failed to run gh")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create issue '{}': {}",
+            followup.title,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let text = "Done.\n<followup>Add pagination to\n the list endpoint</followup>\n\
+                    - TODO: handle expired tokens\nNot a TODO: line\n\
+                    <followup>Add pagination to the list endpoint</followup>\n<status>DONE</status>";
+        assert_eq!(
+            extract(text),
+            [
+                "Add pagination to the list endpoint",
+                "handle expired tokens"
+            ]
+        );
+        assert!(extract("<status>DONE</status>").is_empty());
+    }
+
+    #[test]
+    fn test_followups_dedupe_by_title() {
+        let mut followups = Followups::new();
+        followups.collect("Add login", "<followup>Rate-limit logins</followup>");
+        followups.collect("Add logout", "<followup>Rate-limit logins</followup>");
+        followups.skipped("Add billing", "its budget was used up");
+        let titles: Vec<&str> = followups
+            .pending()
+            .iter()
+            .map(|f| f.title.as_str())
+            .collect();
+        assert_eq!(titles, ["Rate-limit logins", "Skipped: Add billing"]);
+        assert!(followups.pending()[0].body.contains("Add login"));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::repair::{self, Checked, Repair};
+use crate::{git, Rejected};
+use anyhow::{Context, Result};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::path::Path;
+
+/// Gate script picked up from the working directory when `--gate` isn't given.
+pub const GATE_FILE: &str = "gate.rhai";
+
+/// Repair rounds allowed after the script first asks for a retry.
+pub const MAX_GATE_ROUNDS: usize = 2;
+
+/// Operations a single call may run before it is stopped, so a runaway loop
+/// in a script can't hang the run.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What the gate script decided about a finished task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// Fail the task, with the reason
+    Deny(String),
+    /// Send the reason back to the agent to fix
+    Retry(String),
+}
+
+/// What the script is told about the task.
+#[derive(Debug, Clone, Default)]
+pub struct TaskInfo {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub iteration: usize,
+    pub engine: String,
+    /// Workspace package the task is scoped to
+    pub package: Option<String>,
+}
+
+/// Verification that ran before the gate. Failing checks stop a task
+/// before it gets here, so a check that ran has passed.
+#[derive(Debug, Clone, Default)]
+pub struct Checks {
+    pub tests_skipped: bool,
+    pub lint_skipped: bool,
+    /// Whether the scoped package's tests ran
+    pub package_tests: Option<bool>,
+    /// Whether the reviewer approved under `--review`
+    pub review: Option<bool>,
+    /// Whether the `--verify-cmd` commands ran
+    pub verified: bool,
+}
+
+/// A compiled gate script. The script defines `fn gate(task, diff, checks)`
+/// and returns `"allow"`, `"deny"` or `"retry"`, or a map like
+/// `#{ decision: "deny", reason: "..." }`.
+pub struct Gate {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Gate {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_source(&source)
+            .with_context(|| format!("Invalid gate script {}", path.display()))
+    }
+
+    pub fn from_source(source: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "gate" && f.params.len() == 3)
+        {
+            anyhow::bail!("the script must define fn gate(task, diff, checks)");
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the script's `gate` function for a task.
+    pub fn decide(
+        &self,
+        task: &TaskInfo,
+        diff: &[(String, usize, usize)],
+        checks: &Checks,
+    ) -> Result<Decision> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                "gate",
+                (task_map(task), diff_map(diff), checks_map(checks)),
+            )
+            .map_err(|e| anyhow::anyhow!("Gate script failed: {}", e))?;
+        parse_decision(result)
+    }
+}
+
+fn task_map(task: &TaskInfo) -> Map {
+    let mut map = Map::new();
+    map.insert("title".into(), task.title.clone().into());
+    let tags: Array = task.tags.iter().cloned().map(Dynamic::from).collect();
+    map.insert("tags".into(), tags.into());
+    map.insert("iteration".into(), (task.iteration as i64).into());
+    map.insert("engine".into(), task.engine.clone().into());
+    map.insert(
+        "package".into(),
+        task.package.clone().map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map
+}
+
+fn diff_map(diff: &[(String, usize, usize)]) -> Map {
+    let files: Array = diff
+        .iter()
+        .map(|(path, added, removed)| {
+            let mut file = Map::new();
+            file.insert("path".into(), path.clone().into());
+            file.insert("insertions".into(), (*added as i64).into());
+            file.insert("deletions".into(), (*removed as i64).into());
+            Dynamic::from_map(file)
+        })
+        .collect();
+
+    let mut map = Map::new();
+    map.insert("files_changed".into(), (diff.len() as i64).into());
+    map.insert(
+        "insertions".into(),
+        (diff.iter().map(|d| d.1).sum::<usize>() as i64).into(),
+    );
+    map.insert(
+        "deletions".into(),
+        (diff.iter().map(|d| d.2).sum::<usize>() as i64).into(),
+    );
+    map.insert("files".into(), files.into());
+    map
+}
+
+fn checks_map(checks: &Checks) -> Map {
+    let mut map = Map::new();
+    map.insert("tests_skipped".into(), checks.tests_skipped.into());
+    map.insert("lint_skipped".into(), checks.lint_skipped.into());
+    if let Some(ran) = checks.package_tests {
+        map.insert(
+            "package_tests".into(),
+            if ran { "passed" } else { "skipped" }.into(),
+        );
+    }
+    if let Some(true) = checks.review {
+        map.insert("review".into(), "approved".into());
+    }
+    if checks.verified {
+        map.insert("verify".into(), "passed".into());
+    }
+    map
+}
+
+/// Read the script's return value.
+pub fn parse_decision(result: Dynamic) -> Result<Decision> {
+    let (decision, reason) = if result.is_string() {
+        (result.into_string().unwrap(), String::new())
+    } else if let Some(map) = result.clone().try_cast::<Map>() {
+        let decision = map
+            .get("decision")
+            .and_then(|d| d.clone().into_string().ok())
+            .context("Gate script returned a map without a decision")?;
+        let reason = map.get("reason").map(|r| r.to_string()).unwrap_or_default();
+        (decision, reason)
+    } else {
+        anyhow::bail!(
+            "Gate script returned {}, expected a decision",
+            result.type_name()
+        );
+    };
+
+    let reason = if reason.is_empty() {
+        "no reason given".to_string()
+    } else {
+        reason
+    };
+    match decision.to_lowercase().as_str() {
+        "allow" => Ok(Decision::Allow),
+        "deny" => Ok(Decision::Deny(reason)),
+        "retry" => Ok(Decision::Retry(reason)),
+        other => anyhow::bail!(
+            "Gate script returned unknown decision '{}' (expected allow, deny or retry)",
+            other
+        ),
+    }
+}
+
+/// Prompt re-running the task with the gate's reason for sending it back.
+pub fn repair_prompt(original: &str, reason: &str) -> String {
+    format!(
+        "{}\n\nNOTE: The project's gate script sent your previous attempt at this task back:\n{}\n\n\
+         Check the current state of the repository and fix this.",
+        original, reason
+    )
+}
+
+/// Have the gate script at `script` allow everything changed since `base`,
+/// sending its reason back to the agent on retry until it allows the task,
+/// denies it, or rounds run out. A task it doesn't allow fails as
+/// [`Rejected`], so its commits are dropped.
+///
+/// Usage of every repair round is added to the returned response.
+pub async fn enforce(
+    script: &Path,
+    executor: &AiExecutor,
+    prompt: &str,
+    task: &TaskInfo,
+    checks: &Checks,
+    base: &str,
+    response: AiResponse,
+) -> Result<AiResponse> {
+    let gate = Gate::load(script)?;
+
+    repair::until_passing(executor, MAX_GATE_ROUNDS, response, || async {
+        let diff = git::numstat_since(executor.dir(), base)?;
+        let reason = match gate.decide(task, &diff, checks)? {
+            Decision::Allow => return Ok(Checked::pass()),
+            Decision::Deny(reason) => {
+                return Err(Rejected(format!("Gate script denied the task: {}", reason)).into())
+            }
+            Decision::Retry(reason) => reason,
+        };
+        Ok(Checked::repair(Repair {
+            headline: "Gate script sent the task back".to_string(),
+            prompt: repair_prompt(prompt, &reason),
+            error: Rejected(format!("Gate script still asks for a retry: {}", reason)).into(),
+            details: reason,
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(title: &str) -> TaskInfo {
+        TaskInfo {
+            title: title.to_string(),
+            tags: vec!["backend".to_string()],
+            iteration: 1,
+            engine: "Mock".to_string(),
+            package: None,
+        }
+    }
+
+    #[test]
+    fn test_decisions() {
+        let gate = Gate::from_source(
+            r#"
+            fn gate(task, diff, checks) {
+                if diff.files.some(|f| f.path.starts_with("migrations/")) {
+                    return #{ decision: "deny", reason: "migrations need a human" };
+                }
+                if diff.insertions > 100 {
+                    return #{ decision: "retry", reason: "split this up" };
+                }
+                if "backend" in task.tags && checks.tests_skipped {
+                    return "deny";
+                }
+                "allow"
+            }
+            "#,
+        )
+        .unwrap();
+        let checks = Checks::default();
+
+        let small = [("src/lib.rs".to_string(), 10, 2)];
+        assert_eq!(
+            gate.decide(&task("Add route"), &small, &checks).unwrap(),
+            Decision::Allow
+        );
+
+        let big = [("src/lib.rs".to_string(), 500, 0)];
+        assert_eq!(
+            gate.decide(&task("Add route"), &big, &checks).unwrap(),
+            Decision::Retry("split this up".to_string())
+        );
+
+        let migration = [("migrations/001.sql".to_string(), 5, 0)];
+        assert_eq!(
+            gate.decide(&task("Add table"), &migration, &checks)
+                .unwrap(),
+            Decision::Deny("migrations need a human".to_string())
+        );
+
+        let skipped = Checks {
+            tests_skipped: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            gate.decide(&task("Add route"), &small, &skipped).unwrap(),
+            Decision::Deny("no reason given".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_scripts() {
+        assert!(Gate::from_source("fn check(task) { \"allow\" }").is_err());
+        assert!(Gate::from_source("fn gate(task, diff, checks) {").is_err());
+
+        let gate = Gate::from_source("fn gate(task, diff, checks) { 42 }").unwrap();
+        assert!(gate.decide(&task("x"), &[], &Checks::default()).is_err());
+
+        let gate = Gate::from_source("fn gate(task, diff, checks) { loop {} }").unwrap();
+        assert!(gate.decide(&task("x"), &[], &Checks::default()).is_err());
+    }
+}
@@ -0,0 +1,964 @@
+use crate::log;
+use crate::prd::{Task, TaskOrigin};
+use crate::retry::output_with_retry;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::{Command, Output};
+
+pub fn is_git_repo() -> Result<bool> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--git-dir")
+        .output()?;
+
+    Ok(output.status.success())
+}
+
+/// Branch `--branch-per-task` puts `task`'s work on.
+pub fn task_branch_name(task: &str) -> String {
+    format!("ralphy/{}", slugify(task))
+}
+
+/// Check out a fresh branch for `task` in the repository at `dir`.
+pub fn create_task_branch(dir: &Path, task: &str, base_branch: Option<&str>) -> Result<String> {
+    let branch_name = task_branch_name(task);
+
+    // Get base branch or current
+    let original = get_current_branch(dir)?;
+    let base = base_branch.map(str::to_string).unwrap_or(original.clone());
+
+    let stashed = stash_in(dir)?;
+
+    let result = switch_to_task_branch(dir, &base, &branch_name);
+    if result.is_err() {
+        // Put the user back where they started before reporting the failure
+        git_in(dir, &["checkout", &original]).ok();
+    }
+
+    if stashed {
+        unstash_in(dir)?;
+    }
+
+    result.map(|()| branch_name)
+}
+
+/// Stash uncommitted changes in `dir`, if there are any, so another branch
+/// can be checked out. Returns whether anything was stashed.
+pub fn stash_in(dir: &Path) -> Result<bool> {
+    let stash = git_in(dir, &["stash", "push", "-m", "ralphy-autostash"])?;
+    Ok(stash.status.success()
+        && !String::from_utf8_lossy(&stash.stdout).contains("No local changes to save"))
+}
+
+/// Re-apply what [`stash_in`] stashed, warning if it no longer applies.
+pub fn unstash_in(dir: &Path) -> Result<()> {
+    let pop = git_in(dir, &["stash", "pop"])?;
+    if !pop.status.success() {
+        log::warn(format!(
+            "Could not re-apply stashed changes (kept in `git stash list`): {}",
+            stderr_of(&pop)
+        ));
+    }
+    Ok(())
+}
+
+/// Check out `branch` in `dir`, creating it at `start` if it doesn't exist.
+pub fn checkout_or_create_in(dir: &Path, branch: &str, start: &str) -> Result<()> {
+    let exists = git_in(
+        dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{}", branch),
+        ],
+    )?
+    .status
+    .success();
+    let output = if exists {
+        git_in(dir, &["checkout", branch])?
+    } else {
+        git_in(dir, &["checkout", "-b", branch, start])?
+    };
+    if !output.status.success() {
+        anyhow::bail!("Failed to check out {}: {}", branch, stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Merge `branch` into the branch checked out in `dir` with a merge commit.
+/// Returns the conflicting paths, with the merge undone, or none once it is
+/// committed.
+pub fn merge_in(dir: &Path, branch: &str, message: &str) -> Result<Vec<String>> {
+    let output = git_in(dir, &["merge", "--no-ff", "-m", message, branch])?;
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+    let conflicts = conflicted_paths_in(dir)?;
+    git_in(dir, &["merge", "--abort"]).ok();
+    if conflicts.is_empty() {
+        anyhow::bail!("Failed to merge {}: {}", branch, stderr_of(&output));
+    }
+    Ok(conflicts)
+}
+
+/// Apply the commits on `branch` since `since` to the branch checked out in
+/// `dir` as a single commit. Returns the conflicting paths, with the change
+/// undone, or none once it is committed.
+pub fn squash_in(dir: &Path, since: &str, branch: &str, message: &str) -> Result<Vec<String>> {
+    let range = format!("{}..{}", since, branch);
+    let output = git_in(dir, &["cherry-pick", "--no-commit", &range])?;
+    if !output.status.success() {
+        let conflicts = conflicted_paths_in(dir)?;
+        git_in(dir, &["cherry-pick", "--abort"]).ok();
+        git_in(dir, &["reset", "--merge"]).ok();
+        if conflicts.is_empty() {
+            anyhow::bail!("Failed to squash {}: {}", branch, stderr_of(&output));
+        }
+        return Ok(conflicts);
+    }
+    if !git_in(dir, &["diff", "--cached", "--quiet"])?
+        .status
+        .success()
+    {
+        let commit = git_in(dir, &["commit", "-q", "-m", message])?;
+        if !commit.status.success() {
+            anyhow::bail!("Failed to commit {}: {}", branch, stderr_of(&commit));
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Whether `ancestor` is `commit` or one of its ancestors in `dir`.
+pub fn is_ancestor_in(dir: &Path, ancestor: &str, commit: &str) -> bool {
+    git_in(dir, &["merge-base", "--is-ancestor", ancestor, commit])
+        .is_ok_and(|output| output.status.success())
+}
+
+/// The best common ancestor of `a` and `b` in `dir`.
+pub fn merge_base_in(dir: &Path, a: &str, b: &str) -> Result<String> {
+    let output = git_in(dir, &["merge-base", a, b])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} and {} have no common history: {}",
+            a,
+            b,
+            stderr_of(&output)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Replay `branch` onto `onto` and fast-forward `onto` to it, leaving `onto`
+/// checked out in `dir`. Returns the conflicting paths, with the rebase
+/// undone, or none once `onto` has moved.
+pub fn rebase_in(dir: &Path, branch: &str, onto: &str) -> Result<Vec<String>> {
+    let output = git_in(dir, &["rebase", onto, branch])?;
+    if !output.status.success() {
+        let conflicts = conflicted_paths_in(dir)?;
+        git_in(dir, &["rebase", "--abort"]).ok();
+        git_in(dir, &["checkout", onto])?;
+        if conflicts.is_empty() {
+            anyhow::bail!("Failed to rebase {}: {}", branch, stderr_of(&output));
+        }
+        return Ok(conflicts);
+    }
+    for args in [vec!["checkout", onto], vec!["merge", "--ff-only", branch]] {
+        let output = git_in(dir, &args)?;
+        if !output.status.success() {
+            anyhow::bail!("Failed to fast-forward {}: {}", onto, stderr_of(&output));
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn conflicted_paths_in(dir: &Path) -> Result<Vec<String>> {
+    let output = git_in(dir, &["diff", "--name-only", "--diff-filter=U"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to list conflicts: {}", stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+fn switch_to_task_branch(dir: &Path, base: &str, branch_name: &str) -> Result<()> {
+    // Checkout base branch
+    let checkout = git_in(dir, &["checkout", base])?;
+    if !checkout.status.success() {
+        anyhow::bail!(
+            "Failed to check out base branch '{}' ({}): {}",
+            base,
+            classify_sync_error(&stderr_of(&checkout)),
+            stderr_of(&checkout)
+        );
+    }
+
+    // Pull latest, if there is a remote to pull from
+    if has_remote(dir, "origin") {
+        let pull = output_with_retry(
+            Command::new("git")
+                .args(["pull", "origin", base])
+                .current_dir(dir),
+        )?;
+        if !pull.status.success() {
+            let stderr = stderr_of(&pull);
+            let reason = classify_sync_error(&stderr);
+            if reason == MISSING_REMOTE_BRANCH {
+                log::warn(format!(
+                    "Base branch '{}' does not exist on origin; branching from the local copy",
+                    base
+                ));
+            } else {
+                // Abort a half-finished merge so the working tree is usable
+                git_in(dir, &["merge", "--abort"]).ok();
+                anyhow::bail!(
+                    "Failed to pull base branch '{}' from origin ({}): {}",
+                    base,
+                    reason,
+                    stderr
+                );
+            }
+        }
+    }
+
+    // Create and checkout new branch
+    let create = git_in(dir, &["checkout", "-b", branch_name])?;
+    if !create.status.success() {
+        // Branch might exist, just checkout
+        let existing = git_in(dir, &["checkout", branch_name])?;
+        if !existing.status.success() {
+            anyhow::bail!(
+                "Failed to create task branch '{}': {}",
+                branch_name,
+                stderr_of(&create)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+const MISSING_REMOTE_BRANCH: &str = "branch missing on remote";
+
+/// Describe why a checkout or pull against the base branch failed.
+fn classify_sync_error(stderr: &str) -> &'static str {
+    let lower = stderr.to_lowercase();
+    if lower.contains("couldn't find remote ref") {
+        MISSING_REMOTE_BRANCH
+    } else if lower.contains("did not match any file(s) known to git")
+        || lower.contains("invalid reference")
+    {
+        "branch does not exist locally"
+    } else if lower.contains("authentication failed")
+        || lower.contains("permission denied")
+        || lower.contains("could not read username")
+        || lower.contains("repository not found")
+    {
+        "authentication failed"
+    } else if lower.contains("conflict")
+        || lower.contains("not possible to fast-forward")
+        || lower.contains("divergent branches")
+    {
+        "merge conflict with remote"
+    } else if lower.contains("would be overwritten") || lower.contains("untracked working tree") {
+        "local changes would be overwritten"
+    } else if crate::retry::is_transient(stderr) {
+        "network error"
+    } else {
+        "unknown error"
+    }
+}
+
+fn has_remote(dir: &Path, name: &str) -> bool {
+    git_in(dir, &["remote", "get-url", name])
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+fn git(args: &[&str]) -> Result<Output> {
+    git_in(Path::new("."), args)
+}
+
+fn git_in(dir: &Path, args: &[&str]) -> Result<Output> {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run git {}", args.join(" ")))
+}
+
+fn stderr_of(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+/// How a task's PR is opened.
+#[derive(Debug, Clone, Default)]
+pub struct PullRequestOptions {
+    /// Body, before the reference to the task's issue (default: "Automated
+    /// implementation by Ralphy")
+    pub body: Option<String>,
+    pub draft: bool,
+    pub labels: Vec<String>,
+    /// GitHub users or teams asked to review
+    pub reviewers: Vec<String>,
+    pub assignees: Vec<String>,
+}
+
+/// Push the current branch of the repository at `dir` and open a PR for it.
+pub fn create_pull_request(
+    dir: &Path,
+    task: &Task,
+    options: &PullRequestOptions,
+) -> Result<String> {
+    let current_branch = get_current_branch(dir)?;
+    push_branch_in(dir, &current_branch)?;
+    open_pull_request_in(dir, &current_branch, task, options)
+}
+
+/// Push `branch` of the repository at `dir` to origin.
+pub fn push_branch_in(dir: &Path, branch: &str) -> Result<()> {
+    let push_output = output_with_retry(
+        Command::new("git")
+            .args(["push", "-u", "origin", branch])
+            .current_dir(dir),
+    )?;
+
+    if !push_output.status.success() {
+        anyhow::bail!(
+            "Failed to push branch {}: {}",
+            branch,
+            String::from_utf8_lossy(&push_output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Open a PR for `branch`, already pushed, titled after `task`.
+pub fn open_pull_request_in(
+    dir: &Path,
+    branch: &str,
+    task: &Task,
+    options: &PullRequestOptions,
+) -> Result<String> {
+    let body = pull_request_body(task, options.body.as_deref());
+    let mut cmd = Command::new("gh");
+    cmd.current_dir(dir).args([
+        "pr",
+        "create",
+        "--head",
+        branch,
+        "--title",
+        &task.title,
+        "--body",
+        &body,
+    ]);
+
+    if options.draft {
+        cmd.arg("--draft");
+    }
+    for (flag, values) in [
+        ("--label", &options.labels),
+        ("--reviewer", &options.reviewers),
+        ("--assignee", &options.assignees),
+    ] {
+        for value in values {
+            cmd.args([flag, value]);
+        }
+    }
+
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create PR: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let pr_url = String::from_utf8(output.stdout)?;
+    Ok(pr_url.trim().to_string())
+}
+
+/// Leave `body` as a comment on the PR at `url`.
+pub fn comment_on_pull_request_in(dir: &Path, url: &str, body: &str) -> Result<()> {
+    let output = output_with_retry(
+        Command::new("gh")
+            .current_dir(dir)
+            .args(["pr", "comment", url, "--body", body]),
+    )?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to comment on {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Body of a task's PR, referring to the issue the task came from so the
+/// tracker links the two.
+fn pull_request_body(task: &Task, body: Option<&str>) -> String {
+    let mut body = body
+        .unwrap_or("Automated implementation by Ralphy")
+        .trim_end()
+        .to_string();
+    match task.origin {
+        Some(TaskOrigin::GitHub { number }) => body.push_str(&format!("\n\nCloses #{}", number)),
+        Some(TaskOrigin::Linear { ref identifier }) => {
+            body.push_str(&format!("\n\nCloses {}", identifier))
+        }
+        Some(TaskOrigin::Jira { ref key }) => body.push_str(&format!("\n\nJira: {}", key)),
+        None => {}
+    }
+    body
+}
+
+/// The branch checked out in `dir`.
+pub fn get_current_branch(dir: &Path) -> Result<String> {
+    let output = git_in(dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+        .context("Failed to get current branch")?;
+
+    if !output.status.success() {
+        anyhow::bail!("Failed to get current branch");
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// The commit currently checked out.
+pub fn head_commit() -> Result<String> {
+    head_commit_in(Path::new("."))
+}
+
+/// The commit checked out in the repository at `dir`.
+pub fn head_commit_in(dir: &Path) -> Result<String> {
+    let output = git_in(dir, &["rev-parse", "HEAD"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to resolve HEAD: {}", stderr_of(&output));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// A tree of the working tree at `dir` as it is now, new files included.
+///
+/// It is built in a copy of the index, so what the user or the engine has
+/// staged is left alone.
+fn snapshot_tree(dir: &Path) -> Result<String> {
+    let output = git_in(
+        dir,
+        &["rev-parse", "--path-format=absolute", "--git-path", "index"],
+    )?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to find the index: {}", stderr_of(&output));
+    }
+    let index = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let scratch = tempfile::tempdir()?;
+    let scratch_index = scratch.path().join("index");
+    // Starting from the real index saves rehashing files that haven't changed
+    if Path::new(&index).exists() {
+        std::fs::copy(&index, &scratch_index).context("Failed to copy the index")?;
+    }
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_INDEX_FILE", &scratch_index)
+            .output()
+            .with_context(|| format!("Failed to run git {}", args.join(" ")))
+    };
+    let output = git(&["add", "-A"])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to snapshot the working tree: {}",
+            stderr_of(&output)
+        );
+    }
+    let output = git(&["write-tree"])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to snapshot the working tree: {}",
+            stderr_of(&output)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Everything that changed since `base` in the repository at `dir`,
+/// including commits made since and files not yet tracked.
+pub fn diff_since(dir: &Path, base: &str) -> Result<String> {
+    let tree = snapshot_tree(dir)?;
+    let output = git_in(dir, &["diff", base, &tree])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to diff against {}: {}", base, stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Paths changed since `base` in the repository at `dir`, including
+/// commits made since and files not yet tracked.
+pub fn changed_files_since(dir: &Path, base: &str) -> Result<Vec<String>> {
+    let tree = snapshot_tree(dir)?;
+    let output = git_in(dir, &["diff", "--name-only", base, &tree])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to diff against {}: {}", base, stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Lines added and removed per file changed since `base` in `dir`,
+/// including new files. Binary files count as 0 lines.
+pub fn numstat_since(dir: &Path, base: &str) -> Result<Vec<(String, usize, usize)>> {
+    let tree = snapshot_tree(dir)?;
+    let output = git_in(dir, &["diff", "--numstat", base, &tree])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to diff against {}: {}", base, stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let added = parts.next()?.parse().unwrap_or(0);
+            let removed = parts.next()?.parse().unwrap_or(0);
+            Some((parts.next()?.to_string(), added, removed))
+        })
+        .collect())
+}
+
+/// Cut `diff` to at most `max_bytes`, noting how much was left out, so it
+/// fits in a prompt.
+pub fn truncate_diff(diff: &str, max_bytes: usize) -> String {
+    if diff.len() <= max_bytes {
+        return diff.to_string();
+    }
+    let mut end = max_bytes;
+    while !diff.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}\n[diff truncated, {} bytes total]",
+        &diff[..end],
+        diff.len()
+    )
+}
+
+/// Replace everything committed since `base`, plus any uncommitted changes,
+/// with a single commit. Returns false when there was nothing to commit.
+pub fn squash_since(dir: &Path, base: &str, message: &str) -> Result<bool> {
+    let output = git_in(dir, &["reset", "--soft", base])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to reset to {}: {}", base, stderr_of(&output));
+    }
+
+    let output = git_in(dir, &["add", "-A"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to stage changes: {}", stderr_of(&output));
+    }
+
+    if git_in(dir, &["diff", "--cached", "--quiet"])?
+        .status
+        .success()
+    {
+        return Ok(false);
+    }
+
+    let output = git_in(dir, &["commit", "-q", "-m", message])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to commit: {}", stderr_of(&output));
+    }
+    Ok(true)
+}
+
+/// Check out a new `branch` at `base` in a separate worktree at `dir`.
+pub fn add_worktree(dir: &Path, branch: &str, base: &str) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let output = git(&["worktree", "add", "-b", branch, &dir, base])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create worktree {} on {}: {}",
+            dir,
+            branch,
+            stderr_of(&output)
+        );
+    }
+    Ok(())
+}
+
+/// Check out the existing `branch` in a new worktree at `dir`, forgetting
+/// worktrees whose directories are gone so one of those doesn't hold it.
+pub fn checkout_worktree(dir: &Path, branch: &str) -> Result<()> {
+    git(&["worktree", "prune"])?;
+    let dir = dir.to_string_lossy();
+    let output = git(&["worktree", "add", &dir, branch])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to check out {} in worktree {}: {}",
+            branch,
+            dir,
+            stderr_of(&output)
+        );
+    }
+    Ok(())
+}
+
+/// Commit anything left uncommitted in the worktree at `dir`, so its branch
+/// holds the whole result.
+pub fn commit_all_in(dir: &Path, message: &str) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let output = git(&["-C", &dir, "add", "-A"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to stage changes in {}: {}", dir, stderr_of(&output));
+    }
+
+    let staged = git(&["-C", &dir, "diff", "--cached", "--quiet"])?;
+    if staged.status.success() {
+        return Ok(());
+    }
+
+    let output = git(&["-C", &dir, "commit", "-q", "-m", message])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to commit in {}: {}", dir, stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Commit whatever an engine left uncommitted in `dir` with `message`.
+/// Returns false when the tree was already clean.
+pub fn auto_commit(dir: &Path, message: &str) -> Result<bool> {
+    let output = git_in(dir, &["add", "-A"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to stage changes: {}", stderr_of(&output));
+    }
+
+    if git_in(dir, &["diff", "--cached", "--quiet"])?
+        .status
+        .success()
+    {
+        return Ok(false);
+    }
+
+    let output = git_in(dir, &["commit", "-q", "-m", message])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to commit: {}", stderr_of(&output));
+    }
+    Ok(true)
+}
+
+/// Summary of how the worktree at `dir` differs from `base`.
+pub fn diff_stat_in(dir: &Path, base: &str) -> Result<String> {
+    let dir = dir.to_string_lossy();
+    let output = git(&["-C", &dir, "diff", "--stat", base])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to diff {}: {}", dir, stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+pub fn branch_exists(name: &str) -> bool {
+    git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("refs/heads/{}", name),
+    ])
+    .is_ok_and(|output| output.status.success())
+}
+
+/// Remove the worktree at `dir`, discarding anything left in it.
+pub fn remove_worktree(dir: &Path) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let output = git(&["worktree", "remove", "--force", &dir])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to remove worktree {}: {}", dir, stderr_of(&output));
+    }
+    Ok(())
+}
+
+pub fn delete_branch(name: &str) -> Result<()> {
+    let output = git(&["branch", "-D", name])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to delete branch {}: {}", name, stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Put `path` in the worktree at `dir` back to how it was at `base`.
+pub fn restore_in(dir: &Path, base: &str, path: &str) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let output = git(&["-C", &dir, "checkout", base, "--", path])?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to restore {} in {}: {}",
+            path,
+            dir,
+            stderr_of(&output)
+        );
+    }
+    Ok(())
+}
+
+/// Merge `branch` into the current branch with a merge commit.
+///
+/// Returns false when the merge stopped on conflicts, leaving them in the
+/// working directory; any other failure is aborted and returned as an error.
+pub fn merge_branch(branch: &str, message: &str) -> Result<bool> {
+    let output = git(&["merge", "--no-ff", "-m", message, branch])?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    if merge_in_progress() && !unmerged_paths()?.is_empty() {
+        return Ok(false);
+    }
+    if merge_in_progress() {
+        abort_merge()?;
+    }
+    anyhow::bail!("Failed to merge {}: {}", branch, stderr_of(&output))
+}
+
+/// Files still marked as conflicted.
+pub fn unmerged_paths() -> Result<Vec<String>> {
+    let output = git(&["diff", "--name-only", "--diff-filter=U"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to list conflicts: {}", stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn merge_in_progress() -> bool {
+    git(&["rev-parse", "--verify", "--quiet", "MERGE_HEAD"])
+        .is_ok_and(|output| output.status.success())
+}
+
+pub fn abort_merge() -> Result<()> {
+    let output = git(&["merge", "--abort"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to abort merge: {}", stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Conclude a merge whose conflicts have all been resolved and staged.
+pub fn conclude_merge() -> Result<()> {
+    let output = git(&["commit", "--no-edit"])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to commit merge: {}", stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Move the current branch back to `commit`, keeping unrelated local changes.
+pub fn reset_keep(commit: &str) -> Result<()> {
+    reset_keep_in(Path::new("."), commit)
+}
+
+/// Move the current branch of the repository at `dir` back to `commit`,
+/// keeping unrelated local changes.
+pub fn reset_keep_in(dir: &Path, commit: &str) -> Result<()> {
+    let output = git_in(dir, &["reset", "--keep", commit])?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to reset to {}: {}", commit, stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// URL of the named remote.
+pub fn remote_url(name: &str) -> Result<String> {
+    let output = git(&["remote", "get-url", name])?;
+    if !output.status.success() {
+        anyhow::bail!("No '{}' remote: {}", name, stderr_of(&output));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Whether `commit` is on any remote-tracking branch, so a fresh clone can
+/// check it out.
+pub fn is_pushed(commit: &str) -> bool {
+    git(&["branch", "-r", "--contains", commit])
+        .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Clone `url` into `dir`.
+pub fn clone_repo(url: &str, dir: &Path) -> Result<()> {
+    let output = output_with_retry(Command::new("git").args(["clone", "--"]).arg(url).arg(dir))?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to clone {}: {}", url, stderr_of(&output));
+    }
+    Ok(())
+}
+
+/// Fetch every branch from origin in the repository at `dir`.
+pub fn fetch_in(dir: &Path) -> Result<()> {
+    let output = output_with_retry(
+        Command::new("git")
+            .args(["fetch", "origin"])
+            .current_dir(dir),
+    )?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to fetch in {}: {}",
+            dir.display(),
+            stderr_of(&output)
+        );
+    }
+    Ok(())
+}
+
+/// Tracked and untracked files in `dir`, leaving out ignored ones.
+pub fn listed_files(dir: &Path) -> Result<Vec<String>> {
+    let output = git_in(
+        dir,
+        &["ls-files", "--cached", "--others", "--exclude-standard"],
+    )?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to list files: {}", stderr_of(&output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn is_git_repo_in(dir: &Path) -> bool {
+    git_in(dir, &["rev-parse", "--git-dir"]).is_ok_and(|output| output.status.success())
+}
+
+/// Fetch `branch` from origin into a local branch of the same name.
+pub fn fetch_branch(branch: &str) -> Result<()> {
+    let refspec = format!("refs/heads/{0}:refs/heads/{0}", branch);
+    let output = output_with_retry(Command::new("git").args(["fetch", "origin", &refspec]))?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to fetch {}: {}", branch, stderr_of(&output));
+    }
+    Ok(())
+}
+
+pub fn slugify(text: &str) -> String {
+    let slug = text
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let slug = crate::text::take_width(&slug, 50);
+    let slug = slug.trim_end_matches('-');
+    // Branches are files under .git/refs, and Windows reserves these names
+    if is_reserved_on_windows(slug) {
+        format!("{}-task", slug)
+    } else {
+        slug.to_string()
+    }
+}
+
+fn is_reserved_on_windows(name: &str) -> bool {
+    let numbered = name
+        .strip_prefix("com")
+        .or_else(|| name.strip_prefix("lpt"));
+    matches!(name, "con" | "prn" | "aux" | "nul")
+        || matches!(numbered.map(str::as_bytes), Some([b'1'..=b'9']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_since_leaves_the_index_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+        run(&["init", "-q"]);
+        std::fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        run(&["add", "a.txt"]);
+        run(&["commit", "-q", "-m", "init"]);
+        let base = head_commit_in(dir.path()).unwrap();
+
+        std::fs::write(dir.path().join("a.txt"), "two\n").unwrap();
+        std::fs::write(dir.path().join("new.txt"), "new\n").unwrap();
+        let diff = diff_since(dir.path(), &base).unwrap();
+        assert!(diff.contains("+two") && diff.contains("+new"), "{}", diff);
+
+        // Nothing was staged, not even as intent-to-add
+        assert_eq!(run(&["diff", "--cached", "--name-only"]), "");
+        assert_eq!(run(&["status", "--porcelain"]), " M a.txt\n?? new.txt\n");
+    }
+
+    #[test]
+    fn test_classify_sync_error() {
+        assert_eq!(
+            classify_sync_error("fatal: couldn't find remote ref feature/x"),
+            MISSING_REMOTE_BRANCH
+        );
+        assert_eq!(
+            classify_sync_error("remote: Invalid username or password.\nfatal: Authentication failed for 'https://github.com/o/r/'"),
+            "authentication failed"
+        );
+        assert_eq!(
+            classify_sync_error("CONFLICT (content): Merge conflict in src/lib.rs"),
+            "merge conflict with remote"
+        );
+        assert_eq!(
+            classify_sync_error("error: pathspec 'nope' did not match any file(s) known to git"),
+            "branch does not exist locally"
+        );
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(
+            slugify("Create User Authentication"),
+            "create-user-authentication"
+        );
+        assert_eq!(slugify("Add API Endpoints!"), "add-api-endpoints");
+        assert_eq!(slugify("Fix bug in parser.rs"), "fix-bug-in-parser-rs");
+        assert_eq!(
+            slugify("Implement the user settings page with avatar upload support"),
+            "implement-the-user-settings-page-with-avatar-uploa"
+        );
+        assert_eq!(slugify(&"添加".repeat(20)).chars().count(), 25);
+        assert_eq!(slugify("NUL"), "nul-task");
+        assert_eq!(slugify("com1"), "com1-task");
+        assert_eq!(slugify("com10"), "com10");
+    }
+
+    #[test]
+    fn test_pull_request_body_links_the_issue() {
+        let task = Task {
+            origin: Some(TaskOrigin::GitHub { number: 12 }),
+            ..Task::new("Fix login")
+        };
+        assert_eq!(
+            pull_request_body(&task, None),
+            "Automated implementation by Ralphy\n\nCloses #12"
+        );
+        assert_eq!(
+            pull_request_body(&Task::new("Fix login"), None),
+            "Automated implementation by Ralphy"
+        );
+        assert_eq!(
+            pull_request_body(&task, Some("## Fix login\n\n")),
+            "## Fix login\n\nCloses #12"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::retry::output_with_retry_async;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    etag: String,
+    body: String,
+}
+
+/// Thin wrapper over `gh api` that sends `If-None-Match` for endpoints it has
+/// seen before, so unchanged responses come back as 304s that don't count
+/// against the rate limit.
+#[derive(Debug, Default)]
+pub struct GithubApi {
+    cache: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl GithubApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// GET `endpoint` and parse the response body as JSON
+    pub async fn get_json(&self, endpoint: &str) -> Result<Value> {
+        let cached = self.cache.lock().unwrap().get(endpoint).cloned();
+
+        let mut cmd = tokio::process::Command::new("gh");
+        cmd.arg("api").arg("--include").arg(endpoint);
+        if let Some(ref cached) = cached {
+            cmd.arg("-H").arg(format!("If-None-Match: {}", cached.etag));
+        }
+
+        let output = output_with_retry_async(&mut cmd)
+            .await
+            .context("Failed to execute gh command")?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let response = parse_response(&stdout);
+
+        let body = match (response.status, cached) {
+            (Some(304), Some(cached)) => cached.body,
+            _ => {
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "gh command failed: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+                let body = response.body.to_string();
+                if let Some(etag) = response.etag {
+                    self.cache.lock().unwrap().insert(
+                        endpoint.to_string(),
+                        CachedResponse {
+                            etag,
+                            body: body.clone(),
+                        },
+                    );
+                }
+                body
+            }
+        };
+
+        serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse GitHub response for {}", endpoint))
+    }
+}
+
+/// REST endpoint listing issues; see [`only_issues`] to drop pull requests
+pub fn issues_endpoint(repo: &str, state: &str, label: Option<&str>) -> String {
+    let mut endpoint = format!("repos/{}/issues?state={}&per_page=100", repo, state);
+    if let Some(label) = label {
+        endpoint.push_str("&labels=");
+        endpoint.push_str(&encode_query(label));
+    }
+    endpoint
+}
+
+/// Label on issues a run has claimed and is working on
+pub const IN_PROGRESS_LABEL: &str = "in-progress";
+
+/// Whether an issue from the issues endpoint carries `label`
+pub fn has_label(issue: &Value, label: &str) -> bool {
+    issue["labels"]
+        .as_array()
+        .is_some_and(|labels| labels.iter().any(|l| l["name"] == label))
+}
+
+/// Drop pull requests, which the issues endpoint also returns
+pub fn only_issues(items: Value) -> Vec<Value> {
+    match items {
+        Value::Array(items) => items
+            .into_iter()
+            .filter(|item| item.get("pull_request").is_none())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+struct Response<'a> {
+    status: Option<u16>,
+    etag: Option<String>,
+    body: &'a str,
+}
+
+/// Split `gh api --include` output into status, ETag and body
+fn parse_response(raw: &str) -> Response<'_> {
+    let (head, body) = raw
+        .split_once("\r\n\r\n")
+        .or_else(|| raw.split_once("\n\n"))
+        .unwrap_or(("", raw));
+
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok());
+
+    let etag = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("etag")
+            .then(|| value.trim().to_string())
+    });
+
+    Response { status, etag, body }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let raw = "HTTP/2.0 200 OK\r\nContent-Type: application/json\r\nEtag: W/\"abc\"\r\n\r\n[{\"number\":1}]";
+        let response = parse_response(raw);
+        assert_eq!(response.status, Some(200));
+        assert_eq!(response.etag.as_deref(), Some("W/\"abc\""));
+        assert_eq!(response.body, "[{\"number\":1}]");
+
+        let response = parse_response("HTTP/2.0 304 Not Modified\nEtag: \"abc\"\n\n");
+        assert_eq!(response.status, Some(304));
+        assert_eq!(response.body, "");
+    }
+
+    #[test]
+    fn test_issues_endpoint() {
+        assert_eq!(
+            issues_endpoint("o/r", "open", Some("good first issue")),
+            "repos/o/r/issues?state=open&per_page=100&labels=good%20first%20issue"
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Environment variable holding the site URL, e.g. `https://acme.atlassian.net`.
+pub const URL_VAR: &str = "JIRA_URL";
+
+/// Environment variable holding the account email the API token belongs to.
+pub const EMAIL_VAR: &str = "JIRA_EMAIL";
+
+/// Environment variable holding the API token.
+pub const TOKEN_VAR: &str = "JIRA_API_TOKEN";
+
+/// Most issues asked for per search page.
+const PAGE_SIZE: usize = 100;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to reach the Jira Cloud REST API, from the environment.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub base_url: String,
+    pub email: String,
+    pub api_token: String,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .with_context(|| format!("{} is not set", name))
+        };
+        Ok(Self {
+            base_url: var(URL_VAR)?.trim_end_matches('/').to_string(),
+            email: var(EMAIL_VAR)?,
+            api_token: var(TOKEN_VAR)?,
+        })
+    }
+}
+
+/// An issue as far as tasks are concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub key: String,
+    pub summary: String,
+}
+
+/// A Task to open with [`JiraApi::create_task`].
+#[derive(Debug, Clone, Default)]
+pub struct NewIssue {
+    pub summary: String,
+    /// Plain text, shown preformatted
+    pub description: Option<String>,
+    pub labels: Vec<String>,
+    /// Account ID of the user it's assigned to
+    pub assignee: Option<String>,
+}
+
+impl NewIssue {
+    /// The issue's `fields` in a create request.
+    fn fields(&self, project: &str) -> Value {
+        let mut fields = json!({
+            "project": { "key": project },
+            "summary": self.summary,
+            "issuetype": { "name": "Task" },
+        });
+        if let Some(ref description) = self.description {
+            // Descriptions are Atlassian documents, not plain strings
+            fields["description"] = json!({
+                "type": "doc",
+                "version": 1,
+                "content": [{
+                    "type": "codeBlock",
+                    "content": [{ "type": "text", "text": description }],
+                }],
+            });
+        }
+        if !self.labels.is_empty() {
+            fields["labels"] = json!(self.labels);
+        }
+        if let Some(ref assignee) = self.assignee {
+            fields["assignee"] = json!({ "accountId": assignee });
+        }
+        fields
+    }
+}
+
+/// Client for the few Jira endpoints the PRD source needs.
+pub struct JiraApi {
+    client: reqwest::Client,
+    settings: Settings,
+}
+
+impl JiraApi {
+    pub fn new(settings: Settings) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()?;
+        Ok(Self { client, settings })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(Settings::from_env()?)
+    }
+
+    /// Every issue `jql` matches, in the order it sorts them.
+    pub async fn search(&self, jql: &str) -> Result<Vec<Issue>> {
+        let mut issues = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut body = json!({
+                "jql": jql,
+                "fields": ["summary"],
+                "maxResults": PAGE_SIZE,
+            });
+            if let Some(ref token) = page_token {
+                body["nextPageToken"] = json!(token);
+            }
+            let page = self
+                .send(reqwest::Method::POST, "search/jql", Some(&body))
+                .await?;
+            issues.extend(parse_issues(&page));
+
+            page_token = page["nextPageToken"].as_str().map(str::to_string);
+            if page["isLast"].as_bool().unwrap_or(true) || page_token.is_none() {
+                return Ok(issues);
+            }
+        }
+    }
+
+    /// How many issues `jql` matches.
+    pub async fn count(&self, jql: &str) -> Result<usize> {
+        let body = json!({ "jql": jql });
+        let response = self
+            .send(
+                reqwest::Method::POST,
+                "search/approximate-count",
+                Some(&body),
+            )
+            .await?;
+        response["count"]
+            .as_u64()
+            .map(|count| count as usize)
+            .context("Jira sent a count without a number")
+    }
+
+    /// Move `key` to In Progress, unless it has left To Do since it was
+    /// read. Returns whether this call moved it.
+    pub async fn mark_started(&self, key: &str) -> Result<bool> {
+        if self.category(key).await? != Category::ToDo {
+            return Ok(false);
+        }
+        self.move_to(key, Category::InProgress).await?;
+        Ok(true)
+    }
+
+    /// Move `key` back to To Do.
+    pub async fn mark_unstarted(&self, key: &str) -> Result<()> {
+        self.move_to(key, Category::ToDo).await
+    }
+
+    /// Move `key` to Done.
+    pub async fn mark_done(&self, key: &str) -> Result<()> {
+        self.move_to(key, Category::Done).await
+    }
+
+    /// Status category `key` is in now.
+    async fn category(&self, key: &str) -> Result<Category> {
+        let path = format!("issue/{}?fields=status", key);
+        let issue = self.send(reqwest::Method::GET, &path, None).await?;
+        let category = &issue["fields"]["status"]["statusCategory"]["key"];
+        [Category::ToDo, Category::InProgress, Category::Done]
+            .into_iter()
+            .find(|known| category == known.key())
+            .with_context(|| format!("{} is in an unknown status category {}", key, category))
+    }
+
+    /// Move `key` through whichever of its workflow's transitions ends in
+    /// `category`.
+    async fn move_to(&self, key: &str, category: Category) -> Result<()> {
+        let path = format!("issue/{}/transitions", key);
+        let transitions = self.send(reqwest::Method::GET, &path, None).await?;
+        let id = transition_into(&transitions, category).with_context(|| {
+            format!("{} has no transition to a {} status", key, category.name())
+        })?;
+        self.send(
+            reqwest::Method::POST,
+            &path,
+            Some(&json!({ "transition": { "id": id } })),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Open a Task in `project`, returning its key.
+    pub async fn create_issue(&self, project: &str, summary: &str) -> Result<String> {
+        let issue = NewIssue {
+            summary: summary.to_string(),
+            ..Default::default()
+        };
+        self.create_task(project, &issue).await
+    }
+
+    /// Open a Task in `project` with the details in `issue`, returning its
+    /// key.
+    pub async fn create_task(&self, project: &str, issue: &NewIssue) -> Result<String> {
+        let body = json!({ "fields": issue.fields(project) });
+        let created = self
+            .send(reqwest::Method::POST, "issue", Some(&body))
+            .await?;
+        created["key"]
+            .as_str()
+            .map(str::to_string)
+            .context("Jira created an issue without a key")
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value> {
+        let mut request = self
+            .client
+            .request(
+                method,
+                format!("{}/rest/api/3/{}", self.settings.base_url, path),
+            )
+            .basic_auth(&self.settings.email, Some(&self.settings.api_token))
+            .header(reqwest::header::ACCEPT, "application/json");
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let http = request.send().await.context("Failed to reach Jira")?;
+
+        let status = http.status();
+        let text = http.text().await.unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("Jira returned {}: {}", status, error_message(&text));
+        }
+        // Transitions answer with an empty 204
+        if text.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&text).context("Jira sent a response that isn't JSON")
+    }
+}
+
+/// The status categories every Jira workflow status falls into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    ToDo,
+    InProgress,
+    Done,
+}
+
+impl Category {
+    /// Key the REST API reports the category by.
+    fn key(self) -> &'static str {
+        match self {
+            Category::ToDo => "new",
+            Category::InProgress => "indeterminate",
+            Category::Done => "done",
+        }
+    }
+
+    /// Name the Jira UI shows for the category.
+    fn name(self) -> &'static str {
+        match self {
+            Category::ToDo => "To Do",
+            Category::InProgress => "In Progress",
+            Category::Done => "Done",
+        }
+    }
+}
+
+/// Query for the project's issues that aren't done, narrowed by `filter`.
+pub fn open_jql(project: &str, filter: Option<&str>) -> String {
+    format!(
+        "{} ORDER BY Rank ASC",
+        scoped_jql(project, "statusCategory != Done", filter)
+    )
+}
+
+/// Query for the project's issues still in To Do, narrowed by `filter`.
+/// Issues another run has moved to In Progress are left out.
+pub fn to_do_jql(project: &str, filter: Option<&str>) -> String {
+    format!(
+        "{} ORDER BY Rank ASC",
+        scoped_jql(project, "statusCategory = \"To Do\"", filter)
+    )
+}
+
+/// Query for the project's issues in progress, narrowed by `filter`.
+pub fn started_jql(project: &str, filter: Option<&str>) -> String {
+    scoped_jql(project, "statusCategory = \"In Progress\"", filter)
+}
+
+/// Query for the project's done issues, narrowed by `filter`.
+pub fn done_jql(project: &str, filter: Option<&str>) -> String {
+    scoped_jql(project, "statusCategory = Done", filter)
+}
+
+fn scoped_jql(project: &str, status: &str, filter: Option<&str>) -> String {
+    let mut jql = format!("project = \"{}\" AND {}", quote(project), status);
+    if let Some(filter) = filter.map(str::trim).filter(|filter| !filter.is_empty()) {
+        jql.push_str(&format!(" AND ({})", filter));
+    }
+    jql
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn parse_issues(page: &Value) -> Vec<Issue> {
+    page["issues"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|issue| {
+            Some(Issue {
+                key: issue["key"].as_str()?.to_string(),
+                summary: issue["fields"]["summary"].as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Id of the first transition that lands in `category`.
+fn transition_into(transitions: &Value, category: Category) -> Option<String> {
+    transitions["transitions"]
+        .as_array()?
+        .iter()
+        .find(|transition| transition["to"]["statusCategory"]["key"] == category.key())
+        .and_then(|transition| transition["id"].as_str())
+        .map(str::to_string)
+}
+
+/// The messages in a Jira error body, or the body itself.
+fn error_message(text: &str) -> String {
+    let Ok(json) = serde_json::from_str::<Value>(text) else {
+        return text.to_string();
+    };
+    let messages: Vec<String> = json["errorMessages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message.as_str().map(str::to_string))
+        .chain(
+            json["errors"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(field, message)| format!("{}: {}", field, message.as_str().unwrap_or(""))),
+        )
+        .collect();
+    if messages.is_empty() {
+        text.to_string()
+    } else {
+        messages.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jql() {
+        assert_eq!(
+            open_jql("PAY", Some("labels = ralphy")),
+            "project = \"PAY\" AND statusCategory != Done AND (labels = ralphy) ORDER BY Rank ASC"
+        );
+        assert_eq!(
+            done_jql("PAY", None),
+            "project = \"PAY\" AND statusCategory = Done"
+        );
+        assert_eq!(
+            to_do_jql("PAY", None),
+            "project = \"PAY\" AND statusCategory = \"To Do\" ORDER BY Rank ASC"
+        );
+        assert_eq!(
+            started_jql("PAY", Some("labels = ralphy")),
+            "project = \"PAY\" AND statusCategory = \"In Progress\" AND (labels = ralphy)"
+        );
+    }
+
+    #[test]
+    fn test_transition_into() {
+        let transitions = json!({"transitions": [
+            {"id": "11", "name": "Start", "to": {"statusCategory": {"key": "indeterminate"}}},
+            {"id": "31", "name": "Ship it", "to": {"statusCategory": {"key": "done"}}},
+        ]});
+        assert_eq!(
+            transition_into(&transitions, Category::Done).as_deref(),
+            Some("31")
+        );
+        assert_eq!(
+            transition_into(&transitions, Category::InProgress).as_deref(),
+            Some("11")
+        );
+        assert_eq!(transition_into(&transitions, Category::ToDo), None);
+        assert_eq!(
+            transition_into(&json!({"transitions": []}), Category::Done),
+            None
+        );
+    }
+
+    #[test]
+    fn test_new_issue_fields() {
+        let issue = NewIssue {
+            summary: "Task failed: Add billing".to_string(),
+            description: Some("Ralphy gave up.\n".to_string()),
+            labels: vec!["ralphy-failure".to_string()],
+            assignee: Some("5b10a2844c20165700ede21g".to_string()),
+        };
+        let fields = issue.fields("PAY");
+        assert_eq!(fields["project"]["key"], "PAY");
+        assert_eq!(
+            fields["description"]["content"][0]["content"][0]["text"],
+            "Ralphy gave up.\n"
+        );
+        assert_eq!(fields["labels"], json!(["ralphy-failure"]));
+        assert_eq!(fields["assignee"]["accountId"], "5b10a2844c20165700ede21g");
+
+        let bare = NewIssue::default().fields("PAY");
+        assert!(bare.get("description").is_none() && bare.get("assignee").is_none());
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(r#"{"errorMessages":[],"errors":{"summary":"Field is required"}}"#),
+            "summary: Field is required"
+        );
+        assert_eq!(error_message("Bad Gateway"), "Bad Gateway");
+    }
+}
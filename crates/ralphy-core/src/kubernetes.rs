@@ -0,0 +1,396 @@
+use crate::ai::{AiEngine, AiResponse};
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::process::{self, EngineChild};
+use crate::settings::KubernetesSettings;
+use crate::{contract, git, prompt};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::Stdio;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Finished Jobs are garbage-collected by the cluster after this long.
+const JOB_TTL_SECS: u64 = 3600;
+
+/// How long to wait for a pod to start (image pulls, scheduling).
+const POD_START_TIMEOUT: &str = "15m";
+
+/// Runs inside the pod: check out the base commit on a new branch, run the
+/// engine, then commit and push whatever it changed.
+const POD_SCRIPT: &str = r#"set -eu
+git clone --quiet "$RALPHY_REPO" /workspace
+cd /workspace
+git checkout --quiet -b "$RALPHY_BRANCH" "$RALPHY_BASE"
+{engine}
+git add -A
+git -c user.name="${GIT_AUTHOR_NAME:-Ralphy}" -c user.email="${GIT_AUTHOR_EMAIL:-ralphy@localhost}" \
+    commit --quiet -m "$RALPHY_TASK" || true
+git push --quiet origin "HEAD:refs/heads/$RALPHY_BRANCH"
+"#;
+
+/// Shell line that runs `engine` on the prompt in `$RALPHY_PROMPT`, with
+/// the model in `$RALPHY_MODEL` if that is set.
+pub fn engine_script(engine: AiEngine) -> Result<&'static str> {
+    Ok(match engine {
+        AiEngine::Claude => {
+            r#"printf '%s' "$RALPHY_PROMPT" | claude --dangerously-skip-permissions --verbose --output-format stream-json ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} -p"#
+        }
+        AiEngine::OpenCode => {
+            r#"OPENCODE_PERMISSION='{"*":"allow"}' opencode run --format json ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} "$RALPHY_PROMPT""#
+        }
+        AiEngine::Cursor => {
+            r#"agent --print --force --output-format stream-json ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} "$RALPHY_PROMPT""#
+        }
+        AiEngine::Codex => {
+            r#"printf '%s' "$RALPHY_PROMPT" | codex exec --full-auto --json ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} -"#
+        }
+        AiEngine::Qwen => {
+            r#"qwen --output-format stream-json --approval-mode yolo ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} -p "$RALPHY_PROMPT""#
+        }
+        AiEngine::Aider => {
+            r#"aider --yes-always --no-pretty --no-stream --no-check-update ${RALPHY_MODEL:+--model "$RALPHY_MODEL"} --message "$RALPHY_PROMPT""#
+        }
+        AiEngine::AnthropicApi | AiEngine::OpenAiApi | AiEngine::Ollama => {
+            anyhow::bail!("The {} engine cannot run in a Kubernetes pod", engine)
+        }
+        AiEngine::Mock => anyhow::bail!("The mock engine cannot run in a Kubernetes pod"),
+    })
+}
+
+/// A task's Job and the branch its pod pushes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskJob {
+    pub name: String,
+    pub branch: String,
+}
+
+impl TaskJob {
+    /// Names for `task`, made unique per run with `stamp`. Job names must be
+    /// DNS labels: lowercase ASCII, digits and dashes, at most 63 characters.
+    pub fn new(task: &str, agent: usize, stamp: u64) -> Self {
+        let slug: String = git::slugify(task)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .take(32)
+            .collect();
+        let slug = slug.trim_matches('-');
+        let id = format!("{}-{:x}", agent, stamp);
+        let name = if slug.is_empty() {
+            format!("ralphy-{}", id)
+        } else {
+            format!("ralphy-{}-{}", slug, id)
+        };
+        Self {
+            branch: format!("ralphy/{}", &name["ralphy-".len()..]),
+            name,
+        }
+    }
+}
+
+/// Job manifest that runs `task` on `engine` and pushes the result.
+pub fn job_manifest(
+    settings: &KubernetesSettings,
+    job: &TaskJob,
+    engine: AiEngine,
+    repo: &str,
+    base: &str,
+    task: &str,
+    prompt: &str,
+) -> Result<Value> {
+    let script = POD_SCRIPT.replace("{engine}", engine_script(engine)?);
+    let mut container = json!({
+        "name": "agent",
+        "image": settings.image,
+        "command": ["sh", "-c", script],
+        "env": [
+            {"name": "RALPHY_REPO", "value": repo},
+            {"name": "RALPHY_BASE", "value": base},
+            {"name": "RALPHY_BRANCH", "value": job.branch},
+            {"name": "RALPHY_TASK", "value": task},
+            {"name": "RALPHY_PROMPT", "value": prompt},
+        ],
+    });
+    if let Some(ref secret) = settings.env_from_secret {
+        container["envFrom"] = json!([{"secretRef": {"name": secret}}]);
+    }
+
+    let mut pod_spec = json!({
+        "restartPolicy": "Never",
+        "containers": [container],
+    });
+    if let Some(ref account) = settings.service_account {
+        pod_spec["serviceAccountName"] = json!(account);
+    }
+
+    let mut manifest = json!({
+        "apiVersion": "batch/v1",
+        "kind": "Job",
+        "metadata": {
+            "name": job.name,
+            "labels": {"app.kubernetes.io/managed-by": "ralphy"},
+        },
+        "spec": {
+            "backoffLimit": 0,
+            "ttlSecondsAfterFinished": JOB_TTL_SECS,
+            "template": {"spec": pod_spec},
+        },
+    });
+    if let Some(ref namespace) = settings.namespace {
+        manifest["metadata"]["namespace"] = json!(namespace);
+    }
+    Ok(manifest)
+}
+
+/// Check that a pod will be able to clone the repository and check out the
+/// current commit.
+pub fn check_repo() -> Result<()> {
+    git::remote_url("origin").context("--backend kubernetes clones the 'origin' remote")?;
+    let head = git::head_commit()?;
+    if !git::is_pushed(&head) {
+        anyhow::bail!(
+            "--backend kubernetes starts pods from the current commit, but {} has not been pushed to a remote",
+            &head[..head.len().min(12)]
+        );
+    }
+    Ok(())
+}
+
+/// Run `task` as a Job, streaming its logs, then fetch the branch it pushed.
+pub async fn run_task(config: &RunConfig, task: &str, agent: usize) -> Result<AiResponse> {
+    let settings = config
+        .kubernetes
+        .as_ref()
+        .context("No [kubernetes] settings loaded")?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let job = TaskJob::new(task, agent, stamp);
+    let repo = git::remote_url("origin")?;
+    let base = git::head_commit()?;
+    let prompt = prompt::build_prompt(config, Some(task));
+
+    let mut manifest = job_manifest(
+        settings,
+        &job,
+        config.ai_engine,
+        &repo,
+        &base,
+        task,
+        &prompt,
+    )?;
+    if let Some(ref model) = config.model {
+        let env = manifest
+            .pointer_mut("/spec/template/spec/containers/0/env")
+            .and_then(Value::as_array_mut)
+            .context("Job manifest has no container env")?;
+        env.push(json!({"name": "RALPHY_MODEL", "value": model}));
+    }
+    apply(settings, &manifest)?;
+    if config.verbose > 0 {
+        events::info(format!("Started job/{}", job.name));
+    }
+
+    let logs = stream_logs(settings, &job, agent, config.verbose > 0).await?;
+    if !wait_complete(settings, &job).await? {
+        let tail = &logs[logs.len().saturating_sub(10)..];
+        anyhow::bail!("job/{} failed:\n{}", job.name, tail.join("\n"));
+    }
+
+    let response = parse_logs(&logs);
+    if let Some(contract::AgentStatus::Blocked(reason)) =
+        contract::parse_status(&response.text, &config.completion_marker)
+    {
+        anyhow::bail!("Agent reported the task as blocked: {}", reason);
+    }
+
+    git::fetch_branch(&job.branch)?;
+    events::notice(Level::Passed, format!("{} pushed {}", job.name, job.branch));
+    Ok(response)
+}
+
+fn kubectl(settings: &KubernetesSettings) -> tokio::process::Command {
+    let mut cmd = process::engine_command("kubectl");
+    if let Some(ref namespace) = settings.namespace {
+        cmd.args(["--namespace", namespace]);
+    }
+    cmd
+}
+
+fn apply(settings: &KubernetesSettings, manifest: &Value) -> Result<()> {
+    let mut cmd = std::process::Command::new("kubectl");
+    if let Some(ref namespace) = settings.namespace {
+        cmd.args(["--namespace", namespace]);
+    }
+    let mut child = cmd
+        .args(["apply", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run kubectl apply")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open kubectl stdin")?
+        .write_all(manifest.to_string().as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "kubectl apply failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Follow the Job's logs until its pod exits, echoing them when verbose.
+async fn stream_logs(
+    settings: &KubernetesSettings,
+    job: &TaskJob,
+    agent: usize,
+    echo: bool,
+) -> Result<Vec<String>> {
+    let mut child = EngineChild::spawn(
+        kubectl(settings)
+            .args(["logs", "--follow"])
+            .arg(format!("--pod-running-timeout={}", POD_START_TIMEOUT))
+            .arg(format!("job/{}", job.name))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null()),
+    )
+    .context("Failed to run kubectl logs")?;
+
+    let stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let mut logs = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        if echo {
+            events::notice(Level::Plain, format!("  [agent {}] {}", agent, line));
+        }
+        logs.push(line);
+    }
+    child.wait().await?;
+    Ok(logs)
+}
+
+async fn wait_complete(settings: &KubernetesSettings, job: &TaskJob) -> Result<bool> {
+    let output = kubectl(settings)
+        .args(["wait", "--for=condition=complete", "--timeout=120s"])
+        .arg(format!("job/{}", job.name))
+        .output()
+        .await
+        .context("Failed to run kubectl wait")?;
+    Ok(output.status.success())
+}
+
+/// Pull the engine's result out of the pod's logs. Engines that emit a
+/// stream-json `result` event report text and usage; for the rest the whole
+/// log is the response.
+pub fn parse_logs(logs: &[String]) -> AiResponse {
+    let mut response = AiResponse {
+        text: logs.join("\n"),
+        input_tokens: 0,
+        output_tokens: 0,
+        actual_cost: None,
+        duration_ms: None,
+        model: None,
+    };
+
+    for json in logs
+        .iter()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+    {
+        match json["type"].as_str() {
+            Some("system") if response.model.is_none() => {
+                response.model = json["model"].as_str().map(str::to_string);
+            }
+            Some("result") => {
+                if let Some(result) = json["result"].as_str() {
+                    response.text = result.to_string();
+                }
+                response.input_tokens =
+                    json["usage"]["input_tokens"].as_u64().unwrap_or(0) as usize;
+                response.output_tokens =
+                    json["usage"]["output_tokens"].as_u64().unwrap_or(0) as usize;
+                response.actual_cost = json["total_cost_usd"].as_f64();
+                response.duration_ms = json["duration_ms"].as_u64();
+            }
+            _ => {}
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_job_names() {
+        let job = TaskJob::new("Add user login (OAuth)", 2, 0xabc);
+        assert_eq!(job.name, "ralphy-add-user-login-oauth-2-abc");
+        assert_eq!(job.branch, "ralphy/add-user-login-oauth-2-abc");
+
+        let long = TaskJob::new(&"x".repeat(200), 10, u64::MAX);
+        assert!(long.name.len() <= 63);
+
+        let symbols = TaskJob::new("日本語", 1, 1);
+        assert_eq!(symbols.name, "ralphy-1-1");
+    }
+
+    #[test]
+    fn test_job_manifest() {
+        let settings = KubernetesSettings {
+            image: "ghcr.io/acme/agent:1".to_string(),
+            namespace: Some("agents".to_string()),
+            env_from_secret: Some("agent-keys".to_string()),
+            service_account: None,
+        };
+        let job = TaskJob::new("Add login", 1, 1);
+        let manifest = job_manifest(
+            &settings,
+            &job,
+            AiEngine::Claude,
+            "git@example.com:acme/app.git",
+            "abc123",
+            "Add login",
+            "prompt",
+        )
+        .unwrap();
+
+        assert_eq!(manifest["metadata"]["namespace"], "agents");
+        assert_eq!(manifest["spec"]["backoffLimit"], 0);
+        let pod = &manifest["spec"]["template"]["spec"];
+        assert!(pod.get("serviceAccountName").is_none());
+        let container = &pod["containers"][0];
+        assert_eq!(container["image"], "ghcr.io/acme/agent:1");
+        assert_eq!(container["envFrom"][0]["secretRef"]["name"], "agent-keys");
+        assert!(container["command"][2]
+            .as_str()
+            .unwrap()
+            .contains("| claude --dangerously-skip-permissions"));
+        assert_eq!(container["env"][2]["value"], "ralphy/add-login-1-1");
+
+        assert!(job_manifest(&settings, &job, AiEngine::Mock, "", "", "", "").is_err());
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        let logs = vec![
+            r#"{"type":"system","model":"claude-sonnet-4"}"#.to_string(),
+            "Cloning...".to_string(),
+            r#"{"type":"result","result":"Done <status>DONE</status>","usage":{"input_tokens":10,"output_tokens":5},"total_cost_usd":0.02}"#.to_string(),
+        ];
+        let response = parse_logs(&logs);
+        assert_eq!(response.text, "Done <status>DONE</status>");
+        assert_eq!(response.input_tokens, 10);
+        assert_eq!(response.actual_cost, Some(0.02));
+        assert_eq!(response.model.as_deref(), Some("claude-sonnet-4"));
+
+        let plain = parse_logs(&["working".to_string(), "<status>DONE</status>".to_string()]);
+        assert_eq!(plain.text, "working\n<status>DONE</status>");
+    }
+}
@@ -0,0 +1,147 @@
+pub mod ab;
+pub mod ai;
+pub mod anthropic;
+pub mod approval;
+pub mod backend;
+pub mod backoff;
+pub mod budget;
+pub mod checkpoint;
+pub mod commit_message;
+pub mod config;
+pub mod contract;
+pub mod diff_scan;
+pub mod events;
+pub mod followups;
+pub mod gate;
+pub mod git;
+pub mod github;
+pub mod jira;
+pub mod kubernetes;
+pub mod linear;
+pub mod log;
+pub mod merge_back;
+pub mod merge_queue;
+pub mod monitor;
+pub mod ollama;
+pub mod openai;
+pub mod prd;
+pub mod preflight;
+pub mod pricing;
+pub mod process;
+pub mod progress;
+pub mod progress_summary;
+pub mod prompt;
+pub mod prompt_budget;
+pub mod pull_request;
+pub mod relevance;
+pub mod repair;
+pub mod replan;
+pub mod repo_map;
+pub mod repos;
+pub mod retry;
+pub mod review;
+pub mod run;
+pub mod schedule;
+pub mod security;
+pub mod session;
+pub mod settings;
+pub mod shutdown;
+pub mod stats;
+pub mod text;
+pub mod tools;
+pub mod triage;
+pub mod verify;
+pub mod workspace;
+
+use anyhow::{Context, Result};
+use stats::RunStats;
+
+pub use run::run_autonomous_loop;
+
+/// Run the prompt and check the response ends with a valid status, re-prompting
+/// once with a correction if it doesn't.
+pub async fn execute_with_contract(
+    executor: &ai::AiExecutor,
+    prompt: &str,
+) -> Result<ai::AiResponse> {
+    let mut response = executor.execute(prompt).await?;
+
+    let marker = executor.completion_marker();
+    let status = match contract::parse_status(&response.text, marker) {
+        Some(status) => status,
+        None => {
+            log::warn("Response is missing a status line, re-prompting once...");
+            let retry = executor
+                .execute(&contract::correction_prompt(prompt))
+                .await?;
+            let status = contract::parse_status(&retry.text, marker);
+            let previous = std::mem::replace(&mut response, retry);
+            response.absorb_usage(&previous);
+            status.context("Agent response did not include a status line after re-prompting")?
+        }
+    };
+
+    if let contract::AgentStatus::Blocked(reason) = status {
+        anyhow::bail!("Agent reported the task as blocked: {}", reason);
+    }
+
+    Ok(response)
+}
+
+/// How a run ended, used to pick the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// Every attempted task succeeded
+    Complete,
+    /// Tasks failed or the run was interrupted
+    WorkRemaining,
+    /// The run stopped at `--max-cost` or `--max-tokens`
+    LimitReached,
+}
+
+impl RunOutcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunOutcome::Complete => 0,
+            RunOutcome::WorkRemaining => shutdown::EXIT_WORK_REMAINING,
+            RunOutcome::LimitReached => budget::EXIT_LIMIT_REACHED,
+        }
+    }
+}
+
+/// A task's change turned down by a check run after the engine, such as
+/// the reviewer. The commits the task made are dropped when it fails this
+/// way, so the next attempt or task doesn't build on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected(pub String);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// What the run's tasks have cost, estimating what engines don't report.
+pub fn run_cost(pricing: &pricing::Pricing, stats: &RunStats) -> f64 {
+    stats
+        .usage()
+        .map(|agent| {
+            agent.actual_cost.unwrap_or_else(|| {
+                pricing.cost(
+                    agent.engine,
+                    agent.model.as_deref(),
+                    agent.input_tokens,
+                    agent.output_tokens,
+                )
+            })
+        })
+        .sum()
+}
+
+/// Average cost of the tasks that finished, if any have
+pub(crate) fn typical_cost(pricing: &pricing::Pricing, stats: &RunStats) -> Option<f64> {
+    (!stats.agents.is_empty()).then(|| run_cost(pricing, stats) / stats.agents.len() as f64)
+}
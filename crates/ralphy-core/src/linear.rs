@@ -0,0 +1,313 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Where requests go unless `LINEAR_API_URL` says otherwise.
+pub const DEFAULT_API_URL: &str = "https://api.linear.app/graphql";
+
+/// Environment variable holding the API key.
+pub const API_KEY_VAR: &str = "LINEAR_API_KEY";
+
+/// Most issues asked for per page.
+const PAGE_SIZE: usize = 100;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to reach Linear's GraphQL API, from the environment.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub api_key: String,
+    pub api_url: String,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Ok(Self {
+            api_key: var(API_KEY_VAR).with_context(|| format!("{} is not set", API_KEY_VAR))?,
+            api_url: var("LINEAR_API_URL").unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+        })
+    }
+}
+
+/// An issue as far as tasks are concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Team key and number, e.g. `ENG-12`
+    pub identifier: String,
+    pub title: String,
+}
+
+/// Which of a team's issues to look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Not started, completed or canceled
+    Open,
+    /// In a started state, such as In Progress
+    Started,
+    Completed,
+}
+
+/// Workflow state types Ralphy moves issues to.
+const UNSTARTED: &str = "unstarted";
+const STARTED: &str = "started";
+const COMPLETED: &str = "completed";
+const CANCELED: &str = "canceled";
+
+/// Client for the few Linear queries the PRD source needs.
+pub struct LinearApi {
+    client: reqwest::Client,
+    settings: Settings,
+}
+
+impl LinearApi {
+    pub fn new(settings: Settings) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()?;
+        Ok(Self { client, settings })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(Settings::from_env()?)
+    }
+
+    /// The team's issues matching `filter` (and `label`, if given), oldest
+    /// first.
+    pub async fn issues(
+        &self,
+        team: &str,
+        label: Option<&str>,
+        filter: Filter,
+    ) -> Result<Vec<Issue>> {
+        const QUERY: &str = "query Issues($filter: IssueFilter, $first: Int, $after: String) {
+  issues(filter: $filter, first: $first, after: $after, orderBy: createdAt) {
+    nodes { identifier title createdAt }
+    pageInfo { hasNextPage endCursor }
+  }
+}";
+        let mut issues: Vec<(String, Issue)> = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let variables = json!({
+                "filter": issue_filter(team, label, filter),
+                "first": PAGE_SIZE,
+                "after": after,
+            });
+            let data = self.query(QUERY, variables).await?;
+            let page = &data["issues"];
+            issues.extend(
+                page["nodes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|node| {
+                        let issue = Issue {
+                            identifier: node["identifier"].as_str()?.to_string(),
+                            title: node["title"].as_str()?.to_string(),
+                        };
+                        Some((node["createdAt"].as_str().unwrap_or("").to_string(), issue))
+                    }),
+            );
+
+            after = page["pageInfo"]["endCursor"].as_str().map(str::to_string);
+            if !page["pageInfo"]["hasNextPage"].as_bool().unwrap_or(false) || after.is_none() {
+                break;
+            }
+        }
+        // `orderBy: createdAt` sorts newest first
+        issues.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(issues.into_iter().map(|(_, issue)| issue).collect())
+    }
+
+    /// Move `issue` to the team's first workflow state of the started type,
+    /// e.g. "In Progress", unless it is no longer open since it was read.
+    /// Returns whether this call moved it.
+    pub async fn mark_started(&self, team: &str, issue: &str) -> Result<bool> {
+        if [STARTED, COMPLETED, CANCELED].contains(&self.state_type(issue).await?.as_str()) {
+            return Ok(false);
+        }
+        self.move_to(team, issue, STARTED).await?;
+        Ok(true)
+    }
+
+    /// Move `issue` back to the team's first workflow state of the
+    /// unstarted type, e.g. "Todo".
+    pub async fn mark_unstarted(&self, team: &str, issue: &str) -> Result<()> {
+        self.move_to(team, issue, UNSTARTED).await
+    }
+
+    /// Move `issue` to the team's first workflow state of the completed
+    /// type, e.g. "Done".
+    pub async fn mark_completed(&self, team: &str, issue: &str) -> Result<()> {
+        self.move_to(team, issue, COMPLETED).await
+    }
+
+    /// Open an issue in `team`, labelled `label` if given.
+    pub async fn create_issue(&self, team: &str, label: Option<&str>, title: &str) -> Result<()> {
+        const QUERY: &str = "query Team($key: String!) {
+  teams(filter: { key: { eq: $key } }) {
+    nodes { id labels { nodes { id name } } }
+  }
+}";
+        const MUTATION: &str = "mutation Create($input: IssueCreateInput!) {
+  issueCreate(input: $input) { success }
+}";
+        let data = self.query(QUERY, json!({ "key": team })).await?;
+        let team_node = &data["teams"]["nodes"][0];
+        let team_id = team_node["id"]
+            .as_str()
+            .with_context(|| format!("Linear has no team with key {}", team))?;
+        let mut input = json!({ "teamId": team_id, "title": title });
+        if let Some(label) = label {
+            let label_id = team_node["labels"]["nodes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|node| node["name"] == label)
+                .and_then(|node| node["id"].as_str())
+                .with_context(|| format!("Linear team {} has no label {}", team, label))?;
+            input["labelIds"] = json!([label_id]);
+        }
+        self.query(MUTATION, json!({ "input": input })).await?;
+        Ok(())
+    }
+
+    /// Type of the workflow state `issue` is in now, e.g. "started".
+    async fn state_type(&self, issue: &str) -> Result<String> {
+        const QUERY: &str = "query Issue($id: String!) {
+  issue(id: $id) { state { type } }
+}";
+        let data = self.query(QUERY, json!({ "id": issue })).await?;
+        data["issue"]["state"]["type"]
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("Linear sent {} without a workflow state", issue))
+    }
+
+    async fn move_to(&self, team: &str, issue: &str, state_type: &str) -> Result<()> {
+        const QUERY: &str = "query States($filter: WorkflowStateFilter) {
+  workflowStates(filter: $filter) { nodes { id position } }
+}";
+        const MUTATION: &str = "mutation Move($id: String!, $stateId: String!) {
+  issueUpdate(id: $id, input: { stateId: $stateId }) { success }
+}";
+        let filter = json!({
+            "team": { "key": { "eq": team } },
+            "type": { "eq": state_type },
+        });
+        let data = self.query(QUERY, json!({ "filter": filter })).await?;
+        let state = first_state(&data).with_context(|| {
+            format!("Linear team {} has no {} workflow state", team, state_type)
+        })?;
+        self.query(MUTATION, json!({ "id": issue, "stateId": state }))
+            .await?;
+        Ok(())
+    }
+
+    /// Run a GraphQL document, returning its `data`.
+    async fn query(&self, query: &str, variables: Value) -> Result<Value> {
+        let http = self
+            .client
+            .post(&self.settings.api_url)
+            .header(reqwest::header::AUTHORIZATION, &self.settings.api_key)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await
+            .context("Failed to reach Linear")?;
+
+        let status = http.status();
+        let text = http.text().await.unwrap_or_default();
+        let body: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+        if let Some(message) = error_message(&body) {
+            anyhow::bail!("Linear returned {}: {}", status, message);
+        }
+        if !status.is_success() {
+            anyhow::bail!("Linear returned {}: {}", status, text);
+        }
+        match body.get("data") {
+            Some(data) if !data.is_null() => Ok(data.clone()),
+            _ => anyhow::bail!("Linear sent a response without data"),
+        }
+    }
+}
+
+/// GraphQL `IssueFilter` for a team's issues.
+fn issue_filter(team: &str, label: Option<&str>, filter: Filter) -> Value {
+    let state = match filter {
+        Filter::Open => json!({ "type": { "nin": [STARTED, COMPLETED, CANCELED] } }),
+        Filter::Started => json!({ "type": { "eq": STARTED } }),
+        Filter::Completed => json!({ "type": { "eq": COMPLETED } }),
+    };
+    let mut issue_filter = json!({
+        "team": { "key": { "eq": team } },
+        "state": state,
+    });
+    if let Some(label) = label {
+        issue_filter["labels"] = json!({ "name": { "eq": label } });
+    }
+    issue_filter
+}
+
+/// Id of the workflow state that comes first on the team's board.
+fn first_state(data: &Value) -> Option<String> {
+    data["workflowStates"]["nodes"]
+        .as_array()?
+        .iter()
+        .min_by(|a, b| {
+            let position = |node: &Value| node["position"].as_f64().unwrap_or(f64::MAX);
+            position(a).total_cmp(&position(b))
+        })
+        .and_then(|node| node["id"].as_str())
+        .map(str::to_string)
+}
+
+/// The messages of a GraphQL error response.
+fn error_message(body: &Value) -> Option<String> {
+    let messages: Vec<&str> = body["errors"]
+        .as_array()?
+        .iter()
+        .filter_map(|error| error["message"].as_str())
+        .collect();
+    (!messages.is_empty()).then(|| messages.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_filter() {
+        assert_eq!(
+            issue_filter("ENG", Some("ralphy"), Filter::Open),
+            json!({
+                "team": { "key": { "eq": "ENG" } },
+                "state": { "type": { "nin": ["started", "completed", "canceled"] } },
+                "labels": { "name": { "eq": "ralphy" } },
+            })
+        );
+        assert!(issue_filter("ENG", None, Filter::Completed)
+            .get("labels")
+            .is_none());
+    }
+
+    #[test]
+    fn test_first_state() {
+        let data = json!({"workflowStates": {"nodes": [
+            {"id": "review", "position": 3.0},
+            {"id": "in-progress", "position": 2.0},
+        ]}});
+        assert_eq!(first_state(&data).as_deref(), Some("in-progress"));
+        assert_eq!(first_state(&json!({"workflowStates": {"nodes": []}})), None);
+    }
+
+    #[test]
+    fn test_error_message() {
+        let body = json!({"errors": [{"message": "Authentication required"}], "data": null});
+        assert_eq!(
+            error_message(&body).as_deref(),
+            Some("Authentication required")
+        );
+        assert_eq!(error_message(&json!({"data": {}})), None);
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::OnceLock;
+
+/// How much a message matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warn,
+}
+
+/// Where engine messages go.
+pub type Sink = fn(Level, &str);
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Send engine messages to `sink` instead of plain stdout and stderr. Only
+/// the first call has any effect.
+pub fn set_sink(sink: Sink) {
+    SINK.set(sink).ok();
+}
+
+fn plain(level: Level, message: &str) {
+    match level {
+        Level::Info => println!("[INFO] {}", message),
+        Level::Warn => eprintln!("[WARN] {}", message),
+    }
+}
+
+fn emit(level: Level, message: &str) {
+    SINK.get().copied().unwrap_or(plain)(level, message)
+}
+
+pub fn info(message: impl AsRef<str>) {
+    emit(Level::Info, message.as_ref())
+}
+
+pub fn warn(message: impl AsRef<str>) {
+    emit(Level::Warn, message.as_ref())
+}
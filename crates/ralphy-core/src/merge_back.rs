@@ -0,0 +1,101 @@
+use crate::commit_message;
+use crate::config::MergeStrategy;
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::git;
+use anyhow::Result;
+use std::path::Path;
+
+/// A task branch the run completed.
+#[derive(Debug, Clone)]
+pub struct TaskBranch {
+    pub task: String,
+    pub branch: String,
+}
+
+/// Bring `branches`, in the order their tasks completed, into the
+/// `--merge-into` branch, or else into `base`, the branch task branches were
+/// made from. Branches that conflict are left as they are and reported.
+pub fn run(config: &RunConfig, base: &str, branches: &[TaskBranch]) -> Result<()> {
+    if config.merge_strategy == MergeStrategy::None || branches.is_empty() {
+        return Ok(());
+    }
+    let dir = Path::new(".");
+    let target = config.merge_into.as_deref().unwrap_or(base);
+    events::info(format!(
+        "Bringing {} task branch(es) into {}",
+        branches.len(),
+        target
+    ));
+
+    // The run's own edits to the PRD and progress log come along
+    let stashed = git::stash_in(dir)?;
+    let result = land_all(config, dir, base, target, branches);
+    if stashed {
+        git::unstash_in(dir)?;
+    }
+    let conflicted = result?;
+
+    if conflicted.is_empty() {
+        events::success(format!("All task branches are in {}", target));
+    } else {
+        events::warn(format!(
+            "{} of {} task branch(es) conflict with {} and were left unmerged; merge them by hand",
+            conflicted.len(),
+            branches.len(),
+            target
+        ));
+    }
+    Ok(())
+}
+
+/// Land each branch on `target`, returning the ones that conflicted.
+fn land_all<'a>(
+    config: &RunConfig,
+    dir: &Path,
+    base: &str,
+    target: &str,
+    branches: &'a [TaskBranch],
+) -> Result<Vec<&'a TaskBranch>> {
+    git::checkout_or_create_in(dir, target, base)?;
+    let mut conflicted = Vec::new();
+    let mut previous: Option<&str> = None;
+    for branch in branches {
+        let conflicts = match config.merge_strategy {
+            MergeStrategy::None => Vec::new(),
+            MergeStrategy::Merge => {
+                let message = format!("Merge branch '{}'", branch.branch);
+                git::merge_in(dir, &branch.branch, &message)?
+            }
+            MergeStrategy::Squash => {
+                // Without --base-branch each task branch starts from the
+                // last, so only its own commits are new
+                let since = match previous {
+                    Some(previous) if git::is_ancestor_in(dir, previous, &branch.branch) => {
+                        previous.to_string()
+                    }
+                    _ => git::merge_base_in(dir, base, &branch.branch)?,
+                };
+                let message = commit_message::fallback_message(
+                    config,
+                    &branch.task,
+                    &[],
+                    branch.task.clone(),
+                );
+                git::squash_in(dir, &since, &branch.branch, &message)?
+            }
+            MergeStrategy::Rebase => git::rebase_in(dir, &branch.branch, target)?,
+        };
+        previous = Some(&branch.branch);
+        if conflicts.is_empty() {
+            events::notice(Level::Passed, branch.branch.clone());
+        } else {
+            events::notice(
+                Level::Failed,
+                format!("{} conflicts in {}", branch.branch, conflicts.join(", ")),
+            );
+            conflicted.push(branch);
+        }
+    }
+    Ok(conflicted)
+}
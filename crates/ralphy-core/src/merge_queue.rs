@@ -0,0 +1,218 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::config::RunConfig;
+use crate::events::{self, RunEvent};
+use crate::prd::{PrdSource, Task};
+use crate::{commit_message, contract, git, progress, prompt, pull_request, verify};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// What resolving a merge's conflicts is recorded as.
+pub const RESOLVE_TASK: &str = "Resolve merge conflicts";
+
+/// A parallel task's branch, checked out in its own worktree.
+#[derive(Debug, Clone)]
+pub struct TaskBranch {
+    pub task: String,
+    /// The task as the PRD describes it, for its PR
+    pub spec: Task,
+    pub branch: String,
+    pub dir: PathBuf,
+}
+
+impl TaskBranch {
+    /// Check out a new branch for `task` at `base` in a fresh worktree.
+    pub fn create(spec: &Task, base: &str) -> Result<Self> {
+        let task = spec.name();
+        let slug = git::slugify(&task);
+        let mut name = slug.clone();
+        let mut n = 1;
+        while git::branch_exists(&format!("ralphy/{}", name)) {
+            n += 1;
+            name = format!("{}-{}", slug, n);
+        }
+
+        let dir = worktree_dir(&name);
+        if dir.exists() {
+            // Left behind by a run whose branch has since been deleted
+            git::remove_worktree(&dir)?;
+        }
+
+        let branch = format!("ralphy/{}", name);
+        git::add_worktree(&dir, &branch, base)?;
+        events::publish(RunEvent::BranchCreated {
+            task: task.clone(),
+            branch: branch.clone(),
+        });
+        Ok(Self {
+            task,
+            spec: spec.clone(),
+            branch,
+            dir,
+        })
+    }
+
+    /// Carry on with `task` on `branch`, where an interrupted run left its
+    /// work, in a fresh worktree. Starts a new branch at `base` instead when
+    /// that one is gone.
+    pub fn resume(spec: &Task, branch: &str, base: &str) -> Result<Self> {
+        let Some(name) = branch
+            .strip_prefix("ralphy/")
+            .filter(|_| git::branch_exists(branch))
+        else {
+            return Self::create(spec, base);
+        };
+
+        let dir = worktree_dir(name);
+        if dir.exists() {
+            // What the killed agent left uncommitted can't be trusted
+            git::remove_worktree(&dir)?;
+        }
+        git::checkout_worktree(&dir, branch)?;
+        Ok(Self {
+            task: spec.name(),
+            spec: spec.clone(),
+            branch: branch.to_string(),
+            dir,
+        })
+    }
+
+    /// Commit whatever the agent left uncommitted, undoing its edits to the
+    /// PRD so branches don't conflict over checkboxes; tasks are marked
+    /// complete once their branch is merged.
+    pub fn finish(&self, config: &RunConfig, base: &str) -> Result<()> {
+        if let PrdSource::Markdown { path } | PrdSource::Yaml { path } = &config.prd_source {
+            let relative = prompt::prompt_path(path);
+            if Path::new(&relative).is_relative() {
+                // Fails only if the PRD isn't tracked, in which case the
+                // worktree never had a copy to edit
+                git::restore_in(&self.dir, base, &relative).ok();
+            }
+        }
+        let message = commit_message::fallback_message(
+            config,
+            &self.task,
+            &self.spec.budget_labels(),
+            self.task.clone(),
+        );
+        git::commit_all_in(&self.dir, &message)
+    }
+
+    /// Remove the worktree and branch once the branch has been merged.
+    pub fn remove(&self) -> Result<()> {
+        git::remove_worktree(&self.dir)?;
+        git::delete_branch(&self.branch)
+    }
+
+    /// Remove just the worktree, keeping the branch, once it has been pushed.
+    pub fn remove_worktree(&self) -> Result<()> {
+        git::remove_worktree(&self.dir)
+    }
+}
+
+fn worktree_dir(name: &str) -> PathBuf {
+    PathBuf::from(progress::STATE_DIR)
+        .join("worktrees")
+        .join(name)
+}
+
+/// Push a task branch to origin for review instead of merging it, opening a
+/// PR with `--create-pr` whose body can refer to `response` and to what
+/// changed since `base`. Returns what became of the branch.
+pub fn push(
+    config: &RunConfig,
+    branch: &TaskBranch,
+    base: Option<&str>,
+    response: &AiResponse,
+) -> Result<String> {
+    let root = Path::new(".");
+    git::push_branch_in(root, &branch.branch)?;
+    if config.create_pr {
+        let context = pull_request::Context {
+            task: &branch.spec,
+            response,
+            branch: &branch.branch,
+            dir: &branch.dir,
+            base,
+        };
+        let options = pull_request::options(config, &context)?;
+        let url = git::open_pull_request_in(root, &branch.branch, &branch.spec, &options)?;
+        events::publish(RunEvent::PullRequestOpened {
+            task: branch.task.clone(),
+            branch: branch.branch.clone(),
+            url,
+        });
+    }
+    Ok(format!("Pushed {}", branch.branch))
+}
+
+/// Merge a task branch into the current branch, asking the engine to resolve
+/// any conflicts, then run the verification command. A merge that can't be
+/// resolved or fails verification is undone.
+pub async fn merge(config: &RunConfig, branch: &TaskBranch) -> Result<()> {
+    let before = git::head_commit()?;
+    // commitlint skips merge commits it recognises by their message
+    let message = if config.conventional_commits {
+        format!("Merge branch '{}'", branch.branch)
+    } else {
+        format!("Merge task: {}", branch.task)
+    };
+    if !git::merge_branch(&branch.branch, &message)? {
+        resolve_conflicts(config, branch).await.inspect_err(|_| {
+            git::abort_merge().ok();
+        })?;
+    }
+
+    if let Err(e) = verify::check(config, Path::new(".")) {
+        git::reset_keep(&before)?;
+        return Err(e.context(format!(
+            "Verification failed after merging {}",
+            branch.branch
+        )));
+    }
+    Ok(())
+}
+
+async fn resolve_conflicts(config: &RunConfig, branch: &TaskBranch) -> Result<()> {
+    let conflicts = git::unmerged_paths()?;
+    events::warn(format!(
+        "Conflicts merging {} in {}, asking {} to resolve them",
+        branch.branch,
+        conflicts.join(", "),
+        config.ai_engine
+    ));
+
+    let executor = AiExecutor::new(config.ai_engine)
+        .with_backend(config.backend)
+        .with_rate_limit(config.rate_limit)
+        .with_model(config.model.as_deref())
+        .with_extra_args(config.engine_args(config.ai_engine))
+        .with_task(&branch.task);
+    crate::execute_with_contract(&executor, &conflict_prompt(branch, &conflicts)).await?;
+
+    let remaining = git::unmerged_paths()?;
+    if !remaining.is_empty() {
+        anyhow::bail!("Conflicts left unresolved in {}", remaining.join(", "));
+    }
+    if git::merge_in_progress() {
+        git::conclude_merge()?;
+    }
+    Ok(())
+}
+
+/// Prompt asking the engine to finish a merge that stopped on conflicts.
+pub fn conflict_prompt(branch: &TaskBranch, conflicts: &[String]) -> String {
+    format!(
+        "A merge of branch {} into the current branch stopped with conflicts in:\n{}\n\n\
+         The branch implements this task:\n{}\n\n\
+         Resolve every conflict so that both the current branch's changes and the task's changes are kept. \
+         Remove all conflict markers, run `git add` on each resolved file, and do not commit or abort the merge.{}",
+        branch.branch,
+        conflicts
+            .iter()
+            .map(|file| format!("- {}", file))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        branch.task,
+        contract::STATUS_INSTRUCTIONS
+    )
+}
@@ -0,0 +1,76 @@
+//! Hooks for showing tasks while they run. The CLI draws a spinner for each
+//! task run on its own and a `--tui` table for each parallel batch; a run
+//! given [`NoMonitor`] draws nothing and only publishes its events.
+
+use crate::ai::{AiEngine, AiResponse, StepSender, UsageSender};
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::sync::watch;
+
+/// Resolves once a monitor has drawn its final state.
+pub type Finished = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Shows the run's tasks as they go.
+pub trait Monitor: Send + Sync {
+    /// Start showing `task`, run on its own, following the steps its engine
+    /// reports on `steps`.
+    fn task(
+        &self,
+        task: &str,
+        engine: AiEngine,
+        steps: watch::Receiver<String>,
+    ) -> Box<dyn TaskMonitor>;
+
+    /// Start a table with room for `slots` parallel agents. Fails when
+    /// there's nowhere to draw one.
+    fn batch(&self, engine: AiEngine, slots: usize) -> Result<Box<dyn BatchMonitor>>;
+}
+
+/// A task being shown on its own.
+pub trait TaskMonitor: Send {
+    /// Stop following the task, leaving whether it succeeded.
+    fn finish(self: Box<Self>, success: bool) -> Finished;
+}
+
+/// A table of the agents in a parallel batch.
+pub trait BatchMonitor: Send + Sync {
+    /// Add a row for `task`, starting its clock.
+    fn add(&self, task: &str) -> Box<dyn AgentMonitor>;
+
+    /// Draw the final state of every row and stop.
+    fn finish(self: Box<Self>) -> Finished;
+}
+
+/// One agent's row in a [`BatchMonitor`].
+pub trait AgentMonitor: Send + Sync {
+    /// Where the agent's executor reports its current step
+    fn steps(&self) -> StepSender;
+
+    /// Where the agent's executor reports tokens as they stream
+    fn usage(&self) -> UsageSender;
+
+    /// Stop the row's clock, showing the response's final usage when the
+    /// agent succeeded.
+    fn finish(&self, response: Option<&AiResponse>);
+}
+
+/// Draws nothing, for runs without a terminal of their own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMonitor;
+
+impl Monitor for NoMonitor {
+    fn task(&self, _: &str, _: AiEngine, _: watch::Receiver<String>) -> Box<dyn TaskMonitor> {
+        Box::new(NoMonitor)
+    }
+
+    fn batch(&self, _: AiEngine, _: usize) -> Result<Box<dyn BatchMonitor>> {
+        anyhow::bail!("--tui needs a terminal; showing plain output instead")
+    }
+}
+
+impl TaskMonitor for NoMonitor {
+    fn finish(self: Box<Self>, _: bool) -> Finished {
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,217 @@
+use crate::ai::{AiResponse, RateLimiter};
+use crate::tools::{self, ChatApi, Reply, ToolCall, Workspace};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+/// Where the Ollama server listens unless `OLLAMA_HOST` says otherwise.
+pub const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// Model used unless `OLLAMA_MODEL` names another. It must support tool
+/// calling.
+pub const DEFAULT_MODEL: &str = "qwen2.5-coder";
+
+/// How to reach the local Ollama server, from the environment.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub host: String,
+    pub model: String,
+    /// Context window to load the model with (`OLLAMA_NUM_CTX`); Ollama's
+    /// default is often too small for a task prompt plus tool output
+    pub num_ctx: Option<u32>,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let num_ctx = var("OLLAMA_NUM_CTX")
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("OLLAMA_NUM_CTX is not a number: {}", value))
+            })
+            .transpose()?;
+        Ok(Self {
+            host: host_url(&var("OLLAMA_HOST").unwrap_or_else(|| DEFAULT_HOST.to_string())),
+            model: var("OLLAMA_MODEL").unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            num_ctx,
+        })
+    }
+
+    fn request(&self, system: &str, messages: &[Value]) -> Value {
+        let system = json!({"role": "system", "content": system});
+        let messages: Vec<&Value> = std::iter::once(&system).chain(messages).collect();
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "tools": tools::function_tools(),
+            "stream": false,
+        });
+        if let Some(num_ctx) = self.num_ctx {
+            body["options"] = json!({"num_ctx": num_ctx});
+        }
+        body
+    }
+}
+
+/// `OLLAMA_HOST` as the `ollama` CLI reads it, where the scheme is optional
+/// (`0.0.0.0:11434`), turned into a base URL.
+fn host_url(host: &str) -> String {
+    let host = host.trim_end_matches('/');
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+/// Work on `prompt` with a local model, carrying out its tool calls in
+/// `workspace` until it answers without any, each request waiting its turn
+/// under `limiter`. `report_step` hears what the
+/// model is doing as it goes.
+pub async fn run(
+    settings: &Settings,
+    workspace: &Workspace,
+    limiter: &RateLimiter,
+    prompt: &str,
+    report_step: impl Fn(&str),
+) -> Result<AiResponse> {
+    let mut response = tools::run_agent(settings, workspace, limiter, prompt, report_step).await?;
+    // Local models cost nothing to run
+    response.actual_cost = Some(0.0);
+    Ok(response)
+}
+
+impl ChatApi for Settings {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        system: &str,
+        messages: &[Value],
+    ) -> Result<Reply> {
+        let http = client
+            .post(format!("{}/api/chat", self.host))
+            .json(&self.request(system, messages))
+            .send()
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to reach Ollama at {} (is `ollama serve` running?)",
+                    self.host
+                )
+            })?;
+
+        let status = http.status();
+        let text = http.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|json| json["error"].as_str().map(str::to_string))
+                .unwrap_or(text);
+            anyhow::bail!("Ollama returned {}: {}", status, message);
+        }
+        let reply: Value =
+            serde_json::from_str(&text).context("Ollama sent a response that isn't JSON")?;
+        Ok(self.reply(&reply))
+    }
+
+    fn tool_results(&self, results: Vec<(&ToolCall, Result<String>)>) -> Vec<Value> {
+        results
+            .into_iter()
+            .map(|(call, result)| {
+                json!({
+                    "role": "tool",
+                    "tool_name": call.name,
+                    "content": result.unwrap_or_else(|e| format!("Error: {:#}", e)),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Settings {
+    /// The model's message in a chat reply.
+    fn reply(&self, reply: &Value) -> Reply {
+        let message = &reply["message"];
+        let calls = message["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|call| {
+                let function = &call["function"];
+                // Arguments usually arrive as an object, but some models send a string
+                let input = match &function["arguments"] {
+                    Value::String(text) => serde_json::from_str(text)
+                        .map_err(|e| anyhow::anyhow!("Arguments are not valid JSON: {}", e)),
+                    arguments => Ok(arguments.clone()),
+                };
+                ToolCall {
+                    id: Value::Null,
+                    name: function["name"].as_str().unwrap_or("").to_string(),
+                    input,
+                }
+            })
+            .collect();
+        let (input_tokens, output_tokens) = usage(reply);
+        Reply {
+            message: message.clone(),
+            text: message["content"].as_str().unwrap_or("").to_string(),
+            calls,
+            input_tokens,
+            output_tokens,
+            model: Some(self.model.clone()),
+        }
+    }
+}
+
+/// Prompt and reply tokens from a chat reply's evaluation counts.
+fn usage(reply: &Value) -> (usize, usize) {
+    let count = |field: &str| reply[field].as_u64().unwrap_or(0) as usize;
+    (count("prompt_eval_count"), count("eval_count"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_url() {
+        assert_eq!(host_url(DEFAULT_HOST), DEFAULT_HOST);
+        assert_eq!(host_url("0.0.0.0:11434"), "http://0.0.0.0:11434");
+        assert_eq!(
+            host_url("https://ollama.internal/"),
+            "https://ollama.internal"
+        );
+    }
+
+    #[test]
+    fn test_request_and_usage() {
+        let settings = Settings {
+            host: DEFAULT_HOST.to_string(),
+            model: "llama3.1".to_string(),
+            num_ctx: Some(32768),
+        };
+        let body = settings.request("Be brief", &[json!({"role": "user", "content": "hi"})]);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["options"]["num_ctx"], 32768);
+        assert_eq!(body["tools"][1]["function"]["name"], tools::EDITOR_TOOL);
+
+        let reply = json!({"message": {"role": "assistant", "content": "ok"}, "done": true, "prompt_eval_count": 812, "eval_count": 36});
+        assert_eq!(usage(&reply), (812, 36));
+        assert_eq!(usage(&json!({"done": true})), (0, 0));
+
+        let reply = settings.reply(
+            &json!({"message": {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "bash", "arguments": {"command": "ls"}}},
+                {"function": {"name": "bash", "arguments": "{\"command\": \"pwd\"}"}}
+            ]}}),
+        );
+        assert_eq!(reply.calls[0].input.as_ref().unwrap()["command"], "ls");
+        assert_eq!(reply.calls[1].input.as_ref().unwrap()["command"], "pwd");
+        assert_eq!(reply.model.as_deref(), Some("llama3.1"));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::ai::{AiResponse, Fatal, RateLimited, RateLimiter};
+use crate::tools::{self, ChatApi, Reply, ToolCall, Workspace};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+/// Where requests go unless `OPENAI_BASE_URL` says otherwise.
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Model used unless `OPENAI_MODEL` names another.
+pub const DEFAULT_MODEL: &str = "gpt-4.1";
+
+/// Environment variable holding the API key.
+pub const API_KEY_VAR: &str = "OPENAI_API_KEY";
+
+/// How to reach the Chat Completions API, from the environment.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+    /// Sampling temperature (`OPENAI_TEMPERATURE`); the API's default if unset
+    pub temperature: Option<f64>,
+    /// Most tokens the model may write per turn (`OPENAI_MAX_TOKENS`)
+    pub max_tokens: Option<u32>,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let api_key =
+            var(API_KEY_VAR).ok_or_else(|| Fatal(format!("{} is not set", API_KEY_VAR)))?;
+        let temperature = var("OPENAI_TEMPERATURE")
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("OPENAI_TEMPERATURE is not a number: {}", value))
+            })
+            .transpose()?;
+        let max_tokens = var("OPENAI_MAX_TOKENS")
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("OPENAI_MAX_TOKENS is not a number: {}", value))
+            })
+            .transpose()?;
+        Ok(Self {
+            api_key,
+            base_url: var("OPENAI_BASE_URL")
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model: var("OPENAI_MODEL").unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            temperature,
+            max_tokens,
+        })
+    }
+
+    fn request(&self, system: &str, messages: &[Value]) -> Value {
+        let system = json!({"role": "system", "content": system});
+        let messages: Vec<&Value> = std::iter::once(&system).chain(messages).collect();
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "tools": tools::function_tools(),
+        });
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            body["max_completion_tokens"] = json!(max_tokens);
+        }
+        body
+    }
+}
+
+/// Work on `prompt` through Chat Completions, carrying out the model's
+/// function calls in `workspace` until it answers without any. Requests
+/// wait their turn under `limiter`, which retries them when rate limited.
+/// `report_step` hears what the model is doing as it goes.
+pub async fn run(
+    settings: &Settings,
+    workspace: &Workspace,
+    limiter: &RateLimiter,
+    prompt: &str,
+    report_step: impl Fn(&str),
+) -> Result<AiResponse> {
+    tools::run_agent(settings, workspace, limiter, prompt, report_step).await
+}
+
+impl ChatApi for Settings {
+    fn name(&self) -> &'static str {
+        "OpenAI API"
+    }
+
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        system: &str,
+        messages: &[Value],
+    ) -> Result<Reply> {
+        let http = client
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&self.request(system, messages))
+            .send()
+            .await
+            .context("Failed to reach the OpenAI API")?;
+
+        let status = http.status();
+        let text = http.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let json = serde_json::from_str::<Value>(&text).ok();
+            let code = json
+                .as_ref()
+                .and_then(|json| json["error"]["code"].as_str());
+            // Out of credit, the account stays that way however long it waits
+            let out_of_quota = code == Some("insufficient_quota");
+            let message = json
+                .as_ref()
+                .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+                .unwrap_or(text);
+            let error = format!("OpenAI API returned {}: {}", status, message);
+            match status.as_u16() {
+                // A bad key stays bad however often the request is retried
+                401 | 403 => return Err(Fatal(error).into()),
+                429 if out_of_quota => return Err(Fatal(error).into()),
+                429 => return Err(RateLimited(error).into()),
+                _ => anyhow::bail!(error),
+            }
+        }
+        let completion: Value =
+            serde_json::from_str(&text).context("OpenAI API sent a response that isn't JSON")?;
+        Ok(reply(&completion))
+    }
+
+    fn tool_results(&self, results: Vec<(&ToolCall, Result<String>)>) -> Vec<Value> {
+        results
+            .into_iter()
+            .map(|(call, result)| {
+                json!({
+                    "role": "tool",
+                    "tool_call_id": call.id,
+                    "content": result.unwrap_or_else(|e| format!("Error: {:#}", e)),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The model's reply in a chat completion.
+fn reply(completion: &Value) -> Reply {
+    let message = &completion["choices"][0]["message"];
+    let calls = message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|call| {
+            let function = &call["function"];
+            ToolCall {
+                id: call["id"].clone(),
+                name: function["name"].as_str().unwrap_or("").to_string(),
+                input: serde_json::from_str(function["arguments"].as_str().unwrap_or("{}"))
+                    .map_err(|e| anyhow::anyhow!("Arguments are not valid JSON: {}", e)),
+            }
+        })
+        .collect();
+    let (input_tokens, output_tokens) = usage(&completion["usage"]).unwrap_or_default();
+    Reply {
+        message: message.clone(),
+        text: message["content"].as_str().unwrap_or("").to_string(),
+        calls,
+        input_tokens,
+        output_tokens,
+        model: completion["model"].as_str().map(str::to_string),
+    }
+}
+
+/// Prompt and completion tokens from a usage object.
+fn usage(usage: &Value) -> Option<(usize, usize)> {
+    let input = usage["prompt_tokens"].as_u64()?;
+    let output = usage["completion_tokens"].as_u64().unwrap_or(0);
+    Some((input as usize, output as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            api_key: "key".to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            model: "gpt-test".to_string(),
+            temperature: None,
+            max_tokens: None,
+        }
+    }
+
+    #[test]
+    fn test_request_includes_only_configured_options() {
+        let messages = [json!({"role": "user", "content": "hi"})];
+        let body = settings().request("Be brief", &messages);
+        assert_eq!(body["model"], "gpt-test");
+        assert_eq!(body["messages"][0]["content"], "Be brief");
+        assert_eq!(body["messages"][1]["content"], "hi");
+        assert_eq!(body["tools"][0]["function"]["name"], tools::BASH_TOOL);
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("max_completion_tokens").is_none());
+
+        let tuned = Settings {
+            temperature: Some(0.2),
+            max_tokens: Some(4096),
+            ..settings()
+        };
+        let body = tuned.request("", &messages);
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["max_completion_tokens"], 4096);
+    }
+
+    #[test]
+    fn test_reply_reads_tool_calls() {
+        let completion = json!({
+            "model": "gpt-test-2025",
+            "choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "c1", "type": "function", "function": {"name": "bash", "arguments": "{\"command\": \"ls\"}"}},
+                {"id": "c2", "type": "function", "function": {"name": "bash", "arguments": "{oops"}}
+            ]}}],
+            "usage": {"prompt_tokens": 50, "completion_tokens": 5}
+        });
+        let reply = reply(&completion);
+        assert_eq!(reply.text, "");
+        assert_eq!(reply.calls[0].id, "c1");
+        assert_eq!(reply.calls[0].input.as_ref().unwrap()["command"], "ls");
+        assert!(reply.calls[1].input.is_err());
+        assert_eq!((reply.input_tokens, reply.output_tokens), (50, 5));
+        assert_eq!(reply.model.as_deref(), Some("gpt-test-2025"));
+    }
+
+    #[test]
+    fn test_usage() {
+        let reported = json!({"prompt_tokens": 900, "completion_tokens": 40, "total_tokens": 940});
+        assert_eq!(usage(&reported), Some((900, 40)));
+        assert_eq!(usage(&Value::Null), None);
+    }
+}
@@ -0,0 +1,1515 @@
+use crate::github::{self, GithubApi};
+use crate::jira::{self, JiraApi};
+use crate::linear::{self, LinearApi};
+use crate::progress;
+use crate::retry::output_with_retry_async;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Debug, Clone)]
+pub enum PrdSource {
+    Markdown {
+        path: PathBuf,
+    },
+    Yaml {
+        path: PathBuf,
+    },
+    GitHub {
+        repo: String,
+        label: Option<String>,
+        /// Issue authors allowed to supply tasks; empty allows anyone
+        authors: Vec<String>,
+    },
+    Jira {
+        /// Project key, e.g. `PAY`
+        project: String,
+        /// Extra JQL the project's open issues must also match
+        jql: Option<String>,
+    },
+    Linear {
+        /// Team key, e.g. `ENG`
+        team: String,
+        label: Option<String>,
+    },
+}
+
+impl PrdSource {
+    pub fn display_name(&self) -> String {
+        match self {
+            PrdSource::Markdown { path } => path.display().to_string(),
+            PrdSource::Yaml { path } => path.display().to_string(),
+            PrdSource::GitHub { repo, label, .. } => {
+                if let Some(label) = label {
+                    format!("{} (label: {})", repo, label)
+                } else {
+                    repo.clone()
+                }
+            }
+            PrdSource::Jira { project, jql } => {
+                if let Some(jql) = jql {
+                    format!("Jira {} (jql: {})", project, jql)
+                } else {
+                    format!("Jira {}", project)
+                }
+            }
+            PrdSource::Linear { team, label } => {
+                if let Some(label) = label {
+                    format!("Linear {} (label: {})", team, label)
+                } else {
+                    format!("Linear {}", team)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub title: String,
+    #[serde(default)]
+    pub completed: bool,
+    /// Claimed by a run that is working on it, so no other run picks it up
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub in_progress: bool,
+    #[serde(default)]
+    pub parallel_group: usize,
+    /// Free-form labels, e.g. for budgeting with `--budget experimental=2`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Files, directories or globs the task is expected to touch, used to
+    /// keep conflicting tasks out of the same parallel batch
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Same as `files`, for PRDs that describe ownership rather than edits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owns: Vec<String>,
+    /// Repository the task runs in, as a git URL or a path relative to the
+    /// YAML file, when it isn't the current one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Titles of tasks that must be complete before this one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// What the task is about, beyond its title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Conditions the work has to meet to count as done
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acceptance_criteria: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    /// Added to the end of this task's prompt, e.g. files to look at or
+    /// constraints to keep to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_instructions: Option<String>,
+    /// The issue the task was read from, for tasks from an issue tracker
+    #[serde(skip)]
+    pub origin: Option<TaskOrigin>,
+}
+
+/// Where a task stands in the PRD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Pending,
+    /// Claimed by a run; in Markdown, `- [~]`
+    InProgress,
+    Done,
+}
+
+impl TaskState {
+    /// The character between a markdown checkbox's brackets
+    fn mark(self) -> char {
+        match self {
+            TaskState::Pending => ' ',
+            TaskState::InProgress => '~',
+            TaskState::Done => 'x',
+        }
+    }
+}
+
+/// The issue in a tracker a task stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskOrigin {
+    GitHub { number: u64 },
+    Jira { key: String },
+    Linear { identifier: String },
+}
+
+impl TaskOrigin {
+    /// The issue's number or key, as the tracker shows it
+    pub fn id(&self) -> String {
+        match self {
+            TaskOrigin::GitHub { number } => number.to_string(),
+            TaskOrigin::Jira { key } => key.clone(),
+            TaskOrigin::Linear { identifier } => identifier.clone(),
+        }
+    }
+}
+
+/// How urgent a task is, as the PRD author sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Critical => "critical",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The parts of a task that tell the agent more than its title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskDetails {
+    pub description: Option<String>,
+    pub acceptance_criteria: Vec<String>,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    pub extra_instructions: Option<String>,
+}
+
+impl TaskDetails {
+    pub fn is_empty(&self) -> bool {
+        self == &TaskDetails::default()
+    }
+}
+
+impl Task {
+    /// An incomplete task with nothing but a title.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            completed: false,
+            in_progress: false,
+            parallel_group: 0,
+            tags: Vec::new(),
+            files: Vec::new(),
+            owns: Vec::new(),
+            repo: None,
+            depends_on: Vec::new(),
+            description: None,
+            acceptance_criteria: Vec::new(),
+            priority: None,
+            extra_instructions: None,
+            origin: None,
+        }
+    }
+
+    pub fn state(&self) -> TaskState {
+        match (self.completed, self.in_progress) {
+            (true, _) => TaskState::Done,
+            (false, true) => TaskState::InProgress,
+            (false, false) => TaskState::Pending,
+        }
+    }
+
+    fn set_state(&mut self, state: TaskState) {
+        self.completed = state == TaskState::Done;
+        self.in_progress = state == TaskState::InProgress;
+    }
+
+    fn from_issue(title: impl Into<String>, origin: TaskOrigin) -> Self {
+        Self {
+            origin: Some(origin),
+            ..Self::new(title)
+        }
+    }
+
+    /// What the rest of Ralphy calls the task: its title, prefixed with the
+    /// issue's id (`12:Fix login`) for tasks from an issue tracker.
+    pub fn name(&self) -> String {
+        match self.origin {
+            Some(ref origin) => format!("{}:{}", origin.id(), self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Names this task's spending counts against: its tags, plus
+    /// `group:N` when it belongs to a parallel group.
+    pub fn budget_labels(&self) -> Vec<String> {
+        let mut labels = self.tags.clone();
+        if self.parallel_group > 0 {
+            labels.push(format!("group:{}", self.parallel_group));
+        }
+        labels
+    }
+
+    /// Everything the task declares it touches, from `files` and `owns`
+    pub fn ownership(&self) -> Vec<String> {
+        self.files.iter().chain(&self.owns).cloned().collect()
+    }
+
+    pub fn details(&self) -> TaskDetails {
+        TaskDetails {
+            description: self
+                .description
+                .as_deref()
+                .map(str::trim)
+                .filter(|description| !description.is_empty())
+                .map(str::to_string),
+            acceptance_criteria: self.acceptance_criteria.clone(),
+            priority: self.priority,
+            tags: self.tags.clone(),
+            extra_instructions: self
+                .extra_instructions
+                .as_deref()
+                .map(str::trim)
+                .filter(|instructions| !instructions.is_empty())
+                .map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YamlTasks {
+    pub tasks: Vec<Task>,
+}
+
+/// The state of the PRD as of a single read. Tasks are looked up by
+/// [`Task::name`].
+#[derive(Debug, Clone, Default)]
+pub struct PrdSnapshot {
+    /// Incomplete tasks, in PRD order
+    pub tasks: Vec<Task>,
+    /// Number of completed tasks
+    pub completed: usize,
+    /// Number of tasks claimed by a run, which are left out of `tasks`
+    pub in_progress: usize,
+    /// Repository of each incomplete task that runs outside the current one
+    /// (YAML only), with local paths resolved
+    pub repos: HashMap<String, String>,
+    /// Incomplete tasks each incomplete task still waits on (YAML only)
+    pub deps: HashMap<String, Vec<String>>,
+}
+
+impl PrdSnapshot {
+    pub fn next_task(&self) -> Option<&Task> {
+        self.tasks.first()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Names of the incomplete tasks, in PRD order
+    pub fn names(&self) -> Vec<String> {
+        self.tasks.iter().map(Task::name).collect()
+    }
+
+    /// The incomplete task called `name`
+    pub fn task(&self, name: &str) -> Option<&Task> {
+        self.tasks.iter().find(|t| t.name() == name)
+    }
+
+    pub fn labels_of(&self, task: &str) -> Vec<String> {
+        self.task(task).map(Task::budget_labels).unwrap_or_default()
+    }
+
+    pub fn files_of(&self, task: &str) -> Vec<String> {
+        self.task(task).map(Task::ownership).unwrap_or_default()
+    }
+
+    pub fn repo_of(&self, task: &str) -> Option<&str> {
+        self.repos.get(task).map(String::as_str)
+    }
+
+    pub fn deps_of(&self, task: &str) -> &[String] {
+        self.deps.get(task).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Look up an incomplete task by exact name, falling back to a
+    /// case-insensitive substring that matches only one task.
+    pub fn find_task(&self, name: &str) -> Option<&Task> {
+        match self.matching_tasks(name)[..] {
+            [task] => Some(task),
+            _ => None,
+        }
+    }
+
+    /// Incomplete tasks named `pattern` exactly or, failing that, with
+    /// `pattern` anywhere in their name, ignoring case.
+    pub fn matching_tasks(&self, pattern: &str) -> Vec<&Task> {
+        if let Some(task) = self.task(pattern) {
+            return vec![task];
+        }
+
+        let needle = pattern.to_lowercase();
+        self.tasks
+            .iter()
+            .filter(|t| t.name().to_lowercase().contains(&needle))
+            .collect()
+    }
+}
+
+pub struct PrdManager {
+    source: PrdSource,
+    /// Where each task was (markdown line or YAML index) as of the last load
+    locations: Mutex<Locations>,
+    /// Last loaded snapshot, served to queries until refreshed or invalidated
+    snapshot: Mutex<Option<PrdSnapshot>>,
+    /// ETag-caching client for the GitHub source
+    github: GithubApi,
+    /// Held for each read-modify-write of the PRD, so parallel agents
+    /// finishing at once don't drop each other's updates
+    writes: tokio::sync::Mutex<()>,
+}
+
+/// Held for a read-modify-write of the PRD: the in-process lock, and for a
+/// PRD file the advisory lock that keeps other runs out too.
+struct WriteLock<'a> {
+    _writes: tokio::sync::MutexGuard<'a, ()>,
+    _file: Option<fs::File>,
+}
+
+impl PrdManager {
+    pub fn new(source: PrdSource) -> Self {
+        Self {
+            source,
+            locations: Mutex::new(Locations::default()),
+            snapshot: Mutex::new(None),
+            github: GithubApi::new(),
+            writes: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Take the locks for a read-modify-write of the PRD.
+    async fn lock_writes(&self) -> Result<WriteLock<'_>> {
+        let writes = self.writes.lock().await;
+        let file = match &self.source {
+            PrdSource::Markdown { path } | PrdSource::Yaml { path } => {
+                let path = path.clone();
+                Some(tokio::task::spawn_blocking(move || lock_prd_file(&path)).await??)
+            }
+            _ => None,
+        };
+        Ok(WriteLock {
+            _writes: writes,
+            _file: file,
+        })
+    }
+
+    fn remember_locations(&self, locations: Locations) {
+        *self.locations.lock().unwrap() = locations;
+    }
+
+    /// The task's position at load time and the titles at every position then
+    fn location_of(&self, task: &str) -> (Option<usize>, Vec<Option<String>>) {
+        let locations = self.locations.lock().unwrap();
+        (
+            locations.positions.get(task).copied(),
+            locations.titles.clone(),
+        )
+    }
+
+    /// Re-read the PRD source and cache the result for subsequent queries
+    pub async fn refresh(&self) -> Result<PrdSnapshot> {
+        let snapshot = match &self.source {
+            PrdSource::Markdown { path } => self.load_markdown(path)?,
+            PrdSource::Yaml { path } => self.load_yaml(path)?,
+            PrdSource::GitHub {
+                repo,
+                label,
+                authors,
+            } => self.load_github(repo, label.as_deref(), authors).await?,
+            PrdSource::Jira { project, jql } => self.load_jira(project, jql.as_deref()).await?,
+            PrdSource::Linear { team, label } => self.load_linear(team, label.as_deref()).await?,
+        };
+        *self.snapshot.lock().unwrap() = Some(snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// The cached snapshot, loading it if nothing is cached yet
+    pub async fn snapshot(&self) -> Result<PrdSnapshot> {
+        let cached = self.snapshot.lock().unwrap().clone();
+        match cached {
+            Some(snapshot) => Ok(snapshot),
+            None => self.refresh().await,
+        }
+    }
+
+    /// Drop the cached snapshot so the next query re-reads the source
+    pub fn invalidate(&self) {
+        *self.snapshot.lock().unwrap() = None;
+    }
+
+    /// Get all incomplete tasks
+    pub async fn get_tasks(&self) -> Result<Vec<Task>> {
+        Ok(self.snapshot().await?.tasks)
+    }
+
+    /// Get the next incomplete task
+    pub async fn get_next_task(&self) -> Result<Option<Task>> {
+        Ok(self.snapshot().await?.next_task().cloned())
+    }
+
+    /// Count remaining tasks
+    pub async fn count_remaining(&self) -> Result<usize> {
+        Ok(self.snapshot().await?.remaining())
+    }
+
+    /// Count completed tasks
+    pub async fn count_completed(&self) -> Result<usize> {
+        Ok(self.snapshot().await?.completed)
+    }
+
+    /// Mark a task as complete
+    pub async fn mark_complete(&self, task: &str) -> Result<()> {
+        const OPEN: &[TaskState] = &[TaskState::Pending, TaskState::InProgress];
+        let _writing = self.lock_writes().await?;
+        let result = match &self.source {
+            PrdSource::Markdown { path } => self
+                .move_markdown(path, task, OPEN, TaskState::Done)
+                .map(drop),
+            PrdSource::Yaml { path } => self.move_yaml(path, task, OPEN, TaskState::Done).map(drop),
+            PrdSource::GitHub { repo, .. } => self.mark_github_complete(repo, task).await,
+            PrdSource::Jira { .. } => mark_jira_complete(task).await,
+            PrdSource::Linear { team, .. } => mark_linear_complete(team, task).await,
+        };
+        self.invalidate();
+        result
+    }
+
+    /// Claim a task for this run before it is dispatched, so neither a
+    /// restart nor another run against the same PRD picks it up too.
+    ///
+    /// Returns false when the task is no longer pending, e.g. another run
+    /// claimed it since the PRD was read.
+    pub async fn mark_started(&self, task: &str) -> Result<bool> {
+        let _writing = self.lock_writes().await?;
+        let result = match &self.source {
+            PrdSource::Markdown { path } => {
+                self.move_markdown(path, task, &[TaskState::Pending], TaskState::InProgress)
+            }
+            PrdSource::Yaml { path } => {
+                self.move_yaml(path, task, &[TaskState::Pending], TaskState::InProgress)
+            }
+            PrdSource::GitHub { repo, .. } => self.claim_github_issue(repo, task).await,
+            PrdSource::Jira { .. } => {
+                let key = issue_id(task)?;
+                JiraApi::from_env()?
+                    .mark_started(key)
+                    .await
+                    .with_context(|| format!("Failed to move {} to In Progress", key))
+            }
+            PrdSource::Linear { team, .. } => {
+                let id = issue_id(task)?;
+                LinearApi::from_env()?
+                    .mark_started(team, id)
+                    .await
+                    .with_context(|| format!("Failed to move {} to In Progress", id))
+            }
+        };
+        self.invalidate();
+        result
+    }
+
+    /// Hand a claimed task back, e.g. after it failed or the run was
+    /// interrupted, so the next run picks it up again. A task the agent
+    /// checked off itself is unchecked, since it never got through.
+    pub async fn release(&self, task: &str) -> Result<()> {
+        const CLAIMED: &[TaskState] = &[TaskState::InProgress, TaskState::Done];
+        let _writing = self.lock_writes().await?;
+        let result = match &self.source {
+            PrdSource::Markdown { path } => self
+                .move_markdown(path, task, CLAIMED, TaskState::Pending)
+                .map(drop),
+            PrdSource::Yaml { path } => self
+                .move_yaml(path, task, CLAIMED, TaskState::Pending)
+                .map(drop),
+            PrdSource::GitHub { repo, .. } => {
+                edit_github_labels(repo, task, "--remove-label").await
+            }
+            PrdSource::Jira { .. } => {
+                let key = issue_id(task)?;
+                JiraApi::from_env()?
+                    .mark_unstarted(key)
+                    .await
+                    .with_context(|| format!("Failed to move {} back to To Do", key))
+            }
+            PrdSource::Linear { team, .. } => {
+                let id = issue_id(task)?;
+                LinearApi::from_env()?
+                    .mark_unstarted(team, id)
+                    .await
+                    .with_context(|| format!("Failed to move {} back to Todo", id))
+            }
+        };
+        self.invalidate();
+        result
+    }
+
+    /// Add new incomplete tasks at the end of the PRD
+    pub async fn add_tasks(&self, titles: &[String]) -> Result<()> {
+        let _writing = self.lock_writes().await?;
+        let result = match &self.source {
+            PrdSource::Markdown { path } => add_markdown_tasks(path, titles),
+            PrdSource::Yaml { path } => add_yaml_tasks(path, titles.iter().map(Task::new)),
+            PrdSource::GitHub { repo, label, .. } => {
+                add_github_tasks(repo, label.as_deref(), titles).await
+            }
+            PrdSource::Jira { project, .. } => add_jira_tasks(project, titles).await,
+            PrdSource::Linear { team, label } => {
+                add_linear_tasks(team, label.as_deref(), titles).await
+            }
+        };
+        self.invalidate();
+        result
+    }
+
+    /// Add one task at the end of the PRD. Only YAML task files keep more
+    /// than its title.
+    pub async fn add_task(&self, task: Task) -> Result<()> {
+        let PrdSource::Yaml { path } = &self.source else {
+            return self.add_tasks(&[task.title]).await;
+        };
+        let _writing = self.lock_writes().await?;
+        let result = add_yaml_tasks(path, [task]);
+        self.invalidate();
+        result
+    }
+
+    /// Get tasks by parallel group (YAML only)
+    pub async fn get_tasks_in_group(&self, group: usize) -> Result<Vec<String>> {
+        match &self.source {
+            PrdSource::Yaml { path } => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read YAML file: {}", path.display()))?;
+                let yaml_tasks: YamlTasks =
+                    serde_yaml::from_str(&content).with_context(|| "Failed to parse YAML")?;
+
+                Ok(yaml_tasks
+                    .tasks
+                    .into_iter()
+                    .filter(|t| !t.completed && t.parallel_group == group)
+                    .map(|t| t.title)
+                    .collect())
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    // ============================================
+    // MARKDOWN IMPLEMENTATION
+    // ============================================
+
+    fn load_markdown(&self, path: &PathBuf) -> Result<PrdSnapshot> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read PRD file: {}", path.display()))?;
+
+        let mut locations = Locations::default();
+        let mut completed = 0;
+        let mut in_progress = 0;
+        let mut tasks = Vec::new();
+
+        for (idx, line) in content.lines().enumerate() {
+            let entry = checkbox(line);
+            locations
+                .titles
+                .push(entry.as_ref().map(|(_, title)| title.clone()));
+            match entry {
+                Some((TaskState::Pending, title)) => {
+                    locations.positions.entry(title.clone()).or_insert(idx);
+                    tasks.push(Task::new(title));
+                }
+                Some((TaskState::InProgress, title)) => {
+                    // Still located, so the run that claimed it can finish it
+                    locations.positions.entry(title).or_insert(idx);
+                    in_progress += 1;
+                }
+                Some((TaskState::Done, _)) => completed += 1,
+                None => {}
+            }
+        }
+
+        self.remember_locations(locations);
+        Ok(PrdSnapshot {
+            tasks,
+            completed,
+            in_progress,
+            repos: HashMap::new(),
+            deps: HashMap::new(),
+        })
+    }
+
+    /// Move a task's checkbox to `to` if it is in one of the `from` states.
+    /// Returns whether it moved.
+    fn move_markdown(
+        &self,
+        path: &PathBuf,
+        task: &str,
+        from: &[TaskState],
+        to: TaskState,
+    ) -> Result<bool> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read PRD file: {}", path.display()))?;
+
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+        let state_of = |line: &str| checkbox(line).map(|(state, _)| state);
+        let titles: Vec<Option<String>> = lines
+            .iter()
+            .map(|line| checkbox(line).map(|(_, title)| title))
+            .collect();
+        let (recorded, loaded) = self.location_of(task);
+        let target = locate(task, recorded, &loaded, &titles, |idx| {
+            state_of(&lines[idx]).is_some_and(|state| from.contains(&state))
+        })?;
+
+        // Already moved on, e.g. checked off by the agent
+        let Some(idx) = target else {
+            return Ok(false);
+        };
+        if !state_of(&lines[idx]).is_some_and(|state| from.contains(&state)) {
+            return Ok(false);
+        }
+        let mark = lines[idx].find("- [").unwrap_or(0) + 3;
+        lines[idx].replace_range(mark..mark + 1, &to.mark().to_string());
+
+        let mut new_content = lines.join("\n");
+        if content.ends_with('\n') {
+            new_content.push('\n');
+        }
+
+        write_atomic(path, &new_content)
+            .with_context(|| format!("Failed to write PRD file: {}", path.display()))?;
+
+        Ok(true)
+    }
+
+    // ============================================
+    // YAML IMPLEMENTATION
+    // ============================================
+
+    fn load_yaml(&self, path: &PathBuf) -> Result<PrdSnapshot> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read YAML file: {}", path.display()))?;
+
+        let yaml_tasks: YamlTasks =
+            serde_yaml::from_str(&content).with_context(|| "Failed to parse YAML")?;
+
+        let mut locations = Locations {
+            titles: yaml_tasks
+                .tasks
+                .iter()
+                .map(|t| Some(t.title.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let mut completed = 0;
+        let mut tasks = Vec::new();
+        let mut repos = HashMap::new();
+        let mut deps = HashMap::new();
+
+        let titles: Vec<&str> = yaml_tasks.tasks.iter().map(|t| t.title.as_str()).collect();
+        let open: Vec<&str> = yaml_tasks
+            .tasks
+            .iter()
+            .filter(|t| !t.completed)
+            .map(|t| t.title.as_str())
+            .collect();
+        for t in &yaml_tasks.tasks {
+            if let Some(dep) = t.depends_on.iter().find(|d| !titles.contains(&d.as_str())) {
+                anyhow::bail!("Task '{}' depends on unknown task '{}'", t.title, dep);
+            }
+            let waiting_on: Vec<String> = t
+                .depends_on
+                .iter()
+                .filter(|d| open.contains(&d.as_str()))
+                .cloned()
+                .collect();
+            if !t.completed && !waiting_on.is_empty() {
+                deps.insert(t.title.clone(), waiting_on);
+            }
+        }
+        if let Some(cycle) = dependency_cycle(&deps) {
+            anyhow::bail!("Task dependencies form a cycle: {}", cycle.join(" -> "));
+        }
+
+        let mut in_progress = 0;
+        for (idx, t) in yaml_tasks.tasks.into_iter().enumerate() {
+            if t.completed {
+                completed += 1;
+                continue;
+            }
+            locations.positions.entry(t.title.clone()).or_insert(idx);
+            if t.in_progress {
+                in_progress += 1;
+                continue;
+            }
+            if let Some(ref repo) = t.repo {
+                repos.insert(t.title.clone(), crate::repos::resolve_spec(repo, path));
+            }
+            tasks.push(t);
+        }
+
+        self.remember_locations(locations);
+        Ok(PrdSnapshot {
+            tasks,
+            completed,
+            in_progress,
+            repos,
+            deps,
+        })
+    }
+
+    /// Move a task to `to` if it is in one of the `from` states. Returns
+    /// whether it moved.
+    fn move_yaml(
+        &self,
+        path: &PathBuf,
+        task: &str,
+        from: &[TaskState],
+        to: TaskState,
+    ) -> Result<bool> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read YAML file: {}", path.display()))?;
+
+        let mut yaml_tasks: YamlTasks =
+            serde_yaml::from_str(&content).with_context(|| "Failed to parse YAML")?;
+
+        let tasks = &mut yaml_tasks.tasks;
+        let titles: Vec<Option<String>> = tasks.iter().map(|t| Some(t.title.clone())).collect();
+        let (recorded, loaded) = self.location_of(task);
+        let target = locate(task, recorded, &loaded, &titles, |idx| {
+            from.contains(&tasks[idx].state())
+        })?;
+
+        let idx = match target {
+            Some(idx) if from.contains(&tasks[idx].state()) => idx,
+            _ => return Ok(false),
+        };
+
+        // Edit the task's lines so comments and formatting survive; only a
+        // task written some other way, e.g. in flow style, is re-serialised
+        let new_content = match set_yaml_task_state(&content, idx, to) {
+            Some(new_content) => new_content,
+            None => {
+                tasks[idx].set_state(to);
+                serde_yaml::to_string(&yaml_tasks).with_context(|| "Failed to serialize YAML")?
+            }
+        };
+
+        write_atomic(path, &new_content)
+            .with_context(|| format!("Failed to write YAML file: {}", path.display()))?;
+
+        Ok(true)
+    }
+
+    // ============================================
+    // GITHUB IMPLEMENTATION
+    // ============================================
+
+    async fn load_github(
+        &self,
+        repo: &str,
+        label: Option<&str>,
+        authors: &[String],
+    ) -> Result<PrdSnapshot> {
+        let issues = self
+            .github
+            .get_json(&github::issues_endpoint(repo, "open", label))
+            .await?;
+        let (claimed, open): (Vec<_>, Vec<_>) = github::only_issues(issues)
+            .into_iter()
+            .filter(|issue| {
+                authors.is_empty()
+                    || issue["user"]["login"]
+                        .as_str()
+                        .is_some_and(|login| authors.iter().any(|a| a.eq_ignore_ascii_case(login)))
+            })
+            .partition(|issue| github::has_label(issue, github::IN_PROGRESS_LABEL));
+
+        Ok(PrdSnapshot {
+            tasks: open
+                .iter()
+                .filter_map(|issue| {
+                    let number = issue["number"].as_u64()?;
+                    let title = issue["title"].as_str()?;
+                    Some(Task::from_issue(title, TaskOrigin::GitHub { number }))
+                })
+                .collect(),
+            completed: self.count_github_completed(repo, label).await?,
+            in_progress: claimed.len(),
+            repos: HashMap::new(),
+            deps: HashMap::new(),
+        })
+    }
+
+    async fn count_github_completed(&self, repo: &str, label: Option<&str>) -> Result<usize> {
+        let issues = self
+            .github
+            .get_json(&github::issues_endpoint(repo, "closed", label))
+            .await?;
+
+        Ok(github::only_issues(issues).len())
+    }
+
+    /// Label the issue in progress, unless it was closed or another run
+    /// labelled it since the PRD was read.
+    async fn claim_github_issue(&self, repo: &str, task: &str) -> Result<bool> {
+        let endpoint = format!("repos/{}/issues/{}", repo, issue_id(task)?);
+        let issue = self.github.get_json(&endpoint).await?;
+        if issue["state"] != "open" || github::has_label(&issue, github::IN_PROGRESS_LABEL) {
+            return Ok(false);
+        }
+        edit_github_labels(repo, task, "--add-label").await?;
+        Ok(true)
+    }
+
+    async fn mark_github_complete(&self, repo: &str, task: &str) -> Result<()> {
+        // Extract issue number from "number:title" format
+        let issue_num = task.split(':').next().context("Invalid task format")?;
+
+        let mut cmd = tokio::process::Command::new("gh");
+        cmd.arg("issue")
+            .arg("close")
+            .arg(issue_num)
+            .arg("--repo")
+            .arg(repo);
+
+        let output = output_with_retry_async(&mut cmd)
+            .await
+            .context("Failed to close GitHub issue")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to close issue: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+
+    // ============================================
+    // JIRA IMPLEMENTATION
+    // ============================================
+
+    async fn load_jira(&self, project: &str, filter: Option<&str>) -> Result<PrdSnapshot> {
+        let api = JiraApi::from_env()?;
+        let issues = api
+            .search(&jira::to_do_jql(project, filter))
+            .await
+            .context("Failed to fetch Jira issues")?;
+        let completed = api
+            .count(&jira::done_jql(project, filter))
+            .await
+            .context("Failed to count done Jira issues")?;
+        let in_progress = api
+            .count(&jira::started_jql(project, filter))
+            .await
+            .context("Failed to count Jira issues in progress")?;
+
+        Ok(PrdSnapshot {
+            tasks: issues
+                .into_iter()
+                .map(|issue| Task::from_issue(issue.summary, TaskOrigin::Jira { key: issue.key }))
+                .collect(),
+            completed,
+            in_progress,
+            repos: HashMap::new(),
+            deps: HashMap::new(),
+        })
+    }
+
+    // ============================================
+    // LINEAR IMPLEMENTATION
+    // ============================================
+
+    async fn load_linear(&self, team: &str, label: Option<&str>) -> Result<PrdSnapshot> {
+        let api = LinearApi::from_env()?;
+        let issues = api
+            .issues(team, label, linear::Filter::Open)
+            .await
+            .context("Failed to fetch Linear issues")?;
+        let completed = api
+            .issues(team, label, linear::Filter::Completed)
+            .await
+            .context("Failed to fetch completed Linear issues")?
+            .len();
+        let in_progress = api
+            .issues(team, label, linear::Filter::Started)
+            .await
+            .context("Failed to fetch started Linear issues")?
+            .len();
+
+        Ok(PrdSnapshot {
+            tasks: issues
+                .into_iter()
+                .map(|issue| {
+                    let origin = TaskOrigin::Linear {
+                        identifier: issue.identifier,
+                    };
+                    Task::from_issue(issue.title, origin)
+                })
+                .collect(),
+            completed,
+            in_progress,
+            repos: HashMap::new(),
+            deps: HashMap::new(),
+        })
+    }
+}
+
+/// Issue number or key of a task from an issue tracker, from "KEY-12:title"
+/// format
+fn issue_id(task: &str) -> Result<&str> {
+    task.split(':').next().context("Invalid task format")
+}
+
+async fn mark_jira_complete(task: &str) -> Result<()> {
+    let key = issue_id(task)?;
+    JiraApi::from_env()?
+        .mark_done(key)
+        .await
+        .with_context(|| format!("Failed to move {} to Done", key))
+}
+
+fn add_markdown_tasks(path: &PathBuf, titles: &[String]) -> Result<()> {
+    let mut content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read PRD file: {}", path.display()))?;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for title in titles {
+        content.push_str(&format!("- [ ] {}\n", title));
+    }
+    write_atomic(path, &content)
+        .with_context(|| format!("Failed to write PRD file: {}", path.display()))
+}
+
+fn add_yaml_tasks(path: &PathBuf, tasks: impl IntoIterator<Item = Task>) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read YAML file: {}", path.display()))?;
+    let mut yaml_tasks: YamlTasks =
+        serde_yaml::from_str(&content).with_context(|| "Failed to parse YAML")?;
+    let tasks: Vec<Task> = tasks.into_iter().collect();
+
+    // Append to the text so comments and formatting survive; only a task
+    // list written some other way, e.g. `tasks: []`, is re-serialised
+    let new_content = match append_yaml_tasks(&content, &tasks)? {
+        Some(new_content) => new_content,
+        None => {
+            yaml_tasks.tasks.extend(tasks);
+            serde_yaml::to_string(&yaml_tasks).with_context(|| "Failed to serialize YAML")?
+        }
+    };
+    write_atomic(path, &new_content)
+        .with_context(|| format!("Failed to write YAML file: {}", path.display()))
+}
+
+/// `content` with `tasks` added after the last item of its top-level
+/// `tasks:` block sequence, or `None` when it has no such sequence.
+fn append_yaml_tasks(content: &str, tasks: &[Task]) -> Result<Option<String>> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let Some((item_indent, items)) = yaml_task_items(&lines) else {
+        return Ok(None);
+    };
+    let last = items.last().map_or(0, |item| item.end - 1);
+
+    let items = serde_yaml::to_string(tasks).with_context(|| "Failed to serialize YAML")?;
+    let mut new_content: String = lines[..=last].concat();
+    if !new_content.ends_with('\n') {
+        new_content.push('\n');
+    }
+    for line in items.lines() {
+        new_content.push_str(&format!("{}{}\n", " ".repeat(item_indent), line));
+    }
+    new_content.push_str(&lines[last + 1..].concat());
+    Ok(Some(new_content))
+}
+
+async fn add_github_tasks(repo: &str, label: Option<&str>, titles: &[String]) -> Result<()> {
+    for title in titles {
+        let mut cmd = tokio::process::Command::new("gh");
+        cmd.args(["issue", "create", "--repo", repo, "--title", title])
+            .args(["--body", "_Added by Ralphy while re-planning._"]);
+        if let Some(label) = label {
+            cmd.args(["--label", label]);
+        }
+
+        let output = output_with_retry_async(&mut cmd)
+            .await
+            .context("Failed to create GitHub issue")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to create issue '{}': {}",
+                title,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Add or remove (`flag`) the in-progress label on a GitHub task's issue,
+/// creating the label the first time.
+async fn edit_github_labels(repo: &str, task: &str, flag: &str) -> Result<()> {
+    let issue_num = issue_id(task)?;
+    if flag == "--add-label" {
+        let mut cmd = tokio::process::Command::new("gh");
+        cmd.args(["label", "create", github::IN_PROGRESS_LABEL, "--force"])
+            .args(["--description", "Ralphy is working on this", "--repo", repo]);
+        output_with_retry_async(&mut cmd)
+            .await
+            .context("Failed to create the in-progress label")?;
+    }
+
+    let mut cmd = tokio::process::Command::new("gh");
+    cmd.args(["issue", "edit", issue_num, flag, github::IN_PROGRESS_LABEL])
+        .args(["--repo", repo]);
+    let output = output_with_retry_async(&mut cmd)
+        .await
+        .context("Failed to label GitHub issue")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to label issue #{}: {}",
+            issue_num,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+async fn add_jira_tasks(project: &str, titles: &[String]) -> Result<()> {
+    let api = JiraApi::from_env()?;
+    for title in titles {
+        api.create_issue(project, title)
+            .await
+            .with_context(|| format!("Failed to create Jira issue '{}'", title))?;
+    }
+    Ok(())
+}
+
+async fn mark_linear_complete(team: &str, task: &str) -> Result<()> {
+    let id = issue_id(task)?;
+    LinearApi::from_env()?
+        .mark_completed(team, id)
+        .await
+        .with_context(|| format!("Failed to move {} to Done", id))
+}
+
+async fn add_linear_tasks(team: &str, label: Option<&str>, titles: &[String]) -> Result<()> {
+    let api = LinearApi::from_env()?;
+    for title in titles {
+        api.create_issue(team, label, title)
+            .await
+            .with_context(|| format!("Failed to create Linear issue '{}'", title))?;
+    }
+    Ok(())
+}
+
+/// Lock the PRD at `path` against other processes until the returned file
+/// is dropped. The lock is on a file of its own under the state directory
+/// next to the PRD, since every write replaces the PRD itself.
+fn lock_prd_file(path: &Path) -> Result<fs::File> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+    .join(progress::STATE_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, "*\n")?;
+    }
+
+    let name = path.file_name().context("PRD path has no file name")?;
+    let lock_path = dir.join(format!("{}.lock", name.to_string_lossy()));
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+    Ok(file)
+}
+
+/// Replace `path` with `content` through a temp file renamed over it, so
+/// the PRD is never left half-written for an agent or a crash to find.
+fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(content.as_bytes())?;
+    // Keep the PRD's mode rather than the temp file's owner-only one
+    if let Ok(metadata) = fs::metadata(path) {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    file.persist(path)?;
+    Ok(())
+}
+
+/// State and title of a markdown checkbox line.
+fn checkbox(line: &str) -> Option<(TaskState, String)> {
+    static CHECKBOX_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^- \[([ xX~])\] (.+)$").unwrap());
+    let cap = CHECKBOX_RE.captures(line.trim())?;
+    let state = match &cap[1] {
+        " " => TaskState::Pending,
+        "~" => TaskState::InProgress,
+        _ => TaskState::Done,
+    };
+    Some((state, cap[2].trim().to_string()))
+}
+
+/// Find the entry a task refers to, given the title at each position now
+/// and at load time.
+///
+/// Prefers the position recorded at load time when its title still matches,
+/// then any open entry with the same title (the file shifted). The recorded
+/// position is only taken with a different title when nothing else in the
+/// file moved or changed, i.e. the task was reworded in place; anything else
+/// is an error rather than a guess that could tick the wrong task. None
+/// means the task is there but no longer open.
+fn locate(
+    task: &str,
+    recorded: Option<usize>,
+    loaded: &[Option<String>],
+    titles: &[Option<String>],
+    is_open: impl Fn(usize) -> bool,
+) -> Result<Option<usize>> {
+    let recorded = recorded.filter(|&idx| idx < titles.len());
+    let title_matches = |idx: usize| titles[idx].as_deref() == Some(task);
+
+    if let Some(idx) = recorded.filter(|&idx| title_matches(idx)) {
+        return Ok(Some(idx));
+    }
+    if let Some(idx) = (0..titles.len()).find(|&idx| title_matches(idx) && is_open(idx)) {
+        return Ok(Some(idx));
+    }
+    if (0..titles.len()).any(title_matches) {
+        return Ok(None);
+    }
+    let reworded_in_place = recorded.filter(|&idx| {
+        is_open(idx)
+            && titles.len() == loaded.len()
+            && (0..titles.len()).all(|other| other == idx || titles[other] == loaded[other])
+    });
+    match reworded_in_place {
+        Some(idx) => Ok(Some(idx)),
+        None => anyhow::bail!(
+            "Task '{}' is no longer in the PRD and can't be matched to an entry safely; \
+             update it by hand",
+            task
+        ),
+    }
+}
+
+/// `content` with the `idx`th task's `completed` and `in_progress` keys set
+/// for `state` in place, or `None` when the task isn't a block mapping that
+/// can be edited line by line.
+fn set_yaml_task_state(content: &str, idx: usize, state: TaskState) -> Option<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let (item_indent, items) = yaml_task_items(&lines)?;
+    let item = items.get(idx)?.clone();
+
+    // The first key sits on the `- ` line, the rest line up under it
+    let after_dash = lines[item.start].trim_start_matches(' ')[1..].trim_end();
+    let first_key = after_dash.trim_start_matches(' ');
+    if first_key.is_empty() || first_key.starts_with('{') {
+        return None;
+    }
+    let key_indent = item_indent + 1 + after_dash.len() - first_key.len();
+    let key_at = |n: usize| {
+        let text = if n == item.start {
+            first_key
+        } else if yaml_indent(lines[n]) == key_indent {
+            lines[n].trim()
+        } else {
+            return None;
+        };
+        text.split_once(':').map(|(key, _)| key.trim_end())
+    };
+
+    let mut edited: Vec<Option<String>> = lines.iter().map(|line| Some(line.to_string())).collect();
+    let mut added = String::new();
+    for (key, value) in [
+        ("completed", state == TaskState::Done),
+        ("in_progress", state == TaskState::InProgress),
+    ] {
+        match item.clone().find(|&n| key_at(n) == Some(key)) {
+            // `in_progress` is left out unless set, as serde writes it
+            Some(n) if key == "in_progress" && !value && n != item.start => edited[n] = None,
+            Some(n) => edited[n] = Some(with_yaml_value(lines[n], key, value)),
+            None if value => {
+                added.push_str(&format!("{}{}: true\n", " ".repeat(key_indent), key));
+            }
+            None => {}
+        }
+    }
+
+    let mut new_content = String::new();
+    for (n, line) in edited.into_iter().enumerate() {
+        new_content.push_str(line.as_deref().unwrap_or_default());
+        if n + 1 == item.end && !added.is_empty() {
+            if !new_content.ends_with('\n') {
+                new_content.push('\n');
+            }
+            new_content.push_str(&added);
+        }
+    }
+
+    // Anything the line edits got wrong falls back to re-serialising
+    let parsed: YamlTasks = serde_yaml::from_str(&new_content).ok()?;
+    (parsed.tasks.get(idx)?.state() == state).then_some(new_content)
+}
+
+/// `line`, a `key: value` mapping entry, with its value replaced by `value`,
+/// keeping any trailing comment.
+fn with_yaml_value(line: &str, key: &str, value: bool) -> String {
+    let at = line
+        .find(&format!("{}:", key))
+        .map_or(line.len(), |at| at + key.len() + 1);
+    let rest = line[at..].trim_end();
+    let comment = rest.find(" #").map_or("", |c| &rest[c..]);
+    let newline = if line.ends_with('\n') { "\n" } else { "" };
+    format!("{} {}{}{}", &line[..at], value, comment, newline)
+}
+
+/// The items of the top-level `tasks:` block sequence in `lines`, each the
+/// range from its `- ` line through its last significant line, and the
+/// indent they share; `None` when there is no such sequence.
+fn yaml_task_items(lines: &[&str]) -> Option<(usize, Vec<std::ops::Range<usize>>)> {
+    let is_item = |line: &str| {
+        let line = line.trim();
+        line == "-" || line.starts_with("- ")
+    };
+    let significant = |line: &&str| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#')
+    };
+
+    let key = lines.iter().position(|line| line.trim_end() == "tasks:")?;
+    let (first, item_indent) = lines
+        .iter()
+        .enumerate()
+        .skip(key + 1)
+        .find(|(_, line)| significant(line))
+        .filter(|(_, line)| is_item(line))
+        .map(|(idx, line)| (idx, yaml_indent(line)))?;
+
+    let mut items: Vec<std::ops::Range<usize>> = Vec::new();
+    for (idx, line) in lines.iter().enumerate().skip(first) {
+        if !significant(line) {
+            continue;
+        }
+        if yaml_indent(line) == item_indent && is_item(line) {
+            items.push(idx..idx + 1);
+        } else if yaml_indent(line) > item_indent {
+            items.last_mut()?.end = idx + 1;
+        } else {
+            break;
+        }
+    }
+    Some((item_indent, items))
+}
+
+fn yaml_indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Where tasks were in the PRD when it was last loaded.
+#[derive(Debug, Default)]
+struct Locations {
+    /// First open entry with each title
+    positions: HashMap<String, usize>,
+    /// Title at each position (markdown line or YAML index), None for
+    /// markdown lines that aren't tasks
+    titles: Vec<Option<String>>,
+}
+
+/// A chain of tasks that wait on each other in a loop, first task repeated
+/// at the end, if `deps` has one.
+fn dependency_cycle(deps: &HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    fn visit<'a>(
+        task: &'a str,
+        deps: &'a HashMap<String, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        cleared: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|t| *t == task) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|t| t.to_string()).collect();
+            cycle.push(task.to_string());
+            return Some(cycle);
+        }
+        if cleared.contains(&task) {
+            return None;
+        }
+        path.push(task);
+        for dep in deps.get(task).into_iter().flatten() {
+            if let Some(cycle) = visit(dep, deps, path, cleared) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        cleared.push(task);
+        None
+    }
+
+    let mut tasks: Vec<&String> = deps.keys().collect();
+    tasks.sort();
+    let mut cleared = Vec::new();
+    tasks
+        .into_iter()
+        .find_map(|task| visit(task, deps, &mut Vec::new(), &mut cleared))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(yaml: &str) -> Result<PrdSnapshot> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.yaml");
+        fs::write(&path, yaml).unwrap();
+        PrdManager::new(PrdSource::Yaml { path: path.clone() }).load_yaml(&path)
+    }
+
+    #[test]
+    fn test_prd_file_lock_keeps_other_writers_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("PRD.md");
+        fs::write(&path, "- [ ] Add login\n").unwrap();
+
+        let held = lock_prd_file(&path).unwrap();
+        let lock_path = dir.path().join(progress::STATE_DIR).join("PRD.md.lock");
+        let other = fs::File::open(&lock_path).unwrap();
+        assert!(other.try_lock().is_err());
+
+        drop(held);
+        assert!(other.try_lock().is_ok());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(progress::STATE_DIR).join(".gitignore")).unwrap(),
+            "*\n"
+        );
+    }
+
+    #[test]
+    fn test_append_yaml_tasks_keeps_the_file_as_written() {
+        let yaml = "# Shop\n\
+                    tasks:\n  \
+                      - title: Add cart # first\n    \
+                        completed: true\n\n  \
+                      - title: Add checkout\n\
+                    \n\
+                    # trailing notes\n";
+        let appended = append_yaml_tasks(yaml, &[Task::new("Add receipts")])
+            .unwrap()
+            .unwrap();
+        assert!(appended.starts_with("# Shop\ntasks:\n  - title: Add cart # first\n"));
+        assert!(appended.contains("  - title: Add checkout\n  - title: Add receipts\n"));
+        assert!(appended.ends_with("\n# trailing notes\n"));
+
+        let parsed: YamlTasks = serde_yaml::from_str(&appended).unwrap();
+        assert_eq!(parsed.tasks.len(), 3);
+        assert_eq!(parsed.tasks[2].title, "Add receipts");
+        assert!(append_yaml_tasks("tasks: []\n", &[Task::new("x")])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_set_yaml_task_state_keeps_the_file_as_written() {
+        let yaml = "# Shop\n\
+                    tasks:\n  \
+                      - title: Add cart # first\n    \
+                        completed: false # not yet\n    \
+                        tags: [ui]\n\n  \
+                      - title: Add checkout\n\
+                    \n\
+                    # trailing notes\n";
+
+        let started = set_yaml_task_state(yaml, 1, TaskState::InProgress).unwrap();
+        assert_eq!(
+            started,
+            yaml.replace(
+                "  - title: Add checkout\n",
+                "  - title: Add checkout\n    in_progress: true\n"
+            )
+        );
+
+        let done = set_yaml_task_state(&started, 0, TaskState::Done).unwrap();
+        assert!(done.contains("  - title: Add cart # first\n    completed: true # not yet\n"));
+
+        let released = set_yaml_task_state(&started, 1, TaskState::Pending).unwrap();
+        assert_eq!(released, yaml);
+
+        assert!(set_yaml_task_state("tasks:\n  - {title: x}\n", 0, TaskState::Done).is_none());
+    }
+
+    #[test]
+    fn test_yaml_dependencies() {
+        let snapshot = load(
+            "tasks:\n\
+             - {title: Schema, completed: true}\n\
+             - {title: API, completed: false, depends_on: [Schema]}\n\
+             - {title: UI, completed: false, depends_on: [API, Schema]}\n",
+        )
+        .unwrap();
+        // Completed dependencies no longer hold anything up
+        assert!(snapshot.deps_of("API").is_empty());
+        assert_eq!(snapshot.deps_of("UI"), ["API"]);
+
+        let unknown = load("tasks:\n- {title: UI, completed: false, depends_on: [API]}\n");
+        assert!(format!("{:#}", unknown.unwrap_err()).contains("unknown task 'API'"));
+    }
+
+    #[test]
+    fn test_yaml_dependency_cycle() {
+        let err = load(
+            "tasks:\n\
+             - {title: A, completed: false, depends_on: [C]}\n\
+             - {title: B, completed: false, depends_on: [A]}\n\
+             - {title: C, completed: false, depends_on: [B]}\n",
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("A -> C -> B -> A"));
+    }
+
+    #[test]
+    fn test_locate_only_takes_a_reworded_task_in_place() {
+        let titles = |names: &[&str]| -> Vec<Option<String>> {
+            names.iter().map(|n| Some(n.to_string())).collect()
+        };
+        let loaded = titles(&["A", "B", "C"]);
+        let open = |_| true;
+
+        // Shifted down a line
+        let now = titles(&["New", "A", "B", "C"]);
+        assert_eq!(locate("B", Some(1), &loaded, &now, open).unwrap(), Some(2));
+        // Reworded with nothing else touched
+        let now = titles(&["A", "B v2", "C"]);
+        assert_eq!(locate("B", Some(1), &loaded, &now, open).unwrap(), Some(1));
+        // Reworded and shifted: the recorded line now holds another task
+        let now = titles(&["New", "A", "B v2", "C"]);
+        assert!(locate("B", Some(1), &loaded, &now, open).is_err());
+        // Shifted and checked off already
+        let now = titles(&["New", "A", "B", "C"]);
+        assert_eq!(
+            locate("B", Some(1), &loaded, &now, |idx| idx != 2).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A required tool that could not be found on `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingTool {
+    pub name: String,
+    pub hint: String,
+}
+
+impl MissingTool {
+    pub fn new(name: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            hint: hint.into(),
+        }
+    }
+}
+
+/// Every tool that was missing during pre-flight, reported together.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct MissingTools(pub Vec<MissingTool>);
+
+impl fmt::Display for MissingTools {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Missing required tools:")?;
+        for tool in &self.0 {
+            writeln!(f, "  - {}: {}", tool.name, tool.hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects missing tools so they can be reported at once.
+#[derive(Debug, Default)]
+pub struct ToolCheck {
+    missing: Vec<MissingTool>,
+}
+
+impl ToolCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `name` as missing unless it can be found on `PATH`.
+    pub fn require(&mut self, name: &str, hint: &str) -> &mut Self {
+        if find_executable(name).is_none() {
+            self.missing.push(MissingTool::new(name, hint));
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<(), MissingTools> {
+        if self.missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingTools(self.missing))
+        }
+    }
+}
+
+/// Search `PATH` for an executable named `name`, without spawning `which`.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    find_executable_in(name, &path)
+}
+
+/// Search the given `PATH`-style list for an executable named `name`.
+pub fn find_executable_in(name: &str, path: &OsStr) -> Option<PathBuf> {
+    // Names containing a separator are treated as paths, like a shell would
+    if Path::new(name).components().count() > 1 {
+        let candidate = PathBuf::from(name);
+        return is_executable(&candidate).then_some(candidate);
+    }
+
+    env::split_paths(path)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| candidates(&dir, name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(windows)]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let mut out = vec![dir.join(name)];
+    out.extend(
+        pathext
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| dir.join(format!("{}{}", name, ext))),
+    );
+    out
+}
+
+#[cfg(not(windows))]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    vec![dir.join(name)]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn make_executable(path: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::write(path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_executable_in() {
+        let dir = TempDir::new().unwrap();
+        let tool = dir.path().join("mytool");
+        make_executable(&tool);
+        std::fs::write(dir.path().join("notexec"), "").unwrap();
+
+        let path = env::join_paths([dir.path()]).unwrap();
+        assert_eq!(find_executable_in("mytool", &path), Some(tool));
+        assert_eq!(find_executable_in("notexec", &path), None);
+        assert_eq!(find_executable_in("missing", &path), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_find_executable_in_finds_cmd_shims() {
+        let dir = TempDir::new().unwrap();
+        let shim = dir.path().join("mytool.CMD");
+        std::fs::write(&shim, "@echo off\r\n").unwrap();
+
+        let path = env::join_paths([dir.path()]).unwrap();
+        assert_eq!(find_executable_in("mytool", &path), Some(shim));
+    }
+
+    #[test]
+    fn test_missing_tools_lists_all() {
+        let err = MissingTools(vec![
+            MissingTool::new("kubectl", "install kubectl"),
+            MissingTool::new("gh", "install gh"),
+        ]);
+        let msg = err.to_string();
+        assert!(msg.contains("kubectl: install kubectl"));
+        assert!(msg.contains("gh: install gh"));
+    }
+}
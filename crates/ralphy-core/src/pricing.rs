@@ -0,0 +1,210 @@
+use crate::ai::{AiEngine, AiResponse};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// What a model charges, in dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    const fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+
+    pub fn cost(&self, input_tokens: usize, output_tokens: usize) -> f64 {
+        (input_tokens as f64 * self.input + output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+const SONNET: Price = Price::new(3.0, 15.0);
+const GPT_5: Price = Price::new(1.25, 10.0);
+const GPT_4_1: Price = Price::new(2.0, 8.0);
+const QWEN3_CODER: Price = Price::new(1.0, 5.0);
+const FREE: Price = Price::new(0.0, 0.0);
+
+/// List prices of the models engines commonly run, by model name prefix;
+/// the longest matching prefix wins.
+const MODELS: &[(&str, Price)] = &[
+    ("claude-opus-4-5", Price::new(5.0, 25.0)),
+    ("claude-opus-4", Price::new(15.0, 75.0)),
+    ("claude-sonnet-4", SONNET),
+    ("claude-haiku-4", Price::new(1.0, 5.0)),
+    ("claude-3-opus", Price::new(15.0, 75.0)),
+    ("claude-3-7-sonnet", SONNET),
+    ("claude-3-5-sonnet", SONNET),
+    ("claude-3-5-haiku", Price::new(0.8, 4.0)),
+    ("gpt-5", GPT_5),
+    ("gpt-5-mini", Price::new(0.25, 2.0)),
+    ("gpt-5-nano", Price::new(0.05, 0.4)),
+    ("gpt-4.1", GPT_4_1),
+    ("gpt-4.1-mini", Price::new(0.4, 1.6)),
+    ("gpt-4.1-nano", Price::new(0.1, 0.4)),
+    ("gpt-4o", Price::new(2.5, 10.0)),
+    ("gpt-4o-mini", Price::new(0.15, 0.6)),
+    ("o3", Price::new(2.0, 8.0)),
+    ("o3-mini", Price::new(1.1, 4.4)),
+    ("o4-mini", Price::new(1.1, 4.4)),
+    ("qwen3-coder-plus", QWEN3_CODER),
+    ("qwen3-coder-flash", Price::new(0.3, 1.5)),
+    ("gemini-2.5-pro", Price::new(1.25, 10.0)),
+    ("gemini-2.5-flash", Price::new(0.3, 2.5)),
+];
+
+/// What an engine's default model costs, for when it doesn't say which
+/// model it ran.
+fn engine_default(engine: AiEngine) -> Price {
+    match engine {
+        AiEngine::Codex => GPT_5,
+        AiEngine::OpenAiApi => GPT_4_1,
+        AiEngine::Qwen => QWEN3_CODER,
+        AiEngine::Ollama => FREE,
+        AiEngine::Claude
+        | AiEngine::OpenCode
+        | AiEngine::Cursor
+        | AiEngine::Aider
+        | AiEngine::AnthropicApi
+        | AiEngine::Mock => SONNET,
+    }
+}
+
+/// Longest key in `prices` that `model` starts with. Provider prefixes such
+/// as `anthropic/` are ignored.
+fn lookup<'a, I>(prices: I, model: &str) -> Option<Price>
+where
+    I: IntoIterator<Item = (&'a str, &'a Price)>,
+{
+    let model = model.rsplit('/').next().unwrap_or(model);
+    prices
+        .into_iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, price)| *price)
+}
+
+/// Prices used to estimate what engines don't report: the built-in table,
+/// under ralphy.toml's `[pricing]`.
+#[derive(Debug, Clone, Default)]
+pub struct Pricing {
+    /// Keyed by model name prefix or by engine, e.g. `codex`
+    overrides: BTreeMap<String, Price>,
+}
+
+impl Pricing {
+    pub fn new(overrides: BTreeMap<String, Price>) -> Self {
+        Self { overrides }
+    }
+
+    /// What `model` costs when `engine` runs it. A price set for the model
+    /// wins, then the built-in one; a price set for the engine only covers
+    /// models neither knows. Local models are free unless priced in
+    /// ralphy.toml.
+    pub fn price(&self, engine: AiEngine, model: Option<&str>) -> Price {
+        let overrides = self
+            .overrides
+            .iter()
+            .map(|(key, price)| (key.as_str(), price));
+        let builtin = MODELS.iter().map(|(prefix, price)| (*prefix, price));
+        let by_model = |model: &str| {
+            lookup(overrides.clone(), model).or_else(|| {
+                (engine != AiEngine::Ollama)
+                    .then(|| lookup(builtin.clone(), model))
+                    .flatten()
+            })
+        };
+        let by_engine = || self.overrides.get(engine.name()).copied();
+        model
+            .and_then(by_model)
+            .or_else(by_engine)
+            .unwrap_or_else(|| engine_default(engine))
+    }
+
+    /// Estimated cost of `engine` running `model` over these tokens
+    pub fn cost(
+        &self,
+        engine: AiEngine,
+        model: Option<&str>,
+        input_tokens: usize,
+        output_tokens: usize,
+    ) -> f64 {
+        self.price(engine, model).cost(input_tokens, output_tokens)
+    }
+
+    /// What a response cost: the engine's own figure, or our estimate
+    pub fn response_cost(&self, engine: AiEngine, response: &AiResponse) -> f64 {
+        response.actual_cost.unwrap_or_else(|| {
+            self.cost(
+                engine,
+                response.model.as_deref(),
+                response.input_tokens,
+                response.output_tokens,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_prices() {
+        let pricing = Pricing::default();
+        let price = |engine, model| pricing.price(engine, model);
+        assert_eq!(
+            price(AiEngine::Claude, Some("claude-opus-4-1-20250805")),
+            Price::new(15.0, 75.0)
+        );
+        assert_eq!(
+            price(AiEngine::Claude, Some("claude-opus-4-5-20251101")),
+            Price::new(5.0, 25.0)
+        );
+        assert_eq!(
+            price(AiEngine::OpenCode, Some("openai/gpt-5-mini")),
+            Price::new(0.25, 2.0)
+        );
+        assert_eq!(price(AiEngine::Codex, None), GPT_5);
+        assert_eq!(price(AiEngine::Codex, Some("some-new-model")), GPT_5);
+        assert_eq!(price(AiEngine::Ollama, Some("gpt-4o")), FREE);
+        assert_eq!(price(AiEngine::Mock, None), SONNET);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let pricing = Pricing::new(BTreeMap::from([
+            ("codex".to_string(), Price::new(2.0, 20.0)),
+            ("gpt-5-mini".to_string(), Price::new(0.5, 4.0)),
+            ("llama3".to_string(), Price::new(0.1, 0.1)),
+        ]));
+        assert_eq!(pricing.price(AiEngine::Codex, None), Price::new(2.0, 20.0));
+        // The built-in price of a known model beats the engine's
+        assert_eq!(pricing.price(AiEngine::Codex, Some("gpt-5")), GPT_5);
+        assert_eq!(
+            pricing.price(AiEngine::Codex, Some("gpt-5-mini")),
+            Price::new(0.5, 4.0)
+        );
+        assert_eq!(
+            pricing.price(AiEngine::Ollama, Some("llama3:8b")),
+            Price::new(0.1, 0.1)
+        );
+    }
+
+    #[test]
+    fn test_cost() {
+        let pricing = Pricing::default();
+        let cost = pricing.cost(AiEngine::Claude, None, 1_000_000, 100_000);
+        assert!((cost - 4.5).abs() < 1e-9);
+        let response = AiResponse {
+            text: String::new(),
+            input_tokens: 1_000_000,
+            output_tokens: 0,
+            actual_cost: Some(0.5),
+            duration_ms: None,
+            model: None,
+        };
+        assert_eq!(pricing.response_cost(AiEngine::Claude, &response), 0.5);
+    }
+}
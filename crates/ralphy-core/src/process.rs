@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tokio::sync::Notify;
+
+/// PIDs of engine processes that are still running, with the
+/// [`with_timeout`] scope each was spawned in, if any.
+fn registry() -> &'static Mutex<HashMap<u32, Option<u64>>> {
+    static CHILDREN: OnceLock<Mutex<HashMap<u32, Option<u64>>>> = OnceLock::new();
+    CHILDREN.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+tokio::task_local! {
+    /// The [`with_timeout`] call the current task is running under
+    static SCOPE: Arc<Scope>;
+}
+
+/// One [`with_timeout`] call, and the time its future spent
+/// [`off_the_clock`].
+#[derive(Default)]
+struct Scope {
+    id: u64,
+    pauses: Mutex<Pauses>,
+    /// Told when the last wait off the clock ends
+    resumed: Notify,
+}
+
+#[derive(Default)]
+struct Pauses {
+    /// Waits still going on
+    open: usize,
+    /// When the first of them started
+    since: Option<Instant>,
+    /// Time spent in the waits that ended
+    total: Duration,
+}
+
+impl Scope {
+    /// Time spent off the clock so far
+    fn paused(&self) -> Duration {
+        let pauses = self.pauses.lock().unwrap();
+        pauses.total + pauses.since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pauses.lock().unwrap().open > 0
+    }
+
+    fn pause(&self) -> Pause<'_> {
+        let mut pauses = self.pauses.lock().unwrap();
+        pauses.open += 1;
+        pauses.since.get_or_insert_with(Instant::now);
+        Pause(self)
+    }
+}
+
+/// Keeps the clock of a [`Scope`] stopped until it is dropped.
+struct Pause<'a>(&'a Scope);
+
+impl Drop for Pause<'_> {
+    fn drop(&mut self) {
+        let mut pauses = self.0.pauses.lock().unwrap();
+        pauses.open -= 1;
+        if pauses.open == 0 {
+            if let Some(since) = pauses.since.take() {
+                pauses.total += since.elapsed();
+            }
+            self.0.resumed.notify_one();
+        }
+    }
+}
+
+static NEXT_SCOPE: AtomicU64 = AtomicU64::new(0);
+
+/// Build a command for an engine CLI.
+///
+/// The child is placed in its own process group so that it, and anything it
+/// spawns, can be signalled together, and it is killed if its handle is dropped.
+pub fn engine_command(program: &str) -> Command {
+    let mut cmd = Command::new(program_path(program));
+    cmd.kill_on_drop(true);
+    new_process_group(&mut cmd);
+    cmd
+}
+
+/// Start `cmd` in a process group of its own, so [`Signal`]s reach the
+/// processes it spawns too.
+pub fn new_process_group(cmd: &mut Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    #[cfg(not(any(unix, windows)))]
+    let _ = cmd;
+}
+
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// Where `program` is. Windows only finds `.exe` files on its own, while npm
+/// installs engine CLIs as `.cmd` shims that need a PATHEXT lookup.
+fn program_path(program: &str) -> OsString {
+    if cfg!(windows) {
+        if let Some(path) = crate::preflight::find_executable(program) {
+            return path.into_os_string();
+        }
+    }
+    program.into()
+}
+
+/// A spawned engine process whose PID is tracked until it exits.
+///
+/// Dropping the handle before `wait` returns (timeout, cancellation, panic)
+/// kills the child's whole process group.
+pub struct EngineChild {
+    child: Child,
+    pid: Option<u32>,
+    finished: bool,
+}
+
+impl EngineChild {
+    pub fn spawn(cmd: &mut Command) -> io::Result<Self> {
+        let child = cmd.spawn()?;
+        let pid = child.id();
+        if let Some(pid) = pid {
+            let scope = SCOPE.try_with(|scope| scope.id).ok();
+            registry().lock().unwrap().insert(pid, scope);
+        }
+        Ok(Self {
+            child,
+            pid,
+            finished: false,
+        })
+    }
+
+    /// Track the process under the [`with_timeout`] scope the current task
+    /// runs in, for a process that outlives the one it was spawned in.
+    pub fn rescope(&self) {
+        if let Some(pid) = self.pid {
+            let scope = SCOPE.try_with(|scope| scope.id).ok();
+            registry().lock().unwrap().insert(pid, scope);
+        }
+    }
+
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait().await?;
+        self.finished = true;
+        self.untrack();
+        Ok(status)
+    }
+
+    fn untrack(&self) {
+        if let Some(pid) = self.pid {
+            registry().lock().unwrap().remove(&pid);
+        }
+    }
+}
+
+impl Deref for EngineChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for EngineChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+impl Drop for EngineChild {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Some(pid) = self.pid {
+                kill_group(pid, Signal::Kill);
+            }
+        }
+        self.untrack();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Terminate,
+    Kill,
+}
+
+/// PIDs of all engine processes currently running.
+pub fn active_pids() -> Vec<u32> {
+    registry().lock().unwrap().keys().copied().collect()
+}
+
+/// Send `signal` to every tracked engine process group.
+pub fn signal_all(signal: Signal) {
+    for pid in active_pids() {
+        kill_group(pid, signal);
+    }
+}
+
+/// Run `future`, giving up on it after `limit`. The engine processes it
+/// started are then sent SIGTERM and given `grace` to exit before they are
+/// killed outright. Returns `None` if the limit was reached.
+///
+/// Time the future spends [`off_the_clock`] doesn't count against `limit`.
+pub async fn with_timeout<F: Future>(
+    limit: Duration,
+    grace: Duration,
+    future: F,
+) -> Option<F::Output> {
+    let scope = Arc::new(Scope {
+        id: NEXT_SCOPE.fetch_add(1, Ordering::Relaxed),
+        ..Default::default()
+    });
+    let start = Instant::now();
+    let future = SCOPE.scope(scope.clone(), future);
+    tokio::pin!(future);
+    loop {
+        let deadline = start + limit + scope.paused();
+        if let Ok(output) = tokio::time::timeout_at(deadline.into(), &mut future).await {
+            return Some(output);
+        }
+        if scope.is_paused() {
+            // The clock only runs out once the wait is over
+            tokio::select! {
+                output = &mut future => return Some(output),
+                _ = scope.resumed.notified() => continue,
+            }
+        }
+        if start + limit + scope.paused() <= Instant::now() {
+            break;
+        }
+    }
+
+    let pids: Vec<u32> = registry()
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, s)| **s == Some(scope.id))
+        .map(|(pid, _)| *pid)
+        .collect();
+    for pid in pids {
+        kill_group(pid, Signal::Terminate);
+    }
+    // Keep driving the future so the engines can wind down; dropping it
+    // kills whatever is still running
+    let _ = tokio::time::timeout(grace, &mut future).await;
+    None
+}
+
+/// Run `future` without the time it takes counting against the
+/// [`with_timeout`] call the current task is under, such as while queueing
+/// for a rate limit.
+pub async fn off_the_clock<F: Future>(future: F) -> F::Output {
+    let Ok(scope) = SCOPE.try_with(Arc::clone) else {
+        return future.await;
+    };
+    let _pause = scope.pause();
+    future.await
+}
+
+#[cfg(unix)]
+fn kill_group(pid: u32, signal: Signal) {
+    let sig = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative PID
+    // addresses the process group created by `engine_command`.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), sig);
+    }
+}
+
+#[cfg(windows)]
+fn kill_group(pid: u32, signal: Signal) {
+    // /T takes the process tree with it; without /F taskkill asks the
+    // processes to close, much like SIGTERM
+    let mut cmd = std::process::Command::new("taskkill");
+    cmd.args(["/T", "/PID", &pid.to_string()]);
+    if signal == Signal::Kill {
+        cmd.arg("/F");
+    }
+    cmd.stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .ok();
+}
+
+#[cfg(not(any(unix, windows)))]
+fn kill_group(_pid: u32, _signal: Signal) {
+    // kill_on_drop terminates the direct child
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracks_pid_until_exit() {
+        let mut child = EngineChild::spawn(engine_command("true").arg("x")).unwrap();
+        let pid = child.id().unwrap();
+        assert!(active_pids().contains(&pid));
+
+        child.wait().await.unwrap();
+        assert!(!active_pids().contains(&pid));
+    }
+
+    #[tokio::test]
+    async fn test_drop_untracks_child() {
+        let child = EngineChild::spawn(engine_command("sleep").arg("30")).unwrap();
+        let pid = child.id().unwrap();
+        assert!(active_pids().contains(&pid));
+
+        drop(child);
+        assert!(!active_pids().contains(&pid));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_stops_its_engines() {
+        let finished = with_timeout(Duration::from_secs(5), Duration::ZERO, async { 7 }).await;
+        assert_eq!(finished, Some(7));
+
+        let started = std::time::Instant::now();
+        let hung = with_timeout(Duration::from_millis(100), Duration::from_secs(5), async {
+            let mut child = EngineChild::spawn(engine_command("sleep").arg("30")).unwrap();
+            child.wait().await.unwrap()
+        })
+        .await;
+        assert!(hung.is_none());
+        // SIGTERM ended it well before the grace period ran out
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_stops_engines_it_took_over() {
+        let mut child = EngineChild::spawn(engine_command("sleep").arg("30")).unwrap();
+
+        let started = std::time::Instant::now();
+        let hung = with_timeout(Duration::from_millis(100), Duration::from_secs(5), async {
+            child.rescope();
+            child.wait().await.unwrap()
+        })
+        .await;
+        assert!(hung.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_stops_the_clock_while_off_it() {
+        let limit = Duration::from_millis(100);
+        let finished = with_timeout(limit, Duration::ZERO, async {
+            off_the_clock(tokio::time::sleep(Duration::from_millis(200))).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            7
+        })
+        .await;
+        assert_eq!(finished, Some(7));
+
+        let hung = with_timeout(limit, Duration::ZERO, async {
+            off_the_clock(tokio::time::sleep(Duration::from_millis(50))).await;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        })
+        .await;
+        assert!(hung.is_none());
+    }
+}
@@ -0,0 +1,464 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Human-readable view of the progress log, which agents read and append
+/// their notes to. Rewritten from the log after every entry.
+pub const PROGRESS_FILE: &str = "progress.txt";
+
+/// Directory for ralphy's own run state; ignored by git.
+pub const STATE_DIR: &str = ".ralphy";
+
+/// The structured progress log, one JSON entry per line, under [`STATE_DIR`].
+pub const LOG_FILE: &str = "progress.jsonl";
+
+/// Once the log grows past this size, older entries move to an archive.
+pub const MAX_LOG_BYTES: u64 = 256 * 1024;
+
+/// Most entries left in the log when it is compacted. Fewer are kept if
+/// they would fill more than half of [`MAX_LOG_BYTES`].
+pub const KEEP_ENTRIES: usize = 50;
+
+/// Create the state directory with a `.gitignore` so agents don't commit it.
+pub async fn ensure_state_dir() -> Result<PathBuf> {
+    ensure_state_dir_in(Path::new("")).await
+}
+
+/// Create the state directory of the repository at `repo`.
+pub async fn ensure_state_dir_in(repo: &Path) -> Result<PathBuf> {
+    let dir = repo.join(STATE_DIR);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        tokio::fs::write(&gitignore, "*\n").await?;
+    }
+    Ok(dir)
+}
+
+/// Progress file a single parallel agent writes to.
+pub fn agent_progress_path(agent: usize) -> PathBuf {
+    Path::new(STATE_DIR)
+        .join("progress")
+        .join(format!("agent-{}.txt", agent))
+}
+
+/// Create an empty per-agent progress file.
+pub async fn prepare_agent_file(path: &Path) -> Result<()> {
+    ensure_state_dir().await?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, "")
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))
+}
+
+/// How the work an entry records ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Completed,
+    Failed,
+    Interrupted,
+    /// Passed over at the `--interactive` question
+    Skipped,
+    /// Notes from a progress.txt written before the log existed
+    Imported,
+    /// Condensed notes standing in for the entries they were written from
+    Summary,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+            Status::Interrupted => "interrupted",
+            Status::Skipped => "skipped",
+            Status::Imported => "imported",
+            Status::Summary => "summary",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One line of the progress log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Stable id of the task, from [`task_id`]; absent for run-level entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    pub status: Status,
+    /// RFC 3339 timestamps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    pub finished_at: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+}
+
+impl Entry {
+    /// A run-level entry not tied to a task, e.g. an interruption.
+    pub fn note(status: Status, notes: &str) -> Self {
+        Self {
+            task_id: None,
+            task: None,
+            status,
+            started_at: None,
+            finished_at: chrono::Local::now().to_rfc3339(),
+            notes: notes.trim().to_string(),
+        }
+    }
+}
+
+/// Short id of a task that stays the same across runs: the FNV-1a hash of
+/// its title, in hex.
+pub fn task_id(task: &str) -> String {
+    let hash = task.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:08x}", hash as u32)
+}
+
+/// What an agent added to a progress file that read `before` when its task
+/// started. Agents are asked to append, but if one rewrote the file, the
+/// lines that weren't there before are taken instead.
+pub fn new_notes(before: &str, after: &str) -> String {
+    let notes = match after.strip_prefix(before) {
+        Some(appended) => appended.to_string(),
+        None => {
+            let old: std::collections::HashSet<&str> = before.lines().collect();
+            after
+                .lines()
+                .filter(|line| !old.contains(line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+    notes.trim().to_string()
+}
+
+/// Render entries as the human-readable progress view.
+pub fn render(entries: &[Entry]) -> String {
+    let mut out = String::from("# Progress\n");
+    for entry in entries {
+        let when = chrono::DateTime::parse_from_rfc3339(&entry.finished_at)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| entry.finished_at.clone());
+        let heading = match (&entry.task, &entry.task_id) {
+            (Some(task), Some(id)) => format!("{} ({}, {})", task, entry.status, id),
+            (Some(task), None) => format!("{} ({})", task, entry.status),
+            _ => match entry.status {
+                Status::Summary => "Summary of earlier progress".to_string(),
+                Status::Imported => "Earlier notes".to_string(),
+                status => format!("Run {}", status),
+            },
+        };
+        out.push_str(&format!("\n## [{}] {}\n", when, heading));
+        if !entry.notes.is_empty() {
+            out.push_str(&format!("{}\n", entry.notes));
+        }
+    }
+    out
+}
+
+/// Parse a log, skipping lines that aren't valid entries.
+pub fn parse_log(content: &str) -> Vec<Entry> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The progress log and its archives in a state directory.
+#[derive(Debug, Clone)]
+pub struct ProgressLog {
+    dir: PathBuf,
+}
+
+impl Default for ProgressLog {
+    fn default() -> Self {
+        Self::in_dir(STATE_DIR)
+    }
+}
+
+impl ProgressLog {
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE)
+    }
+
+    pub fn archive_dir(&self) -> PathBuf {
+        self.dir.join("progress-archive")
+    }
+
+    /// Entries still in the log, oldest first.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        match std::fs::read_to_string(self.path()) {
+            Ok(content) => Ok(parse_log(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", self.path().display())),
+        }
+    }
+
+    /// Archived entries followed by the log's own, oldest first.
+    pub fn all_entries(&self) -> Result<Vec<Entry>> {
+        let mut archives: Vec<PathBuf> = match std::fs::read_dir(self.archive_dir()) {
+            Ok(dir) => dir.flatten().map(|e| e.path()).collect(),
+            Err(_) => Vec::new(),
+        };
+        // Archive names start with a sortable timestamp
+        archives.sort();
+
+        let mut entries = Vec::new();
+        for archive in archives {
+            let content = std::fs::read_to_string(&archive)
+                .with_context(|| format!("Failed to read {}", archive.display()))?;
+            entries.extend(parse_log(&content));
+        }
+        entries.extend(self.entries()?);
+        Ok(entries)
+    }
+
+    /// Append `entry`, compacting the log if it has grown too big.
+    pub async fn append(&self, entry: &Entry) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())
+            .await
+            .with_context(|| format!("Failed to open {}", self.path().display()))?;
+        file.write_all(format!("{}\n", serde_json::to_string(entry)?).as_bytes())
+            .await?;
+        drop(file);
+
+        let size = tokio::fs::metadata(self.path()).await?.len();
+        if size > MAX_LOG_BYTES {
+            self.compact().await?;
+        }
+        Ok(())
+    }
+
+    /// Move all but the newest entries to a timestamped archive file.
+    pub async fn compact(&self) -> Result<()> {
+        let entries = self.entries()?;
+        let mut kept_bytes = 0;
+        let mut keep = 0;
+        for entry in entries.iter().rev().take(KEEP_ENTRIES) {
+            kept_bytes += serde_json::to_string(entry)?.len() as u64 + 1;
+            if keep > 0 && kept_bytes > MAX_LOG_BYTES / 2 {
+                break;
+            }
+            keep += 1;
+        }
+        if keep == entries.len() {
+            return Ok(());
+        }
+        let (old, kept) = entries.split_at(entries.len() - keep);
+        self.archive(old).await?;
+        self.write(kept).await
+    }
+
+    /// Archive every entry and leave `entry` as the only one in the log.
+    pub async fn replace_with(&self, entry: &Entry) -> Result<()> {
+        self.archive(&self.entries()?).await?;
+        self.write(std::slice::from_ref(entry)).await
+    }
+
+    async fn archive(&self, entries: &[Entry]) -> Result<()> {
+        let archive_dir = self.archive_dir();
+        tokio::fs::create_dir_all(&archive_dir).await?;
+        let archive = archive_dir.join(format!(
+            "{}.jsonl",
+            chrono::Local::now().format("%Y%m%d-%H%M%S%.3f")
+        ));
+        tokio::fs::write(&archive, to_jsonl(entries)?)
+            .await
+            .with_context(|| format!("Failed to write {}", archive.display()))
+    }
+
+    async fn write(&self, entries: &[Entry]) -> Result<()> {
+        tokio::fs::write(self.path(), to_jsonl(entries)?)
+            .await
+            .with_context(|| format!("Failed to write {}", self.path().display()))
+    }
+}
+
+fn to_jsonl(entries: &[Entry]) -> Result<String> {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&serde_json::to_string(entry)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Record `entry` in the log and rewrite progress.txt from it.
+pub async fn record(entry: Entry) -> Result<()> {
+    let log = open_log().await?;
+    log.append(&entry).await?;
+    render_view(&log).await
+}
+
+/// The progress log in the state directory, importing the notes of a
+/// progress.txt from before the log existed.
+pub async fn open_log() -> Result<ProgressLog> {
+    let log = ProgressLog::default();
+    ensure_state_dir().await?;
+    if !log.path().exists() {
+        let existing = tokio::fs::read_to_string(PROGRESS_FILE)
+            .await
+            .unwrap_or_default();
+        if !existing.trim().is_empty() {
+            log.append(&Entry::note(Status::Imported, &existing))
+                .await?;
+        }
+    }
+    Ok(log)
+}
+
+/// Rewrite progress.txt from the entries in `log`.
+pub async fn render_view(log: &ProgressLog) -> Result<()> {
+    tokio::fs::write(PROGRESS_FILE, render(&log.entries()?))
+        .await
+        .with_context(|| format!("Failed to write {}", PROGRESS_FILE))
+}
+
+/// Notes an agent writes for one task. Sequential tasks append to
+/// progress.txt itself, parallel agents to their own file; either way,
+/// what was added becomes the task's entry in the log.
+#[derive(Debug, Clone)]
+pub struct TaskProgress {
+    task: String,
+    file: PathBuf,
+    before: String,
+    started_at: String,
+}
+
+impl TaskProgress {
+    pub async fn start(task: &str, file: &Path) -> Result<Self> {
+        let before = match tokio::fs::read_to_string(file).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", file.display())),
+        };
+        Ok(Self {
+            task: task.to_string(),
+            file: file.to_path_buf(),
+            before,
+            started_at: chrono::Local::now().to_rfc3339(),
+        })
+    }
+
+    /// The file the agent writes its notes to.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Record the task's notes with how it ended, returning the notes. A
+    /// per-agent file is removed afterwards.
+    pub async fn finish(&self, status: Status) -> Result<String> {
+        let after = tokio::fs::read_to_string(&self.file)
+            .await
+            .unwrap_or_default();
+        let notes = new_notes(&self.before, &after);
+        record(Entry {
+            task_id: Some(task_id(&self.task)),
+            task: Some(self.task.clone()),
+            status,
+            started_at: Some(self.started_at.clone()),
+            finished_at: chrono::Local::now().to_rfc3339(),
+            notes: notes.clone(),
+        })
+        .await?;
+
+        if !self.file.ends_with(PROGRESS_FILE) {
+            tokio::fs::remove_file(&self.file).await.ok();
+        }
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(task: &str, notes: &str) -> Entry {
+        Entry {
+            task_id: Some(task_id(task)),
+            task: Some(task.to_string()),
+            status: Status::Completed,
+            started_at: None,
+            finished_at: "2026-01-02T03:04:05+00:00".to_string(),
+            notes: notes.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_new_notes() {
+        assert_eq!(
+            new_notes("# Progress\n", "# Progress\nAdded login\n"),
+            "Added login"
+        );
+        assert_eq!(
+            new_notes("# Progress\nold\n", "old\nrewritten\n"),
+            "rewritten"
+        );
+        assert_eq!(new_notes("same", "same"), "");
+    }
+
+    #[test]
+    fn test_task_id_is_stable() {
+        assert_eq!(task_id("Add login"), task_id("Add login"));
+        assert_ne!(task_id("Add login"), task_id("Add logout"));
+        assert_eq!(task_id("Add login").len(), 8);
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = render(&[entry("Add login", "Used bcrypt")]);
+        assert!(rendered.starts_with("# Progress\n"));
+        assert!(rendered.contains(&format!(
+            "Add login (completed, {})\nUsed bcrypt\n",
+            task_id("Add login")
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_log_compacts_into_archive() {
+        let dir = TempDir::new().unwrap();
+        let log = ProgressLog::in_dir(dir.path());
+        let notes = "x".repeat(8 * 1024);
+        let count = (MAX_LOG_BYTES / notes.len() as u64) as usize + 2;
+        for i in 0..count {
+            log.append(&entry(&format!("Task {}", i), &notes))
+                .await
+                .unwrap();
+        }
+
+        let kept = log.entries().unwrap();
+        assert!(kept.len() < count);
+        let last = format!("Task {}", count - 1);
+        assert_eq!(kept.last().unwrap().task.as_deref(), Some(last.as_str()));
+        assert!(std::fs::metadata(log.path()).unwrap().len() <= MAX_LOG_BYTES);
+        let all = log.all_entries().unwrap();
+        assert_eq!(all.len(), count);
+        assert_eq!(all[0].task.as_deref(), Some("Task 0"));
+    }
+}
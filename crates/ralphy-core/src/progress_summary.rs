@@ -0,0 +1,132 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::config::RunConfig;
+use crate::events;
+use crate::progress::{self, Entry, Status, PROGRESS_FILE};
+use anyhow::Result;
+use regex::Regex;
+
+/// What a summary's usage is recorded as.
+pub const TASK: &str = "Summarize progress";
+
+/// Prompt asking the engine to condense the progress notes in `view`.
+pub fn summary_prompt(view: &str) -> String {
+    format!(
+        "Summarize the progress notes below. Do not edit any files or run any commands.\n\n\
+         Notes:\n```\n{}\n```\n\n\
+         Keep what a developer picking up the next task needs: what has been built, \
+         decisions and conventions adopted, known problems and failed approaches, and \
+         anything left unfinished. Drop step-by-step narration and repeated details. \
+         Use short markdown bullet points grouped under a few headings, in well under \
+         a quarter of the original length.\n\n\
+         Put the whole summary between <summary> and </summary>.",
+        view.trim()
+    )
+}
+
+/// Pull the summary out of the engine's response; the last block wins.
+pub fn parse_summary(text: &str) -> Option<String> {
+    let summary_re = Regex::new(r"(?s)<summary>(.*?)</summary>").unwrap();
+    let cap = summary_re.captures_iter(text).last()?;
+    let summary = cap[1].trim();
+    if summary.is_empty() {
+        return None;
+    }
+    Some(summary.to_string())
+}
+
+/// Condenses progress.txt over the course of a run.
+#[derive(Debug, Default)]
+pub struct Summarizer {
+    /// Set once a summary fails, so the log is left alone for the rest of
+    /// the run instead of being sent to the engine again before every task
+    failed: bool,
+}
+
+impl Summarizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Once progress.txt is over `--progress-limit`, have the engine condense
+    /// the log into one summary entry, archiving the entries it replaces.
+    /// Returns what the engine used, for the caller to charge. Failures are
+    /// only warned about: a long progress file doesn't stop a run.
+    pub async fn summarize_if_long(&mut self, config: &RunConfig) -> Option<AiResponse> {
+        if self.failed || config.progress_limit_kb == 0 || config.dry_run {
+            return None;
+        }
+        let size = match std::fs::metadata(PROGRESS_FILE) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return None,
+        };
+        if size <= config.progress_limit_kb * 1024 {
+            return None;
+        }
+
+        events::info(format!(
+            "{} is {} KB, summarizing it...",
+            PROGRESS_FILE,
+            size / 1024
+        ));
+        let mut usage = None;
+        if let Err(e) = summarize(config, &mut usage).await {
+            self.failed = true;
+            events::warn(format!(
+                "Could not summarize {}, leaving it as it is for this run: {:#}",
+                PROGRESS_FILE, e
+            ));
+        }
+        usage
+    }
+}
+
+/// Replace the log with the engine's summary of it, leaving the engine's
+/// reply in `usage` even when it has no summary in it.
+async fn summarize(config: &RunConfig, usage: &mut Option<AiResponse>) -> Result<()> {
+    let log = progress::open_log().await?;
+    let entries = log.entries()?;
+    if entries.is_empty() || matches!(&entries[..], [entry] if entry.status == Status::Summary) {
+        anyhow::bail!("the log has nothing left to condense");
+    }
+
+    let executor = AiExecutor::new(config.ai_engine)
+        .with_backend(config.backend)
+        .with_rate_limit(config.rate_limit)
+        .with_model(config.model.as_deref())
+        .with_extra_args(config.engine_args(config.ai_engine))
+        .with_task(TASK);
+    let reply = executor
+        .execute(&summary_prompt(&progress::render(&entries)))
+        .await?;
+    let summary = parse_summary(&reply.text);
+    *usage = Some(reply);
+    let summary = summary.ok_or_else(|| anyhow::anyhow!("no <summary> block in the response"))?;
+
+    let mut entry = Entry::note(Status::Summary, &summary);
+    entry.started_at = entries.first().map(|e| e.finished_at.clone());
+    log.replace_with(&entry).await?;
+    progress::render_view(&log).await?;
+
+    events::info(format!(
+        "Summarized {} entries; the originals are in {}",
+        entries.len(),
+        log.archive_dir().display()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_summary() {
+        let text = "Done.\n<summary>\n## Built\n- login page\n</summary>";
+        assert_eq!(
+            parse_summary(text),
+            Some("## Built\n- login page".to_string())
+        );
+        assert_eq!(parse_summary("<summary>\n</summary>"), None);
+        assert_eq!(parse_summary("no tags"), None);
+    }
+}
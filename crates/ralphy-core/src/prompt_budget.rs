@@ -0,0 +1,54 @@
+use std::path::Path;
+
+/// Rough size of a token in English text and code, as tokenizers like
+/// tiktoken tend to split them.
+pub const BYTES_PER_TOKEN: usize = 4;
+
+/// Tokens `text` is likely to take up.
+pub fn estimate(text: &str) -> usize {
+    text.len().div_ceil(BYTES_PER_TOKEN)
+}
+
+/// Tokens the file at `path` is likely to take up once an engine reads it;
+/// none for a file that can't be read.
+pub fn file_tokens(path: &Path) -> usize {
+    path.metadata()
+        .map_or(0, |m| (m.len() as usize).div_ceil(BYTES_PER_TOKEN))
+}
+
+/// The last whole lines of `text` that fit in `tokens`, or None when not
+/// even the last line does.
+pub fn tail(text: &str, tokens: usize) -> Option<String> {
+    let max_bytes = tokens * BYTES_PER_TOKEN;
+    let text = text.trim_end();
+    if text.len() <= max_bytes {
+        return (!text.is_empty()).then(|| text.to_string());
+    }
+    let start = text
+        .match_indices('\n')
+        .map(|(idx, _)| idx + 1)
+        .find(|&start| text.len() - start <= max_bytes)?;
+    (start < text.len()).then(|| text[start..].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        assert_eq!(estimate(""), 0);
+        assert_eq!(estimate("abcd"), 1);
+        assert_eq!(estimate("abcde"), 2);
+    }
+
+    #[test]
+    fn test_tail() {
+        let notes = "first\nsecond\nthird\n";
+        assert_eq!(tail(notes, 100).as_deref(), Some("first\nsecond\nthird"));
+        // 12 bytes: "third" and "second\nthird" fit, all three don't
+        assert_eq!(tail(notes, 3).as_deref(), Some("second\nthird"));
+        assert_eq!(tail(notes, 1), None);
+        assert_eq!(tail("", 10), None);
+    }
+}
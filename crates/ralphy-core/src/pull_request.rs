@@ -0,0 +1,109 @@
+use crate::ai::AiResponse;
+use crate::config::RunConfig;
+use crate::git::{self, PullRequestOptions};
+use crate::prd::Task;
+use crate::verify;
+use anyhow::Result;
+use std::path::Path;
+
+/// What a finished task's PR body can refer to.
+pub struct Context<'a> {
+    pub task: &'a Task,
+    pub response: &'a AiResponse,
+    pub branch: &'a str,
+    /// Where the task ran
+    pub dir: &'a Path,
+    /// Commit the task started from
+    pub base: Option<&'a str>,
+}
+
+/// How `config` says to open the PR for a finished task, with its body
+/// template filled in.
+pub fn options(config: &RunConfig, context: &Context) -> Result<PullRequestOptions> {
+    let settings = &config.pull_request;
+    let body = match settings.body {
+        Some(ref template) => Some(render(template, |name| value(config, context, name))?),
+        None => None,
+    };
+    Ok(PullRequestOptions {
+        body,
+        draft: config.draft_pr,
+        labels: settings.labels.clone(),
+        reviewers: settings.reviewers.clone(),
+        assignees: settings.assignees.clone(),
+    })
+}
+
+/// The value of `{{name}}`, or None for a name that isn't a placeholder.
+fn value(config: &RunConfig, context: &Context, name: &str) -> Result<Option<String>> {
+    let response = context.response;
+    let value = match name {
+        "task" => context.task.title.clone(),
+        "description" => context.task.details().description.unwrap_or_default(),
+        "branch" => context.branch.to_string(),
+        "engine" => config.ai_engine.to_string(),
+        "model" => response.model.clone().unwrap_or_default(),
+        "tokens" => (response.input_tokens + response.output_tokens).to_string(),
+        "cost" => format!(
+            "${:.4}",
+            config.pricing.response_cost(config.ai_engine, response)
+        ),
+        "files_changed" => match context.base {
+            Some(base) => git::changed_files_since(context.dir, base)?
+                .iter()
+                .map(|file| format!("- `{}`\n", file))
+                .collect(),
+            None => String::new(),
+        },
+        // Only run for templates that ask for it
+        "test_output" if config.verify_cmd.is_empty() => String::new(),
+        "test_output" => verify::output(config, context.dir)?,
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Replace each `{{name}}` in `template` with what `value` gives for it;
+/// names it doesn't know are left as they are.
+pub fn render<F, E>(template: &str, mut value: F) -> Result<String, E>
+where
+    F: FnMut(&str) -> Result<Option<String>, E>,
+{
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + 2 + end + 2];
+        match value(after[..end].trim())? {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(placeholder),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let value = |name: &str| {
+            Ok::<_, anyhow::Error>(match name {
+                "task" => Some("Add login".to_string()),
+                "cost" => Some("$0.1200".to_string()),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            render("## {{task}}\n\nCost: {{ cost }}, {{unknown}} {{", value).unwrap(),
+            "## Add login\n\nCost: $0.1200, {{unknown}} {{"
+        );
+    }
+}
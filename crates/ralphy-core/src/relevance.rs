@@ -0,0 +1,165 @@
+use crate::git;
+use crate::progress::{PROGRESS_FILE, STATE_DIR};
+use std::path::Path;
+
+/// Files picked for a prompt may add at most this many tokens to it, at the
+/// usual estimate of four bytes per token.
+pub const CONTEXT_TOKEN_BUDGET: u64 = 20_000;
+
+/// Files larger than this are never scanned or suggested.
+const MAX_SCANNED_FILE_BYTES: u64 = 200_000;
+
+/// A keyword's hits in a file's contents count up to this many.
+const MAX_HITS_PER_KEYWORD: usize = 10;
+
+/// Words that say nothing about where in the code a task lives.
+const STOP_WORDS: &[&str] = &[
+    "add",
+    "all",
+    "and",
+    "are",
+    "can",
+    "for",
+    "from",
+    "fix",
+    "get",
+    "has",
+    "have",
+    "implement",
+    "into",
+    "make",
+    "new",
+    "not",
+    "now",
+    "only",
+    "set",
+    "should",
+    "support",
+    "that",
+    "the",
+    "then",
+    "this",
+    "use",
+    "when",
+    "with",
+];
+
+/// Lowercase search terms from a task's title and tags.
+pub fn keywords(task: &str, tags: &[String]) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let text = std::iter::once(task).chain(tags.iter().map(String::as_str));
+    for word in text.flat_map(|t| t.split(|c: char| !c.is_alphanumeric())) {
+        let word = word.to_lowercase();
+        if word.len() >= 3 && !STOP_WORDS.contains(&word.as_str()) && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// How well a file matches `keywords`: a keyword in the path counts more
+/// than one in the contents, and 0 means no match at all.
+pub fn score(path: &str, content: &str, keywords: &[String]) -> usize {
+    let path = path.to_lowercase();
+    let content = content.to_lowercase();
+    keywords
+        .iter()
+        .map(|keyword| {
+            let in_path = if path.contains(keyword.as_str()) {
+                MAX_HITS_PER_KEYWORD
+            } else {
+                0
+            };
+            in_path
+                + content
+                    .matches(keyword.as_str())
+                    .take(MAX_HITS_PER_KEYWORD)
+                    .count()
+        })
+        .sum()
+}
+
+/// Up to `limit` files under `root` that best match the task, best first,
+/// limited to files under `under` when given and together staying within
+/// [`CONTEXT_TOKEN_BUDGET`]. Ralphy's own files and the PRD at `prd` are
+/// never picked.
+pub fn relevant_files(
+    root: &Path,
+    under: Option<&str>,
+    prd: Option<&str>,
+    task: &str,
+    tags: &[String],
+    limit: usize,
+) -> Vec<String> {
+    let keywords = keywords(task, tags);
+    if limit == 0 || keywords.is_empty() {
+        return Vec::new();
+    }
+    let Ok(files) = git::listed_files(root) else {
+        return Vec::new();
+    };
+
+    let mut ranked: Vec<(usize, u64, String)> = Vec::new();
+    for file in files {
+        if under.is_some_and(|dir| !file.starts_with(&format!("{}/", dir)))
+            || file == PROGRESS_FILE
+            || prd == Some(file.as_str())
+            || file.starts_with(&format!("{}/", STATE_DIR))
+        {
+            continue;
+        }
+        let path = root.join(&file);
+        let Ok(size) = path.metadata().map(|m| m.len()) else {
+            continue;
+        };
+        if size > MAX_SCANNED_FILE_BYTES {
+            continue;
+        }
+        // Binary files don't read as UTF-8 and are skipped here
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let score = score(&file, &content, &keywords);
+        if score > 0 {
+            ranked.push((score, size, file));
+        }
+    }
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+
+    let mut tokens = 0;
+    let mut picked = Vec::new();
+    for (_, size, file) in ranked {
+        if picked.len() == limit {
+            break;
+        }
+        if tokens + size / 4 > CONTEXT_TOKEN_BUDGET {
+            continue;
+        }
+        tokens += size / 4;
+        picked.push(file);
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(
+            keywords("Add rate limiting to the API", &["backend".to_string()]),
+            ["rate", "limiting", "api", "backend"]
+        );
+    }
+
+    #[test]
+    fn test_score_prefers_path_matches() {
+        let keywords = keywords("Rate limiter", &[]);
+        let by_path = score("src/rate_limit.rs", "fn check() {}", &keywords);
+        let by_content = score("src/server.rs", "// uses the rate limiter", &keywords);
+        assert!(by_path > by_content);
+        assert!(by_content > 0);
+        assert_eq!(score("src/db.rs", "fn query() {}", &keywords), 0);
+    }
+}
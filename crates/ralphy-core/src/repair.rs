@@ -0,0 +1,86 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::events;
+use anyhow::Result;
+use std::future::Future;
+
+/// What a check found wrong with the agent's work, and how to send it back.
+pub struct Repair {
+    /// Says what failed, e.g. "Reviewer requested changes"
+    pub headline: String,
+    /// Shown after the headline; may be empty
+    pub details: String,
+    /// Re-runs the task with what to fix
+    pub prompt: String,
+    /// What the task fails with once rounds run out
+    pub error: anyhow::Error,
+}
+
+/// What one run of a check made of the agent's work.
+#[derive(Default)]
+pub struct Checked {
+    /// What to send back to the agent, if anything needs fixing
+    pub repair: Option<Repair>,
+    /// What the check itself used, such as a reviewer's response
+    pub usage: Option<AiResponse>,
+}
+
+impl Checked {
+    pub fn pass() -> Self {
+        Self::default()
+    }
+
+    pub fn repair(repair: Repair) -> Self {
+        Self {
+            repair: Some(repair),
+            usage: None,
+        }
+    }
+
+    pub fn with_usage(mut self, usage: AiResponse) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+}
+
+/// Run `check` on the agent's work, sending what it finds back to the agent
+/// up to `max_rounds` times until it passes. Fails with the last repair's
+/// error once rounds run out.
+///
+/// Usage of every check and repair round is added to the returned response.
+pub async fn until_passing<F, Fut>(
+    executor: &AiExecutor,
+    max_rounds: usize,
+    mut response: AiResponse,
+    mut check: F,
+) -> Result<AiResponse>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Checked>>,
+{
+    let mut round = 0;
+    loop {
+        let checked = check().await?;
+        if let Some(ref usage) = checked.usage {
+            response.absorb_usage(usage);
+        }
+        let Some(repair) = checked.repair else {
+            return Ok(response);
+        };
+        if round == max_rounds {
+            return Err(repair.error);
+        }
+        round += 1;
+
+        let details = match repair.details.as_str() {
+            "" => String::new(),
+            details if details.contains('\n') => format!(":\n{}", details),
+            details => format!(": {}", details),
+        };
+        events::warn(format!(
+            "{} (round {}/{}){}",
+            repair.headline, round, max_rounds, details
+        ));
+        let again = crate::execute_with_contract(executor, &repair.prompt).await?;
+        response.absorb_usage(&again);
+    }
+}
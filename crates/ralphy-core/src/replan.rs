@@ -0,0 +1,207 @@
+use crate::ai::AiExecutor;
+use crate::config::RunConfig;
+use crate::events::{self, Level};
+use crate::prd::{PrdManager, PrdSource};
+use crate::stats::RunStats;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// What a re-planning round's usage is recorded as.
+pub const TASK: &str = "Re-plan";
+
+/// Most tasks one re-planning round may add, so a confused review can't
+/// bury the PRD.
+pub const MAX_REPLAN_TASKS: usize = 10;
+
+/// PRDs are cut to this many characters so the prompt fits every engine.
+const MAX_GOAL_CHARS: usize = 20_000;
+
+static TASK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<task>(.*?)</task>").unwrap());
+
+/// Reviews finished runs against the PRD as it was when the run started,
+/// adding tasks for whatever is still missing.
+pub struct Replanner {
+    goal: String,
+    rounds: usize,
+    max_rounds: usize,
+}
+
+impl Replanner {
+    /// Capture the goal to re-plan against, or `None` when `--max-replans`
+    /// is 0.
+    pub async fn new(config: &RunConfig, prd_manager: &PrdManager) -> Result<Option<Self>> {
+        if config.max_replans == 0 {
+            return Ok(None);
+        }
+        let goal = match config.prd_source {
+            PrdSource::Markdown { ref path } | PrdSource::Yaml { ref path } => {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+            }
+            // Issue trackers have no document beyond the issues themselves
+            _ => prd_manager
+                .snapshot()
+                .await?
+                .tasks
+                .iter()
+                .map(|task| format!("- {}\n", task.name()))
+                .collect(),
+        };
+        Ok(Some(Self {
+            goal,
+            rounds: 0,
+            max_rounds: config.max_replans,
+        }))
+    }
+
+    /// Once the PRD is drained, ask the engine what the goal still needs and
+    /// add it to the PRD. Returns the tasks added; none when the goal is met,
+    /// rounds are used up, or re-planning failed, which is only warned about.
+    /// What the engine used is recorded in `stats`.
+    pub async fn replan(
+        &mut self,
+        config: &RunConfig,
+        prd_manager: &PrdManager,
+        stats: &mut RunStats,
+    ) -> Vec<String> {
+        if self.rounds >= self.max_rounds {
+            return Vec::new();
+        }
+        self.rounds += 1;
+
+        events::notice(
+            Level::Plain,
+            format!(
+                "\n>>> Re-planning against the PRD (round {}/{})...",
+                self.rounds, self.max_rounds
+            ),
+        );
+        if config.dry_run {
+            events::info(format!(
+                "DRY RUN - Would ask {} for missing tasks",
+                config.ai_engine
+            ));
+            return Vec::new();
+        }
+
+        match self.add_missing_tasks(config, prd_manager, stats).await {
+            Ok(added) if added.is_empty() => {
+                events::notice(Level::Passed, "Goal met; nothing left to add");
+                added
+            }
+            Ok(added) => {
+                events::info(format!("Added {} task(s) to the PRD:", added.len()));
+                for task in &added {
+                    events::notice(Level::Plain, format!("    - {}", task));
+                }
+                added
+            }
+            Err(e) => {
+                events::warn(format!("Could not re-plan: {:#}", e));
+                Vec::new()
+            }
+        }
+    }
+
+    async fn add_missing_tasks(
+        &self,
+        config: &RunConfig,
+        prd_manager: &PrdManager,
+        stats: &mut RunStats,
+    ) -> Result<Vec<String>> {
+        let executor = AiExecutor::new(config.ai_engine)
+            .with_backend(config.backend)
+            .with_rate_limit(config.rate_limit)
+            .with_model(config.model.as_deref())
+            .with_extra_args(config.engine_args(config.ai_engine))
+            .with_task(TASK);
+        let completed = stats.completed_tasks();
+        let response = executor
+            .execute(&replan_prompt(&self.goal, &completed))
+            .await?;
+        stats.record_overhead(TASK, config.ai_engine, &response);
+
+        let open = prd_manager.refresh().await?.names();
+        let added = new_tasks(&response.text, &open, &completed);
+        if !added.is_empty() {
+            prd_manager.add_tasks(&added).await?;
+        }
+        Ok(added)
+    }
+}
+
+/// Prompt asking the engine which tasks the goal still needs.
+pub fn replan_prompt(goal: &str, completed: &[String]) -> String {
+    let goal = crate::text::truncate(goal, MAX_GOAL_CHARS);
+    let completed = if completed.is_empty() {
+        "(none)".to_string()
+    } else {
+        completed
+            .iter()
+            .map(|task| format!("- {}", task))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    format!(
+        "You are checking whether a project has reached its goal. Do not edit any files.\n\n\
+         Goal (the PRD this run started from):\n{}\n\n\
+         Tasks completed this run:\n{}\n\n\
+         Inspect the repository as it is now and compare it against the goal. \
+         List each piece of work the goal still needs on its own line as <task>one-line task description</task>, \
+         at most {} of them. Only list work that is actually missing, not polish. \
+         If the goal is met, list no tasks.",
+        goal.trim(),
+        completed,
+        MAX_REPLAN_TASKS
+    )
+}
+
+/// Tasks in the engine's response that aren't already open or done.
+pub fn new_tasks(text: &str, open: &[String], completed: &[String]) -> Vec<String> {
+    let known = |title: &str| {
+        open.iter()
+            .chain(completed)
+            .any(|task| task.eq_ignore_ascii_case(title))
+    };
+
+    let mut tasks: Vec<String> = Vec::new();
+    for cap in TASK_RE.captures_iter(text) {
+        let title = cap[1].split_whitespace().collect::<Vec<_>>().join(" ");
+        if !title.is_empty() && !known(&title) && !tasks.contains(&title) {
+            tasks.push(title);
+        }
+    }
+    tasks.truncate(MAX_REPLAN_TASKS);
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_tasks_skips_known_ones() {
+        let response = "The API exists but nothing documents it.\n\
+                        <task>Write API docs</task>\n\
+                        <task>add login page</task>\n\
+                        <task>  Add   rate\nlimiting </task>\n\
+                        <task>Write API docs</task>";
+        let open = vec!["Add login page".to_string()];
+        let completed = vec!["Add user endpoint".to_string()];
+        assert_eq!(
+            new_tasks(response, &open, &completed),
+            ["Write API docs", "Add rate limiting"]
+        );
+        assert!(new_tasks("All done.", &open, &completed).is_empty());
+    }
+
+    #[test]
+    fn test_replan_prompt() {
+        let prompt = replan_prompt("# Shop\n- [x] Add cart\n", &["Add cart".to_string()]);
+        assert!(prompt.contains("# Shop\n- [x] Add cart\n\nTasks completed"));
+        assert!(prompt.contains("- Add cart\n\nInspect"));
+        assert!(replan_prompt("", &[]).contains("(none)"));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::ai::AiEngine;
+use crate::config::RepoMapMode;
+use crate::git;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Tokens the map takes up at most unless `--repo-map-tokens` says
+/// otherwise, so it stays a small part of the prompt.
+pub const DEFAULT_TOKENS: usize = 2_000;
+
+/// Rough size of a token, for keeping the map within its budget.
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Symbols listed per file at most.
+const MAX_SYMBOLS_PER_FILE: usize = 8;
+
+/// Files larger than this are listed without symbols.
+const MAX_SCANNED_FILE_BYTES: u64 = 200_000;
+
+/// Whether prompts for `engine` include a repository map.
+pub fn enabled(mode: RepoMapMode, engine: AiEngine) -> bool {
+    match mode {
+        RepoMapMode::Always => true,
+        RepoMapMode::Never => false,
+        // These engines explore the repository least on their own
+        RepoMapMode::Auto => matches!(
+            engine,
+            AiEngine::Codex
+                | AiEngine::Qwen
+                | AiEngine::AnthropicApi
+                | AiEngine::OpenAiApi
+                | AiEngine::Ollama
+        ),
+    }
+}
+
+/// Top-level definitions worth naming, by file extension.
+fn symbol_re(extension: &str) -> Option<&'static Regex> {
+    static RUST: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?m)^(?:pub(?:\([^)]*\))?\s+)?(?:async\s+)?(?:fn|struct|enum|trait|type|mod)\s+(\w+)",
+        )
+        .unwrap()
+    });
+    static PYTHON: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?m)^(?:async\s+)?(?:def|class)\s+(\w+)").unwrap());
+    static SCRIPT: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?m)^(?:export\s+)?(?:default\s+)?(?:async\s+)?(?:function\*?|class|interface|type|enum)\s+(\w+)",
+        )
+        .unwrap()
+    });
+    static GO: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?m)^(?:func(?:\s+\([^)]*\))?|type)\s+(\w+)").unwrap());
+    static RUBY: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?m)^\s*(?:class|module|def)\s+([\w.]+)").unwrap());
+    static JVM: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"(?m)^\s*(?:public\s+|internal\s+)?(?:abstract\s+|final\s+|sealed\s+|data\s+)*(?:class|interface|enum|record|object)\s+(\w+)",
+        )
+        .unwrap()
+    });
+
+    Some(match extension {
+        "rs" => &RUST,
+        "py" => &PYTHON,
+        "js" | "jsx" | "ts" | "tsx" | "mjs" => &SCRIPT,
+        "go" => &GO,
+        "rb" => &RUBY,
+        "java" | "kt" | "cs" => &JVM,
+        _ => return None,
+    })
+}
+
+/// Names defined at the top level of `content`.
+pub fn symbols(extension: &str, content: &str) -> Vec<String> {
+    let Some(re) = symbol_re(extension) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = Vec::new();
+    for cap in re.captures_iter(content) {
+        let name = cap[1].to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        if names.len() == MAX_SYMBOLS_PER_FILE {
+            break;
+        }
+    }
+    names
+}
+
+/// Directory tree of the repository's files with the main symbols of each
+/// source file, limited to files under `under` when given and cut to about
+/// `max_tokens`. Files git ignores are left out.
+pub fn build(root: &Path, under: Option<&str>, max_tokens: usize) -> Option<String> {
+    let max_bytes = max_tokens * BYTES_PER_TOKEN;
+    let files = git::listed_files(root).ok()?;
+    let files: Vec<&String> = files
+        .iter()
+        .filter(|file| under.is_none_or(|dir| file.starts_with(&format!("{}/", dir))))
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+
+    let mut dirs: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for file in &files {
+        let (dir, name) = file.rsplit_once('/').unwrap_or(("", file));
+        dirs.entry(dir).or_default().push(name);
+    }
+
+    let mut map = String::new();
+    let mut listed = 0;
+    'dirs: for (dir, names) in &dirs {
+        let indent = if dir.is_empty() {
+            ""
+        } else {
+            map.push_str(&format!("{}/\n", dir));
+            "  "
+        };
+        for name in names {
+            let path = if dir.is_empty() {
+                root.join(name)
+            } else {
+                root.join(dir).join(name)
+            };
+            let line = match file_symbols(&path) {
+                symbols if symbols.is_empty() => format!("{}{}\n", indent, name),
+                symbols => format!("{}{}: {}\n", indent, name, symbols.join(", ")),
+            };
+            if map.len() + line.len() > max_bytes {
+                break 'dirs;
+            }
+            map.push_str(&line);
+            listed += 1;
+        }
+    }
+
+    if listed < files.len() {
+        map.push_str(&format!("... {} more files\n", files.len() - listed));
+    }
+    Some(map.trim_end().to_string())
+}
+
+fn file_symbols(path: &Path) -> Vec<String> {
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+        return Vec::new();
+    };
+    if symbol_re(extension).is_none()
+        || path
+            .metadata()
+            .map_or(true, |m| m.len() > MAX_SCANNED_FILE_BYTES)
+    {
+        return Vec::new();
+    }
+    std::fs::read_to_string(path)
+        .map(|content| symbols(extension, &content))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_symbols() {
+        let content = "use std::io;\n\npub struct Config {}\n\nimpl Config {\n    pub fn new() -> Self {}\n}\n\npub(crate) async fn run() {}\nfn helper() {}\nenum Mode {}\n";
+        assert_eq!(symbols("rs", content), ["Config", "run", "helper", "Mode"]);
+    }
+
+    #[test]
+    fn test_typescript_and_python_symbols() {
+        let ts = "export default function App() {}\nexport interface Props {}\nconst x = 1;\nclass Store {}\n";
+        assert_eq!(symbols("ts", ts), ["App", "Props", "Store"]);
+
+        let py = "class User:\n    def save(self):\n        pass\n\ndef main():\n    pass\n";
+        assert_eq!(symbols("py", py), ["User", "main"]);
+
+        assert!(symbols("md", "# fn title").is_empty());
+    }
+
+    #[test]
+    fn test_enabled() {
+        assert!(enabled(RepoMapMode::Auto, AiEngine::Codex));
+        assert!(!enabled(RepoMapMode::Auto, AiEngine::Claude));
+        assert!(enabled(RepoMapMode::Always, AiEngine::Claude));
+        assert!(!enabled(RepoMapMode::Never, AiEngine::Qwen));
+    }
+}
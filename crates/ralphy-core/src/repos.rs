@@ -0,0 +1,125 @@
+use crate::{git, log, progress};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Whether a task's `repo:` is a git URL rather than a local path.
+pub fn is_remote(spec: &str) -> bool {
+    spec.contains("://") || spec.starts_with("git@")
+}
+
+/// Resolve a `repo:` from the YAML file at `prd`: URLs are kept as they are,
+/// relative paths are taken from the YAML file's directory.
+pub fn resolve_spec(spec: &str, prd: &Path) -> String {
+    if is_remote(spec) || Path::new(spec).is_absolute() {
+        return spec.to_string();
+    }
+    prd.parent()
+        .unwrap_or(Path::new("."))
+        .join(spec)
+        .display()
+        .to_string()
+}
+
+/// Where a repository URL is cloned: one directory per host, owner and name,
+/// e.g. `.ralphy/repos/github-com/acme/billing` for
+/// `git@github.com:acme/billing.git`.
+pub fn clone_dir(url: &str) -> PathBuf {
+    let location = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+    // Drop any `user@` and turn scp-style `host:owner/name` into a path
+    let location = location
+        .split_once('@')
+        .map_or(location, |(_, rest)| rest)
+        .replacen(':', "/", 1);
+    location
+        .split('/')
+        .map(git::slugify)
+        .filter(|part| !part.is_empty())
+        .fold(
+            PathBuf::from(progress::STATE_DIR).join("repos"),
+            |dir, part| dir.join(part),
+        )
+}
+
+/// Repositories opened for tasks during a run, so each is cloned or fetched
+/// only once.
+#[derive(Debug, Default)]
+pub struct Repos {
+    opened: HashMap<String, PathBuf>,
+}
+
+impl Repos {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Absolute path of the checkout for `spec`: a local repository as it
+    /// is, or a URL cloned under `.ralphy/repos` (fetched if already there).
+    pub fn open(&mut self, spec: &str) -> Result<PathBuf> {
+        if let Some(dir) = self.opened.get(spec) {
+            return Ok(dir.clone());
+        }
+
+        let dir = if is_remote(spec) {
+            let dir = clone_dir(spec);
+            if dir.exists() {
+                if let Err(e) = git::fetch_in(&dir) {
+                    log::warn(e.to_string());
+                }
+            } else {
+                log::info(format!("Cloning {}...", spec));
+                git::clone_repo(spec, &dir)?;
+            }
+            dir
+        } else {
+            PathBuf::from(spec)
+        };
+
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Repository not found: {}", spec))?;
+        if !git::is_git_repo_in(&dir) {
+            anyhow::bail!("{} is not a git repository", dir.display());
+        }
+        self.opened.insert(spec.to_string(), dir.clone());
+        Ok(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_spec() {
+        let prd = Path::new("/work/plans/tasks.yaml");
+        assert_eq!(resolve_spec("../billing", prd), "/work/plans/../billing");
+        assert_eq!(resolve_spec("/src/api", prd), "/src/api");
+        assert_eq!(
+            resolve_spec("https://github.com/acme/api.git", prd),
+            "https://github.com/acme/api.git"
+        );
+    }
+
+    #[test]
+    fn test_clone_dir() {
+        let base = PathBuf::from(progress::STATE_DIR).join("repos");
+        let billing = base.join("github-com").join("acme").join("billing");
+        assert_eq!(clone_dir("git@github.com:acme/billing.git"), billing);
+        assert_eq!(clone_dir("https://github.com/acme/billing/"), billing);
+        assert_eq!(clone_dir("ssh://git@github.com/acme/billing.git"), billing);
+        // Owners that slug the same as a longer repository name stay apart
+        assert_ne!(
+            clone_dir("https://github.com/acme-billing/api"),
+            clone_dir("https://github.com/acme/billing-api")
+        );
+        assert_eq!(
+            clone_dir("https://gitlab.com/../../etc"),
+            base.join("gitlab-com").join("etc")
+        );
+    }
+}
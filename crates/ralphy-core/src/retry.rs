@@ -0,0 +1,131 @@
+use crate::log;
+use std::io;
+use std::process::Output;
+use std::time::Duration;
+
+/// Attempts made for network-bound `git`/`gh` calls before giving up.
+pub const NETWORK_ATTEMPTS: usize = 3;
+
+/// Delay before the first retry; doubled on each subsequent attempt.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// stderr fragments that indicate a failure worth retrying.
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "connection timed out",
+    "operation timed out",
+    "connection reset",
+    "connection refused",
+    "i/o timeout",
+    "tls handshake timeout",
+    "the remote end hung up unexpectedly",
+    "early eof",
+    "unexpected eof",
+    "error connecting to",
+    "bad gateway",
+    "service unavailable",
+    "gateway timeout",
+    "internal server error",
+    "http 500",
+    "http 502",
+    "http 503",
+    "http 504",
+    "the requested url returned error: 5",
+];
+
+/// Whether a failed command's stderr looks like a transient network or
+/// server-side (5xx) error rather than a permanent one.
+pub fn is_transient(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    TRANSIENT_PATTERNS.iter().any(|p| stderr.contains(p))
+}
+
+fn backoff(attempt: usize) -> Duration {
+    BASE_DELAY * 2u32.pow(attempt.saturating_sub(1) as u32)
+}
+
+fn should_retry(output: &Output, attempt: usize, what: &str) -> bool {
+    if output.status.success() || attempt >= NETWORK_ATTEMPTS {
+        return false;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !is_transient(&stderr) {
+        return false;
+    }
+    log::warn(format!(
+        "{} failed with a transient error (attempt {}/{}), retrying in {}s: {}",
+        what,
+        attempt,
+        NETWORK_ATTEMPTS,
+        backoff(attempt).as_secs(),
+        stderr.trim()
+    ));
+    true
+}
+
+fn describe(program: &std::ffi::OsStr, args: std::process::CommandArgs<'_>) -> String {
+    let mut parts = vec![program.to_string_lossy().into_owned()];
+    parts.extend(args.take(2).map(|a| a.to_string_lossy().into_owned()));
+    parts.join(" ")
+}
+
+/// Run a blocking command, retrying transient failures with backoff.
+///
+/// The final `Output` is returned whether or not it succeeded, so callers keep
+/// their own error reporting.
+pub fn output_with_retry(cmd: &mut std::process::Command) -> io::Result<Output> {
+    let what = describe(cmd.get_program(), cmd.get_args());
+    let mut attempt = 1;
+    loop {
+        let output = cmd.output()?;
+        if !should_retry(&output, attempt, &what) {
+            return Ok(output);
+        }
+        std::thread::sleep(backoff(attempt));
+        attempt += 1;
+    }
+}
+
+/// Async counterpart of [`output_with_retry`].
+pub async fn output_with_retry_async(cmd: &mut tokio::process::Command) -> io::Result<Output> {
+    let std_cmd = cmd.as_std();
+    let what = describe(std_cmd.get_program(), std_cmd.get_args());
+    let mut attempt = 1;
+    loop {
+        let output = cmd.output().await?;
+        if !should_retry(&output, attempt, &what) {
+            return Ok(output);
+        }
+        tokio::time::sleep(backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(
+            "fatal: unable to access 'https://github.com/o/r/': Could not resolve host: github.com"
+        ));
+        assert!(is_transient(
+            "HTTP 502: Bad Gateway (https://api.github.com/graphql)"
+        ));
+        assert!(is_transient("fatal: the remote end hung up unexpectedly"));
+        assert!(!is_transient("could not find issue 42"));
+        assert!(!is_transient(
+            "error: failed to push some refs (non-fast-forward)"
+        ));
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+    }
+}
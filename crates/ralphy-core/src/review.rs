@@ -0,0 +1,194 @@
+use crate::ai::{AiEngine, AiExecutor, AiResponse};
+use crate::config::RunConfig;
+use crate::git;
+use crate::repair::{self, Checked, Repair};
+use crate::Rejected;
+use anyhow::Result;
+use regex::Regex;
+
+/// Repair rounds allowed after the reviewer first asks for changes.
+pub const MAX_REVIEW_ROUNDS: usize = 2;
+
+/// Diffs are cut to this size so the review prompt fits every engine.
+const MAX_REVIEW_DIFF_BYTES: usize = 20_000;
+
+/// What the reviewer decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Approve,
+    /// Changes needed, with the feedback to pass back to the agent
+    Changes(String),
+}
+
+/// Prompt asking the reviewer to judge `diff` as a solution to `task`.
+pub fn review_prompt(task: &str, diff: &str) -> String {
+    let diff = git::truncate_diff(diff, MAX_REVIEW_DIFF_BYTES);
+
+    format!(
+        "You are reviewing a change another agent made. Do not edit any files.\n\n\
+         Task:\n{}\n\n\
+         Diff:\n```diff\n{}\n```\n\n\
+         Check that the diff does what the task asks and has no obvious bugs, missing pieces, or unrelated changes. \
+         Don't ask for stylistic changes.\n\n\
+         End your response with exactly one verdict line: <review>APPROVE</review> if the change is acceptable, \
+         or <review>CHANGES: what must be fixed</review> if it is not.",
+        task, diff
+    )
+}
+
+/// Find the reviewer's verdict; the last review block wins.
+pub fn parse_verdict(text: &str) -> Option<Verdict> {
+    let review_re = Regex::new(r"(?is)<review>\s*(.*?)\s*</review>").unwrap();
+    let cap = review_re.captures_iter(text).last()?;
+    let body = cap[1].trim();
+
+    if body.eq_ignore_ascii_case("approve") {
+        return Some(Verdict::Approve);
+    }
+    if body
+        .get(..7)
+        .is_some_and(|head| head.eq_ignore_ascii_case("changes"))
+    {
+        let feedback = body[7..].trim_start_matches(':').trim();
+        return Some(Verdict::Changes(feedback.to_string()));
+    }
+    None
+}
+
+/// Prompt re-running the task with the reviewer's feedback.
+pub fn repair_prompt(original: &str, feedback: &str) -> String {
+    format!(
+        "{}\n\nNOTE: A reviewer rejected your previous attempt at this task with this feedback:\n{}\n\n\
+         Check the current state of the repository and fix these problems.",
+        original, feedback
+    )
+}
+
+/// The PR comment for a review: its verdict, then what the reviewer wrote.
+pub fn comment_body(engine: AiEngine, text: &str) -> String {
+    let review_re = Regex::new(r"(?is)<review>.*?</review>").unwrap();
+    let notes = review_re.replace_all(text, "");
+    let verdict = match parse_verdict(text) {
+        Some(Verdict::Approve) => "Approved".to_string(),
+        Some(Verdict::Changes(feedback)) => format!("Changes requested: {}", feedback),
+        None => "No verdict".to_string(),
+    };
+    let mut body = format!("### Review\n\n**Verdict:** {}\n", verdict);
+    if !notes.trim().is_empty() {
+        body.push_str(&format!("\n{}\n", notes.trim()));
+    }
+    body.push_str(&format!("\n_Reviewed by Ralphy with {}._", engine));
+    body
+}
+
+/// The engine reviewing under `--review`.
+fn reviewer(config: &RunConfig) -> (AiEngine, AiExecutor) {
+    let engine = config.review_engine.unwrap_or(config.ai_engine);
+    // --model names a model of the engine doing the work
+    let model = config
+        .model
+        .as_deref()
+        .filter(|_| engine == config.ai_engine);
+    let executor = AiExecutor::new(engine)
+        .with_backend(config.backend)
+        .with_rate_limit(config.rate_limit)
+        .with_model(model)
+        .with_extra_args(config.engine_args(engine));
+    (engine, executor)
+}
+
+/// Have the reviewer approve everything changed since `base`, sending its
+/// feedback back to the agent until it approves or rounds run out.
+///
+/// Usage of every review and repair round is added to the returned response.
+pub async fn gate(
+    config: &RunConfig,
+    executor: &AiExecutor,
+    prompt: &str,
+    task: &str,
+    base: &str,
+    response: AiResponse,
+) -> Result<AiResponse> {
+    let (_, reviewer) = reviewer(config);
+
+    repair::until_passing(executor, MAX_REVIEW_ROUNDS, response, || async {
+        let diff = git::diff_since(executor.dir(), base)?;
+        let review = reviewer.execute(&review_prompt(task, &diff)).await?;
+
+        let feedback = match parse_verdict(&review.text) {
+            Some(Verdict::Approve) => return Ok(Checked::pass().with_usage(review)),
+            Some(Verdict::Changes(feedback)) => feedback,
+            // Only an explicit approval lets a change through
+            None => {
+                return Err(Rejected(
+                    "Reviewer gave no verdict, so the change was not accepted".into(),
+                )
+                .into())
+            }
+        };
+        Ok(Checked::repair(Repair {
+            headline: "Reviewer requested changes".to_string(),
+            prompt: repair_prompt(prompt, &feedback),
+            error: Rejected(format!("Reviewer rejected the change: {}", feedback)).into(),
+            details: feedback,
+        })
+        .with_usage(review))
+    })
+    .await
+}
+
+/// Review everything changed since `base` once, without holding the task
+/// up, and return the comment to leave on its PR. The review's usage is
+/// added to `response`.
+pub async fn comment(
+    config: &RunConfig,
+    executor: &AiExecutor,
+    task: &str,
+    base: &str,
+    response: &mut AiResponse,
+) -> Result<String> {
+    let (engine, reviewer) = reviewer(config);
+    let diff = git::diff_since(executor.dir(), base)?;
+    let review = reviewer.execute(&review_prompt(task, &diff)).await?;
+    response.absorb_usage(&review);
+    Ok(comment_body(engine, &review.text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verdict() {
+        assert_eq!(
+            parse_verdict("Looks fine.\n<review>APPROVE</review>"),
+            Some(Verdict::Approve)
+        );
+        assert_eq!(
+            parse_verdict("<review>CHANGES: the test is never run</review>"),
+            Some(Verdict::Changes("the test is never run".to_string()))
+        );
+        assert_eq!(parse_verdict("No verdict here"), None);
+    }
+
+    #[test]
+    fn test_comment_body() {
+        let body = comment_body(
+            AiEngine::Claude,
+            "The handler never checks the token.\n<review>CHANGES: check the token</review>",
+        );
+        assert!(body.starts_with("### Review\n\n**Verdict:** Changes requested: check the token\n"));
+        assert!(body.contains("\nThe handler never checks the token.\n"));
+        assert!(!body.contains("<review>"));
+        assert!(comment_body(AiEngine::Codex, "<review>APPROVE</review>")
+            .contains("**Verdict:** Approved\n\n_Reviewed by Ralphy with"));
+    }
+
+    #[test]
+    fn test_review_prompt_truncates_large_diffs() {
+        let diff = "+é".repeat(MAX_REVIEW_DIFF_BYTES);
+        let prompt = review_prompt("task", &diff);
+        assert!(prompt.len() < MAX_REVIEW_DIFF_BYTES + 2_000);
+        assert!(prompt.contains("[diff truncated"));
+    }
+}
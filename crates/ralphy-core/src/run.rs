@@ -0,0 +1,1507 @@
+//! The loop that works through a PRD, one task at a time or in parallel
+//! batches, and everything that runs a single task: the engine, the checks
+//! after it and the commit.
+
+use crate::backend::{self, Backend};
+use crate::budget::{self, Budgets};
+use crate::config::{MergeStrategy, ReviewMode, RunConfig};
+use crate::events::{self, RunEvent};
+use crate::monitor::{AgentMonitor, Monitor};
+use crate::prd::{self, PrdManager};
+use crate::preflight::ToolCheck;
+use crate::session::{self, SessionSlot};
+use crate::settings::TriageTracker;
+use crate::stats::{self, RunStats};
+use crate::{
+    ab, ai, approval, backoff, checkpoint, commit_message, contract, diff_scan,
+    execute_with_contract, followups, gate, git, jira, kubernetes, linear, merge_back, merge_queue,
+    process, progress, progress_summary, prompt, pull_request, replan, repos, review, run_cost,
+    schedule, security, shutdown, text, triage, typical_cost, verify, workspace, Rejected,
+    RunOutcome,
+};
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration, Instant};
+
+/// Work through the configured PRD until it's done or the run is stopped,
+/// showing tasks on `monitor` as they go.
+pub async fn run_autonomous_loop(
+    config: RunConfig,
+    monitor: Arc<dyn Monitor>,
+) -> Result<RunOutcome> {
+    // Pre-flight checks
+    preflight_checks(&config).await?;
+
+    // Create managers
+    let prd_manager = Arc::new(PrdManager::new(config.prd_source.clone()));
+    events::publish(RunEvent::RunStarted {
+        engine: config.ai_engine,
+        source: config.prd_source.display_name(),
+        parallel: config.parallel,
+    });
+
+    if let Some(comparison) = config.ab {
+        return ab::run_ab(&config, &prd_manager, comparison).await;
+    }
+
+    if config.parallel {
+        run_parallel_loop(config, prd_manager, monitor).await
+    } else {
+        run_sequential_loop(config, prd_manager, monitor).await
+    }
+}
+
+async fn preflight_checks(config: &RunConfig) -> Result<()> {
+    // Check every required binary up front so all missing tools are reported together
+    let mut tools = ToolCheck::new();
+    match config.backend {
+        Backend::Local => {
+            let engines = [Some(config.ai_engine), config.ab, config.review_engine];
+            for engine in engines.into_iter().flatten() {
+                if let Some(binary) = ai::engine_binary(engine) {
+                    tools.require(binary, ai::install_hint(engine));
+                }
+            }
+        }
+        // Engines are installed in the container, not here
+        Backend::Devcontainer => {
+            tools.require(
+                "devcontainer",
+                "Install with: npm install -g @devcontainers/cli",
+            );
+        }
+        Backend::Kubernetes => {
+            tools.require(
+                "kubectl",
+                "Install from https://kubernetes.io/docs/tasks/tools/",
+            );
+        }
+    }
+    for engine in [Some(config.ai_engine), config.ab, config.review_engine]
+        .into_iter()
+        .flatten()
+    {
+        ai::check_api_key(engine)?;
+    }
+    match config.prd_source {
+        prd::PrdSource::Jira { .. } => {
+            jira::Settings::from_env().context("The Jira task source needs credentials")?;
+        }
+        prd::PrdSource::Linear { .. } => {
+            linear::Settings::from_env().context("The Linear task source needs an API key")?;
+        }
+        _ => {}
+    }
+    let triage = config.triage.as_ref().map(|triage| triage.tracker);
+    if config.create_pr || config.file_followups || triage == Some(TriageTracker::GitHub) {
+        tools.require(
+            "gh",
+            "GitHub CLI is required for --create-pr, --file-followups and [triage]. Install from https://cli.github.com/",
+        );
+    }
+    match triage {
+        Some(TriageTracker::GitLab) => {
+            tools.require(
+                "glab",
+                "GitLab CLI is required for [triage] tracker = \"gitlab\". Install from https://gitlab.com/gitlab-org/cli",
+            );
+        }
+        Some(TriageTracker::Jira) => {
+            jira::Settings::from_env().context("[triage] tracker = \"jira\" needs credentials")?;
+        }
+        _ => {}
+    }
+    if let Some(ref security) = config.security {
+        for scanner in &security.scanners {
+            let (binary, hint) = scanner.binary();
+            tools.require(binary, hint);
+        }
+    }
+    if let Err(missing) = tools.finish() {
+        events::error(missing.to_string());
+        return Err(missing.into());
+    }
+
+    // Check for git
+    if !git::is_git_repo()? {
+        anyhow::bail!("Not a git repository. Ralphy requires a git repository to track changes.");
+    }
+
+    backend::start(config.backend)?;
+
+    // Create progress.txt if missing
+    if !Path::new(progress::PROGRESS_FILE).exists() {
+        events::warn(format!(
+            "{} not found, creating it...",
+            progress::PROGRESS_FILE
+        ));
+        tokio::fs::write(progress::PROGRESS_FILE, "").await?;
+    }
+
+    Ok(())
+}
+
+async fn run_sequential_loop(
+    config: RunConfig,
+    prd_manager: Arc<PrdManager>,
+    monitor: Arc<dyn Monitor>,
+) -> Result<RunOutcome> {
+    let mut iteration = 0;
+    let run_started = Instant::now();
+    let mut stats = RunStats::new();
+    let mut budgets = Budgets::new(&config.budgets);
+    let mut summarizer = progress_summary::Summarizer::new();
+    let mut repos = repos::Repos::new();
+    let mut followups = followups::Followups::new();
+    let mut replanner = replan::Replanner::new(&config, &prd_manager).await?;
+    let session = config.reuse_session.then(session::session_slot);
+
+    if let Some(resumed) = checkpoint::resume(&config)? {
+        release_interrupted(&prd_manager, &resumed).await;
+        iteration = resumed.iteration;
+        stats = resumed.stats;
+        budgets.restore(resumed.budget_spent);
+    }
+    // Task branches are made from this, and brought back into it
+    let merge_base = match config.merge_strategy {
+        MergeStrategy::None => None,
+        _ => match config.base_branch {
+            Some(ref base) => Some(base.clone()),
+            None => Some(git::get_current_branch(Path::new("."))?),
+        },
+    };
+    let mut task_branches = Vec::new();
+
+    'tasks: loop {
+        if shutdown::requested() {
+            break;
+        }
+
+        // Check if we've hit max iterations
+        if config.max_iterations > 0 && iteration >= config.max_iterations {
+            events::warn(format!(
+                "Reached max iterations ({})",
+                config.max_iterations
+            ));
+            break;
+        }
+
+        // Re-read the PRD once per iteration; the agent may have edited it
+        let snapshot = prd_manager.refresh().await?;
+
+        // Get next task, skipping ones that already failed, ran out of
+        // budget, or wait on a task that hasn't been completed
+        let mut next = None;
+        for entry in &snapshot.tasks {
+            let t = &entry.name();
+            if stats.failed.contains(t)
+                || stats.over_budget.contains(t)
+                || stats.skipped.contains(t)
+            {
+                continue;
+            }
+            if !snapshot.deps_of(t).is_empty() {
+                continue;
+            }
+            if let Some(label) = budgets.exhausted(&entry.budget_labels()) {
+                warn_over_budget(t, label, &budgets);
+                stats.record_over_budget(t);
+                followups.skipped(t, &format!("budget '{}' was used up", label));
+                continue;
+            }
+            next = Some(entry.clone());
+            break;
+        }
+
+        let entry = match next {
+            Some(entry) => entry,
+            None if stats.failed.is_empty()
+                && stats.over_budget.is_empty()
+                && stats.skipped.is_empty() =>
+            {
+                if let Some(ref mut replanner) = replanner {
+                    if !replanner
+                        .replan(&config, &prd_manager, &mut stats)
+                        .await
+                        .is_empty()
+                    {
+                        continue;
+                    }
+                }
+                events::success("All tasks complete!");
+                note_claimed(&snapshot);
+                break;
+            }
+            None => {
+                events::warn(format!(
+                    "No runnable tasks left ({} failed, {} over budget, {} skipped)",
+                    stats.failed.len(),
+                    stats.over_budget.len(),
+                    stats.skipped.len()
+                ));
+                break;
+            }
+        };
+        let task = entry.name();
+        if !claim(&prd_manager, &task).await {
+            continue;
+        }
+
+        iteration += 1;
+        let running = checkpoint::InProgress {
+            task: task.clone(),
+            branch: config.branch_per_task.then(|| git::task_branch_name(&task)),
+        };
+        events::publish(RunEvent::TaskStarted {
+            task: task.clone(),
+            iteration,
+            branch: running.branch.clone(),
+            prd: Some(events::PrdProgress {
+                completed: snapshot.completed,
+                remaining: snapshot.remaining(),
+            }),
+        });
+
+        // Tasks in other repositories still log progress here
+        let mut progress_file = PathBuf::from(progress::PROGRESS_FILE);
+        let repo_dir = match snapshot.repo_of(&task) {
+            Some(spec) => {
+                stats.record_repo(&task, spec);
+                match repos.open(spec) {
+                    Ok(dir) => {
+                        events::info(format!("Working in {}", dir.display()));
+                        progress_file = std::env::current_dir()?.join(progress_file);
+                        Some(dir)
+                    }
+                    Err(e) => {
+                        events::error(format!("{:#}", e));
+                        events::publish(RunEvent::task_failed(&task, &e, 0, None));
+                        release(&prd_manager, &task).await;
+                        stats.record_failure(&task);
+                        stats.record_error(&e);
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+        let workdir = match repo_dir {
+            Some(ref dir) => Workdir::Repo(dir),
+            None => Workdir::Here,
+        };
+
+        if let Some(usage) = summarizer.summarize_if_long(&config).await {
+            stats.record_overhead(progress_summary::TASK, config.ai_engine, &usage);
+            let cost = config.pricing.response_cost(config.ai_engine, &usage);
+            budgets.charge(&entry.budget_labels(), cost);
+        }
+        let task_progress = progress::TaskProgress::start(&task, &progress_file).await?;
+        checkpoint::Checkpoint::new(iteration, &stats, &budgets, vec![running])
+            .save()
+            .await?;
+
+        // Execute task with retries
+        let task_started = Instant::now();
+        let mut retry_count = 0;
+        let mut errors = Vec::new();
+        let response = loop {
+            let session = session.clone();
+            // Retries are told how the last attempt failed
+            let attempt = Attempt {
+                previous_failure: errors.last().map(String::as_str),
+                typical_cost: typical_cost(&config.pricing, &stats),
+                monitor: Some(monitor.as_ref()),
+                agent: None,
+            };
+            let (result, spent) = ai::tally(execute_task(
+                &config,
+                &entry,
+                iteration,
+                &progress_file,
+                workdir,
+                session,
+                attempt,
+            ))
+            .await;
+            match result {
+                Ok(resp) => break resp,
+                Err(e) => {
+                    // A failed attempt's usage counts against the limits
+                    // like any other
+                    charge_failed_attempts(
+                        &config,
+                        &mut stats,
+                        &mut budgets,
+                        &task,
+                        &entry.budget_labels(),
+                        &spent,
+                    );
+                    if let Some(&declined) = e.downcast_ref::<approval::Declined>() {
+                        events::info(format!("{}: {}", declined, task));
+                        task_progress.finish(progress::Status::Skipped).await?;
+                        release(&prd_manager, &task).await;
+                        stats.record_skipped(&task);
+                        if declined == approval::Declined::Stop {
+                            break 'tasks;
+                        }
+                        continue 'tasks;
+                    }
+                    if shutdown::requested() {
+                        events::warn(format!("Task interrupted: {}", e));
+                        task_progress.finish(progress::Status::Interrupted).await?;
+                        release(&prd_manager, &task).await;
+                        break 'tasks;
+                    }
+                    if stop_at_run_limit(&config, &mut stats, &prd_manager).await {
+                        task_progress.finish(progress::Status::Interrupted).await?;
+                        release(&prd_manager, &task).await;
+                        break 'tasks;
+                    }
+                    retry_count += 1;
+                    errors.push(format!("{:#}", e));
+                    if retry_count >= config.max_retries || !backoff::is_retryable(&e) {
+                        events::error(format!(
+                            "Task failed after {} attempt{}: {}",
+                            retry_count,
+                            if retry_count == 1 { "" } else { "s" },
+                            e
+                        ));
+                        events::publish(RunEvent::task_failed(
+                            &task,
+                            &e,
+                            retry_count,
+                            config.branch_per_task.then(|| git::task_branch_name(&task)),
+                        ));
+                        // Leave the task incomplete and continue to the next one
+                        release(&prd_manager, &task).await;
+                        stats.record_failure(&task);
+                        stats.record_error(&e);
+                        let notes = task_progress.finish(progress::Status::Failed).await?;
+                        triage::report(
+                            &config,
+                            &triage::Failure {
+                                task: task.clone(),
+                                errors,
+                                notes,
+                                branch: git::get_current_branch(workdir.path()).ok(),
+                                engine: config.ai_engine.to_string(),
+                            },
+                        )
+                        .await;
+                        continue 'tasks;
+                    }
+                    let wait = config.retry_backoff.jittered(retry_count);
+                    events::warn(format!(
+                        "Attempt {}/{} failed: {}. Retrying in {:.1}s...",
+                        retry_count,
+                        config.max_retries,
+                        e,
+                        wait.as_secs_f64()
+                    ));
+                    tokio::select! {
+                        _ = sleep(wait) => {}
+                        _ = shutdown::wait() => {
+                            task_progress.finish(progress::Status::Interrupted).await?;
+                            release(&prd_manager, &task).await;
+                            break 'tasks;
+                        }
+                    }
+                }
+            }
+        };
+
+        task_progress.finish(progress::Status::Completed).await?;
+
+        // Update totals
+        let wall = task_started.elapsed();
+        stats.record(&task, config.ai_engine, &response, wall);
+        let cost = config.pricing.response_cost(config.ai_engine, &response);
+        events::publish(RunEvent::task_completed(
+            &task,
+            &response,
+            cost,
+            wall,
+            config.branch_per_task.then(|| git::task_branch_name(&task)),
+        ));
+        followups.collect(&task, &response.text);
+        budgets.charge(&entry.budget_labels(), cost);
+        // Branches in other repositories are left for their owners
+        if config.branch_per_task && repo_dir.is_none() {
+            task_branches.push(merge_back::TaskBranch {
+                task: task.clone(),
+                branch: git::task_branch_name(&task),
+            });
+        }
+
+        // Mark task complete
+        prd_manager.mark_complete(&task).await?;
+        checkpoint::Checkpoint::new(iteration, &stats, &budgets, Vec::new())
+            .save()
+            .await?;
+
+        if !response.text.is_empty() {
+            events::notice(events::Level::Plain, format!("\n{}", response.text));
+        }
+
+        if stop_at_run_limit(&config, &mut stats, &prd_manager).await {
+            break;
+        }
+
+        // Trust the engine's word that nothing is left rather than running
+        // it again on tasks it has already covered
+        if contract::declares_complete(&response.text, &config.completion_marker)
+            && stop_at_declared_completion(&mut stats, &prd_manager).await?
+        {
+            break;
+        }
+    }
+    if let (Some(ref base), false) = (merge_base, shutdown::requested() || config.dry_run) {
+        merge_back::run(&config, base, &task_branches)?;
+    }
+
+    if shutdown::requested() {
+        checkpoint_interrupted(&prd_manager).await?;
+        stats.iterations = iteration;
+        stats.wall_ms = run_started.elapsed().as_millis() as u64;
+        events::publish(RunEvent::run_interrupted(&stats, &config.pricing));
+        return Ok(RunOutcome::WorkRemaining);
+    }
+    // A run stopped at a limit can carry on with --resume once it is raised
+    if stats.limit_reached.is_none() {
+        checkpoint::Checkpoint::clear()?;
+    }
+
+    stats.iterations = iteration;
+    stats.wall_ms = run_started.elapsed().as_millis() as u64;
+    events::publish(RunEvent::run_finished(
+        &stats,
+        &config.pricing,
+        stats.outcome(),
+    ));
+    if config.file_followups {
+        followups.file(&config).await;
+    }
+
+    Ok(stats.outcome())
+}
+
+async fn run_parallel_loop(
+    config: RunConfig,
+    prd_manager: Arc<PrdManager>,
+    monitor: Arc<dyn Monitor>,
+) -> Result<RunOutcome> {
+    events::info(format!(
+        "Running {} parallel agents ({})...",
+        config.max_parallel,
+        if config.merge_queue {
+            "each in its own worktree, merged one at a time"
+        } else if config.push_branches {
+            "each in its own worktree, pushed as its own branch"
+        } else if config.backend == Backend::Kubernetes {
+            "each as a Kubernetes Job pushing its own branch"
+        } else {
+            "sharing the working directory"
+        }
+    ));
+
+    let mut snapshot = prd_manager.snapshot().await?;
+    let mut all_tasks = snapshot.names();
+    if all_tasks.is_empty() {
+        events::info("No tasks to run");
+        note_claimed(&snapshot);
+        return Ok(RunOutcome::Complete);
+    }
+
+    // Worktrees and Jobs are set up from the current repository only
+    if !snapshot.repos.is_empty()
+        && (config.merge_queue || config.push_branches || config.backend == Backend::Kubernetes)
+    {
+        anyhow::bail!(
+            "Tasks with a repo: can't run with --merge-queue, --push-branches or --backend kubernetes; \
+             run them with plain --parallel or sequentially"
+        );
+    }
+
+    events::info(format!("Found {} tasks to process", all_tasks.len()));
+
+    let run_started = Instant::now();
+    let mut stats = RunStats::new();
+    let mut budgets = Budgets::new(&config.budgets);
+    let mut summarizer = progress_summary::Summarizer::new();
+    let mut repos = repos::Repos::new();
+    let mut followups = followups::Followups::new();
+    let mut iteration = 0;
+    // Branches interrupted tasks left their work on, to carry on from
+    let mut earlier_branches: HashMap<String, String> = HashMap::new();
+
+    if let Some(resumed) = checkpoint::resume(&config)? {
+        release_interrupted(&prd_manager, &resumed).await;
+        iteration = resumed.iteration;
+        stats = resumed.stats;
+        budgets.restore(resumed.budget_spent);
+        earlier_branches = resumed
+            .in_progress
+            .into_iter()
+            .filter_map(|running| Some((running.task, running.branch?)))
+            .collect();
+        // Tasks that already failed stay failed, as they would have had the
+        // run not been interrupted
+        all_tasks.retain(|task| !stats.failed.contains(task));
+    }
+
+    // Each task is one iteration, so the cap applies as tasks are dispatched
+    // rather than after a whole batch has run, and covers replanned tasks too
+    let at_cap = |iteration: usize| config.max_iterations > 0 && iteration >= config.max_iterations;
+    let mut capped = false;
+
+    let mut replanner = replan::Replanner::new(&config, &prd_manager).await?;
+    // One session per agent slot, handed to whichever task runs in it
+    let sessions: Vec<SessionSlot> = match config.reuse_session {
+        true => (0..config.max_parallel)
+            .map(|_| session::session_slot())
+            .collect(),
+        false => Vec::new(),
+    };
+
+    let mut pending: VecDeque<String> = all_tasks.into();
+    let mut batch_num = 0;
+
+    // Process tasks in batches
+    loop {
+        if shutdown::requested() {
+            break;
+        }
+
+        if pending.is_empty() {
+            let drained =
+                !at_cap(iteration) && stats.failed.is_empty() && stats.over_budget.is_empty();
+            let added = match replanner {
+                Some(ref mut replanner) if drained => {
+                    replanner.replan(&config, &prd_manager, &mut stats).await
+                }
+                _ => Vec::new(),
+            };
+            if added.is_empty() {
+                break;
+            }
+            snapshot = prd_manager.refresh().await?;
+            pending.extend(added);
+        }
+        if at_cap(iteration) {
+            capped = true;
+            break;
+        }
+
+        // Budgets are charged after each batch, so drop tasks whose budget
+        // the previous batches used up
+        pending.retain(|task| match budgets.exhausted(&snapshot.labels_of(task)) {
+            Some(label) => {
+                warn_over_budget(task, label, &budgets);
+                stats.record_over_budget(task);
+                followups.skipped(task, &format!("budget '{}' was used up", label));
+                false
+            }
+            None => true,
+        });
+
+        // Tasks wait for a later batch until their dependencies are done,
+        // and while their file hints overlap a task in the same repository.
+        // A fresh read only lists dependencies that aren't complete yet.
+        let waiting = if snapshot.deps.is_empty() {
+            HashMap::new()
+        } else {
+            prd_manager.refresh().await?.deps
+        };
+        let (chunk, deferred) = schedule::next_batch(
+            &mut pending,
+            config.max_parallel,
+            |task| waiting.contains_key(task),
+            |a, b| {
+                snapshot.repo_of(a) == snapshot.repo_of(b)
+                    && schedule::files_overlap(&snapshot.files_of(a), &snapshot.files_of(b))
+            },
+        );
+        if chunk.is_empty() {
+            if !pending.is_empty() {
+                events::warn(format!(
+                    "{} task(s) wait on tasks that did not complete",
+                    pending.len()
+                ));
+            }
+            break;
+        }
+
+        batch_num += 1;
+        let completed_before = stats.agents.len();
+        let failed_before = stats.failed.len();
+        events::info(format!(
+            "Batch {}: Spawning {} parallel agents",
+            batch_num,
+            chunk.len()
+        ));
+        if deferred > 0 {
+            events::info(format!(
+                "Deferred {} task(s) that share files with this batch",
+                deferred
+            ));
+        }
+
+        if let Some(usage) = summarizer.summarize_if_long(&config).await {
+            stats.record_overhead(progress_summary::TASK, config.ai_engine, &usage);
+            // The summary is for the whole batch, so it counts against each
+            // budget the batch's tasks do
+            let cost = config.pricing.response_cost(config.ai_engine, &usage);
+            let mut labels: Vec<String> = chunk
+                .iter()
+                .flat_map(|task| snapshot.labels_of(task))
+                .collect();
+            labels.sort();
+            labels.dedup();
+            budgets.charge(&labels, cost);
+        }
+        let mut handles = vec![];
+        let mut running = Vec::new();
+
+        // Task branches all start from the base as it is before this batch
+        let base = if config.merge_queue || config.push_branches {
+            Some(git::head_commit()?)
+        } else {
+            None
+        };
+
+        let dashboard = match config.tui {
+            true => monitor
+                .batch(config.ai_engine, chunk.len())
+                .map_err(|e| events::warn(format!("{:#}", e)))
+                .ok(),
+            false => None,
+        };
+
+        for (slot, task) in chunk.into_iter().enumerate() {
+            if at_cap(iteration) {
+                capped = true;
+                break;
+            }
+            if !claim(&prd_manager, &task).await {
+                continue;
+            }
+            iteration += 1;
+            let session = sessions.get(slot).cloned();
+            let config_clone = config.clone();
+            let task_clone = task.clone();
+            // Replanned tasks may not be in the snapshot under the name
+            // they were added with
+            let entry = snapshot
+                .task(&task)
+                .cloned()
+                .unwrap_or_else(|| prd::Task::new(task.clone()));
+
+            // Each agent gets its own progress file so concurrent writes don't interleave
+            let mut progress_file = progress::agent_progress_path(iteration);
+            progress::prepare_agent_file(&progress_file).await?;
+
+            let branch = match base {
+                Some(ref base) => {
+                    // The agent runs in the worktree, so point it back at this directory
+                    progress_file = std::env::current_dir()?.join(progress_file);
+                    Some(match earlier_branches.remove(&task) {
+                        Some(earlier) => merge_queue::TaskBranch::resume(&entry, &earlier, base)?,
+                        None => merge_queue::TaskBranch::create(&entry, base)?,
+                    })
+                }
+                None => None,
+            };
+
+            let repo_dir = match snapshot.repo_of(&task) {
+                Some(spec) => {
+                    stats.record_repo(&task, spec);
+                    match repos.open(spec) {
+                        Ok(dir) => {
+                            progress_file = std::env::current_dir()?.join(progress_file);
+                            Some(dir)
+                        }
+                        Err(e) => {
+                            events::publish(RunEvent::task_failed(&task, &e, 0, None));
+                            release(&prd_manager, &task).await;
+                            stats.record_failure(&task);
+                            stats.record_error(&e);
+                            events::notice(events::Level::Failed, format!("{:#}", e));
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let task_progress = progress::TaskProgress::start(&task, &progress_file).await?;
+            running.push(checkpoint::InProgress {
+                task: task.clone(),
+                branch: branch.as_ref().map(|branch| branch.branch.clone()),
+            });
+            events::publish(RunEvent::TaskStarted {
+                task: task.clone(),
+                iteration,
+                branch: branch.as_ref().map(|branch| branch.branch.clone()),
+                prd: None,
+            });
+
+            let row = dashboard.as_ref().map(|dashboard| dashboard.add(&task));
+            let handle = events::spawn(async move {
+                let started = Instant::now();
+                let workdir = match (&branch, &repo_dir) {
+                    (Some(branch), _) => Workdir::Worktree(&branch.dir),
+                    (None, Some(dir)) => Workdir::Repo(dir),
+                    (None, None) => Workdir::Here,
+                };
+                let mut errors = Vec::new();
+                // What failed attempts used, charged once the batch is in
+                let mut wasted = ai::AiResponse::default();
+                let result = loop {
+                    let result = if config_clone.backend == Backend::Kubernetes {
+                        kubernetes::run_task(&config_clone, &task_clone, iteration).await
+                    } else {
+                        let attempt = Attempt {
+                            previous_failure: errors.last().map(String::as_str),
+                            agent: row.as_deref(),
+                            ..Default::default()
+                        };
+                        let (result, spent) = ai::tally(execute_task(
+                            &config_clone,
+                            &entry,
+                            iteration,
+                            task_progress.file(),
+                            workdir,
+                            session.clone(),
+                            attempt,
+                        ))
+                        .await;
+                        if result.is_err() {
+                            wasted.absorb_usage(&spent);
+                        }
+                        result
+                    };
+                    let e = match result {
+                        Err(e)
+                            if errors.len() + 1 < config_clone.max_retries
+                                && backoff::is_retryable(&e)
+                                && !shutdown::requested() =>
+                        {
+                            e
+                        }
+                        result => break result,
+                    };
+                    errors.push(format!("{:#}", e));
+                    let wait = config_clone.retry_backoff.jittered(errors.len());
+                    match row {
+                        Some(ref row) => {
+                            row.steps()
+                                .send_replace(format!("Retrying in {:.0}s", wait.as_secs_f64()));
+                        }
+                        None => events::warn(format!(
+                            "Attempt {}/{} failed: {} - {}. Retrying in {:.1}s...",
+                            errors.len(),
+                            config_clone.max_retries,
+                            text::truncate(&task_clone, 50),
+                            e,
+                            wait.as_secs_f64()
+                        )),
+                    }
+                    tokio::select! {
+                        _ = sleep(wait) => {}
+                        _ = shutdown::wait() => break Err(e),
+                    }
+                };
+                if let Some(ref row) = row {
+                    row.finish(result.as_ref().ok());
+                }
+                let attempts = errors.len() + 1;
+                (
+                    task_clone,
+                    task_progress,
+                    branch,
+                    started.elapsed(),
+                    result,
+                    attempts,
+                    wasted,
+                )
+            });
+
+            handles.push(handle);
+        }
+
+        checkpoint::Checkpoint::new(iteration, &stats, &budgets, running)
+            .save()
+            .await?;
+
+        // Wait for all parallel tasks
+        let results = join_all(handles).await;
+        if let Some(dashboard) = dashboard {
+            dashboard.finish().await;
+        }
+        let mut queue = Vec::new();
+
+        // Process results
+        let mut declared_complete = false;
+        for result in results {
+            match result {
+                Ok((task, task_progress, branch, wall, Ok(response), _, wasted)) => {
+                    declared_complete |=
+                        contract::declares_complete(&response.text, &config.completion_marker);
+                    charge_failed_attempts(
+                        &config,
+                        &mut stats,
+                        &mut budgets,
+                        &task,
+                        &snapshot.labels_of(&task),
+                        &wasted,
+                    );
+                    task_progress.finish(progress::Status::Completed).await?;
+                    stats.record(&task, config.ai_engine, &response, wall);
+                    let cost = config.pricing.response_cost(config.ai_engine, &response);
+                    events::publish(RunEvent::task_completed(
+                        &task,
+                        &response,
+                        cost,
+                        wall,
+                        branch.as_ref().map(|branch| branch.branch.clone()),
+                    ));
+                    followups.collect(&task, &response.text);
+                    budgets.charge(&snapshot.labels_of(&task), cost);
+
+                    events::notice(
+                        events::Level::Passed,
+                        format!("Agent completed: {}", text::truncate(&task, 50)),
+                    );
+
+                    match (branch, &base) {
+                        (Some(branch), Some(base)) => match branch.finish(&config, base) {
+                            Ok(()) => queue.push((branch, response)),
+                            Err(e) => {
+                                events::publish(RunEvent::task_failed(
+                                    &task,
+                                    &e,
+                                    1,
+                                    Some(branch.branch.clone()),
+                                ));
+                                release(&prd_manager, &task).await;
+                                stats.record_failure(&task);
+                                stats.record_error(&e);
+                                events::notice(
+                                    events::Level::Failed,
+                                    format!("Could not commit {}: {}", branch.branch, e),
+                                );
+                            }
+                        },
+                        // A pod's pushed branch still needs review, so its
+                        // task stays in progress until that branch merges
+                        _ if config.backend == Backend::Kubernetes => {}
+                        _ => prd_manager.mark_complete(&task).await?,
+                    }
+                }
+                Ok((task, task_progress, branch, _, Err(e), attempts, wasted)) => {
+                    charge_failed_attempts(
+                        &config,
+                        &mut stats,
+                        &mut budgets,
+                        &task,
+                        &snapshot.labels_of(&task),
+                        &wasted,
+                    );
+                    let notes = task_progress.finish(progress::Status::Failed).await?;
+                    release(&prd_manager, &task).await;
+                    stats.record_failure(&task);
+                    stats.record_error(&e);
+                    events::notice(
+                        events::Level::Failed,
+                        format!("Agent failed: {} - {}", text::truncate(&task, 50), e),
+                    );
+                    let branch = match branch {
+                        Some(branch) => Some(branch.branch),
+                        None => match snapshot.repo_of(&task) {
+                            Some(spec) => repos.open(spec).ok(),
+                            None => Some(PathBuf::from(".")),
+                        }
+                        .and_then(|dir| git::get_current_branch(&dir).ok()),
+                    };
+                    events::publish(RunEvent::task_failed(&task, &e, attempts, branch.clone()));
+                    triage::report(
+                        &config,
+                        &triage::Failure {
+                            task,
+                            errors: vec![format!("{:#}", e)],
+                            notes,
+                            branch,
+                            engine: config.ai_engine.to_string(),
+                        },
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    events::notice(events::Level::Failed, format!("Task join error: {}", e));
+                }
+            }
+        }
+
+        // Merge finished branches one at a time so each merge sees the last,
+        // or push them for review
+        for (branch, response) in queue {
+            let landed = if config.push_branches {
+                merge_queue::push(&config, &branch, base.as_deref(), &response)
+            } else {
+                let (merged, spent) = ai::tally(merge_queue::merge(&config, &branch)).await;
+                if spent.has_usage() {
+                    stats.record_overhead(merge_queue::RESOLVE_TASK, config.ai_engine, &spent);
+                }
+                merged.map(|()| format!("Merged {}", branch.branch))
+            };
+            match landed {
+                Ok(outcome) => {
+                    // Pushed branches still need review, so their tasks stay
+                    // in progress until they merge
+                    if !config.push_branches {
+                        prd_manager.mark_complete(&branch.task).await?;
+                    }
+                    let cleanup = if config.push_branches {
+                        branch.remove_worktree()
+                    } else {
+                        branch.remove()
+                    };
+                    if let Err(e) = cleanup {
+                        events::warn(e.to_string());
+                    }
+                    events::notice(events::Level::Passed, outcome);
+                }
+                Err(e) => {
+                    events::publish(RunEvent::task_failed(
+                        &branch.task,
+                        &e,
+                        1,
+                        Some(branch.branch.clone()),
+                    ));
+                    release(&prd_manager, &branch.task).await;
+                    stats.record_failure(&branch.task);
+                    stats.record_error(&e);
+                    events::notice(
+                        events::Level::Failed,
+                        format!(
+                            "Could not {} {} (left in {}): {:#}",
+                            if config.push_branches {
+                                "push"
+                            } else {
+                                "merge"
+                            },
+                            branch.branch,
+                            branch.dir.display(),
+                            e
+                        ),
+                    );
+                    triage::report(
+                        &config,
+                        &triage::Failure {
+                            task: branch.task.clone(),
+                            errors: vec![format!("{:#}", e)],
+                            notes: String::new(),
+                            branch: Some(branch.branch.clone()),
+                            engine: config.ai_engine.to_string(),
+                        },
+                    )
+                    .await;
+                }
+            }
+        }
+        checkpoint::Checkpoint::new(iteration, &stats, &budgets, Vec::new())
+            .save()
+            .await?;
+
+        events::publish(RunEvent::BatchFinished {
+            batch: batch_num,
+            completed: stats.agents.len() - completed_before,
+            failed: stats.failed.len() - failed_before,
+        });
+
+        if stop_at_run_limit(&config, &mut stats, &prd_manager).await {
+            break;
+        }
+        if declared_complete && stop_at_declared_completion(&mut stats, &prd_manager).await? {
+            break;
+        }
+    }
+
+    if capped && !shutdown::requested() && stats.limit_reached.is_none() {
+        events::warn(format!(
+            "Reached max iterations ({})",
+            config.max_iterations
+        ));
+    }
+
+    if shutdown::requested() {
+        checkpoint_interrupted(&prd_manager).await?;
+        stats.iterations = iteration;
+        stats.wall_ms = run_started.elapsed().as_millis() as u64;
+        events::publish(RunEvent::run_interrupted(&stats, &config.pricing));
+        return Ok(RunOutcome::WorkRemaining);
+    }
+    // A run stopped at a limit can carry on with --resume once it is raised
+    if stats.limit_reached.is_none() {
+        checkpoint::Checkpoint::clear()?;
+    }
+
+    stats.iterations = iteration;
+    stats.wall_ms = run_started.elapsed().as_millis() as u64;
+    events::publish(RunEvent::run_finished(
+        &stats,
+        &config.pricing,
+        stats.outcome(),
+    ));
+    if config.file_followups {
+        followups.file(&config).await;
+    }
+
+    Ok(stats.outcome())
+}
+
+/// Stop the run if it has reached `--max-cost` or `--max-tokens`, saying how
+/// far it got. Costs engines don't report are estimated.
+async fn stop_at_run_limit(
+    config: &RunConfig,
+    stats: &mut RunStats,
+    prd_manager: &PrdManager,
+) -> bool {
+    let Some(reason) = budget::run_limit_reached(
+        config.max_cost,
+        config.max_tokens,
+        run_cost(&config.pricing, stats),
+        stats.input_tokens + stats.output_tokens,
+    ) else {
+        return false;
+    };
+
+    let remaining = match prd_manager.refresh().await {
+        Ok(snapshot) => snapshot.remaining(),
+        Err(_) => 0,
+    };
+    events::warn(format!(
+        "Stopping: {} limit after {} task(s), {} remaining; raise it and run again with --resume to carry on",
+        reason,
+        stats.agents.len(),
+        remaining
+    ));
+    stats.limit_reached = Some(reason);
+    true
+}
+
+/// Run one of a task's engine steps under `--task-timeout`, if set. Each
+/// step gets the whole limit, so a slow review doesn't eat into the time
+/// the engine had for the task itself.
+async fn within_task_timeout<T>(
+    config: &RunConfig,
+    step: &str,
+    work: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(secs) = config.task_timeout else {
+        return work.await;
+    };
+    let limit = Duration::from_secs(secs);
+    let grace = Duration::from_secs(config.timeout_grace);
+    process::with_timeout(limit, grace, work)
+        .await
+        .unwrap_or_else(|| {
+            Err(anyhow::anyhow!(
+                "{} timed out after {}",
+                step,
+                stats::format_duration(secs * 1000)
+            ))
+        })
+}
+
+/// Stop the run after the engine declared the PRD complete, unless it is:
+/// tasks still unchecked are left for the next run, and the run ends as
+/// incomplete rather than finished. Returns false when nothing is left, so
+/// the run ends as it would have anyway.
+async fn stop_at_declared_completion(
+    stats: &mut RunStats,
+    prd_manager: &PrdManager,
+) -> Result<bool> {
+    let remaining = prd_manager.refresh().await?.remaining();
+    if remaining == 0 {
+        return Ok(false);
+    }
+    events::warn(format!(
+        "The engine declared the PRD complete; stopping with {} task(s) still unchecked",
+        remaining
+    ));
+    stats.left_unchecked = remaining;
+    Ok(true)
+}
+
+/// Record what failed attempts at `task` used, so `--max-cost`,
+/// `--max-tokens` and the task's `--budget` labels see it too.
+fn charge_failed_attempts(
+    config: &RunConfig,
+    stats: &mut RunStats,
+    budgets: &mut Budgets,
+    task: &str,
+    labels: &[String],
+    spent: &ai::AiResponse,
+) {
+    if !spent.has_usage() {
+        return;
+    }
+    stats.record_overhead(
+        &format!("{} (failed attempt)", task),
+        config.ai_engine,
+        spent,
+    );
+    let cost = config.pricing.response_cost(config.ai_engine, spent);
+    budgets.charge(labels, cost);
+}
+
+fn warn_over_budget(task: &str, label: &str, budgets: &Budgets) {
+    events::warn(format!(
+        "Skipping {}: budget '{}' used up (${:.4} of ${:.2})",
+        text::truncate(task, 50),
+        label,
+        budgets.spent(label),
+        budgets.limit(label).unwrap_or(0.0)
+    ));
+}
+
+/// Claim `task` in the PRD before it runs. Returns false when another run
+/// got to it first; a source that can't be updated only gets a warning and
+/// the task runs regardless.
+async fn claim(prd_manager: &PrdManager, task: &str) -> bool {
+    match prd_manager.mark_started(task).await {
+        Ok(true) => true,
+        Ok(false) => {
+            events::info(format!(
+                "Skipping {}: another run has started it",
+                text::truncate(task, 50)
+            ));
+            false
+        }
+        Err(e) => {
+            events::warn(format!("{:#}", e));
+            true
+        }
+    }
+}
+
+/// Hand `task` back to the PRD after it failed or was cut short, so a later
+/// run picks it up again.
+async fn release(prd_manager: &PrdManager, task: &str) {
+    if let Err(e) = prd_manager.release(task).await {
+        events::warn(format!("{:#}", e));
+    }
+}
+
+/// Release the tasks a killed run had claimed; they run again in this one.
+async fn release_interrupted(prd_manager: &PrdManager, resumed: &checkpoint::Checkpoint) {
+    for running in &resumed.in_progress {
+        release(prd_manager, &running.task).await;
+    }
+}
+
+/// Mention tasks left out of the run because they are marked in progress.
+fn note_claimed(snapshot: &prd::PrdSnapshot) {
+    if snapshot.in_progress > 0 {
+        events::info(format!(
+            "{} task(s) are marked in progress by another run, or one that was killed",
+            snapshot.in_progress
+        ));
+    }
+}
+
+/// Record an interrupted run in the progress log so the next run can pick up
+/// where this one stopped.
+async fn checkpoint_interrupted(prd_manager: &PrdManager) -> Result<()> {
+    let remaining = match prd_manager.refresh().await {
+        Ok(snapshot) => snapshot.remaining(),
+        Err(_) => 0,
+    };
+    let note = format!("Run interrupted; {} task(s) remaining.", remaining);
+    progress::record(progress::Entry::note(progress::Status::Interrupted, &note)).await?;
+
+    events::warn(format!(
+        "Run interrupted with {} task(s) remaining; run again with --resume to carry on",
+        remaining
+    ));
+    Ok(())
+}
+
+/// What the loop knows about an attempt before starting it.
+#[derive(Clone, Copy, Default)]
+struct Attempt<'a> {
+    /// How the previous attempt at the task failed
+    previous_failure: Option<&'a str>,
+    /// Average cost of the tasks finished so far, shown by `--interactive`
+    typical_cost: Option<f64>,
+    /// Shows the task while it runs on its own
+    monitor: Option<&'a dyn Monitor>,
+    /// The task's `--tui` dashboard row
+    agent: Option<&'a dyn AgentMonitor>,
+}
+
+/// Where a task's engine runs.
+#[derive(Debug, Clone, Copy)]
+enum Workdir<'a> {
+    /// The current directory
+    Here,
+    /// A merge-queue worktree of this repository
+    Worktree(&'a Path),
+    /// Another repository, named by the task's `repo:`
+    Repo(&'a Path),
+}
+
+impl Workdir<'_> {
+    fn path(&self) -> &Path {
+        match self {
+            Workdir::Here => Path::new("."),
+            Workdir::Worktree(dir) | Workdir::Repo(dir) => dir,
+        }
+    }
+}
+
+async fn execute_task(
+    config: &RunConfig,
+    entry: &prd::Task,
+    iteration: usize,
+    progress_file: &Path,
+    workdir: Workdir<'_>,
+    session: Option<SessionSlot>,
+    attempt: Attempt<'_>,
+) -> Result<ai::AiResponse> {
+    let progress_file = progress_file.to_string_lossy();
+    let name = entry.name();
+    let task = name.as_str();
+
+    // Tasks tagged with a workspace package are confined to it
+    let tags = entry.budget_labels();
+    let details = entry.details();
+    let workspace = detect_workspace(workdir.path());
+    let package = workspace.package_for(&tags);
+    let scope = prompt::TaskScope {
+        other_repo: matches!(workdir, Workdir::Repo(_)),
+        package,
+        tags: &tags,
+        details: (!details.is_empty()).then_some(&details),
+        previous_failure: attempt.previous_failure,
+    };
+
+    if config.dry_run {
+        events::info("DRY RUN - Would execute:");
+        let prompt = prompt::build_scoped_prompt(config, task, &progress_file, scope);
+        events::notice(events::Level::Plain, prompt);
+        return Ok(ai::AiResponse {
+            text: "Dry run".to_string(),
+            input_tokens: 0,
+            output_tokens: 0,
+            actual_cost: None,
+            duration_ms: None,
+            model: None,
+        });
+    }
+
+    // Build prompt
+    let mut prompt = prompt::build_scoped_prompt(config, task, &progress_file, scope);
+    if config.interactive {
+        let price = config
+            .pricing
+            .price(config.ai_engine, config.model.as_deref());
+        prompt = approval::confirm(task, prompt, attempt.typical_cost, price).await?;
+    }
+
+    // Create branch if needed
+    if config.branch_per_task {
+        let branch = git::create_task_branch(workdir.path(), task, config.base_branch.as_deref())?;
+        events::publish(RunEvent::BranchCreated {
+            task: task.to_string(),
+            branch,
+        });
+    }
+
+    // Review, package checks, gates, commit rewriting and PR bodies cover
+    // everything changed from here on
+    let task_base = if config.review
+        || config.rewrite_commit_messages
+        || config.create_pr
+        || package.is_some()
+        || config.gate_script.is_some()
+        || config.security.is_some()
+    {
+        Some(git::head_commit_in(workdir.path())?)
+    } else {
+        None
+    };
+
+    // Only findings the task introduces block it
+    let security_baseline = match config.security {
+        Some(ref security) => Some(security::scan(security, workdir.path())?),
+        None => None,
+    };
+
+    // Secrets are looked for in everything the task adds; a repository
+    // without commits yet is scanned whole
+    let scan_base = match config.diff_scan {
+        Some(_) => task_base
+            .clone()
+            .or_else(|| git::head_commit_in(workdir.path()).ok()),
+        None => None,
+    };
+
+    // Execute AI
+    let (step_tx, step_rx) = ai::step_channel();
+    let mut executor = ai::AiExecutor::new(config.ai_engine)
+        .with_backend(config.backend)
+        .with_rate_limit(config.rate_limit)
+        .with_model(config.model.as_deref())
+        .with_extra_args(config.engine_args(config.ai_engine))
+        .with_completion_marker(&config.completion_marker)
+        .with_steps(step_tx)
+        .with_task(task);
+    if let Workdir::Worktree(dir) | Workdir::Repo(dir) = workdir {
+        executor = executor.with_dir(dir);
+    }
+    if let Some(session) = session {
+        executor = executor.with_session(session);
+    }
+    if let Some(agent) = attempt.agent {
+        executor = executor.with_steps(agent.steps()).with_usage(agent.usage());
+    }
+
+    // Start progress monitor
+    let monitor_handle = attempt
+        .monitor
+        .map(|monitor| monitor.task(task, config.ai_engine, step_rx));
+
+    let work = async {
+        let mut response =
+            within_task_timeout(config, "Task", execute_with_contract(&executor, &prompt)).await?;
+        let mut review_comment = None;
+        if !config.verify_cmd.is_empty() {
+            let verified = verify::enforce(config, &executor, &prompt, response);
+            response = within_task_timeout(config, "Verification", verified).await?;
+        }
+        if let Some(ref base) = task_base {
+            if let Some(package) = package {
+                workspace::check_package(config, workdir.path(), package, base)?;
+            }
+            if config.review {
+                match config.review_mode {
+                    ReviewMode::Fix => {
+                        let reviewed =
+                            review::gate(config, &executor, &prompt, task, base, response);
+                        response = within_task_timeout(config, "Review", reviewed).await?;
+                    }
+                    ReviewMode::Comment => {
+                        let comment = review::comment(config, &executor, task, base, &mut response);
+                        review_comment =
+                            Some(within_task_timeout(config, "Review", comment).await?);
+                    }
+                }
+            }
+            if let Some(ref script) = config.gate_script {
+                let info = gate::TaskInfo {
+                    title: task.to_string(),
+                    tags: tags.to_vec(),
+                    iteration,
+                    engine: config.ai_engine.to_string(),
+                    package: package.map(|p| p.name.clone()),
+                };
+                let checks = gate::Checks {
+                    tests_skipped: config.skip_tests,
+                    lint_skipped: config.skip_lint,
+                    package_tests: package.map(|_| !config.skip_tests),
+                    review: config.review.then_some(true),
+                    verified: !config.verify_cmd.is_empty(),
+                };
+                let gated =
+                    gate::enforce(script, &executor, &prompt, &info, &checks, base, response);
+                response = within_task_timeout(config, "Gate", gated).await?;
+            }
+            if let (Some(security), Some(baseline)) = (&config.security, &security_baseline) {
+                let secured =
+                    security::enforce(security, &executor, &prompt, baseline, base, response);
+                response = within_task_timeout(config, "Security check", secured).await?;
+            }
+        }
+        if let Some(ref settings) = config.diff_scan {
+            diff_scan::check(workdir.path(), scan_base.as_deref(), settings)?;
+        }
+        if let (true, Some(ref base)) = (config.rewrite_commit_messages, &task_base) {
+            let committed =
+                commit_message::commit_task(config, &executor, task, &tags, base, &mut response);
+            within_task_timeout(config, "Commit message", committed).await?;
+        }
+        let message =
+            commit_message::fallback_message(config, task, &tags, format!("ralphy: {}", task));
+        if config.auto_commit && git::auto_commit(workdir.path(), &message)? {
+            events::info("Committed changes the engine left uncommitted");
+        }
+        Ok::<_, anyhow::Error>((response, review_comment))
+    };
+    let response = work.await;
+
+    // Stop monitor
+    if let Some(handle) = monitor_handle {
+        handle.finish(response.is_ok()).await;
+    }
+    if let (Err(e), Some(base)) = (&response, task_base.as_ref().or(scan_base.as_ref())) {
+        if e.is::<Rejected>() {
+            drop_rejected(workdir.path(), base);
+        }
+    }
+    let (response, review_comment) = response?;
+
+    // Create PR if needed
+    if config.create_pr && config.branch_per_task {
+        let context = pull_request::Context {
+            task: entry,
+            response: &response,
+            branch: &git::task_branch_name(task),
+            dir: workdir.path(),
+            base: task_base.as_deref(),
+        };
+        let options = pull_request::options(config, &context)?;
+        let url = git::create_pull_request(workdir.path(), entry, &options)?;
+        if let Some(ref comment) = review_comment {
+            match git::comment_on_pull_request_in(workdir.path(), &url, comment) {
+                Ok(()) => events::info("Posted the review on the PR"),
+                Err(e) => events::warn(format!("Could not post the review: {:#}", e)),
+            }
+        }
+        events::publish(RunEvent::PullRequestOpened {
+            task: task.to_string(),
+            branch: git::task_branch_name(task),
+            url,
+        });
+    }
+
+    Ok(response)
+}
+
+/// Move the branch at `dir` back to `base`, the commit a task started from,
+/// so the commits of a change that was turned down don't stay in history.
+fn drop_rejected(dir: &Path, base: &str) {
+    if git::head_commit_in(dir).is_ok_and(|head| head == base) {
+        return;
+    }
+    match git::reset_keep_in(dir, base) {
+        Ok(()) => events::info("Dropped the rejected change's commits"),
+        Err(e) => events::warn(format!(
+            "Could not drop the rejected change's commits: {:#}",
+            e
+        )),
+    }
+}
+
+/// Packages of the workspace at `dir`; a workspace that can't be read just
+/// leaves tasks unscoped.
+pub fn detect_workspace(dir: &Path) -> workspace::Workspace {
+    workspace::Workspace::detect(dir).unwrap_or_else(|e| {
+        events::warn(format!("{:#}", e));
+        workspace::Workspace::default()
+    })
+}
@@ -0,0 +1,181 @@
+use glob::{MatchOptions, Pattern};
+use std::collections::VecDeque;
+use std::path::Path;
+
+/// Whether two tasks' ownership hints could cover any of the same files.
+///
+/// Plain paths cover everything below them (`src/api` overlaps
+/// `src/api/user.rs`). Globs are compared conservatively: when in doubt they
+/// count as overlapping, since a false overlap only costs parallelism.
+pub fn files_overlap(a: &[String], b: &[String]) -> bool {
+    a.iter().any(|x| b.iter().any(|y| hints_overlap(x, y)))
+}
+
+fn hints_overlap(a: &str, b: &str) -> bool {
+    let a = normalize(a);
+    let b = normalize(b);
+    match (is_glob(a), is_glob(b)) {
+        (false, false) => covers(a, b) || covers(b, a),
+        (true, true) => {
+            let (pa, pb) = (literal_prefix(a), literal_prefix(b));
+            covers(pa, pb) || covers(pb, pa)
+        }
+        (true, false) => glob_overlaps_path(a, b),
+        (false, true) => glob_overlaps_path(b, a),
+    }
+}
+
+fn glob_overlaps_path(glob: &str, path: &str) -> bool {
+    let prefix = literal_prefix(glob);
+    if covers(path, prefix) {
+        // The path is a directory holding everything the glob can match
+        return true;
+    }
+    if !covers(prefix, path) {
+        return false;
+    }
+
+    // Under the glob's base: a file must match it, but a directory may
+    // contain matches, so anything without an extension counts
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    glob.contains("**")
+        || Path::new(path).extension().is_none()
+        || Pattern::new(glob).map_or(true, |p| p.matches_with(path, options))
+}
+
+fn is_glob(hint: &str) -> bool {
+    hint.contains(['*', '?', '['])
+}
+
+/// The directories of `hint` before its first glob character.
+fn literal_prefix(hint: &str) -> &str {
+    let first_glob = hint.find(['*', '?', '[']).unwrap_or(hint.len());
+    match hint[..first_glob].rfind('/') {
+        Some(slash) => &hint[..slash],
+        None => "",
+    }
+}
+
+fn covers(dir: &str, path: &str) -> bool {
+    dir.is_empty() || path == dir || path.starts_with(&format!("{}/", dir))
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_end_matches('/')
+}
+
+/// Take up to `max` tasks from the front of `pending` for the next parallel
+/// batch, leaving any still waiting on another task, or that conflict with a
+/// task already in the batch, for a later one. Returns the batch and how
+/// many tasks were deferred for conflicts.
+pub fn next_batch(
+    pending: &mut VecDeque<String>,
+    max: usize,
+    waiting: impl Fn(&str) -> bool,
+    conflicts: impl Fn(&str, &str) -> bool,
+) -> (Vec<String>, usize) {
+    let mut batch: Vec<String> = Vec::new();
+    let mut held = VecDeque::new();
+    let mut count = 0;
+
+    while batch.len() < max {
+        let Some(task) = pending.pop_front() else {
+            break;
+        };
+        if waiting(&task) {
+            held.push_back(task);
+        } else if batch.iter().any(|other| conflicts(other, &task)) {
+            count += 1;
+            held.push_back(task);
+        } else {
+            batch.push(task);
+        }
+    }
+
+    // Held tasks keep their place at the front of the queue
+    while let Some(task) = held.pop_back() {
+        pending.push_front(task);
+    }
+    (batch, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_files_overlap() {
+        assert!(files_overlap(
+            &files(&["src/api"]),
+            &files(&["./src/api/user.rs"])
+        ));
+        assert!(files_overlap(
+            &files(&["README.md"]),
+            &files(&["README.md"])
+        ));
+        assert!(!files_overlap(
+            &files(&["src/api"]),
+            &files(&["src/api_v2.rs"])
+        ));
+        assert!(!files_overlap(&[], &files(&["src"])));
+    }
+
+    #[test]
+    fn test_glob_ownership_overlap() {
+        let api = files(&["src/api/*.rs"]);
+        assert!(files_overlap(&api, &files(&["src/api/user.rs"])));
+        assert!(files_overlap(&api, &files(&["src"])));
+        assert!(files_overlap(&api, &files(&["src/api/**"])));
+        assert!(!files_overlap(&api, &files(&["src/api/user.ts"])));
+        assert!(!files_overlap(&api, &files(&["src/web/**"])));
+        assert!(!files_overlap(&api, &files(&["docs/api.md"])));
+        // Globs are relative to the repository root
+        assert!(files_overlap(&files(&["*.md"]), &files(&["README.md"])));
+        assert!(!files_overlap(&files(&["*.md"]), &files(&["src/lib.rs"])));
+    }
+
+    #[test]
+    fn test_next_batch_defers_conflicting_tasks() {
+        let mut pending: VecDeque<String> = ["a1", "a2", "b", "c", "d"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        // Tasks sharing a first letter touch the same files
+        let conflicts = |x: &str, y: &str| x[..1] == y[..1];
+
+        let (batch, deferred) = next_batch(&mut pending, 3, |_| false, conflicts);
+        assert_eq!(batch, ["a1", "b", "c"]);
+        assert_eq!(deferred, 1);
+        assert_eq!(pending, ["a2", "d"]);
+
+        let (batch, deferred) = next_batch(&mut pending, 3, |_| false, conflicts);
+        assert_eq!(batch, ["a2", "d"]);
+        assert_eq!(deferred, 0);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_next_batch_holds_waiting_tasks() {
+        let mut pending: VecDeque<String> = ["schema", "api", "docs", "ui"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        let waiting = |t: &str| t == "api" || t == "ui";
+
+        let (batch, deferred) = next_batch(&mut pending, 3, waiting, |_, _| false);
+        assert_eq!(batch, ["schema", "docs"]);
+        assert_eq!(deferred, 0);
+        assert_eq!(pending, ["api", "ui"]);
+
+        let (batch, _) = next_batch(&mut pending, 3, waiting, |_, _| false);
+        assert!(batch.is_empty());
+        assert_eq!(pending, ["api", "ui"]);
+    }
+}
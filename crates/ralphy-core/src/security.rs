@@ -0,0 +1,382 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::git;
+use crate::repair::{self, Checked, Repair};
+use crate::settings::SecuritySettings;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+/// Repair rounds allowed after a scan first finds something new.
+pub const MAX_SCAN_ROUNDS: usize = 2;
+
+/// Semgrep rules used when `[security]` doesn't name any.
+pub const DEFAULT_SEMGREP_CONFIG: &str = "auto";
+
+/// Scanners the security gate can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scanner {
+    /// `cargo audit` against Cargo.lock
+    CargoAudit,
+    /// `npm audit` against package-lock.json
+    NpmAudit,
+    /// `semgrep` against the source
+    Semgrep,
+}
+
+impl std::fmt::Display for Scanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scanner::CargoAudit => write!(f, "cargo-audit"),
+            Scanner::NpmAudit => write!(f, "npm-audit"),
+            Scanner::Semgrep => write!(f, "semgrep"),
+        }
+    }
+}
+
+/// A problem a scanner reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub scanner: Scanner,
+    /// Identifies the finding across scans; stays the same when code moves
+    pub id: String,
+    pub summary: String,
+}
+
+impl Scanner {
+    /// Binary that has to be installed, with how to get it.
+    pub fn binary(self) -> (&'static str, &'static str) {
+        match self {
+            Scanner::CargoAudit => ("cargo-audit", "Install with: cargo install cargo-audit"),
+            Scanner::NpmAudit => ("npm", "Install Node.js from https://nodejs.org/"),
+            Scanner::Semgrep => ("semgrep", "Install with: pip install semgrep"),
+        }
+    }
+
+    /// Lockfile an audit checks; semgrep checks the source instead.
+    pub fn lockfile(self) -> Option<&'static str> {
+        match self {
+            Scanner::CargoAudit => Some("Cargo.lock"),
+            Scanner::NpmAudit => Some("package-lock.json"),
+            Scanner::Semgrep => None,
+        }
+    }
+
+    /// Whether there is anything for the scanner to check in `dir`.
+    pub fn applies(self, dir: &Path) -> bool {
+        self.lockfile()
+            .is_none_or(|lockfile| dir.join(lockfile).exists())
+    }
+
+    /// The scanner's command; semgrep looks only at `targets` when given.
+    fn command(self, dir: &Path, semgrep_config: &str, targets: &[String]) -> Command {
+        let mut cmd = match self {
+            Scanner::CargoAudit => {
+                let mut cmd = Command::new("cargo");
+                cmd.args(["audit", "--json"]);
+                cmd
+            }
+            Scanner::NpmAudit => {
+                let mut cmd = Command::new("npm");
+                cmd.args(["audit", "--json"]);
+                cmd
+            }
+            Scanner::Semgrep => {
+                let mut cmd = Command::new("semgrep");
+                cmd.args(["scan", "--json", "--quiet", "--config", semgrep_config]);
+                if !targets.is_empty() {
+                    cmd.arg("--").args(targets);
+                }
+                cmd
+            }
+        };
+        cmd.current_dir(dir);
+        cmd
+    }
+
+    /// Read the scanner's JSON report.
+    pub fn parse(self, report: &Value) -> Vec<Finding> {
+        let finding = |id: String, summary: String| Finding {
+            scanner: self,
+            id,
+            summary,
+        };
+        match self {
+            Scanner::CargoAudit => report["vulnerabilities"]["list"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|vuln| {
+                    let advisory = &vuln["advisory"];
+                    let package = format!(
+                        "{} {}",
+                        vuln["package"]["name"].as_str().unwrap_or("?"),
+                        vuln["package"]["version"].as_str().unwrap_or("?")
+                    );
+                    finding(
+                        format!("{} {}", advisory["id"].as_str().unwrap_or("?"), package),
+                        format!(
+                            "{} in {}: {}",
+                            advisory["id"].as_str().unwrap_or("?"),
+                            package,
+                            advisory["title"].as_str().unwrap_or("")
+                        ),
+                    )
+                })
+                .collect(),
+            // Only direct advisories; packages that are vulnerable through a
+            // dependency point at that dependency's entry
+            Scanner::NpmAudit => report["vulnerabilities"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .flat_map(|(name, vuln)| {
+                    let severity = vuln["severity"].as_str().unwrap_or("unknown");
+                    vuln["via"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|via| via.is_object())
+                        .map(move |via| {
+                            let advisory = via["url"]
+                                .as_str()
+                                .map(str::to_string)
+                                .unwrap_or_else(|| via["source"].to_string());
+                            finding(
+                                format!("{} {}", name, advisory),
+                                format!(
+                                    "{} ({}): {} {}",
+                                    name,
+                                    severity,
+                                    via["title"].as_str().unwrap_or(""),
+                                    advisory
+                                ),
+                            )
+                        })
+                })
+                .collect(),
+            // Keyed by the matched code rather than its line, so findings
+            // that only moved aren't new
+            Scanner::Semgrep => report["results"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|result| {
+                    let rule = result["check_id"].as_str().unwrap_or("?");
+                    let path = result["path"].as_str().unwrap_or("?");
+                    let code = result["extra"]["lines"]
+                        .as_str()
+                        .unwrap_or("")
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    finding(
+                        format!("{} {} {}", rule, path, code),
+                        format!(
+                            "{}:{} {}: {}",
+                            path,
+                            result["start"]["line"].as_u64().unwrap_or(0),
+                            rule,
+                            result["extra"]["message"].as_str().unwrap_or("").trim()
+                        ),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Run the scanner in `dir`, over only `targets` if any are given.
+    /// Audits exit non-zero when they find something, so only a report that
+    /// isn't JSON counts as a failure.
+    pub fn run(self, dir: &Path, semgrep_config: &str, targets: &[String]) -> Result<Vec<Finding>> {
+        let output = self
+            .command(dir, semgrep_config, targets)
+            .output()
+            .with_context(|| format!("Failed to run {}", self))?;
+        let report: Value = serde_json::from_slice(&output.stdout).map_err(|_| {
+            anyhow::anyhow!(
+                "{} did not produce a JSON report: {}",
+                self,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        })?;
+        Ok(self.parse(&report))
+    }
+}
+
+fn semgrep_config(settings: &SecuritySettings) -> &str {
+    settings
+        .semgrep_config
+        .as_deref()
+        .unwrap_or(DEFAULT_SEMGREP_CONFIG)
+}
+
+/// Findings from every configured scanner that applies to `dir`.
+pub fn scan(settings: &SecuritySettings, dir: &Path) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for scanner in &settings.scanners {
+        if scanner.applies(dir) {
+            findings.extend(scanner.run(dir, semgrep_config(settings), &[])?);
+        }
+    }
+    Ok(findings)
+}
+
+/// Findings in the files of `dir` listed in `changed`: semgrep looks at the
+/// changed files that still exist, and an audit runs only if its lockfile
+/// changed.
+pub fn scan_changed(
+    settings: &SecuritySettings,
+    dir: &Path,
+    changed: &[String],
+) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for scanner in &settings.scanners {
+        let targets = match scanner.lockfile() {
+            Some(lockfile) => {
+                if !changed.iter().any(|file| file == lockfile) || !scanner.applies(dir) {
+                    continue;
+                }
+                Vec::new()
+            }
+            None => {
+                let targets: Vec<String> = changed
+                    .iter()
+                    .filter(|file| dir.join(file).is_file())
+                    .cloned()
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                targets
+            }
+        };
+        findings.extend(scanner.run(dir, semgrep_config(settings), &targets)?);
+    }
+    Ok(findings)
+}
+
+/// Findings in `after` that weren't in `before`.
+pub fn new_findings(before: &[Finding], after: Vec<Finding>) -> Vec<Finding> {
+    after
+        .into_iter()
+        .filter(|f| {
+            !before
+                .iter()
+                .any(|b| b.scanner == f.scanner && b.id == f.id)
+        })
+        .collect()
+}
+
+fn report(findings: &[Finding]) -> String {
+    findings
+        .iter()
+        .map(|f| format!("- [{}] {}", f.scanner, f.summary))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prompt re-running the task with the findings it introduced.
+pub fn repair_prompt(original: &str, findings: &[Finding]) -> String {
+    format!(
+        "{}\n\nNOTE: Security scanners found problems your previous attempt at this task introduced:\n{}\n\n\
+         Check the current state of the repository and fix them without disabling the scanners.",
+        original,
+        report(findings)
+    )
+}
+
+/// Have the scanners find nothing in what changed since `base` beyond
+/// `baseline`, the findings from before the task, sending new findings back
+/// to the agent until they are gone or rounds run out.
+///
+/// Usage of every repair round is added to the returned response.
+pub async fn enforce(
+    settings: &SecuritySettings,
+    executor: &AiExecutor,
+    prompt: &str,
+    baseline: &[Finding],
+    base: &str,
+    response: AiResponse,
+) -> Result<AiResponse> {
+    let dir = executor.dir();
+
+    repair::until_passing(executor, MAX_SCAN_ROUNDS, response, || async {
+        let changed = git::changed_files_since(dir, base)?;
+        let found = new_findings(baseline, scan_changed(settings, dir, &changed)?);
+        if found.is_empty() {
+            return Ok(Checked::pass());
+        }
+        Ok(Checked::repair(Repair {
+            headline: format!("Security scan found {} new problem(s)", found.len()),
+            details: report(&found),
+            prompt: repair_prompt(prompt, &found),
+            error: anyhow::anyhow!(
+                "Security scan still finds {} new problem(s):\n{}",
+                found.len(),
+                report(&found)
+            ),
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_reports() {
+        let cargo = json!({"vulnerabilities": {"list": [{
+            "advisory": {"id": "RUSTSEC-2020-0071", "title": "Potential segfault"},
+            "package": {"name": "time", "version": "0.1.43"}
+        }]}});
+        let findings = Scanner::CargoAudit.parse(&cargo);
+        assert_eq!(findings[0].id, "RUSTSEC-2020-0071 time 0.1.43");
+        assert!(findings[0].summary.ends_with("Potential segfault"));
+
+        let npm = json!({"vulnerabilities": {
+            "lodash": {"severity": "high", "via": [{
+                "title": "Prototype Pollution",
+                "url": "https://github.com/advisories/GHSA-p6mc-m468-83gw"
+            }]},
+            "app-utils": {"severity": "high", "via": ["lodash"]}
+        }});
+        let findings = Scanner::NpmAudit.parse(&npm);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].id,
+            "lodash https://github.com/advisories/GHSA-p6mc-m468-83gw"
+        );
+
+        let semgrep = json!({"results": [{
+            "check_id": "python.lang.security.audit.eval",
+            "path": "app.py",
+            "start": {"line": 12},
+            "extra": {"message": "eval on user input", "lines": "  eval(request.args['q'])\n"}
+        }]});
+        let findings = Scanner::Semgrep.parse(&semgrep);
+        assert_eq!(
+            findings[0].id,
+            "python.lang.security.audit.eval app.py eval(request.args['q'])"
+        );
+        assert!(findings[0].summary.starts_with("app.py:12 "));
+        assert!(Scanner::Semgrep.parse(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_new_findings() {
+        let finding = |id: &str| Finding {
+            scanner: Scanner::Semgrep,
+            id: id.to_string(),
+            summary: id.to_string(),
+        };
+        let before = [finding("old")];
+        let found = new_findings(&before, vec![finding("old"), finding("new")]);
+        assert_eq!(found, [finding("new")]);
+        assert!(repair_prompt("Do it", &found).contains("- [semgrep] new"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::ai::AiResponse;
+use crate::process::EngineChild;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{ChildStdin, ChildStdout, Command};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// A session one worker hands from task to task. Empty until the first
+/// prompt starts it, and emptied again when the engine dies so the next
+/// prompt starts afresh.
+pub type SessionSlot = Arc<Mutex<Option<EngineSession>>>;
+
+pub fn session_slot() -> SessionSlot {
+    Arc::new(Mutex::new(None))
+}
+
+/// A prompt's turn on the session in a slot, holding the slot until it ends.
+///
+/// A turn that doesn't finish, because it failed or was dropped by a timeout
+/// or Ctrl-C, empties the slot: the process may still be answering, and the
+/// next prompt would read that answer as its own.
+pub struct Turn<'a> {
+    slot: MutexGuard<'a, Option<EngineSession>>,
+    finished: bool,
+}
+
+impl<'a> Turn<'a> {
+    pub async fn begin(slot: &'a SessionSlot) -> Turn<'a> {
+        Turn {
+            slot: slot.lock().await,
+            finished: false,
+        }
+    }
+
+    /// The session the turn runs on, if one is running.
+    pub fn slot(&mut self) -> &mut Option<EngineSession> {
+        &mut self.slot
+    }
+
+    /// Run `prompt` on the session, which must have been started.
+    pub async fn send(&mut self, prompt: &str) -> Result<AiResponse> {
+        let session = self.slot.as_mut().context("No Claude session to send to")?;
+        // A task timeout has to reach the process even though an earlier
+        // task started it
+        session.child.rescope();
+        let result = session.send(prompt).await;
+        self.finished = result.is_ok();
+        result
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Start over with a fresh process rather than trust this one
+            *self.slot = None;
+        }
+    }
+}
+
+/// A Claude process kept running between prompts. Each prompt goes in as a
+/// stream-json user message and ends with a `result` event, so only the
+/// first one pays for the engine's start-up and context discovery.
+pub struct EngineSession {
+    child: EngineChild,
+    stdin: ChildStdin,
+    lines: Lines<BufReader<ChildStdout>>,
+    /// Directory the process runs in; prompts for elsewhere need another
+    dir: PathBuf,
+    model: Option<String>,
+    /// Cost Claude reported for the session so far
+    cost: f64,
+}
+
+impl EngineSession {
+    /// Spawn the engine with `cmd`, which must read stream-json from stdin
+    /// and write stream-json to stdout.
+    pub fn start(cmd: &mut Command, dir: &Path) -> Result<Self> {
+        let mut child = EngineChild::spawn(
+            cmd.stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null()),
+        )
+        .context("Failed to spawn claude session")?;
+        let stdin = child.stdin.take().context("Failed to capture stdin")?;
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        Ok(Self {
+            child,
+            stdin,
+            lines: BufReader::new(stdout).lines(),
+            dir: dir.to_path_buf(),
+            model: None,
+            cost: 0.0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Run `prompt` as the session's next turn.
+    pub async fn send(&mut self, prompt: &str) -> Result<AiResponse> {
+        let message = json!({
+            "type": "user",
+            "message": {"role": "user", "content": prompt},
+        });
+        self.stdin
+            .write_all(format!("{}\n", message).as_bytes())
+            .await
+            .context("Claude session is no longer running")?;
+        self.stdin.flush().await?;
+
+        while let Some(line) = self.lines.next_line().await? {
+            let Ok(json) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            match json["type"].as_str() {
+                Some("system") if self.model.is_none() => {
+                    self.model = json["model"].as_str().map(str::to_string);
+                }
+                Some("result") => return self.finish_turn(&json),
+                _ => {}
+            }
+        }
+
+        let status = self.child.wait().await?;
+        anyhow::bail!("Claude session exited mid-task with status: {}", status)
+    }
+
+    fn finish_turn(&mut self, result: &Value) -> Result<AiResponse> {
+        let text = result["result"].as_str().unwrap_or("").to_string();
+        if result["is_error"].as_bool() == Some(true) {
+            anyhow::bail!("Claude session reported an error: {}", text);
+        }
+
+        // Claude reports the session's running cost; this turn's is the rise
+        let actual_cost = result["total_cost_usd"].as_f64().map(|total| {
+            let turn = (total - self.cost).max(0.0);
+            self.cost = total;
+            turn
+        });
+        let usage = &result["usage"];
+        Ok(AiResponse {
+            text,
+            input_tokens: usage["input_tokens"].as_u64().unwrap_or(0) as usize,
+            output_tokens: usage["output_tokens"].as_u64().unwrap_or(0) as usize,
+            actual_cost,
+            duration_ms: result["duration_ms"].as_u64(),
+            model: self.model.clone(),
+        })
+    }
+}
@@ -0,0 +1,524 @@
+use crate::ai::AiEngine;
+use crate::backend::Backend;
+use crate::config::{MergeStrategy, RepoMapMode, ReviewMode};
+use crate::pricing::Price;
+use crate::security::Scanner;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Project settings file, read from the working directory if present.
+pub const SETTINGS_FILE: &str = "ralphy.toml";
+
+/// Where the settings file is read from when there's no ralphy.toml, for
+/// projects that keep Ralphy's files together.
+pub const STATE_SETTINGS_FILE: &str = ".ralphy/config.toml";
+
+/// Settings that don't fit on the command line.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub defaults: DefaultSettings,
+    pub kubernetes: Option<KubernetesSettings>,
+    pub reporting: Option<ReportingSettings>,
+    pub triage: Option<TriageSettings>,
+    pub email: Option<EmailSettings>,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    #[serde(default)]
+    pub pull_request: PullRequestSettings,
+    pub security: Option<SecuritySettings>,
+    #[serde(default)]
+    pub diff_scan: DiffScanSettings,
+    /// Prices to estimate cost with, by model name prefix or engine
+    #[serde(default)]
+    pub pricing: BTreeMap<String, Price>,
+    /// Extra placeholders for `--prompt-template`, by name
+    #[serde(default)]
+    pub prompt_vars: BTreeMap<String, String>,
+    /// Settings for each engine, by engine name
+    #[serde(default)]
+    pub engine: HashMap<AiEngine, EngineSettings>,
+}
+
+/// Settings for one engine (`[engine.claude]` and the like in ralphy.toml).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineSettings {
+    /// Arguments added to every run of the engine's CLI
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// Values used when the matching flag isn't given (`[defaults]` in
+/// ralphy.toml). Keys are the flags' names with underscores, so
+/// `max_retries = 5` stands in for `--max-retries 5`; switches that can only
+/// be turned on from the command line can be turned on here too.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultSettings {
+    /// Engine to run tasks with, e.g. "codex"
+    pub engine: Option<AiEngine>,
+    pub model: Option<String>,
+    /// Engine to review with under `--review`
+    pub review_engine: Option<AiEngine>,
+    pub review_mode: Option<ReviewMode>,
+    /// Commands that must pass after each task and each merge under
+    /// `--merge-queue`; a single command or a list
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub verify_cmd: Option<Vec<String>>,
+    /// Task source: a markdown PRD, a YAML task file, a GitHub repository,
+    /// a Jira project or a Linear team. Ignored when any of them is given as a flag
+    pub prd: Option<PathBuf>,
+    pub yaml: Option<PathBuf>,
+    pub github: Option<String>,
+    pub github_label: Option<String>,
+    pub github_author: Option<Vec<String>>,
+    pub jira_project: Option<String>,
+    pub jira_jql: Option<String>,
+    pub linear_team: Option<String>,
+    pub linear_label: Option<String>,
+    pub no_tests: Option<bool>,
+    pub no_lint: Option<bool>,
+    pub no_commits: Option<bool>,
+    pub repo_map: Option<RepoMapMode>,
+    pub repo_map_tokens: Option<usize>,
+    pub max_prompt_tokens: Option<usize>,
+    pub context_files: Option<usize>,
+    /// Files or globs every prompt includes; a single one or a list
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub context: Option<Vec<String>>,
+    pub progress_limit: Option<u64>,
+    pub max_iterations: Option<usize>,
+    pub max_retries: Option<usize>,
+    pub retry_delay: Option<u64>,
+    pub retry_multiplier: Option<f64>,
+    pub retry_max_delay: Option<u64>,
+    pub retry_jitter: Option<f64>,
+    pub task_timeout: Option<u64>,
+    pub timeout_grace: Option<u64>,
+    pub rpm: Option<u32>,
+    pub min_delay: Option<u64>,
+    pub max_replans: Option<usize>,
+    pub completion_marker: Option<String>,
+    pub prompt_template: Option<PathBuf>,
+    pub reuse_session: Option<bool>,
+    pub backend: Option<Backend>,
+    /// Spending cap per label, like `--budget LABEL=USD`; flags override
+    /// the same label
+    pub budget: Option<BTreeMap<String, f64>>,
+    pub max_cost: Option<f64>,
+    pub max_tokens: Option<usize>,
+    pub parallel: Option<bool>,
+    pub max_parallel: Option<usize>,
+    pub merge_queue: Option<bool>,
+    pub push_branches: Option<bool>,
+    pub tui: Option<bool>,
+    pub review: Option<bool>,
+    pub gate: Option<PathBuf>,
+    pub rewrite_commit_messages: Option<bool>,
+    pub auto_commit: Option<bool>,
+    pub conventional_commits: Option<bool>,
+    pub commit_scope: Option<String>,
+    pub branch_per_task: Option<bool>,
+    pub base_branch: Option<String>,
+    pub merge_strategy: Option<MergeStrategy>,
+    pub merge_into: Option<String>,
+    pub create_pr: Option<bool>,
+    pub draft_pr: Option<bool>,
+    pub file_followups: Option<bool>,
+    pub no_notify: Option<bool>,
+    pub webhook: Option<String>,
+}
+
+impl DefaultSettings {
+    /// These defaults, with `other`'s filling in any that are unset.
+    pub fn or(self, other: DefaultSettings) -> Self {
+        Self {
+            engine: self.engine.or(other.engine),
+            model: self.model.or(other.model),
+            review_engine: self.review_engine.or(other.review_engine),
+            review_mode: self.review_mode.or(other.review_mode),
+            verify_cmd: self.verify_cmd.or(other.verify_cmd),
+            prd: self.prd.or(other.prd),
+            yaml: self.yaml.or(other.yaml),
+            github: self.github.or(other.github),
+            github_label: self.github_label.or(other.github_label),
+            github_author: self.github_author.or(other.github_author),
+            jira_project: self.jira_project.or(other.jira_project),
+            jira_jql: self.jira_jql.or(other.jira_jql),
+            linear_team: self.linear_team.or(other.linear_team),
+            linear_label: self.linear_label.or(other.linear_label),
+            no_tests: self.no_tests.or(other.no_tests),
+            no_lint: self.no_lint.or(other.no_lint),
+            no_commits: self.no_commits.or(other.no_commits),
+            repo_map: self.repo_map.or(other.repo_map),
+            repo_map_tokens: self.repo_map_tokens.or(other.repo_map_tokens),
+            max_prompt_tokens: self.max_prompt_tokens.or(other.max_prompt_tokens),
+            context_files: self.context_files.or(other.context_files),
+            context: self.context.or(other.context),
+            progress_limit: self.progress_limit.or(other.progress_limit),
+            max_iterations: self.max_iterations.or(other.max_iterations),
+            max_retries: self.max_retries.or(other.max_retries),
+            retry_delay: self.retry_delay.or(other.retry_delay),
+            retry_multiplier: self.retry_multiplier.or(other.retry_multiplier),
+            retry_max_delay: self.retry_max_delay.or(other.retry_max_delay),
+            retry_jitter: self.retry_jitter.or(other.retry_jitter),
+            task_timeout: self.task_timeout.or(other.task_timeout),
+            timeout_grace: self.timeout_grace.or(other.timeout_grace),
+            rpm: self.rpm.or(other.rpm),
+            min_delay: self.min_delay.or(other.min_delay),
+            max_replans: self.max_replans.or(other.max_replans),
+            completion_marker: self.completion_marker.or(other.completion_marker),
+            prompt_template: self.prompt_template.or(other.prompt_template),
+            reuse_session: self.reuse_session.or(other.reuse_session),
+            backend: self.backend.or(other.backend),
+            budget: self.budget.or(other.budget),
+            max_cost: self.max_cost.or(other.max_cost),
+            max_tokens: self.max_tokens.or(other.max_tokens),
+            parallel: self.parallel.or(other.parallel),
+            max_parallel: self.max_parallel.or(other.max_parallel),
+            merge_queue: self.merge_queue.or(other.merge_queue),
+            tui: self.tui.or(other.tui),
+            push_branches: self.push_branches.or(other.push_branches),
+            review: self.review.or(other.review),
+            gate: self.gate.or(other.gate),
+            rewrite_commit_messages: self
+                .rewrite_commit_messages
+                .or(other.rewrite_commit_messages),
+            auto_commit: self.auto_commit.or(other.auto_commit),
+            conventional_commits: self.conventional_commits.or(other.conventional_commits),
+            commit_scope: self.commit_scope.or(other.commit_scope),
+            branch_per_task: self.branch_per_task.or(other.branch_per_task),
+            base_branch: self.base_branch.or(other.base_branch),
+            merge_strategy: self.merge_strategy.or(other.merge_strategy),
+            merge_into: self.merge_into.or(other.merge_into),
+            create_pr: self.create_pr.or(other.create_pr),
+            draft_pr: self.draft_pr.or(other.draft_pr),
+            file_followups: self.file_followups.or(other.file_followups),
+            no_notify: self.no_notify.or(other.no_notify),
+            webhook: self.webhook.or(other.webhook),
+        }
+    }
+}
+
+/// How to run tasks as Kubernetes Jobs (`[kubernetes]` in ralphy.toml).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KubernetesSettings {
+    /// Image with git, the engine CLI and credentials to push branches
+    pub image: String,
+    /// Namespace to create Jobs in (default: kubectl's current namespace)
+    pub namespace: Option<String>,
+    /// Secret whose keys are exposed to the pod as environment variables,
+    /// e.g. engine API keys and a git token
+    pub env_from_secret: Option<String>,
+    /// Service account the pods run as
+    pub service_account: Option<String>,
+}
+
+/// Where to send usage records at the end of each run (`[reporting]` in
+/// ralphy.toml).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportingSettings {
+    /// URL the usage record is POSTed to as JSON
+    pub endpoint: String,
+    /// Label identifying the project in reports, e.g. a team or service name
+    pub repo_tag: Option<String>,
+}
+
+/// Where failed tasks are reported (`[triage]` in ralphy.toml). Having the
+/// section turns triage issues on.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriageSettings {
+    /// Where the issues are opened (default: github)
+    #[serde(default)]
+    pub tracker: TriageTracker,
+    /// GitLab project (`group/name`) or Jira project key the issues go to
+    pub project: Option<String>,
+    /// User the issue is assigned to: a GitHub or GitLab username, or a
+    /// Jira account ID
+    pub assignee: Option<String>,
+    /// Label for triage issues (default: ralphy-failure)
+    pub label: Option<String>,
+}
+
+/// Issue trackers triage issues can be opened in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TriageTracker {
+    /// Through `gh`
+    #[default]
+    GitHub,
+    /// Through `glab`
+    GitLab,
+    /// Through the REST API, with the `JIRA_*` variables
+    Jira,
+}
+
+/// How `--create-pr` PRs are written and who they go to (`[pull_request]`
+/// in ralphy.toml).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PullRequestSettings {
+    /// Body template, with placeholders such as `{{task}}` and `{{cost}}`
+    pub body: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub reviewers: Vec<String>,
+    #[serde(default)]
+    pub assignees: Vec<String>,
+}
+
+/// Who is emailed the summary of each run and any error that stops one
+/// (`[email]` in ralphy.toml).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailSettings {
+    /// SMTP server, e.g. smtp.example.com
+    pub host: String,
+    /// Default: 587, or 465 with `security = "tls"` and 25 with "none"
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Account to log in as; the password comes from RALPHY_SMTP_PASSWORD
+    pub username: Option<String>,
+    /// Sender address (default: the username)
+    pub from: Option<String>,
+    /// Recipient addresses
+    pub to: Vec<String>,
+}
+
+/// Where notifications go and which events each sink gets
+/// (`[notifications]` in ralphy.toml). Desktop popups and sound are on, and
+/// email once `[email]` is set up, unless turned off with `on = []`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationSettings {
+    pub desktop: Option<SinkSettings>,
+    pub sound: Option<SinkSettings>,
+    pub email: Option<SinkSettings>,
+    /// A Slack incoming webhook
+    pub slack: Option<UrlSinkSettings>,
+    /// Any URL that takes the notification as JSON
+    pub webhook: Option<UrlSinkSettings>,
+}
+
+/// What a notification is about; sinks choose which of these they get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    /// The run ended with every task done
+    RunCompleted,
+    /// The run ended with tasks failed, skipped or over budget, or at a limit
+    RunFailed,
+    /// A parallel batch finished
+    BatchFinished,
+    /// A task gave up
+    TaskFailed,
+    /// An error stopped the run
+    Error,
+}
+
+/// Which events a sink gets.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSettings {
+    /// Any of "run_completed", "run_failed", "batch_finished",
+    /// "task_failed" and "error" (default: run_completed, run_failed and
+    /// error; just run_completed for sound)
+    pub on: Option<Vec<NotifyEvent>>,
+}
+
+/// A sink that POSTs to a URL.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UrlSinkSettings {
+    pub url: String,
+    /// As for the other sinks
+    pub on: Option<Vec<NotifyEvent>>,
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS
+    #[default]
+    Starttls,
+    /// TLS from the start
+    Tls,
+    /// Unencrypted, e.g. a relay on localhost
+    None,
+}
+
+/// Scanners that must find nothing new after each task (`[security]` in
+/// ralphy.toml).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecuritySettings {
+    /// Any of "cargo-audit", "npm-audit" and "semgrep"
+    pub scanners: Vec<Scanner>,
+    /// Rules semgrep runs (default: auto)
+    pub semgrep_config: Option<String>,
+}
+
+/// What each task's diff is checked for besides secrets (`[diff_scan]` in
+/// ralphy.toml).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiffScanSettings {
+    /// License IDs whose headers may not be added, e.g. "GPL" or "AGPL-3.0"
+    #[serde(default)]
+    pub disallowed_licenses: Vec<String>,
+    /// Globs of paths that aren't scanned, e.g. test fixtures
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+impl Settings {
+    /// Load `ralphy.toml`, or `.ralphy/config.toml` when there isn't one,
+    /// or defaults when there's neither.
+    pub fn load() -> Result<Self> {
+        let path = [SETTINGS_FILE, STATE_SETTINGS_FILE]
+            .iter()
+            .map(Path::new)
+            .find(|path| path.exists())
+            .unwrap_or(Path::new(SETTINGS_FILE));
+        Self::load_from(path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// A string or a list of strings, as a list.
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kubernetes_settings() {
+        let settings: Settings = toml::from_str(
+            "[kubernetes]\nimage = \"ghcr.io/acme/agent:1\"\nenv_from_secret = \"agent-keys\"\n",
+        )
+        .unwrap();
+        let kube = settings.kubernetes.unwrap();
+        assert_eq!(kube.image, "ghcr.io/acme/agent:1");
+        assert_eq!(kube.env_from_secret.as_deref(), Some("agent-keys"));
+        assert_eq!(kube.namespace, None);
+    }
+
+    #[test]
+    fn test_missing_file_gives_defaults() {
+        let settings = Settings::load_from(Path::new("does-not-exist.toml")).unwrap();
+        assert!(settings.kubernetes.is_none());
+    }
+
+    #[test]
+    fn test_parse_reporting_settings() {
+        let settings: Settings =
+            toml::from_str("[reporting]\nendpoint = \"https://usage.internal/runs\"\n").unwrap();
+        let reporting = settings.reporting.unwrap();
+        assert_eq!(reporting.endpoint, "https://usage.internal/runs");
+        assert_eq!(reporting.repo_tag, None);
+        assert!(toml::from_str::<Settings>("[reporting]\nurl = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_triage_settings() {
+        let settings: Settings = toml::from_str("[triage]\nassignee = \"octocat\"\n").unwrap();
+        let triage = settings.triage.unwrap();
+        assert_eq!(triage.assignee.as_deref(), Some("octocat"));
+        assert_eq!(triage.label, None);
+        assert!(toml::from_str::<Settings>("[triage]\nteam = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_security_settings() {
+        let settings: Settings =
+            toml::from_str("[security]\nscanners = [\"cargo-audit\", \"semgrep\"]\n").unwrap();
+        let security = settings.security.unwrap();
+        assert_eq!(security.scanners, [Scanner::CargoAudit, Scanner::Semgrep]);
+        assert!(toml::from_str::<Settings>("[security]\nscanners = [\"trivy\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let settings: Settings =
+            toml::from_str("[defaults]\nengine = \"open-code\"\nverify_cmd = \"cargo test\"\n")
+                .unwrap();
+        assert_eq!(settings.defaults.engine, Some(AiEngine::OpenCode));
+        assert_eq!(settings.defaults.verify_cmd.unwrap(), ["cargo test"]);
+        assert!(toml::from_str::<Settings>("[defaults]\nengine = \"gpt\"\n").is_err());
+
+        let settings: Settings =
+            toml::from_str("[defaults]\nverify_cmd = [\"cargo clippy\", \"cargo test\"]\n")
+                .unwrap();
+        assert_eq!(
+            settings.defaults.verify_cmd.unwrap(),
+            ["cargo clippy", "cargo test"]
+        );
+    }
+
+    #[test]
+    fn test_parse_flag_defaults() {
+        let settings: Settings = toml::from_str(
+            "[defaults]\nyaml = \"tasks.yaml\"\nmax_retries = 5\nparallel = true\n\
+             backend = \"devcontainer\"\nrepo_map = \"never\"\n\n\
+             [defaults.budget]\nexperimental = 2.5\n",
+        )
+        .unwrap();
+        let defaults = settings.defaults;
+        assert_eq!(defaults.yaml, Some(PathBuf::from("tasks.yaml")));
+        assert_eq!(defaults.max_retries, Some(5));
+        assert_eq!(defaults.parallel, Some(true));
+        assert_eq!(defaults.backend, Some(Backend::Devcontainer));
+        assert_eq!(defaults.repo_map, Some(RepoMapMode::Never));
+        assert_eq!(defaults.budget.unwrap()["experimental"], 2.5);
+        assert!(toml::from_str::<Settings>("[defaults]\nmax-retries = 5\n").is_err());
+    }
+
+    #[test]
+    fn test_defaults_fall_back() {
+        let project = DefaultSettings {
+            engine: Some(AiEngine::Codex),
+            ..Default::default()
+        };
+        let fallback = DefaultSettings {
+            engine: Some(AiEngine::Claude),
+            verify_cmd: Some(vec!["cargo test".to_string()]),
+            ..Default::default()
+        };
+        let defaults = project.or(fallback);
+        assert_eq!(defaults.engine, Some(AiEngine::Codex));
+        assert_eq!(defaults.verify_cmd.unwrap(), ["cargo test"]);
+        assert_eq!(defaults.review_engine, None);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::events;
+use crate::process::{self, Signal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+
+/// Exit code used when a run stops before every task is complete.
+pub const EXIT_WORK_REMAINING: i32 = 2;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+fn notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// Whether a shutdown has been requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Request a graceful shutdown: stop scheduling new work and terminate
+/// running engine processes.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+    process::signal_all(Signal::Terminate);
+    notify().notify_waiters();
+}
+
+/// Resolve once a shutdown has been requested.
+pub async fn wait() {
+    let notified = notify().notified();
+    if requested() {
+        return;
+    }
+    notified.await;
+}
+
+/// Listen for SIGINT and SIGTERM (Ctrl+C, Ctrl+Break and closing the console
+/// on Windows). The first signal triggers a graceful
+/// shutdown; a second one exits immediately.
+pub fn install_handlers() {
+    tokio::spawn(async {
+        if wait_for_signal().await.is_err() {
+            return;
+        }
+        events::warn(
+            "Shutdown requested, stopping after the current task (press Ctrl+C again to force)...",
+        );
+        request();
+
+        if wait_for_signal().await.is_ok() {
+            process::signal_all(Signal::Kill);
+            std::process::exit(EXIT_WORK_REMAINING);
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res,
+        _ = term.recv() => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+async fn wait_for_signal() -> std::io::Result<()> {
+    use tokio::signal::windows::{ctrl_break, ctrl_close};
+
+    let mut brk = ctrl_break()?;
+    let mut close = ctrl_close()?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res,
+        _ = brk.recv() => Ok(()),
+        _ = close.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn wait_for_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,469 @@
+use crate::ai::{AiEngine, AiResponse};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// What a failure's message mentions, with the category it falls in; the
+/// first match wins.
+static CATEGORIES: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        ("blocked", "blocked"),
+        ("timed out", "timeout"),
+        ("status line", "contract"),
+        ("reviewer", "review"),
+        ("gate script", "gate"),
+        ("security scan", "security-scan"),
+        ("diff scan", "diff-scan"),
+        ("conflict", "merge"),
+        ("verification", "verify"),
+        ("failed to spawn", "engine"),
+        ("command failed", "engine"),
+        // Not "github" or "gitlab"
+        (r"\bgit\b", "git"),
+    ]
+    .into_iter()
+    .map(|(pattern, category)| (Regex::new(pattern).unwrap(), category))
+    .collect()
+});
+
+/// Coarse kind of a task failure, from its message.
+pub fn categorize(error: &anyhow::Error) -> &'static str {
+    let message = format!("{:#}", error).to_lowercase();
+    CATEGORIES
+        .iter()
+        .find(|(re, _)| re.is_match(&message))
+        .map_or("other", |(_, category)| *category)
+}
+
+/// Usage reported by a single agent run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentUsage {
+    pub task: String,
+    pub engine: AiEngine,
+    pub model: Option<String>,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub actual_cost: Option<f64>,
+    pub duration_ms: Option<u64>,
+    /// Wall-clock time from starting the task to finishing it, retries
+    /// included
+    #[serde(default)]
+    pub wall_ms: u64,
+}
+
+/// How long the tasks of a run took from start to finish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Timing {
+    pub min_ms: u64,
+    pub max_ms: u64,
+    pub avg_ms: u64,
+}
+
+/// Usage summed over every task run with one engine and model.
+#[derive(Debug, Clone)]
+pub struct EngineUsage {
+    pub engine: AiEngine,
+    pub model: Option<String>,
+    pub tasks: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub actual_cost: Option<f64>,
+    pub duration_ms: u64,
+}
+
+impl EngineUsage {
+    /// `Engine (model)`, or just the engine when the model is unknown
+    pub fn label(&self) -> String {
+        match &self.model {
+            Some(model) => format!("{} ({})", self.engine, model),
+            None => self.engine.to_string(),
+        }
+    }
+}
+
+/// Totals accumulated over a whole run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    pub iterations: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub actual_cost: f64,
+    pub duration_ms: u64,
+    pub agents: Vec<AgentUsage>,
+    /// Usage outside the finished tasks, such as progress summaries, which
+    /// still counts towards the totals and limits
+    #[serde(default)]
+    pub overhead: Vec<AgentUsage>,
+    /// Tasks that failed and were left incomplete
+    pub failed: Vec<String>,
+    /// Tasks skipped because a budget they count against was used up
+    pub over_budget: Vec<String>,
+    /// Tasks the user skipped at the `--interactive` question
+    #[serde(skip)]
+    pub skipped: Vec<String>,
+    /// Repository of each task that ran outside the current one
+    pub task_repos: Vec<(String, String)>,
+    /// The `--max-cost` or `--max-tokens` limit the run stopped at
+    #[serde(skip)]
+    pub limit_reached: Option<String>,
+    /// Tasks still unchecked when the engine declared the PRD complete
+    #[serde(skip)]
+    pub left_unchecked: usize,
+    /// Coarse kind of each error a task failed with, for telemetry
+    #[serde(default)]
+    pub error_categories: Vec<String>,
+    /// Wall-clock time of the whole run, set when it ends
+    #[serde(skip)]
+    pub wall_ms: u64,
+}
+
+/// Outcome of the tasks that ran in one other repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoOutcome {
+    pub repo: String,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a task's response and how long it took to the totals and the
+    /// per-agent breakdown
+    pub fn record(&mut self, task: &str, engine: AiEngine, response: &AiResponse, wall: Duration) {
+        let usage = self.add(task, engine, response, wall);
+        self.agents.push(usage);
+    }
+
+    /// Add a response that isn't a finished task's, such as a progress
+    /// summary, to the totals without counting it as a task
+    pub fn record_overhead(&mut self, what: &str, engine: AiEngine, response: &AiResponse) {
+        let wall = Duration::from_millis(response.duration_ms.unwrap_or(0));
+        let usage = self.add(what, engine, response, wall);
+        self.overhead.push(usage);
+    }
+
+    fn add(
+        &mut self,
+        task: &str,
+        engine: AiEngine,
+        response: &AiResponse,
+        wall: Duration,
+    ) -> AgentUsage {
+        self.input_tokens += response.input_tokens;
+        self.output_tokens += response.output_tokens;
+        if let Some(cost) = response.actual_cost {
+            self.actual_cost += cost;
+        }
+        if let Some(dur) = response.duration_ms {
+            self.duration_ms += dur;
+        }
+
+        AgentUsage {
+            task: task.to_string(),
+            engine,
+            model: response.model.clone(),
+            input_tokens: response.input_tokens,
+            output_tokens: response.output_tokens,
+            actual_cost: response.actual_cost,
+            duration_ms: response.duration_ms,
+            wall_ms: wall.as_millis() as u64,
+        }
+    }
+
+    /// Every recorded response, finished tasks first
+    pub fn usage(&self) -> impl Iterator<Item = &AgentUsage> {
+        self.agents.iter().chain(&self.overhead)
+    }
+
+    /// Shortest, longest and average wall-clock time of the finished tasks
+    pub fn task_timing(&self) -> Option<Timing> {
+        let times = self.agents.iter().map(|agent| agent.wall_ms);
+        Some(Timing {
+            min_ms: times.clone().min()?,
+            max_ms: times.clone().max()?,
+            avg_ms: times.sum::<u64>() / self.agents.len() as u64,
+        })
+    }
+
+    /// Tasks that finished, in the order they did
+    pub fn completed_tasks(&self) -> Vec<String> {
+        self.agents.iter().map(|agent| agent.task.clone()).collect()
+    }
+
+    /// Note a task that failed and was left incomplete
+    pub fn record_failure(&mut self, task: &str) {
+        self.failed.push(task.to_string());
+    }
+
+    /// Note what kind of error a task failed with
+    pub fn record_error(&mut self, error: &anyhow::Error) {
+        self.error_categories.push(categorize(error).to_string());
+    }
+
+    /// Usage grouped by engine and model, in the order each was first used
+    pub fn by_engine(&self) -> Vec<EngineUsage> {
+        let mut groups: Vec<EngineUsage> = Vec::new();
+        for (i, agent) in self.usage().enumerate() {
+            let group = match groups
+                .iter()
+                .position(|g| g.engine == agent.engine && g.model == agent.model)
+            {
+                Some(i) => &mut groups[i],
+                None => {
+                    groups.push(EngineUsage {
+                        engine: agent.engine,
+                        model: agent.model.clone(),
+                        tasks: 0,
+                        input_tokens: 0,
+                        output_tokens: 0,
+                        actual_cost: None,
+                        duration_ms: 0,
+                    });
+                    groups.last_mut().unwrap()
+                }
+            };
+            if i < self.agents.len() {
+                group.tasks += 1;
+            }
+            group.input_tokens += agent.input_tokens;
+            group.output_tokens += agent.output_tokens;
+            if let Some(cost) = agent.actual_cost {
+                group.actual_cost = Some(group.actual_cost.unwrap_or(0.0) + cost);
+            }
+            group.duration_ms += agent.duration_ms.unwrap_or(0);
+        }
+        groups
+    }
+
+    /// Note that `task` runs in the repository `repo`
+    pub fn record_repo(&mut self, task: &str, repo: &str) {
+        self.task_repos.push((task.to_string(), repo.to_string()));
+    }
+
+    /// Completed and failed tasks per other repository, in the order each
+    /// was first used
+    pub fn by_repo(&self) -> Vec<RepoOutcome> {
+        let mut outcomes: Vec<RepoOutcome> = Vec::new();
+        for (task, repo) in &self.task_repos {
+            let outcome = match outcomes.iter().position(|o| &o.repo == repo) {
+                Some(i) => &mut outcomes[i],
+                None => {
+                    outcomes.push(RepoOutcome {
+                        repo: repo.clone(),
+                        completed: 0,
+                        failed: 0,
+                    });
+                    outcomes.last_mut().unwrap()
+                }
+            };
+            if self.failed.contains(task) {
+                outcome.failed += 1;
+            } else if self.agents.iter().any(|agent| &agent.task == task) {
+                outcome.completed += 1;
+            }
+        }
+        outcomes
+    }
+
+    /// Tasks picked up this run that neither failed nor were skipped
+    pub fn completed(&self) -> usize {
+        self.iterations
+            .saturating_sub(self.failed.len() + self.skipped.len())
+    }
+
+    /// Note a task the user chose not to run
+    pub fn record_skipped(&mut self, task: &str) {
+        self.skipped.push(task.to_string());
+    }
+
+    /// Note a task skipped because its budget was used up
+    pub fn record_over_budget(&mut self, task: &str) {
+        self.over_budget.push(task.to_string());
+    }
+
+    pub fn outcome(&self) -> crate::RunOutcome {
+        if self.limit_reached.is_some() {
+            crate::RunOutcome::LimitReached
+        } else if self.failed.is_empty()
+            && self.over_budget.is_empty()
+            && self.skipped.is_empty()
+            && self.left_unchecked == 0
+        {
+            crate::RunOutcome::Complete
+        } else {
+            crate::RunOutcome::WorkRemaining
+        }
+    }
+}
+
+/// Format a millisecond duration as `1m 5s` or `5s`
+pub fn format_duration(duration_ms: u64) -> String {
+    let dur_sec = duration_ms / 1000;
+    let dur_min = dur_sec / 60;
+    let dur_sec_rem = dur_sec % 60;
+    if dur_min > 0 {
+        format!("{}m {}s", dur_min, dur_sec_rem)
+    } else {
+        format!("{}s", dur_sec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_categorize() {
+        let blocked = anyhow::anyhow!("Agent reported the task as blocked: no database");
+        assert_eq!(categorize(&blocked), "blocked");
+        let engine = anyhow::anyhow!("Claude command failed with status: exit status: 1");
+        assert_eq!(categorize(&engine), "engine");
+        let wrapped = anyhow::anyhow!("permission denied").context("Gate script failed");
+        assert_eq!(categorize(&wrapped), "gate");
+        let git = anyhow::anyhow!("Failed to run git commit");
+        assert_eq!(categorize(&git), "git");
+        let github = anyhow::anyhow!("Failed to reach github.com");
+        assert_eq!(categorize(&github), "other");
+        assert_eq!(categorize(&anyhow::anyhow!("disk full")), "other");
+    }
+
+    fn response(cost: Option<f64>, duration_ms: Option<u64>) -> AiResponse {
+        AiResponse {
+            text: String::new(),
+            input_tokens: 10,
+            output_tokens: 5,
+            actual_cost: cost,
+            duration_ms,
+            model: None,
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_record_aggregates_cost_and_duration() {
+        let mut stats = RunStats::new();
+        stats.record(
+            "a",
+            AiEngine::Claude,
+            &response(Some(0.5), Some(1500)),
+            secs(2),
+        );
+        stats.record("b", AiEngine::Claude, &response(None, Some(500)), secs(9));
+        stats.record("c", AiEngine::Claude, &response(Some(0.25), None), secs(4));
+
+        assert_eq!(stats.input_tokens, 30);
+        assert_eq!(stats.output_tokens, 15);
+        assert!((stats.actual_cost - 0.75).abs() < f64::EPSILON);
+        assert_eq!(stats.duration_ms, 2000);
+        assert_eq!(stats.agents.len(), 3);
+        assert_eq!(stats.agents[1].task, "b");
+        assert_eq!(stats.agents[1].wall_ms, 9000);
+        assert_eq!(
+            stats.task_timing(),
+            Some(Timing {
+                min_ms: 2000,
+                max_ms: 9000,
+                avg_ms: 5000
+            })
+        );
+        assert_eq!(RunStats::new().task_timing(), None);
+    }
+
+    #[test]
+    fn test_overhead_counts_towards_totals_but_not_tasks() {
+        let mut stats = RunStats::new();
+        stats.record(
+            "a",
+            AiEngine::Claude,
+            &response(Some(0.5), Some(1000)),
+            secs(1),
+        );
+        stats.record_overhead(
+            "Summarize progress",
+            AiEngine::Claude,
+            &response(Some(0.25), Some(500)),
+        );
+
+        assert_eq!(stats.input_tokens, 20);
+        assert!((stats.actual_cost - 0.75).abs() < f64::EPSILON);
+        assert_eq!(stats.agents.len(), 1);
+        assert_eq!(stats.usage().count(), 2);
+        let groups = stats.by_engine();
+        assert_eq!(groups[0].tasks, 1);
+        assert_eq!(groups[0].input_tokens, 20);
+        assert_eq!(stats.task_timing().unwrap().max_ms, 1000);
+    }
+
+    #[test]
+    fn test_completed_leaves_out_failed_and_skipped_tasks() {
+        let mut stats = RunStats::new();
+        stats.iterations = 4;
+        stats.record_failure("a");
+        stats.record_skipped("b");
+        assert_eq!(stats.completed(), 2);
+
+        stats.record_failure("a");
+        stats.record_failure("c");
+        stats.record_skipped("d");
+        assert_eq!(stats.completed(), 0);
+    }
+
+    #[test]
+    fn test_by_engine_groups_engine_and_model() {
+        let mut sonnet = response(Some(0.5), Some(1000));
+        sonnet.model = Some("sonnet".to_string());
+
+        let mut stats = RunStats::new();
+        stats.record("a", AiEngine::Claude, &sonnet, secs(1));
+        stats.record("b", AiEngine::Codex, &response(None, None), secs(1));
+        stats.record("c", AiEngine::Claude, &sonnet, secs(1));
+
+        let groups = stats.by_engine();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].label(), "Claude Code (sonnet)");
+        assert_eq!(groups[0].tasks, 2);
+        assert_eq!(groups[0].input_tokens, 20);
+        assert_eq!(groups[0].actual_cost, Some(1.0));
+        assert_eq!(groups[1].label(), "Codex");
+        assert_eq!(groups[1].actual_cost, None);
+    }
+
+    #[test]
+    fn test_by_repo_counts_outcomes() {
+        let mut stats = RunStats::new();
+        stats.record_repo("a", "../api");
+        stats.record_repo("b", "../web");
+        stats.record_repo("c", "../api");
+        stats.record("a", AiEngine::Claude, &response(None, None), secs(1));
+        stats.record_failure("c");
+
+        assert_eq!(
+            stats.by_repo(),
+            [
+                RepoOutcome {
+                    repo: "../api".to_string(),
+                    completed: 1,
+                    failed: 1
+                },
+                RepoOutcome {
+                    repo: "../web".to_string(),
+                    completed: 0,
+                    failed: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(5_000), "5s");
+        assert_eq!(format_duration(65_000), "1m 5s");
+    }
+}
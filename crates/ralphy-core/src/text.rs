@@ -0,0 +1,97 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const ELLIPSIS: &str = "…";
+
+/// Display width of `text` in terminal columns.
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// Shorten `text` to at most `max_width` terminal columns, ending with an
+/// ellipsis when anything was cut. Never splits a grapheme cluster.
+pub fn truncate(text: &str, max_width: usize) -> String {
+    if text.width() <= max_width {
+        return text.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+
+    let mut out = take_width(text, max_width - ELLIPSIS.width());
+    out.push_str(ELLIPSIS);
+    out
+}
+
+/// Truncate to `width` columns and pad with spaces to exactly `width`.
+pub fn truncate_padded(text: &str, width: usize) -> String {
+    let mut out = truncate(text, width);
+    let pad = width.saturating_sub(out.width());
+    out.extend(std::iter::repeat_n(' ', pad));
+    out
+}
+
+/// Leading graphemes of `text` that fit in `max_width` columns, no ellipsis.
+pub fn take_width(text: &str, max_width: usize) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let w = grapheme.width();
+        if width + w > max_width {
+            break;
+        }
+        width += w;
+        out.push_str(grapheme);
+    }
+    out
+}
+
+/// The last `lines` lines of `text`, without trailing blank lines.
+pub fn tail_lines(text: &str, lines: usize) -> String {
+    let all: Vec<&str> = text.trim_end().lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_lines() {
+        let text: String = (1..=50).map(|n| format!("line {}\n", n)).collect();
+        assert_eq!(tail_lines(&text, 3), "line 48\nline 49\nline 50");
+        assert_eq!(tail_lines("one\n\n", 3), "one");
+        assert_eq!(tail_lines("", 3), "");
+    }
+
+    #[test]
+    fn test_truncate_ascii() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("a longer title", 8), "a longe…");
+    }
+
+    #[test]
+    fn test_truncate_wide_chars() {
+        // Each CJK character is two columns wide
+        let title = "添加用户认证";
+        assert_eq!(truncate(title, 12), title);
+        let cut = truncate(title, 7);
+        assert_eq!(cut, "添加用…");
+        assert!(display_width(&cut) <= 7);
+    }
+
+    #[test]
+    fn test_truncate_keeps_graphemes() {
+        // Family emoji is a single grapheme built from several code points
+        let title = "fix 👨‍👩‍👧 bug";
+        let cut = truncate(title, 6);
+        assert!(cut.starts_with("fix "));
+        assert!(!cut.contains('\u{200d}'));
+    }
+
+    #[test]
+    fn test_truncate_padded() {
+        assert_eq!(truncate_padded("ab", 4), "ab  ");
+        assert_eq!(display_width(&truncate_padded("添加用户认证", 7)), 7);
+    }
+}
@@ -0,0 +1,620 @@
+use crate::ai::{AiResponse, RateLimited, RateLimiter};
+use crate::backend::{self, Backend};
+use crate::process::{self, EngineChild};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+/// Longest a single shell command may run before it is killed.
+pub const BASH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Tool output is cut to this many characters so one noisy command can't
+/// fill the model's context.
+pub const MAX_TOOL_OUTPUT: usize = 30_000;
+
+/// Name of the shell tool.
+pub const BASH_TOOL: &str = "bash";
+
+/// Name of the file editor tool, the one Anthropic models are trained on.
+pub const EDITOR_TOOL: &str = "str_replace_based_edit_tool";
+
+/// The shell and editor tools as JSON Schema function definitions, for APIs
+/// that take OpenAI-style tools rather than knowing them already.
+pub fn function_tools() -> Value {
+    json!([
+        {
+            "type": "function",
+            "function": {
+                "name": BASH_TOOL,
+                "description": "Run a shell command in the repository and return its output. \
+                                Each command starts in a fresh shell in the repository root.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "command": {"type": "string", "description": "The command to run"}
+                    },
+                    "required": ["command"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": EDITOR_TOOL,
+                "description": "View, create and edit files. `view` shows a file with line numbers \
+                                (or lists a directory), `create` writes a whole file, `str_replace` \
+                                replaces one exact, unique occurrence of old_str with new_str, and \
+                                `insert` adds insert_text after line insert_line (0 for the top).",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "command": {"type": "string", "enum": ["view", "create", "str_replace", "insert"]},
+                        "path": {"type": "string", "description": "Path relative to the repository root"},
+                        "file_text": {"type": "string"},
+                        "old_str": {"type": "string"},
+                        "new_str": {"type": "string"},
+                        "insert_line": {"type": "integer"},
+                        "insert_text": {"type": "string"},
+                        "view_range": {"type": "array", "items": {"type": "integer"}}
+                    },
+                    "required": ["command", "path"]
+                }
+            }
+        }
+    ])
+}
+
+/// Most request/tool round trips one prompt may take, so a model that never
+/// stops calling tools can't run forever.
+const MAX_TURNS: usize = 200;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest an API may go quiet mid-response before the request is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest one API request may take, however steadily it answers.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1800);
+
+/// A model asking to run one of the tools.
+pub(crate) struct ToolCall {
+    /// What the API calls it, for handing back its result
+    pub id: Value,
+    pub name: String,
+    /// The call's arguments, or why they couldn't be read
+    pub input: Result<Value>,
+}
+
+/// One reply of a chat API that calls tools.
+pub(crate) struct Reply {
+    /// The reply as it goes back into the conversation
+    pub message: Value,
+    pub text: String,
+    /// Tools the model wants run; none once it has finished
+    pub calls: Vec<ToolCall>,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub model: Option<String>,
+}
+
+/// What sets one chat API apart from another: how the conversation is sent
+/// and read back, and how tool results are handed to the model.
+pub(crate) trait ChatApi {
+    /// What errors call the API, e.g. "OpenAI API"
+    fn name(&self) -> &'static str;
+
+    /// Send the conversation so far, and read the model's reply.
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        system: &str,
+        messages: &[Value],
+    ) -> Result<Reply>;
+
+    /// Messages handing each call's output, or what it failed with, back to
+    /// the model.
+    fn tool_results(&self, results: Vec<(&ToolCall, Result<String>)>) -> Vec<Value>;
+}
+
+/// Work on `prompt` through `api`, carrying out the model's tool calls in
+/// `workspace` until it answers without any. Each request waits its turn
+/// under `limiter`, and is sent again on its own if it was rate limited.
+/// `report_step` hears what the model is doing as it goes.
+pub(crate) async fn run_agent(
+    api: &impl ChatApi,
+    workspace: &Workspace,
+    limiter: &RateLimiter,
+    prompt: &str,
+    report_step: impl Fn(&str),
+) -> Result<AiResponse> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let start = Instant::now();
+    let system = format!(
+        "You are an autonomous coding agent working in the repository at {}. \
+         Every command starts in that directory. Use the {} tool to run commands \
+         and the {} tool to read and change files, and keep working until the task is done.",
+        workspace.dir().display(),
+        BASH_TOOL,
+        EDITOR_TOOL
+    );
+    let mut messages = vec![json!({"role": "user", "content": prompt})];
+    let mut response = AiResponse {
+        text: String::new(),
+        input_tokens: 0,
+        output_tokens: 0,
+        actual_cost: None,
+        duration_ms: None,
+        model: None,
+    };
+
+    for _ in 0..MAX_TURNS {
+        report_step("Thinking");
+        let reply = limiter
+            .run(
+                |err| err.is::<RateLimited>(),
+                || api.send(&client, &system, &messages),
+            )
+            .await?;
+        response.input_tokens += reply.input_tokens;
+        response.output_tokens += reply.output_tokens;
+        if response.model.is_none() {
+            response.model = reply.model;
+        }
+        if !reply.text.is_empty() {
+            response.text = reply.text;
+        }
+        messages.push(reply.message);
+        if reply.calls.is_empty() {
+            response.duration_ms = Some(start.elapsed().as_millis() as u64);
+            return Ok(response);
+        }
+
+        let mut results = Vec::new();
+        for call in &reply.calls {
+            let result = match call.input {
+                Ok(ref input) => workspace.call(&call.name, input, &report_step).await,
+                Err(ref e) => Err(anyhow::anyhow!("{:#}", e)),
+            };
+            results.push((call, result));
+        }
+        messages.extend(api.tool_results(results));
+    }
+
+    anyhow::bail!(
+        "{} run stopped after {} turns without finishing",
+        api.name(),
+        MAX_TURNS
+    )
+}
+
+/// Tools that let an engine reached over HTTP work on the repository the way
+/// the engine CLIs do: a shell, and a file editor following the
+/// `str_replace_based_edit_tool` protocol (`view`, `create`, `str_replace`
+/// and `insert`).
+pub struct Workspace {
+    dir: PathBuf,
+    backend: Backend,
+}
+
+impl Workspace {
+    pub fn new(dir: &Path, backend: Backend) -> Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+        Ok(Self { dir, backend })
+    }
+
+    /// Absolute path of the repository the tools work in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Carry out a call to the tool `name`, telling `report_step` what it is.
+    pub async fn call(
+        &self,
+        name: &str,
+        input: &Value,
+        report_step: impl Fn(&str),
+    ) -> Result<String> {
+        match name {
+            BASH_TOOL => {
+                if input["restart"].as_bool() == Some(true) {
+                    return Ok(
+                        "Each command runs in a fresh shell; nothing to restart.".to_string()
+                    );
+                }
+                report_step("Running command");
+                let command = input["command"].as_str().context("Missing 'command'")?;
+                self.bash(command).await
+            }
+            EDITOR_TOOL => {
+                report_step(match input["command"].as_str() {
+                    Some("view") => "Reading files",
+                    _ => "Editing files",
+                });
+                self.edit(input)
+            }
+            other => anyhow::bail!("Unknown tool '{}'", other),
+        }
+    }
+
+    /// Run a shell command in the repository, returning its combined output
+    /// and, when it failed, its exit status.
+    pub async fn bash(&self, command: &str) -> Result<String> {
+        let mut cmd = tokio::process::Command::from(backend::shell_command(self.backend, command));
+        cmd.current_dir(&self.dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        process::new_process_group(&mut cmd);
+
+        let mut child = EngineChild::spawn(&mut cmd).context("Failed to spawn shell")?;
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let stderr = child.stderr.take().context("Failed to capture stderr")?;
+        let run = async {
+            let (out, err) = tokio::join!(read_all(stdout), read_all(stderr));
+            let status = child.wait().await?;
+            anyhow::Ok((out, err, status))
+        };
+        let (out, err, status) = tokio::time::timeout(BASH_TIMEOUT, run)
+            .await
+            .with_context(|| {
+                format!(
+                    "Command timed out after {}s: {}",
+                    BASH_TIMEOUT.as_secs(),
+                    command
+                )
+            })??;
+
+        let mut output = out;
+        if !err.is_empty() {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str(&err);
+        }
+        if !status.success() {
+            output.push_str(&format!("\n(exited with {})", status));
+        }
+        Ok(cap_output(&output))
+    }
+
+    /// Carry out one `str_replace_based_edit_tool` call.
+    pub fn edit(&self, input: &Value) -> Result<String> {
+        let command = input["command"].as_str().context("Missing 'command'")?;
+        let path = self.resolve(input["path"].as_str().context("Missing 'path'")?)?;
+        let text = |field: &str| {
+            input[field]
+                .as_str()
+                .with_context(|| format!("Missing '{}'", field))
+        };
+
+        match command {
+            "view" if path.is_dir() => self.list(&path),
+            "view" => {
+                let content = read(&path)?;
+                let lines: Vec<&str> = content.lines().collect();
+                let (start, end) = match input["view_range"].as_array() {
+                    Some(range) if range.len() == 2 => {
+                        let start = range[0].as_i64().unwrap_or(1).max(1) as usize;
+                        let end = match range[1].as_i64() {
+                            Some(end) if end > 0 => (end as usize).min(lines.len()),
+                            _ => lines.len(),
+                        };
+                        (start, end)
+                    }
+                    _ => (1, lines.len()),
+                };
+                let numbered: Vec<String> = lines
+                    .iter()
+                    .enumerate()
+                    .take(end)
+                    .skip(start - 1)
+                    .map(|(i, line)| format!("{:>6}\t{}", i + 1, line))
+                    .collect();
+                Ok(cap_output(&numbered.join("\n")))
+            }
+            "create" => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, text("file_text")?)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(format!("Created {}", self.relative(&path)))
+            }
+            "str_replace" => {
+                let content = read(&path)?;
+                let old = text("old_str")?;
+                let new = input["new_str"].as_str().unwrap_or("");
+                match content.matches(old).count() {
+                    0 => anyhow::bail!("old_str does not appear in {}", self.relative(&path)),
+                    1 => {}
+                    n => anyhow::bail!(
+                        "old_str appears {} times in {}; include more context so it is unique",
+                        n,
+                        self.relative(&path)
+                    ),
+                }
+                write(&path, &content.replacen(old, new, 1))?;
+                Ok(format!("Edited {}", self.relative(&path)))
+            }
+            "insert" => {
+                let content = read(&path)?;
+                let line = input["insert_line"]
+                    .as_u64()
+                    .context("Missing 'insert_line'")? as usize;
+                let new = text("insert_text").or_else(|_| text("new_str"))?;
+                let mut lines: Vec<&str> = content.lines().collect();
+                if line > lines.len() {
+                    anyhow::bail!(
+                        "insert_line {} is past the end of {} ({} lines)",
+                        line,
+                        self.relative(&path),
+                        lines.len()
+                    );
+                }
+                lines.splice(line..line, new.lines());
+                let mut updated = lines.join("\n");
+                if content.ends_with('\n') || content.is_empty() {
+                    updated.push('\n');
+                }
+                write(&path, &updated)?;
+                Ok(format!("Edited {}", self.relative(&path)))
+            }
+            other => anyhow::bail!("Unknown editor command '{}'", other),
+        }
+    }
+
+    /// Files and directories up to two levels below `dir`, skipping hidden ones.
+    fn list(&self, dir: &Path) -> Result<String> {
+        let mut entries = Vec::new();
+        let mut stack = vec![(dir.to_path_buf(), 0)];
+        while let Some((current, depth)) = stack.pop() {
+            let mut children: Vec<PathBuf> = std::fs::read_dir(&current)
+                .with_context(|| format!("Failed to list {}", current.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    !path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+                })
+                .collect();
+            children.sort();
+            for child in children.into_iter().rev() {
+                if child.is_dir() && depth < 1 {
+                    stack.push((child.clone(), depth + 1));
+                }
+                entries.push(child);
+            }
+        }
+        entries.sort();
+        let listing: Vec<String> = entries
+            .iter()
+            .map(|path| {
+                let suffix = if path.is_dir() { "/" } else { "" };
+                format!("{}{}", self.relative(path), suffix)
+            })
+            .collect();
+        Ok(cap_output(&listing.join("\n")))
+    }
+
+    /// `path` made absolute against the repository, refusing anything that
+    /// would leave it.
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let joined = self.dir.join(path);
+        let mut resolved = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::CurDir => {}
+                other => resolved.push(other),
+            }
+        }
+        // Symlinks can lead out of the repository too, so check where the
+        // deepest part of the path that exists really is
+        let real = resolved
+            .ancestors()
+            .find_map(|ancestor| ancestor.canonicalize().ok())
+            .unwrap_or_default();
+        if !resolved.starts_with(&self.dir) || !real.starts_with(&self.dir) {
+            anyhow::bail!("{} is outside the repository", path);
+        }
+        Ok(resolved)
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.dir) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => path.display().to_string(),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+async fn read_all(mut reader: impl tokio::io::AsyncRead + Unpin) -> String {
+    use tokio::io::AsyncReadExt;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await.ok();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn cap_output(output: &str) -> String {
+    if output.len() <= MAX_TOOL_OUTPUT {
+        return output.to_string();
+    }
+    let mut end = MAX_TOOL_OUTPUT;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}\n... (output cut at {} characters)",
+        &output[..end],
+        MAX_TOOL_OUTPUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::RateLimit;
+    use serde_json::json;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Answers with canned replies, remembering how long each conversation
+    /// it was sent was.
+    struct CannedApi {
+        replies: Mutex<VecDeque<Result<Reply>>>,
+        sent: Mutex<Vec<usize>>,
+    }
+
+    impl ChatApi for CannedApi {
+        fn name(&self) -> &'static str {
+            "Canned API"
+        }
+
+        async fn send(
+            &self,
+            _client: &reqwest::Client,
+            _system: &str,
+            messages: &[Value],
+        ) -> Result<Reply> {
+            self.sent.lock().unwrap().push(messages.len());
+            self.replies.lock().unwrap().pop_front().unwrap()
+        }
+
+        fn tool_results(&self, results: Vec<(&ToolCall, Result<String>)>) -> Vec<Value> {
+            results
+                .into_iter()
+                .map(|(_, result)| json!({"role": "tool", "content": result.unwrap()}))
+                .collect()
+        }
+    }
+
+    fn canned_reply(calls: Vec<ToolCall>) -> Result<Reply> {
+        Ok(Reply {
+            message: json!({"role": "assistant"}),
+            text: String::new(),
+            calls,
+            input_tokens: 10,
+            output_tokens: 5,
+            model: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_is_sent_again_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path(), Backend::Local).unwrap();
+        let api = CannedApi {
+            replies: Mutex::new(VecDeque::from([
+                canned_reply(vec![ToolCall {
+                    id: json!("call-1"),
+                    name: BASH_TOOL.to_string(),
+                    input: Ok(json!({"command": "echo run >> ran.txt"})),
+                }]),
+                Err(RateLimited("Canned API returned 429".to_string()).into()),
+                canned_reply(Vec::new()),
+            ])),
+            sent: Mutex::new(Vec::new()),
+        };
+        let limiter = RateLimiter::new(RateLimit::default(), Duration::ZERO);
+
+        let response = run_agent(&api, &workspace, &limiter, "Log a run", |_| {})
+            .await
+            .unwrap();
+        // The turn before the 429 was kept, and counted
+        assert_eq!(*api.sent.lock().unwrap(), [1, 3, 3]);
+        assert_eq!(response.input_tokens, 20);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("ran.txt")).unwrap(),
+            "run\n"
+        );
+    }
+
+    #[test]
+    fn test_editor_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path(), Backend::Local).unwrap();
+
+        let created = workspace.edit(&json!({"command": "create", "path": "src/lib.rs", "file_text": "fn a() {}\nfn b() {}\n"}));
+        assert_eq!(created.unwrap(), "Created src/lib.rs");
+        workspace
+            .edit(&json!({"command": "str_replace", "path": "src/lib.rs", "old_str": "fn b() {}", "new_str": "fn c() {}"}))
+            .unwrap();
+        workspace
+            .edit(&json!({"command": "insert", "path": "src/lib.rs", "insert_line": 1, "insert_text": "// between"}))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "fn a() {}\n// between\nfn c() {}\n"
+        );
+
+        let view = workspace
+            .edit(&json!({"command": "view", "path": "src/lib.rs", "view_range": [2, -1]}))
+            .unwrap();
+        assert_eq!(view, "     2\t// between\n     3\tfn c() {}");
+        let listing = workspace
+            .edit(&json!({"command": "view", "path": "."}))
+            .unwrap();
+        assert_eq!(listing, "src/\nsrc/lib.rs");
+
+        let ambiguous = workspace.edit(&json!({"command": "str_replace", "path": "src/lib.rs", "old_str": "fn", "new_str": "pub fn"}));
+        assert!(ambiguous
+            .unwrap_err()
+            .to_string()
+            .contains("appears 2 times"));
+        let outside = workspace.edit(&json!({"command": "view", "path": "../../etc/passwd"}));
+        assert!(outside
+            .unwrap_err()
+            .to_string()
+            .contains("outside the repository"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_editor_refuses_symlinks_out_of_the_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("out")).unwrap();
+        let workspace = Workspace::new(dir.path(), Backend::Local).unwrap();
+
+        for input in [
+            json!({"command": "create", "path": "out/new/file.txt", "file_text": "x"}),
+            json!({"command": "view", "path": "out"}),
+        ] {
+            let refused = workspace.edit(&input).unwrap_err();
+            assert!(refused.to_string().contains("outside the repository"));
+        }
+        assert!(!outside.path().join("new").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bash_reports_output_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path(), Backend::Local).unwrap();
+
+        assert_eq!(workspace.bash("echo hi").await.unwrap(), "hi\n");
+        let failed = workspace.bash("echo oops >&2; exit 3").await.unwrap();
+        assert!(failed.starts_with("oops\n"), "{}", failed);
+        assert!(failed.contains("exit status: 3"), "{}", failed);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::config::RunConfig;
+use crate::events;
+use crate::followups::{ensure_label, gh, issue_repo, open_issue_titles};
+use crate::jira::{self, JiraApi, NewIssue};
+use crate::prd::PrdSource;
+use crate::retry::output_with_retry;
+use crate::settings::{TriageSettings, TriageTracker};
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// Label triage issues get unless `[triage]` names another.
+pub const DEFAULT_TRIAGE_LABEL: &str = "ralphy-failure";
+
+/// Progress notes quoted in an issue are cut to their last this many lines.
+const MAX_EXCERPT_LINES: usize = 40;
+
+/// Issue titles are cut to this many characters.
+const MAX_TITLE_CHARS: usize = 100;
+
+/// A task that failed for good, as reported in its triage issue.
+#[derive(Debug, Clone, Default)]
+pub struct Failure {
+    pub task: String,
+    /// Error of each attempt, oldest first
+    pub errors: Vec<String>,
+    /// Progress notes the agent wrote before failing
+    pub notes: String,
+    /// Branch the failed work was left on
+    pub branch: Option<String>,
+    pub engine: String,
+}
+
+impl Failure {
+    pub fn title(&self) -> String {
+        crate::text::truncate(&format!("Task failed: {}", self.task), MAX_TITLE_CHARS)
+    }
+
+    pub fn body(&self) -> String {
+        let mut body = format!(
+            "Ralphy gave up on this task after {} attempt{}.\n\n\
+             **Task:** {}\n**Engine:** {}\n",
+            self.errors.len(),
+            if self.errors.len() == 1 { "" } else { "s" },
+            self.task,
+            self.engine
+        );
+        if let Some(ref branch) = self.branch {
+            body.push_str(&format!("**Branch:** `{}`\n", branch));
+        }
+        if let Some(last) = self.errors.last() {
+            body.push_str(&format!("\n### Last error\n\n```\n{}\n```\n", last.trim()));
+        }
+        if self.errors.len() > 1 {
+            body.push_str("\n### Earlier attempts\n\n");
+            for (i, error) in self.errors[..self.errors.len() - 1].iter().enumerate() {
+                body.push_str(&format!("{}. {}\n", i + 1, one_line(error)));
+            }
+        }
+        let excerpt = excerpt(&self.notes);
+        if !excerpt.is_empty() {
+            body.push_str(&format!("\n### Progress log\n\n```\n{}\n```\n", excerpt));
+        }
+        body.push_str("\n_Filed by Ralphy._");
+        body
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The last [`MAX_EXCERPT_LINES`] lines of `notes`.
+pub fn excerpt(notes: &str) -> String {
+    let lines: Vec<&str> = notes.trim().lines().collect();
+    let start = lines.len().saturating_sub(MAX_EXCERPT_LINES);
+    let mut excerpt = lines[start..].join("\n");
+    if start > 0 {
+        excerpt = format!("... {} earlier lines\n{}", start, excerpt);
+    }
+    excerpt
+}
+
+/// Open a triage issue for `failure` when `[triage]` is configured. An open
+/// issue with the same title is left alone, and failures to file are only
+/// warned about.
+pub async fn report(config: &RunConfig, failure: &Failure) {
+    let Some(ref settings) = config.triage else {
+        return;
+    };
+    if config.dry_run {
+        events::info(format!(
+            "DRY RUN - Would file triage issue: {}",
+            failure.title()
+        ));
+        return;
+    }
+    let filed = match settings.tracker {
+        TriageTracker::GitHub => file_issue(config, settings, failure),
+        TriageTracker::GitLab => file_gitlab_issue(settings, failure),
+        TriageTracker::Jira => file_jira_issue(config, settings, failure).await,
+    };
+    match filed {
+        Ok(Some(url)) => events::info(format!("Filed triage issue {}", url)),
+        Ok(None) => events::info("A triage issue for this task is already open"),
+        Err(e) => events::warn(format!("Could not file triage issue: {:#}", e)),
+    }
+}
+
+fn file_issue(
+    config: &RunConfig,
+    settings: &TriageSettings,
+    failure: &Failure,
+) -> Result<Option<String>> {
+    let repo = issue_repo(config);
+    let label = settings.label.as_deref().unwrap_or(DEFAULT_TRIAGE_LABEL);
+    ensure_label(repo, label, "D93F0B", "Task Ralphy could not complete")?;
+
+    let title = failure.title();
+    if open_issue_titles(repo, label)?.contains(&title) {
+        return Ok(None);
+    }
+
+    let body = failure.body();
+    let mut args = vec![
+        "issue", "create", "--title", &title, "--body", &body, "--label", label,
+    ];
+    if let Some(ref assignee) = settings.assignee {
+        args.extend(["--assignee", assignee.as_str()]);
+    }
+    let output = gh(repo, &args).output().context("Failed to run gh")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create issue '{}': {}",
+            title,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+}
+
+fn file_gitlab_issue(settings: &TriageSettings, failure: &Failure) -> Result<Option<String>> {
+    let glab = |args: &[&str]| {
+        let mut cmd = Command::new("glab");
+        cmd.args(args);
+        if let Some(ref project) = settings.project {
+            cmd.args(["--repo", project]);
+        }
+        cmd
+    };
+    let label = settings.label.as_deref().unwrap_or(DEFAULT_TRIAGE_LABEL);
+    let title = failure.title();
+
+    let output = output_with_retry(&mut glab(&[
+        "issue",
+        "list",
+        "--label",
+        label,
+        "--per-page",
+        "100",
+        "--output",
+        "json",
+    ]))
+    .context("Failed to run glab")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list {} issues: {}",
+            label,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let issues: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).context("Failed to parse glab issue list output")?;
+    if issues.iter().any(|issue| issue["title"] == title.as_str()) {
+        return Ok(None);
+    }
+
+    // GitLab creates labels that don't exist yet
+    let body = failure.body();
+    let mut args = vec![
+        "issue",
+        "create",
+        "--title",
+        &title,
+        "--description",
+        &body,
+        "--label",
+        label,
+        "--yes",
+    ];
+    if let Some(ref assignee) = settings.assignee {
+        args.extend(["--assignee", assignee.as_str()]);
+    }
+    let output = glab(&args).output().context("Failed to run glab")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create issue '{}': {}",
+            title,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // glab prints the new issue's URL last
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .last()
+        .map(|line| line.trim().to_string()))
+}
+
+async fn file_jira_issue(
+    config: &RunConfig,
+    settings: &TriageSettings,
+    failure: &Failure,
+) -> Result<Option<String>> {
+    let project = match (&settings.project, &config.prd_source) {
+        (Some(project), _) => project.as_str(),
+        (None, PrdSource::Jira { project, .. }) => project.as_str(),
+        (None, _) => anyhow::bail!("[triage] tracker = \"jira\" needs a project"),
+    };
+    let label = settings.label.as_deref().unwrap_or(DEFAULT_TRIAGE_LABEL);
+    let title = failure.title();
+    let api = JiraApi::from_env()?;
+
+    let filter = format!("labels = \"{}\"", label);
+    let open = api.search(&jira::open_jql(project, Some(&filter))).await?;
+    if open.iter().any(|issue| issue.summary == title) {
+        return Ok(None);
+    }
+
+    let issue = NewIssue {
+        summary: title,
+        description: Some(failure.body()),
+        labels: vec![label.to_string()],
+        assignee: settings.assignee.clone(),
+    };
+    let key = api.create_task(project, &issue).await?;
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body() {
+        let failure = Failure {
+            task: "Add login page".to_string(),
+            errors: vec![
+                "Claude command failed\nwith status: 1".to_string(),
+                "Agent reported the task as blocked: no database".to_string(),
+            ],
+            notes: "Tried sqlite\n".to_string(),
+            branch: Some("ralphy/add-login-page".to_string()),
+            engine: "Claude".to_string(),
+        };
+        let body = failure.body();
+        assert_eq!(failure.title(), "Task failed: Add login page");
+        assert!(body.contains("after 2 attempts"));
+        assert!(body.contains("**Branch:** `ralphy/add-login-page`"));
+        assert!(body.contains("### Last error\n\n```\nAgent reported the task as blocked"));
+        assert!(body.contains("1. Claude command failed with status: 1"));
+        assert!(body.contains("```\nTried sqlite\n```"));
+    }
+
+    #[test]
+    fn test_excerpt_keeps_the_end() {
+        let notes: String = (1..=50).map(|i| format!("line {}\n", i)).collect();
+        let excerpt = excerpt(&notes);
+        assert!(excerpt.starts_with("... 10 earlier lines\nline 11\n"));
+        assert!(excerpt.ends_with("line 50"));
+        assert_eq!(super::excerpt(""), "");
+    }
+}
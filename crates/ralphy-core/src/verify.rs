@@ -0,0 +1,139 @@
+use crate::ai::{AiExecutor, AiResponse};
+use crate::backend;
+use crate::config::RunConfig;
+use crate::repair::{self, Checked, Repair};
+use crate::text;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Repair rounds allowed after verification first fails.
+pub const MAX_VERIFY_ROUNDS: usize = 2;
+
+/// Lines from the end of a failing command's output that are kept.
+const OUTPUT_TAIL_LINES: usize = 40;
+
+/// A `--verify-cmd` that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub command: String,
+    /// How it exited, e.g. "exit status: 101"
+    pub status: String,
+    /// The end of its stdout and stderr
+    pub output: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` failed with {}", self.command, self.status)?;
+        if !self.output.is_empty() {
+            write!(f, ":\n{}", self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `commands` through the shell in `dir`, in order, stopping at the
+/// first that fails.
+pub fn run(config: &RunConfig, dir: &Path, commands: &[String]) -> Result<Option<Failure>> {
+    for command in commands {
+        let output = backend::shell_command(config.backend, command)
+            .current_dir(dir)
+            .output()
+            .with_context(|| format!("Failed to run verification command: {}", command))?;
+        if !output.status.success() {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            return Ok(Some(Failure {
+                command: command.clone(),
+                status: output.status.to_string(),
+                output: text::tail_lines(&text, OUTPUT_TAIL_LINES),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// What the `--verify-cmd` commands print in `dir`, the end of each after a
+/// `$ command` line, whether they pass or not.
+pub fn output(config: &RunConfig, dir: &Path) -> Result<String> {
+    let mut transcript = String::new();
+    for command in &config.verify_cmd {
+        let output = backend::shell_command(config.backend, command)
+            .current_dir(dir)
+            .output()
+            .with_context(|| format!("Failed to run verification command: {}", command))?;
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        transcript.push_str(&format!("$ {}\n", command));
+        transcript.push_str(&text::tail_lines(&text, OUTPUT_TAIL_LINES));
+        if !transcript.ends_with('\n') {
+            transcript.push('\n');
+        }
+        if !output.status.success() {
+            transcript.push_str(&format!("({})\n", output.status));
+        }
+    }
+    Ok(transcript)
+}
+
+/// Run the `--verify-cmd` commands in `dir`, failing with the first that
+/// doesn't pass.
+pub fn check(config: &RunConfig, dir: &Path) -> Result<()> {
+    match run(config, dir, &config.verify_cmd)? {
+        Some(failure) => anyhow::bail!("{}", failure),
+        None => Ok(()),
+    }
+}
+
+/// Prompt re-running the task with the output of the check it failed.
+pub fn repair_prompt(original: &str, failure: &Failure) -> String {
+    format!(
+        "{}\n\nNOTE: Your previous attempt at this task did not pass verification. \
+         `{}` failed with {}:\n```\n{}\n```\n\n\
+         Check the current state of the repository and fix this.",
+        original, failure.command, failure.status, failure.output
+    )
+}
+
+/// Run the `--verify-cmd` commands where the agent worked, sending the
+/// failing command's output back to the agent until they all pass or rounds
+/// run out.
+///
+/// Usage of every repair round is added to the returned response.
+pub async fn enforce(
+    config: &RunConfig,
+    executor: &AiExecutor,
+    prompt: &str,
+    response: AiResponse,
+) -> Result<AiResponse> {
+    repair::until_passing(executor, MAX_VERIFY_ROUNDS, response, || async {
+        let Some(failure) = run(config, executor.dir(), &config.verify_cmd)? else {
+            return Ok(Checked::pass());
+        };
+        Ok(Checked::repair(Repair {
+            headline: format!("`{}` failed, sending the task back", failure.command),
+            details: String::new(),
+            prompt: repair_prompt(prompt, &failure),
+            error: anyhow::anyhow!("Verification still fails: {}", failure),
+        }))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_prompt() {
+        let failure = Failure {
+            command: "cargo test".to_string(),
+            status: "exit status: 101".to_string(),
+            output: "test add ... FAILED".to_string(),
+        };
+        let prompt = repair_prompt("Add a route", &failure);
+        assert!(prompt.starts_with("Add a route\n\nNOTE:"));
+        assert!(prompt
+            .contains("`cargo test` failed with exit status: 101:\n```\ntest add ... FAILED\n```"));
+    }
+}
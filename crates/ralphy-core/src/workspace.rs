@@ -0,0 +1,374 @@
+use crate::config::RunConfig;
+use crate::events;
+use crate::prd::PrdSource;
+use crate::{backend, git, progress, prompt};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Directories never searched for Nx projects: installed dependencies and
+/// build output can hold thousands of directories and no projects of ours.
+const SKIPPED_DIRS: [&str; 3] = ["node_modules", "dist", "target"];
+
+/// Tool that manages a workspace's packages and runs their tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Cargo,
+    Pnpm,
+    Nx,
+}
+
+/// A package in a monorepo workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    /// Directory relative to the workspace root, with `/` separators
+    pub path: String,
+    pub tool: Tool,
+}
+
+impl Package {
+    /// Command that runs only this package's tests.
+    pub fn test_command(&self) -> String {
+        match self.tool {
+            Tool::Cargo => format!("cargo test -p {}", self.name),
+            Tool::Pnpm => format!("pnpm --filter {} test", self.name),
+            Tool::Nx => format!("npx nx test {}", self.name),
+        }
+    }
+
+    /// Whether `file` (relative to the workspace root) is inside the package.
+    pub fn contains(&self, file: &str) -> bool {
+        file.strip_prefix(&self.path)
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Packages of the workspace rooted at a directory, if it is one.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    pub packages: Vec<Package>,
+}
+
+impl Workspace {
+    /// Find packages from a Cargo workspace, a pnpm workspace and Nx
+    /// projects. A directory claimed by Nx is listed once, as an Nx project.
+    pub fn detect(root: &Path) -> Result<Self> {
+        let mut packages = nx_projects(root)?;
+        for package in pnpm_packages(root)?.into_iter().chain(cargo_members(root)?) {
+            if !packages.iter().any(|p| p.path == package.path) {
+                packages.push(package);
+            }
+        }
+        Ok(Self { packages })
+    }
+
+    /// The package a task is scoped to: the first of its tags that names one.
+    pub fn package_for(&self, tags: &[String]) -> Option<&Package> {
+        tags.iter()
+            .find_map(|tag| self.packages.iter().find(|p| &p.name == tag))
+    }
+}
+
+/// After a task scoped to `package` has run in `dir`: flag any edits made
+/// outside the package, then run the package's tests unless tests are
+/// skipped.
+pub fn check_package(config: &RunConfig, dir: &Path, package: &Package, base: &str) -> Result<()> {
+    let outside = outside_package(package, &git::changed_files_since(dir, base)?, config);
+    if !outside.is_empty() {
+        events::warn(format!(
+            "Task edited files outside the {} package: {}",
+            package.name,
+            outside.join(", ")
+        ));
+    }
+
+    if config.skip_tests {
+        return Ok(());
+    }
+    let cmd = package.test_command();
+    let output = backend::shell_command(config.backend, &cmd)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run {}", cmd))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(10)..];
+        anyhow::bail!("`{}` failed: {}", cmd, tail.join("\n"));
+    }
+    Ok(())
+}
+
+/// Changed files that don't belong to `package`, ignoring Ralphy's own
+/// progress notes and PRD.
+fn outside_package(package: &Package, changed: &[String], config: &RunConfig) -> Vec<String> {
+    let prd = match &config.prd_source {
+        PrdSource::Markdown { path } | PrdSource::Yaml { path } => Some(prompt::prompt_path(path)),
+        _ => None,
+    };
+    changed
+        .iter()
+        .filter(|file| !package.contains(file))
+        .filter(|file| file.as_str() != progress::PROGRESS_FILE)
+        .filter(|file| !file.starts_with(&format!("{}/", progress::STATE_DIR)))
+        .filter(|file| prd.as_deref() != Some(file.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Directories matching workspace member globs, relative to `root`.
+fn member_dirs(root: &Path, patterns: &[String]) -> Vec<String> {
+    let (excludes, includes): (Vec<_>, Vec<_>) = patterns.iter().partition(|p| p.starts_with('!'));
+    let excludes: Vec<glob::Pattern> = excludes
+        .iter()
+        .filter_map(|p| glob::Pattern::new(p.trim_start_matches('!')).ok())
+        .collect();
+
+    // Globs match against an absolute root so results can be made relative
+    let Ok(root) = root.canonicalize() else {
+        return Vec::new();
+    };
+    let escaped_root = glob::Pattern::escape(&root.to_string_lossy());
+
+    let mut dirs = Vec::new();
+    for pattern in includes {
+        let full = format!("{}/{}", escaped_root, pattern.trim_end_matches('/'));
+        let Ok(paths) = glob::glob(&full) else {
+            continue;
+        };
+        for path in paths.flatten().filter(|p| p.is_dir()) {
+            let Ok(relative) = path.strip_prefix(&root) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if !excludes.iter().any(|e| e.matches(&relative)) && !dirs.contains(&relative) {
+                dirs.push(relative);
+            }
+        }
+    }
+    dirs
+}
+
+fn cargo_members(root: &Path) -> Result<Vec<Package>> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        package: Option<CargoPackage>,
+        workspace: Option<CargoWorkspace>,
+    }
+    #[derive(Deserialize)]
+    struct CargoPackage {
+        name: String,
+    }
+    #[derive(Deserialize)]
+    struct CargoWorkspace {
+        #[serde(default)]
+        members: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    }
+
+    let read = |dir: &Path| -> Result<Option<Manifest>> {
+        let path = dir.join("Cargo.toml");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    };
+
+    let Some(workspace) = read(root)?.and_then(|m| m.workspace) else {
+        return Ok(Vec::new());
+    };
+    let mut patterns = workspace.members;
+    patterns.extend(workspace.exclude.iter().map(|e| format!("!{}", e)));
+
+    let mut packages = Vec::new();
+    for dir in member_dirs(root, &patterns) {
+        if let Some(package) = read(&root.join(&dir))?.and_then(|m| m.package) {
+            packages.push(Package {
+                name: package.name,
+                path: dir,
+                tool: Tool::Cargo,
+            });
+        }
+    }
+    Ok(packages)
+}
+
+/// Name in a directory's package.json, if it has one.
+fn npm_name(dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(dir.join("package.json")).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+    json["name"].as_str().map(str::to_string)
+}
+
+fn pnpm_packages(root: &Path) -> Result<Vec<Package>> {
+    #[derive(Deserialize)]
+    struct PnpmWorkspace {
+        #[serde(default)]
+        packages: Vec<String>,
+    }
+
+    let path = root.join("pnpm-workspace.yaml");
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let workspace: PnpmWorkspace = serde_yaml::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    Ok(member_dirs(root, &workspace.packages)
+        .into_iter()
+        .filter_map(|dir| {
+            Some(Package {
+                name: npm_name(&root.join(&dir))?,
+                path: dir,
+                tool: Tool::Pnpm,
+            })
+        })
+        .collect())
+}
+
+/// Nx projects declared with a `project.json`, up to three directories deep.
+fn nx_projects(root: &Path) -> Result<Vec<Package>> {
+    if !root.join("nx.json").is_file() {
+        return Ok(Vec::new());
+    }
+
+    let mut projects = Vec::new();
+    for dir in project_dirs(root, 3) {
+        let path = root.join(&dir).join("project.json");
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let json: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let name = json["name"]
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| npm_name(&root.join(&dir)))
+            .unwrap_or_else(|| dir.rsplit('/').next().unwrap_or(&dir).to_string());
+        projects.push(Package {
+            name,
+            path: dir,
+            tool: Tool::Nx,
+        });
+    }
+    Ok(projects)
+}
+
+/// Directories up to `depth` levels below `root`, relative to it, without
+/// descending into hidden directories or dependency and build output.
+fn project_dirs(root: &Path, depth: usize) -> Vec<String> {
+    let mut dirs = Vec::new();
+    let mut stack = vec![(String::new(), 0)];
+    while let Some((relative, level)) = stack.pop() {
+        if level == depth {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(root.join(&relative)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_str()) {
+                continue;
+            }
+            if !entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                continue;
+            }
+            let child = if relative.is_empty() {
+                name
+            } else {
+                format!("{}/{}", relative, name)
+            };
+            dirs.push(child.clone());
+            stack.push((child, level + 1));
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_detect_cargo_workspace() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/old\"]\n",
+        );
+        write(root, "crates/api/Cargo.toml", "[package]\nname = \"api\"\n");
+        write(root, "crates/old/Cargo.toml", "[package]\nname = \"old\"\n");
+
+        let workspace = Workspace::detect(root).unwrap();
+        assert_eq!(
+            workspace.packages,
+            [Package {
+                name: "api".to_string(),
+                path: "crates/api".to_string(),
+                tool: Tool::Cargo,
+            }]
+        );
+        assert_eq!(workspace.packages[0].test_command(), "cargo test -p api");
+    }
+
+    #[test]
+    fn test_detect_pnpm_and_nx() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "pnpm-workspace.yaml",
+            "packages:\n  - \"packages/*\"\n",
+        );
+        write(
+            root,
+            "packages/web/package.json",
+            r#"{"name": "@acme/web"}"#,
+        );
+        write(root, "packages/ui/package.json", r#"{"name": "@acme/ui"}"#);
+        write(root, "nx.json", "{}");
+        write(root, "packages/ui/project.json", r#"{"name": "ui"}"#);
+        write(
+            root,
+            "node_modules/nx/project.json",
+            r#"{"name": "vendored"}"#,
+        );
+
+        let workspace = Workspace::detect(root).unwrap();
+        let tags = ["frontend".to_string(), "@acme/web".to_string()];
+        let web = workspace.package_for(&tags).unwrap();
+        assert_eq!(web.test_command(), "pnpm --filter @acme/web test");
+        let ui = workspace.package_for(&["ui".to_string()]).unwrap();
+        assert_eq!(ui.tool, Tool::Nx);
+        assert_eq!(workspace.packages.len(), 2);
+    }
+
+    #[test]
+    fn test_package_contains() {
+        let package = Package {
+            name: "api".to_string(),
+            path: "crates/api".to_string(),
+            tool: Tool::Cargo,
+        };
+        assert!(package.contains("crates/api/src/lib.rs"));
+        assert!(!package.contains("crates/api-client/src/lib.rs"));
+        assert!(!package.contains("Cargo.lock"));
+    }
+}
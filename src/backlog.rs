@@ -0,0 +1,123 @@
+use crate::prd::{PrdManager, PrdSource, Priority, Task};
+use anyhow::Result;
+use colored::*;
+
+/// Add a task to the end of the PRD. A priority and parallel group only
+/// have somewhere to go in a YAML task file.
+pub async fn add(
+    source: &PrdSource,
+    title: &str,
+    priority: Option<Priority>,
+    group: Option<usize>,
+) -> Result<()> {
+    let title = title.trim();
+    if title.is_empty() {
+        anyhow::bail!("A task needs a title");
+    }
+    if (priority.is_some() || group.is_some()) && !matches!(source, PrdSource::Yaml { .. }) {
+        anyhow::bail!("--priority and --group need a YAML task file (--yaml tasks.yaml)");
+    }
+
+    let task = Task {
+        priority,
+        parallel_group: group.unwrap_or(0),
+        ..Task::new(title)
+    };
+    PrdManager::new(source.clone()).add_task(task).await?;
+    println!(
+        "{} Added '{}' to {}",
+        "[SUCCESS]".green().bold(),
+        title,
+        source.display_name()
+    );
+    Ok(())
+}
+
+/// Mark the incomplete tasks matching `pattern` complete: the task with
+/// that exact name, or else every task containing it. More than one match
+/// needs `all`, so a loose pattern doesn't check off half the PRD.
+pub async fn done(source: &PrdSource, pattern: &str, all: bool) -> Result<()> {
+    let prd = PrdManager::new(source.clone());
+    let snapshot = prd.refresh().await?;
+    let names: Vec<String> = snapshot
+        .matching_tasks(pattern)
+        .iter()
+        .map(|task| task.name())
+        .collect();
+
+    match names.len() {
+        0 => anyhow::bail!(
+            "No incomplete task matches '{}'. Incomplete tasks:\n  {}",
+            pattern,
+            snapshot.names().join("\n  ")
+        ),
+        1 => {}
+        n if !all => anyhow::bail!(
+            "{} tasks match '{}'; pass --all to mark them all done:\n  {}",
+            n,
+            pattern,
+            names.join("\n  ")
+        ),
+        _ => {}
+    }
+
+    for name in &names {
+        prd.mark_complete(name).await?;
+        println!("  {} {}", "✓".green().bold(), name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prd::YamlTasks;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_add_and_done() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("PRD.md");
+        std::fs::write(&path, "# Tasks\n\n- [ ] Add login\n- [ ] Add logout\n").unwrap();
+        let markdown = PrdSource::Markdown { path: path.clone() };
+
+        add(&markdown, "Write docs", None, None).await.unwrap();
+        assert!(add(&markdown, "Tune", Some(Priority::High), None)
+            .await
+            .is_err());
+
+        let err = done(&markdown, "add", false).await.unwrap_err();
+        assert!(err.to_string().contains("2 tasks match 'add'"));
+        done(&markdown, "docs", false).await.unwrap();
+        done(&markdown, "add", true).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# Tasks\n\n- [x] Add login\n- [x] Add logout\n- [x] Write docs\n"
+        );
+        assert!(done(&markdown, "docs", false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_to_yaml() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("tasks.yaml");
+        std::fs::write(&path, "tasks:\n  - title: First\n    completed: false\n").unwrap();
+        let yaml = PrdSource::Yaml { path: path.clone() };
+
+        add(
+            &yaml,
+            "Implement OAuth login",
+            Some(Priority::High),
+            Some(2),
+        )
+        .await
+        .unwrap();
+        let tasks: YamlTasks =
+            serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let added = &tasks.tasks[1];
+        assert_eq!(added.title, "Implement OAuth login");
+        assert_eq!(added.priority, Some(Priority::High));
+        assert_eq!(added.parallel_group, 2);
+        assert!(!added.completed);
+    }
+}
@@ -0,0 +1,64 @@
+//! `cargo ralphy`: Ralphy run from the root of the current cargo workspace,
+//! verifying tasks and merges with cargo unless told otherwise.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use ralphy_rs::{cli::Cli, run_cli, settings::DefaultSettings, RunOutcome};
+use std::path::PathBuf;
+use std::process::Command;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Verification used when neither `--verify-cmd` nor ralphy.toml set one.
+const CARGO_VERIFY_CMD: &str = "cargo build --workspace --all-targets && cargo test --workspace";
+
+/// Directory of the workspace's root Cargo.toml, as cargo sees it from here.
+fn workspace_root() -> Result<PathBuf> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .output()
+        .context("Failed to run cargo")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Not in a cargo project: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let manifest = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    manifest
+        .parent()
+        .map(PathBuf::from)
+        .context("cargo returned a manifest path without a directory")
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(EnvFilter::from_default_env())
+        .init();
+
+    // Cargo runs `cargo-ralphy ralphy <args>`; run directly, there's no
+    // subcommand name to drop
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("ralphy") {
+        args.next();
+    }
+    let cli = Cli::parse_from(std::iter::once("cargo ralphy".to_string()).chain(args));
+
+    let root = workspace_root()?;
+    std::env::set_current_dir(&root)
+        .with_context(|| format!("Failed to change to {}", root.display()))?;
+
+    let fallback = DefaultSettings {
+        verify_cmd: Some(vec![CARGO_VERIFY_CMD.to_string()]),
+        ..Default::default()
+    };
+    let outcome = run_cli(cli, fallback).await?;
+
+    if outcome != RunOutcome::Complete {
+        std::process::exit(outcome.exit_code());
+    }
+
+    Ok(())
+}
@@ -1,6 +1,12 @@
-use clap::{ArgAction, Parser, ValueEnum};
+use crate::run_log::OutputFormat;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 use std::path::PathBuf;
 
+pub use ralphy_core::ai::AiEngine;
+pub use ralphy_core::backend::Backend;
+pub use ralphy_core::config::{MergeStrategy, RepoMapMode, ReviewMode};
+
 #[derive(Parser, Debug)]
 #[command(
     name = "ralphy",
@@ -14,37 +20,85 @@ use std::path::PathBuf;
     ralphy --codex                            # Run with Codex CLI\n  \
     ralphy --opencode                         # Run with OpenCode\n  \
     ralphy --cursor                           # Run with Cursor agent\n  \
+    ralphy --aider                            # Run with Aider\n  \
+    ralphy --anthropic-api                    # Call the Anthropic API directly\n  \
+    ralphy --openai-api                       # Call the OpenAI API directly\n  \
+    ralphy --ollama                           # Use a local model through Ollama\n  \
     ralphy --parallel --max-parallel 4        # Run 4 tasks concurrently\n  \
     ralphy --branch-per-task --create-pr      # Feature branch workflow\n  \
     ralphy --yaml tasks.yaml                  # Use YAML task file\n  \
     ralphy --github owner/repo                # Fetch from GitHub issues\n  \
     ralphy --fast                             # Skip tests and linting\n  \
-    ralphy --dry-run --verbose                # Preview what would happen\n\
+    ralphy --dry-run --verbose                # Preview what would happen\n  \
+    ralphy --fast prompt                      # Print the prompt for the next task\n  \
+    ralphy --ab codex                         # Compare Claude Code and Codex on one task\n  \
+    ralphy new --template rust-cli            # Start a project from a template\n\
 ")]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     // ============================================
     // AI ENGINE OPTIONS
     // ============================================
     /// Use Claude Code (default)
-    #[arg(long, conflicts_with_all = ["opencode", "cursor", "codex", "qwen"])]
+    #[arg(long, conflicts_with_all = ["opencode", "cursor", "codex", "qwen", "aider", "anthropic_api", "openai_api", "ollama", "mock"])]
     pub claude: bool,
 
     /// Use OpenCode
-    #[arg(long, conflicts_with_all = ["claude", "cursor", "codex", "qwen"])]
+    #[arg(long, conflicts_with_all = ["claude", "cursor", "codex", "qwen", "aider", "anthropic_api", "openai_api", "ollama", "mock"])]
     pub opencode: bool,
 
     /// Use Cursor agent
-    #[arg(long, alias = "agent", conflicts_with_all = ["claude", "opencode", "codex", "qwen"])]
+    #[arg(long, alias = "agent", conflicts_with_all = ["claude", "opencode", "codex", "qwen", "aider", "anthropic_api", "openai_api", "ollama", "mock"])]
     pub cursor: bool,
 
     /// Use Codex CLI
-    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "qwen"])]
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "qwen", "aider", "anthropic_api", "openai_api", "ollama", "mock"])]
     pub codex: bool,
 
     /// Use Qwen-Code
-    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex"])]
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex", "aider", "anthropic_api", "openai_api", "ollama", "mock"])]
     pub qwen: bool,
 
+    /// Use Aider
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex", "qwen", "anthropic_api", "openai_api", "ollama", "mock"])]
+    pub aider: bool,
+
+    /// Call Claude through the Anthropic Messages API instead of a CLI
+    /// (needs ANTHROPIC_API_KEY; ANTHROPIC_MODEL picks the model)
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex", "qwen", "aider", "openai_api", "ollama", "mock"])]
+    pub anthropic_api: bool,
+
+    /// Call an OpenAI model through the Chat Completions API (needs
+    /// OPENAI_API_KEY; OPENAI_MODEL, OPENAI_TEMPERATURE and OPENAI_MAX_TOKENS
+    /// tune it)
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex", "qwen", "aider", "anthropic_api", "ollama", "mock"])]
+    pub openai_api: bool,
+
+    /// Run a local model through an Ollama server, fully offline
+    /// (OLLAMA_HOST and OLLAMA_MODEL pick the server and model)
+    #[arg(long, conflicts_with_all = ["claude", "opencode", "cursor", "codex", "qwen", "aider", "anthropic_api", "openai_api", "mock"])]
+    pub ollama: bool,
+
+    /// Use the built-in mock engine (no CLI needed; for testing your setup)
+    #[arg(
+        long,
+        env = "RALPHY_MOCK_AI",
+        conflicts_with_all = ["claude", "opencode", "cursor", "codex", "qwen", "aider", "anthropic_api", "openai_api", "ollama"]
+    )]
+    pub mock: bool,
+
+    /// Model for the engine to run, e.g. claude-opus-4-1 or gpt-5-mini
+    /// (default: the engine's own); also picks the price cost is estimated at
+    #[arg(long, value_name = "MODEL")]
+    pub model: Option<String>,
+
+    /// Argument passed on to the engine's CLI as is, such as
+    /// --engine-arg=--max-turns --engine-arg=30 (repeatable)
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    pub engine_arg: Vec<String>,
+
     // ============================================
     // WORKFLOW OPTIONS
     // ============================================
@@ -52,37 +106,174 @@ pub struct Cli {
     #[arg(long, alias = "skip-tests")]
     pub no_tests: bool,
 
+    /// Turn off --no-tests set in ralphy.toml
+    #[arg(long = "tests", overrides_with = "no_tests", hide = true)]
+    pub with_tests: bool,
+
     /// Skip linting
     #[arg(long, alias = "skip-lint")]
     pub no_lint: bool,
 
+    /// Turn off --no-lint set in ralphy.toml
+    #[arg(long = "lint", overrides_with = "no_lint", hide = true)]
+    pub with_lint: bool,
+
     /// Skip git commits
     #[arg(long, alias = "skip-commits")]
     pub no_commits: bool,
 
+    /// Turn off --no-commits set in ralphy.toml
+    #[arg(long = "commits", overrides_with = "no_commits", hide = true)]
+    pub with_commits: bool,
+
     /// Skip both tests, linting and git commits (shorthand for --no-tests --no-lint --no-commits)
     #[arg(long)]
     pub fast: bool,
 
+    /// Include a map of the repository's files and their main symbols in
+    /// the prompt (default: auto, only for engines that explore the
+    /// repository least)
+    #[arg(long, value_enum, value_name = "WHEN")]
+    pub repo_map: Option<RepoMapMode>,
+
+    /// Most tokens the repository map may take up (default: 2000)
+    #[arg(long, value_name = "N")]
+    pub repo_map_tokens: Option<usize>,
+
+    /// Keep prompts to about N tokens, leaving out the repository map,
+    /// relevant files, context files and older progress notes in that order
+    #[arg(long, value_name = "N")]
+    pub max_prompt_tokens: Option<usize>,
+
+    /// Point the prompt at up to N files that match the task's title and
+    /// tags (default: 0, off)
+    #[arg(long, value_name = "N")]
+    pub context_files: Option<usize>,
+
+    /// File, or glob of files, every prompt includes, such as architecture
+    /// docs or a schema (repeatable)
+    #[arg(long, value_name = "FILE_OR_GLOB")]
+    pub context: Vec<String>,
+
+    /// Have the engine summarize progress.txt once it grows past KB
+    /// kilobytes (0 = never; default: 64)
+    #[arg(long, value_name = "KB")]
+    pub progress_limit: Option<u64>,
+
     // ============================================
     // EXECUTION OPTIONS
     // ============================================
-    /// Stop after N iterations (0 = unlimited)
-    #[arg(long, default_value = "0", value_name = "N")]
-    pub max_iterations: usize,
+    /// Stop after N iterations (0 = unlimited, the default). Each task
+    /// counts as one iteration, including in parallel mode
+    #[arg(long, value_name = "N")]
+    pub max_iterations: Option<usize>,
+
+    /// Max retries per task on failure (default: 3)
+    #[arg(long, value_name = "N")]
+    pub max_retries: Option<usize>,
+
+    /// Seconds before the first retry (default: 5)
+    #[arg(long, value_name = "N")]
+    pub retry_delay: Option<u64>,
+
+    /// What each wait between retries is multiplied by for the next one
+    /// (default: 2)
+    #[arg(long, value_name = "X")]
+    pub retry_multiplier: Option<f64>,
+
+    /// Longest wait between retries, in seconds (default: 300)
+    #[arg(long, value_name = "SECS")]
+    pub retry_max_delay: Option<u64>,
+
+    /// Fraction of each wait between retries taken off at random, from 0
+    /// to 1 (default: 0.2)
+    #[arg(long, value_name = "FRACTION")]
+    pub retry_jitter: Option<f64>,
+
+    /// Give up on a task attempt whose engine runs longer than this many
+    /// seconds, stopping it and retrying it like any other failure; later
+    /// passes such as review each get the same limit
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub task_timeout: Option<u64>,
+
+    /// Seconds a timed-out engine gets to exit after SIGTERM before it is
+    /// killed (0 kills it at once; default: 10)
+    #[arg(long, value_name = "SECS")]
+    pub timeout_grace: Option<u64>,
+
+    /// Start at most N engine runs a minute, across all parallel agents
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rpm: Option<u32>,
+
+    /// Seconds to leave between the starts of two engine runs (default: 0)
+    #[arg(long, value_name = "SECS")]
+    pub min_delay: Option<u64>,
+
+    /// Once every task is done, have the engine compare the repository
+    /// against the PRD and add tasks for anything missing, up to N times
+    /// (default: 0)
+    #[arg(long, value_name = "N")]
+    pub max_replans: Option<usize>,
+
+    /// Text the engine outputs once every task in the PRD is done, which
+    /// ends a sequential run early (default: <promise>COMPLETE</promise>;
+    /// "" turns it off)
+    #[arg(long, value_name = "TEXT")]
+    pub completion_marker: Option<String>,
+
+    /// Template to build task prompts from instead of the built-in prompt,
+    /// with placeholders such as {{task}}, {{prd_path}}, {{language}} and
+    /// {{default_prompt}}
+    #[arg(long, value_name = "FILE")]
+    pub prompt_template: Option<PathBuf>,
+
+    /// Keep one Claude session running per worker and send it task after
+    /// task, instead of starting the engine for every task
+    #[arg(long)]
+    pub reuse_session: bool,
 
-    /// Max retries per task on failure
-    #[arg(long, default_value = "3", value_name = "N")]
-    pub max_retries: usize,
+    /// Turn off --reuse-session set in ralphy.toml
+    #[arg(long, overrides_with = "reuse_session", hide = true)]
+    pub no_reuse_session: bool,
 
-    /// Seconds between retries
-    #[arg(long, default_value = "5", value_name = "N")]
-    pub retry_delay: u64,
+    /// Carry on an interrupted run: its iteration count, usage totals,
+    /// failures and budget spending, from .ralphy/state.json
+    #[arg(long)]
+    pub resume: bool,
 
     /// Show what would be done without executing
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Before each task, show its prompt and estimated cost and ask whether
+    /// to run, skip or edit it
+    #[arg(long, conflicts_with_all = ["parallel", "dry_run", "ab"])]
+    pub interactive: bool,
+
+    /// Where to run engines and verification commands (default: local)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "BACKEND",
+        conflicts_with_all = ["merge_queue", "push_branches", "ab"]
+    )]
+    pub backend: Option<Backend>,
+
+    /// Cap spending on tasks with a YAML tag, or in a parallel group as
+    /// `group:N`; tasks over budget are skipped (repeatable, e.g. experimental=2)
+    #[arg(long, value_name = "LABEL=USD", value_parser = crate::budget::parse_budget)]
+    pub budget: Vec<(String, f64)>,
+
+    /// Stop the run once its tasks have cost this many dollars (estimated
+    /// for engines that don't report cost); exits with code 3
+    #[arg(long, value_name = "USD", value_parser = crate::budget::parse_usd)]
+    pub max_cost: Option<f64>,
+
+    /// Stop the run once its tasks have used this many input and output
+    /// tokens; exits with code 3
+    #[arg(long, value_name = "N")]
+    pub max_tokens: Option<usize>,
+
     // ============================================
     // PARALLEL EXECUTION
     // ============================================
@@ -90,9 +281,116 @@ pub struct Cli {
     #[arg(long)]
     pub parallel: bool,
 
-    /// Max concurrent tasks (only with --parallel)
-    #[arg(long, default_value = "3", value_name = "N", requires = "parallel")]
-    pub max_parallel: usize,
+    /// Turn off --parallel set in ralphy.toml
+    #[arg(long, overrides_with = "parallel", hide = true)]
+    pub no_parallel: bool,
+
+    /// Max concurrent tasks (only with --parallel; default: 3)
+    #[arg(long, value_name = "N")]
+    pub max_parallel: Option<usize>,
+
+    /// Run each parallel agent on its own branch and worktree, then merge the
+    /// branches one at a time, asking the engine to resolve conflicts
+    /// (needs --parallel)
+    #[arg(long)]
+    pub merge_queue: bool,
+
+    /// Turn off --merge-queue set in ralphy.toml
+    #[arg(long, overrides_with = "merge_queue", hide = true)]
+    pub no_merge_queue: bool,
+
+    /// Run each parallel agent on its own branch and worktree, then push the
+    /// branches to origin instead of merging them (needs --parallel)
+    #[arg(long, conflicts_with = "merge_queue")]
+    pub push_branches: bool,
+
+    /// Turn off --push-branches set in ralphy.toml
+    #[arg(long, overrides_with = "push_branches", hide = true)]
+    pub no_push_branches: bool,
+
+    /// Show a live table of the parallel agents: task, elapsed time, tokens
+    /// and what each is doing (needs --parallel)
+    #[arg(long)]
+    pub tui: bool,
+
+    /// Turn off --tui set in ralphy.toml
+    #[arg(long, overrides_with = "tui", hide = true)]
+    pub no_tui: bool,
+
+    /// Command that must pass after each task, or the task goes back to the
+    /// engine with its output, and after each merge under --merge-queue, or
+    /// the merge is undone (e.g. "cargo test"; repeatable)
+    #[arg(long, value_name = "CMD")]
+    pub verify_cmd: Vec<String>,
+
+    /// Run the next task with both the selected engine and ENGINE, each on
+    /// its own branch and worktree, and show both results to choose from
+    #[arg(
+        long,
+        value_name = "ENGINE",
+        conflicts_with_all = ["parallel", "branch_per_task", "dry_run"]
+    )]
+    pub ab: Option<AiEngine>,
+
+    /// Have a reviewer engine approve each task's diff before it is marked
+    /// complete, sending its feedback back to the agent for repair
+    #[arg(long, conflicts_with = "parallel")]
+    pub review: bool,
+
+    /// Turn off --review set in ralphy.toml
+    #[arg(long, overrides_with = "review", hide = true)]
+    pub no_review: bool,
+
+    /// Engine to review with under --review (default: the engine doing
+    /// the work)
+    #[arg(long, value_name = "ENGINE")]
+    pub review_engine: Option<AiEngine>,
+
+    /// What --review does with the review: send its feedback back to the
+    /// agent, or post it on the task's PR (default: fix)
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub review_mode: Option<ReviewMode>,
+
+    /// Rhai script that allows, denies or sends back each finished task
+    /// (default: gate.rhai, if present)
+    #[arg(long, value_name = "FILE")]
+    pub gate: Option<PathBuf>,
+
+    /// Don't check each task's diff for secrets and disallowed license
+    /// headers before it is committed or pushed
+    #[arg(long)]
+    pub no_diff_scan: bool,
+
+    /// After each task, squash its changes into one commit with a
+    /// conventional-commit message the engine writes from the diff
+    #[arg(long, conflicts_with_all = ["no_commits", "fast", "parallel"])]
+    pub rewrite_commit_messages: bool,
+
+    /// Turn off --rewrite-commit-messages set in ralphy.toml
+    #[arg(long, overrides_with = "rewrite_commit_messages", hide = true)]
+    pub no_rewrite_commit_messages: bool,
+
+    /// After each task, commit whatever the engine left uncommitted as
+    /// "ralphy: <task>"
+    #[arg(long, conflicts_with_all = ["no_commits", "fast"])]
+    pub auto_commit: bool,
+
+    /// Turn off --auto-commit set in ralphy.toml
+    #[arg(long, overrides_with = "auto_commit", hide = true)]
+    pub no_auto_commit: bool,
+
+    /// Have commits follow Conventional Commits (`feat:`, `fix:`, ...), typed
+    /// from each task's tags or title, so the history passes commitlint
+    #[arg(long, conflicts_with_all = ["no_commits", "fast"])]
+    pub conventional_commits: bool,
+
+    /// Turn off --conventional-commits set in ralphy.toml
+    #[arg(long, overrides_with = "conventional_commits", hide = true)]
+    pub no_conventional_commits: bool,
+
+    /// Scope for conventional commit headers, as in `feat(api): ...`
+    #[arg(long, value_name = "SCOPE")]
+    pub commit_scope: Option<String>,
 
     // ============================================
     // GIT BRANCH OPTIONS
@@ -101,35 +399,84 @@ pub struct Cli {
     #[arg(long)]
     pub branch_per_task: bool,
 
-    /// Base branch to create task branches from (default: current branch)
-    #[arg(long, value_name = "NAME", requires = "branch_per_task")]
+    /// Turn off --branch-per-task set in ralphy.toml
+    #[arg(long, overrides_with = "branch_per_task", hide = true)]
+    pub no_branch_per_task: bool,
+
+    /// Base branch to create task branches from under --branch-per-task
+    /// (default: current branch)
+    #[arg(long, value_name = "NAME")]
     pub base_branch: Option<String>,
 
-    /// Create a pull request after each task (requires gh CLI)
-    #[arg(long, requires = "branch_per_task")]
+    /// How task branches from --branch-per-task are brought back into the
+    /// base branch once the run ends (default: none)
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    pub merge_strategy: Option<MergeStrategy>,
+
+    /// Branch to bring task branches into under --merge-strategy, created
+    /// from the base branch if needed (default: the base branch)
+    #[arg(long, value_name = "NAME")]
+    pub merge_into: Option<String>,
+
+    /// Create a pull request after each task (requires gh CLI and
+    /// --branch-per-task or --push-branches)
+    #[arg(long)]
     pub create_pr: bool,
 
-    /// Create PRs as drafts
-    #[arg(long, requires = "create_pr")]
+    /// Turn off --create-pr set in ralphy.toml
+    #[arg(long, overrides_with = "create_pr", hide = true)]
+    pub no_create_pr: bool,
+
+    /// Create PRs as drafts under --create-pr
+    #[arg(long)]
     pub draft_pr: bool,
 
+    /// Turn off --draft-pr set in ralphy.toml
+    #[arg(long, overrides_with = "draft_pr", hide = true)]
+    pub no_draft_pr: bool,
+
+    /// Template for the body of PRs under --create-pr, with placeholders
+    /// such as {{task}}, {{cost}} and {{files_changed}}
+    #[arg(long, value_name = "FILE")]
+    pub pr_template: Option<PathBuf>,
+
+    /// Label PRs opened under --create-pr (repeatable)
+    #[arg(long, value_name = "LABEL")]
+    pub pr_label: Vec<String>,
+
+    /// Request a review of PRs opened under --create-pr (repeatable)
+    #[arg(long, value_name = "LOGIN")]
+    pub pr_reviewer: Vec<String>,
+
+    /// Assign PRs opened under --create-pr (repeatable)
+    #[arg(long, value_name = "LOGIN")]
+    pub pr_assignee: Vec<String>,
+
+    /// Open GitHub issues, labeled ralphy-followup, for work agents defer
+    /// and tasks skipped over budget (requires gh CLI)
+    #[arg(long)]
+    pub file_followups: bool,
+
+    /// Turn off --file-followups set in ralphy.toml
+    #[arg(long, overrides_with = "file_followups", hide = true)]
+    pub no_file_followups: bool,
+
     // ============================================
     // PRD SOURCE OPTIONS
     // ============================================
-    /// PRD file path (markdown format with checkboxes)
+    /// PRD file path (markdown format with checkboxes; default: PRD.md)
     #[arg(
         long,
         value_name = "FILE",
-        default_value = "PRD.md",
-        conflicts_with_all = ["yaml", "github"]
+        conflicts_with_all = ["yaml", "github", "jira_project", "linear_team"]
     )]
-    pub prd: PathBuf,
+    pub prd: Option<PathBuf>,
 
     /// Use YAML task file instead of markdown
     #[arg(
         long,
         value_name = "FILE",
-        conflicts_with_all = ["prd", "github"]
+        conflicts_with_all = ["prd", "github", "jira_project", "linear_team"]
     )]
     pub yaml: Option<PathBuf>,
 
@@ -137,14 +484,45 @@ pub struct Cli {
     #[arg(
         long,
         value_name = "REPO",
-        conflicts_with_all = ["prd", "yaml"]
+        conflicts_with_all = ["prd", "yaml", "jira_project", "linear_team"]
     )]
     pub github: Option<String>,
 
-    /// Filter GitHub issues by label
-    #[arg(long, value_name = "TAG", requires = "github")]
+    /// Filter GitHub issues by label (with --github)
+    #[arg(long, value_name = "TAG")]
     pub github_label: Option<String>,
 
+    /// Only take GitHub issues opened by these users (with --github;
+    /// repeatable)
+    #[arg(long, value_name = "LOGIN")]
+    pub github_author: Vec<String>,
+
+    /// Fetch tasks from a Jira project's open issues (project key; needs
+    /// JIRA_URL, JIRA_EMAIL and JIRA_API_TOKEN)
+    #[arg(
+        long,
+        value_name = "KEY",
+        conflicts_with_all = ["prd", "yaml", "github", "linear_team"]
+    )]
+    pub jira_project: Option<String>,
+
+    /// Only take Jira issues that also match this JQL (with --jira-project)
+    #[arg(long, value_name = "JQL")]
+    pub jira_jql: Option<String>,
+
+    /// Fetch tasks from a Linear team's open issues (team key; needs
+    /// LINEAR_API_KEY)
+    #[arg(
+        long,
+        value_name = "KEY",
+        conflicts_with_all = ["prd", "yaml", "github", "jira_project"]
+    )]
+    pub linear_team: Option<String>,
+
+    /// Only take Linear issues with this label (with --linear-team)
+    #[arg(long, value_name = "LABEL")]
+    pub linear_label: Option<String>,
+
     // ============================================
     // OTHER OPTIONS
     // ============================================
@@ -156,64 +534,187 @@ pub struct Cli {
     #[arg(long)]
     pub no_color: bool,
 
-    /// Disable notifications
+    /// Disable desktop notifications and sound
     #[arg(long)]
     pub no_notify: bool,
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
-pub enum AiEngine {
-    Claude,
-    OpenCode,
-    Cursor,
-    Codex,
-    Qwen,
+    /// Turn off --no-notify set in ralphy.toml
+    #[arg(long = "notify", overrides_with = "no_notify", hide = true)]
+    pub with_notify: bool,
+
+    /// Don't send this run's usage to the [reporting] endpoint in ralphy.toml
+    #[arg(long)]
+    pub no_report: bool,
+
+    /// Append task and run events to FILE as JSON Lines ("-" for stdout)
+    #[arg(long, value_name = "FILE")]
+    pub log_json: Option<PathBuf>,
+
+    /// POST each run log event to URL as JSON, retrying failed deliveries
+    /// and signing them with $RALPHY_WEBHOOK_SECRET if it is set
+    #[arg(long, value_name = "URL")]
+    pub webhook: Option<String>,
+
+    /// What to print on stdout; json prints the run log events there and
+    /// moves everything else to stderr
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, value_name = "FORMAT")]
+    pub output: OutputFormat,
 }
 
-impl std::fmt::Display for AiEngine {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            AiEngine::Claude => write!(f, "Claude Code"),
-            AiEngine::OpenCode => write!(f, "OpenCode"),
-            AiEngine::Cursor => write!(f, "Cursor"),
-            AiEngine::Codex => write!(f, "Codex"),
-            AiEngine::Qwen => write!(f, "Qwen-Code"),
-        }
-    }
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Print the prompt that would be sent for a task, without running it.
+    /// Options go before the subcommand: `ralphy --fast prompt`
+    Prompt {
+        /// Task to build the prompt for, by exact title or unique substring
+        /// (default: the next incomplete task)
+        task: Option<String>,
+    },
+    /// Add a task to the end of the PRD. Options go before the subcommand:
+    /// `ralphy --yaml tasks.yaml add "Implement OAuth login" --priority high`
+    Add {
+        title: String,
+        /// Priority of the task (YAML task files only)
+        #[arg(long, value_enum)]
+        priority: Option<crate::prd::Priority>,
+        /// Parallel group of the task (YAML task files only)
+        #[arg(long)]
+        group: Option<usize>,
+    },
+    /// Mark incomplete tasks complete, by exact title or substring
+    Done {
+        pattern: String,
+        /// Mark every matching task, when more than one matches
+        #[arg(long)]
+        all: bool,
+    },
+    /// Have the engine turn a free-form spec into a YAML task file, with
+    /// dependencies and parallel groups, for `ralphy --yaml` to run
+    Plan {
+        /// Markdown or plain-text description of what to build
+        spec: PathBuf,
+        /// Task file to write
+        #[arg(long, default_value = "tasks.yaml")]
+        output: PathBuf,
+        /// Overwrite an existing task file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Scaffold a PRD and ralphy.toml for a new project from a template
+    New {
+        #[arg(long, value_enum)]
+        template: crate::templates::Template,
+        /// Overwrite an existing PRD.md or ralphy.toml
+        #[arg(long)]
+        force: bool,
+    },
+    /// Start using Ralphy here: a PRD.md with example tasks, progress.txt
+    /// and a .gitignore entry for .ralphy/
+    Init {
+        /// Write tasks.yaml instead of PRD.md
+        #[arg(long)]
+        yaml: bool,
+        /// Also write a commented ralphy.toml
+        #[arg(long)]
+        config: bool,
+        /// Overwrite an existing PRD.md, tasks.yaml or ralphy.toml
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the progress log as readable text
+    Progress {
+        /// Include entries archived when the log was compacted
+        #[arg(long)]
+        all: bool,
+    },
+    /// Replace this binary with the latest GitHub release, after verifying
+    /// its checksum and signature
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Turn anonymous usage telemetry on or off, or show its status
+    Telemetry {
+        #[arg(value_enum)]
+        action: crate::telemetry::TelemetryAction,
+    },
 }
 
 impl Cli {
     pub fn get_ai_engine(&self) -> AiEngine {
-        if self.opencode {
-            AiEngine::OpenCode
+        self.engine_flag().unwrap_or(AiEngine::Claude)
+    }
+
+    /// The engine picked with a flag, if any.
+    pub fn engine_flag(&self) -> Option<AiEngine> {
+        if self.claude {
+            Some(AiEngine::Claude)
+        } else if self.opencode {
+            Some(AiEngine::OpenCode)
         } else if self.cursor {
-            AiEngine::Cursor
+            Some(AiEngine::Cursor)
         } else if self.codex {
-            AiEngine::Codex
+            Some(AiEngine::Codex)
         } else if self.qwen {
-            AiEngine::Qwen
+            Some(AiEngine::Qwen)
+        } else if self.aider {
+            Some(AiEngine::Aider)
+        } else if self.anthropic_api {
+            Some(AiEngine::AnthropicApi)
+        } else if self.openai_api {
+            Some(AiEngine::OpenAiApi)
+        } else if self.ollama {
+            Some(AiEngine::Ollama)
+        } else if self.mock {
+            Some(AiEngine::Mock)
         } else {
-            AiEngine::Claude
+            None
         }
     }
 
+    /// Select `engine`, as its flag would.
+    pub fn set_engine(&mut self, engine: AiEngine) {
+        self.claude = engine == AiEngine::Claude;
+        self.opencode = engine == AiEngine::OpenCode;
+        self.cursor = engine == AiEngine::Cursor;
+        self.codex = engine == AiEngine::Codex;
+        self.qwen = engine == AiEngine::Qwen;
+        self.aider = engine == AiEngine::Aider;
+        self.anthropic_api = engine == AiEngine::AnthropicApi;
+        self.openai_api = engine == AiEngine::OpenAiApi;
+        self.ollama = engine == AiEngine::Ollama;
+        self.mock = engine == AiEngine::Mock;
+    }
+
     pub fn get_prd_file(&self) -> PathBuf {
         if let Some(ref yaml) = self.yaml {
             yaml.clone()
         } else {
-            self.prd.clone()
+            self.prd.clone().unwrap_or_else(|| PathBuf::from("PRD.md"))
         }
     }
 
-    pub fn skip_tests(&self) -> bool {
-        self.no_tests || self.fast
+    pub fn skip_tests(&self) -> Option<bool> {
+        switch(self.no_tests || self.fast, self.with_tests)
     }
 
-    pub fn skip_lint(&self) -> bool {
-        self.no_lint || self.fast
+    pub fn skip_lint(&self) -> Option<bool> {
+        switch(self.no_lint || self.fast, self.with_lint)
     }
 
-    pub fn skip_commits(&self) -> bool {
-        self.no_commits || self.fast
+    pub fn skip_commits(&self) -> Option<bool> {
+        switch(self.no_commits || self.fast, self.with_commits)
+    }
+}
+
+/// A switch as the command line sets it, given its flag and the `--no-`
+/// form that turns it off; `None` when neither was passed, leaving it to
+/// ralphy.toml. Turning a switch off wins, so `--fast --tests` still tests.
+pub fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (_, true) => Some(false),
+        (true, false) => Some(true),
+        (false, false) => None,
     }
 }
@@ -1,152 +1,734 @@
-use crate::cli::{AiEngine, Cli};
+use crate::ai::RateLimit;
+use crate::backoff::Backoff;
+use crate::cli::{switch, AiEngine, Backend, Cli, MergeStrategy, ReviewMode};
+use crate::contract;
+use crate::gate::{Gate, GATE_FILE};
 use crate::prd::PrdSource;
+use crate::pricing::Pricing;
+use crate::repo_map;
+use crate::run_log::{self, OutputFormat};
+use crate::settings::{DefaultSettings, Settings, TriageTracker, SETTINGS_FILE};
 use anyhow::{Context, Result};
+use clap::ValueEnum;
 use colored::*;
-use std::path::PathBuf;
-
-#[derive(Debug, Clone)]
-pub struct Config {
-    pub ai_engine: AiEngine,
-    pub prd_source: PrdSource,
-    pub skip_tests: bool,
-    pub skip_lint: bool,
-    pub skip_commits: bool,
-    pub max_iterations: usize,
-    pub max_retries: usize,
-    pub retry_delay: u64,
-    pub dry_run: bool,
-    pub parallel: bool,
-    pub max_parallel: usize,
-    pub branch_per_task: bool,
-    pub base_branch: Option<String>,
-    pub create_pr: bool,
-    pub draft_pr: bool,
-    pub verbose: u8,
-    pub no_color: bool,
-    pub no_notify: bool,
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub use ralphy_core::config::RunConfig;
+
+/// The run `cli` asks for, with ralphy.toml filling in what the flags don't
+/// set.
+pub fn from_cli(cli: Cli) -> Result<RunConfig> {
+    from_cli_with_defaults(cli, DefaultSettings::default())
 }
 
-impl Config {
-    pub fn from_cli(cli: Cli) -> Result<Self> {
-        // Extract values that need method calls before destructuring
-        let ai_engine = cli.get_ai_engine();
-        let skip_tests = cli.skip_tests();
-        let skip_lint = cli.skip_lint();
-        let skip_commits = cli.skip_commits();
-
-        // Destructure cli to avoid partial move issues
-        let Cli {
-            github,
-            github_label,
-            yaml,
-            prd,
-            max_iterations,
-            max_retries,
-            retry_delay,
-            dry_run,
-            parallel,
-            max_parallel,
-            branch_per_task,
-            base_branch,
-            create_pr,
-            draft_pr,
-            verbose,
-            no_color,
-            no_notify,
-            ..
-        } = cli;
-
-        // Determine PRD source
-        let prd_source = if let Some(github_repo) = github {
-            PrdSource::GitHub {
-                repo: github_repo,
-                label: github_label,
-            }
-        } else if let Some(yaml_path) = yaml {
-            PrdSource::Yaml { path: yaml_path }
-        } else {
-            PrdSource::Markdown { path: prd }
-        };
+/// Like [`from_cli`], with `fallback` used for whatever neither
+/// the flags nor ralphy.toml's `[defaults]` set.
+pub fn from_cli_with_defaults(cli: Cli, fallback: DefaultSettings) -> Result<RunConfig> {
+    // Extract values that need method calls before destructuring
+    let mut settings = Settings::load()?;
+    settings.defaults = settings.defaults.or(fallback);
+    let ai_engine = cli
+        .engine_flag()
+        .or(settings.defaults.engine)
+        .unwrap_or(AiEngine::Claude);
+    let defaults = settings.defaults;
+    let on = |flag: Option<bool>, default: Option<bool>| flag.or(default).unwrap_or(false);
+    let skip_tests = on(cli.skip_tests(), defaults.no_tests);
+    let skip_lint = on(cli.skip_lint(), defaults.no_lint);
+    let skip_commits = on(cli.skip_commits(), defaults.no_commits);
+
+    // Destructure cli to avoid partial move issues
+    let Cli {
+        model,
+        engine_arg,
+        github,
+        github_label,
+        github_author,
+        jira_project,
+        jira_jql,
+        linear_team,
+        linear_label,
+        yaml,
+        prd,
+        repo_map,
+        repo_map_tokens,
+        max_prompt_tokens,
+        context_files,
+        context,
+        progress_limit,
+        max_iterations,
+        max_retries,
+        retry_delay,
+        retry_multiplier,
+        retry_max_delay,
+        retry_jitter,
+        task_timeout,
+        timeout_grace,
+        rpm,
+        min_delay,
+        max_replans,
+        completion_marker,
+        prompt_template,
+        reuse_session,
+        no_reuse_session,
+        resume,
+        dry_run,
+        interactive,
+        backend,
+        budget: budgets,
+        max_cost,
+        max_tokens,
+        parallel,
+        no_parallel,
+        max_parallel,
+        merge_queue,
+        no_merge_queue,
+        push_branches,
+        no_push_branches,
+        tui,
+        no_tui,
+        verify_cmd,
+        ab,
+        review,
+        no_review,
+        review_engine,
+        review_mode,
+        gate,
+        no_diff_scan,
+        rewrite_commit_messages,
+        no_rewrite_commit_messages,
+        auto_commit,
+        no_auto_commit,
+        conventional_commits,
+        no_conventional_commits,
+        commit_scope,
+        branch_per_task,
+        no_branch_per_task,
+        base_branch,
+        merge_strategy,
+        merge_into,
+        create_pr,
+        no_create_pr,
+        draft_pr,
+        no_draft_pr,
+        pr_template,
+        pr_label,
+        pr_reviewer,
+        pr_assignee,
+        file_followups,
+        no_file_followups,
+        verbose,
+        no_color,
+        no_notify,
+        with_notify,
+        no_report,
+        log_json,
+        webhook,
+        output,
+        ..
+    } = cli;
+
+    // Determine PRD source; a source given as a flag replaces the one in
+    // ralphy.toml rather than conflicting with it
+    let flagged = github.is_some()
+        || jira_project.is_some()
+        || linear_team.is_some()
+        || yaml.is_some()
+        || prd.is_some();
+    let (github, jira_project, linear_team, yaml, prd) = if flagged {
+        (github, jira_project, linear_team, yaml, prd)
+    } else {
+        (
+            defaults.github,
+            defaults.jira_project,
+            defaults.linear_team,
+            defaults.yaml,
+            defaults.prd,
+        )
+    };
+    let prd_source = if let Some(github_repo) = github {
+        PrdSource::GitHub {
+            repo: github_repo,
+            label: github_label.or(defaults.github_label),
+            authors: if github_author.is_empty() {
+                defaults.github_author.unwrap_or_default()
+            } else {
+                github_author
+            },
+        }
+    } else if let Some(project) = jira_project {
+        PrdSource::Jira {
+            project,
+            jql: jira_jql.or(defaults.jira_jql),
+        }
+    } else if let Some(team) = linear_team {
+        PrdSource::Linear {
+            team,
+            label: linear_label.or(defaults.linear_label),
+        }
+    } else if let Some(yaml_path) = yaml {
+        PrdSource::Yaml { path: yaml_path }
+    } else {
+        PrdSource::Markdown {
+            path: prd.unwrap_or_else(|| PathBuf::from("PRD.md")),
+        }
+    };
 
-        // Validate PRD file exists for file-based sources
-        if let PrdSource::Markdown { ref path } | PrdSource::Yaml { ref path } = prd_source {
-            if !path.exists() {
+    // Flags win over ralphy.toml, which wins over the built-in defaults
+    let model = model.or(defaults.model).filter(|model| !model.is_empty());
+    let repo_map = repo_map.or(defaults.repo_map).unwrap_or_default();
+    let repo_map_tokens = repo_map_tokens
+        .or(defaults.repo_map_tokens)
+        .unwrap_or(repo_map::DEFAULT_TOKENS);
+    let max_prompt_tokens = max_prompt_tokens.or(defaults.max_prompt_tokens);
+    let context_files = context_files.or(defaults.context_files).unwrap_or(0);
+    let context = if context.is_empty() {
+        defaults.context.unwrap_or_default()
+    } else {
+        context
+    };
+    let context = crate::prompt::resolve_context(&context)?;
+    let progress_limit = progress_limit.or(defaults.progress_limit).unwrap_or(64);
+    let max_iterations = max_iterations.or(defaults.max_iterations).unwrap_or(0);
+    let max_retries = max_retries.or(defaults.max_retries).unwrap_or(3);
+    let fallback = Backoff::default();
+    let retry_backoff = Backoff {
+        base: retry_delay
+            .or(defaults.retry_delay)
+            .map_or(fallback.base, Duration::from_secs),
+        multiplier: retry_multiplier
+            .or(defaults.retry_multiplier)
+            .unwrap_or(fallback.multiplier),
+        max: retry_max_delay
+            .or(defaults.retry_max_delay)
+            .map_or(fallback.max, Duration::from_secs),
+        jitter: retry_jitter
+            .or(defaults.retry_jitter)
+            .unwrap_or(fallback.jitter),
+    };
+    if !(retry_backoff.multiplier >= 1.0 && retry_backoff.multiplier.is_finite()) {
+        anyhow::bail!("The retry multiplier must be at least 1");
+    }
+    if !(0.0..=1.0).contains(&retry_backoff.jitter) {
+        anyhow::bail!("The retry jitter must be between 0 and 1");
+    }
+    let task_timeout = task_timeout.or(defaults.task_timeout);
+    let timeout_grace = timeout_grace.or(defaults.timeout_grace).unwrap_or(10);
+    let rpm = rpm.or(defaults.rpm);
+    let rate_limit = RateLimit {
+        rpm,
+        min_delay: Duration::from_secs(min_delay.or(defaults.min_delay).unwrap_or(0)),
+    };
+    let max_replans = max_replans.or(defaults.max_replans).unwrap_or(0);
+    let prompt_template = match prompt_template.or(defaults.prompt_template) {
+        Some(path) => Some(
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read prompt template {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let completion_marker = completion_marker
+        .or(defaults.completion_marker)
+        .unwrap_or_else(|| contract::COMPLETION_PROMISE.to_string());
+    let reuse_session = on(
+        switch(reuse_session, no_reuse_session),
+        defaults.reuse_session,
+    );
+    let backend = backend.or(defaults.backend).unwrap_or_default();
+    let max_cost = max_cost.or(defaults.max_cost);
+    let max_tokens = max_tokens.or(defaults.max_tokens);
+    let parallel = on(switch(parallel, no_parallel), defaults.parallel);
+    let max_parallel = max_parallel.or(defaults.max_parallel).unwrap_or(3);
+    let merge_queue = on(switch(merge_queue, no_merge_queue), defaults.merge_queue);
+    let push_branches = on(
+        switch(push_branches, no_push_branches),
+        defaults.push_branches,
+    );
+    let tui = on(switch(tui, no_tui), defaults.tui);
+    let review = on(switch(review, no_review), defaults.review);
+    let review_mode = review_mode.or(defaults.review_mode).unwrap_or_default();
+    let gate = gate.or(defaults.gate);
+    let rewrite_commit_messages = on(
+        switch(rewrite_commit_messages, no_rewrite_commit_messages),
+        defaults.rewrite_commit_messages,
+    );
+    let auto_commit = on(switch(auto_commit, no_auto_commit), defaults.auto_commit);
+    let conventional_commits = on(
+        switch(conventional_commits, no_conventional_commits),
+        defaults.conventional_commits,
+    );
+    let commit_scope = commit_scope.or(defaults.commit_scope);
+    let branch_per_task = on(
+        switch(branch_per_task, no_branch_per_task),
+        defaults.branch_per_task,
+    );
+    let base_branch = base_branch.or(defaults.base_branch);
+    let merge_strategy = merge_strategy
+        .or(defaults.merge_strategy)
+        .unwrap_or_default();
+    let merge_into = merge_into.or(defaults.merge_into);
+    let create_pr = on(switch(create_pr, no_create_pr), defaults.create_pr);
+    let draft_pr = on(switch(draft_pr, no_draft_pr), defaults.draft_pr);
+    let mut pull_request = settings.pull_request;
+    if let Some(path) = pr_template {
+        let template = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read PR template {}", path.display()))?;
+        pull_request.body = Some(template);
+    }
+    for (flags, values) in [
+        (pr_label, &mut pull_request.labels),
+        (pr_reviewer, &mut pull_request.reviewers),
+        (pr_assignee, &mut pull_request.assignees),
+    ] {
+        if !flags.is_empty() {
+            *values = flags;
+        }
+    }
+    let file_followups = on(
+        switch(file_followups, no_file_followups),
+        defaults.file_followups,
+    );
+    let no_notify = on(switch(no_notify, with_notify), defaults.no_notify);
+    let webhook = webhook.or(defaults.webhook);
+    let mut budget_limits = defaults.budget.unwrap_or_default();
+    budget_limits.extend(budgets);
+    let budgets: Vec<(String, f64)> = budget_limits.into_iter().collect();
+
+    if let Some(ref url) = webhook {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            anyhow::bail!("--webhook needs an http:// or https:// URL, not {}", url);
+        }
+    }
+    if settings
+        .email
+        .as_ref()
+        .is_some_and(|email| email.to.is_empty())
+    {
+        anyhow::bail!(
+            "[email] in {} needs at least one address in to",
+            SETTINGS_FILE
+        );
+    }
+    for (name, sink) in [
+        ("slack", &settings.notifications.slack),
+        ("webhook", &settings.notifications.webhook),
+    ] {
+        if let Some(sink) = sink {
+            if !sink.url.starts_with("http://") && !sink.url.starts_with("https://") {
                 anyhow::bail!(
-                    "PRD file not found: {}\n\nCreate a PRD file with tasks marked as '- [ ] Task description'\nOr use: --yaml tasks.yaml for YAML task files\nOr use: --github owner/repo for GitHub issues",
-                    path.display()
+                    "[notifications.{}] in {} needs an http:// or https:// URL, not {}",
+                    name,
+                    SETTINGS_FILE,
+                    sink.url
                 );
             }
         }
+    }
+    if task_timeout == Some(0) {
+        anyhow::bail!("task_timeout in {} must be at least 1", SETTINGS_FILE);
+    }
+    if rpm == Some(0) {
+        anyhow::bail!("rpm in {} must be at least 1", SETTINGS_FILE);
+    }
+    let amounts = max_cost
+        .iter()
+        .chain(budgets.iter().map(|(_, amount)| amount));
+    if amounts
+        .into_iter()
+        .any(|amount| !amount.is_finite() || *amount < 0.0)
+    {
+        anyhow::bail!("Budgets in {} must be non-negative numbers", SETTINGS_FILE);
+    }
+    let prices = settings.pricing.values();
+    if prices
+        .flat_map(|price| [price.input, price.output])
+        .any(|amount| !amount.is_finite() || amount < 0.0)
+    {
+        anyhow::bail!("Prices in {} must be non-negative numbers", SETTINGS_FILE);
+    }
+    if let Some(ref triage) = settings.triage {
+        if triage.tracker == TriageTracker::Jira
+            && triage.project.is_none()
+            && !matches!(prd_source, PrdSource::Jira { .. })
+        {
+            anyhow::bail!(
+                "[triage] in {} needs a project to file Jira issues in",
+                SETTINGS_FILE
+            );
+        }
+    }
+
+    // Validate PRD file exists for file-based sources
+    let mut prd_source = prd_source;
+    if let PrdSource::Markdown { ref mut path } | PrdSource::Yaml { ref mut path } = prd_source {
+        if !path.exists() {
+            anyhow::bail!(
+                "PRD file not found: {}\n\nCreate a PRD file with tasks marked as '- [ ] Task description'\nOr use: --yaml tasks.yaml for YAML task files\nOr use: --github owner/repo for GitHub issues\nOr use: --jira-project KEY for Jira issues\nOr use: --linear-team KEY for Linear issues",
+                path.display()
+            );
+        }
+
+        // Resolve now so the PRD can live outside the repository root
+        *path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve PRD path: {}", path.display()))?;
+    }
+
+    // Task branches are checked out in the shared working directory, so
+    // concurrent agents would race on `git checkout`
+    if parallel && branch_per_task {
+        anyhow::bail!(
+            "--branch-per-task cannot be combined with --parallel: each task branch is checked out \
+             in the shared working directory, so parallel agents would switch branches under each other.\n\n\
+             Run without --parallel to get one branch per task, or without --branch-per-task to run tasks in parallel."
+        );
+    }
 
-        Ok(Self {
-            ai_engine,
-            prd_source,
-            skip_tests,
-            skip_lint,
-            skip_commits,
-            max_iterations,
-            max_retries,
-            retry_delay,
-            dry_run,
-            parallel,
-            max_parallel,
-            branch_per_task,
-            base_branch,
-            create_pr,
-            draft_pr,
-            verbose,
-            no_color,
-            no_notify,
-        })
-    }
-
-    pub fn show_banner(&self) {
-        if self.no_color {
-            colored::control::set_override(false);
+    let log_json = match (output, log_json) {
+        (OutputFormat::Json, Some(_)) => {
+            anyhow::bail!("--log-json cannot be combined with --output json")
         }
+        (OutputFormat::Json, None) => Some(PathBuf::from(run_log::STDOUT)),
+        (OutputFormat::Text, log_json) => log_json,
+    };
 
-        println!("{}", "=".repeat(60).bright_black());
-        println!(
-            "{} - Running until PRD is complete",
-            "Ralphy".bright_cyan().bold()
+    // Flags that clap can't check against each other once some of them
+    // come from ralphy.toml
+    if (merge_queue || push_branches) && !parallel {
+        anyhow::bail!("--merge-queue and --push-branches need --parallel");
+    }
+    if tui && !parallel {
+        anyhow::bail!("--tui needs --parallel");
+    }
+    if merge_queue && push_branches {
+        anyhow::bail!("--merge-queue cannot be combined with --push-branches");
+    }
+    if review && parallel {
+        anyhow::bail!("--review cannot be combined with --parallel");
+    }
+    if interactive && parallel {
+        anyhow::bail!("--interactive cannot be combined with --parallel");
+    }
+    if rewrite_commit_messages && (skip_commits || parallel) {
+        anyhow::bail!(
+            "--rewrite-commit-messages cannot be combined with --no-commits or --parallel"
         );
-        println!("Engine: {}", format!("{}", self.ai_engine).bright_magenta());
-        println!(
-            "Source: {} ({})",
-            "PRD".bright_cyan(),
-            self.prd_source.display_name().bright_black()
+    }
+    if auto_commit && skip_commits {
+        anyhow::bail!("--auto-commit cannot be combined with --no-commits or --fast");
+    }
+    if conventional_commits && skip_commits {
+        anyhow::bail!("--conventional-commits cannot be combined with --no-commits or --fast");
+    }
+    if commit_scope.is_some() && !conventional_commits {
+        anyhow::bail!("--commit-scope needs --conventional-commits");
+    }
+    if ab.is_some() && (parallel || branch_per_task) {
+        anyhow::bail!("--ab cannot be combined with --parallel or --branch-per-task");
+    }
+    if backend != Backend::Local && (merge_queue || push_branches || ab.is_some()) {
+        anyhow::bail!(
+            "Only --backend local can be combined with --merge-queue, --push-branches or --ab"
         );
+    }
 
-        let mut mode_parts: Vec<String> = Vec::new();
-        if self.skip_tests {
-            mode_parts.push("no-tests".to_string());
-        }
-        if self.skip_lint {
-            mode_parts.push("no-lint".to_string());
-        }
-        if self.skip_commits {
-            mode_parts.push("no-commits".to_string());
-        }
-        if self.dry_run {
-            mode_parts.push("dry-run".to_string());
-        }
-        if self.parallel {
-            mode_parts.push(format!("parallel:{}", self.max_parallel));
-        }
-        if self.branch_per_task {
-            mode_parts.push("branch-per-task".to_string());
+    if merge_strategy != MergeStrategy::None && !branch_per_task {
+        anyhow::bail!("--merge-strategy needs --branch-per-task");
+    }
+    if merge_into.is_some() && merge_strategy == MergeStrategy::None {
+        anyhow::bail!("--merge-into needs --merge-strategy");
+    }
+    if create_pr && !branch_per_task && !push_branches {
+        anyhow::bail!("--create-pr needs --branch-per-task or --push-branches");
+    }
+    if review && review_mode == ReviewMode::Comment && !(create_pr && branch_per_task) {
+        anyhow::bail!("--review-mode comment needs --create-pr and --branch-per-task");
+    }
+
+    let mut engine_args: HashMap<AiEngine, Vec<String>> = settings
+        .engine
+        .into_iter()
+        .map(|(engine, settings)| (engine, settings.extra_args))
+        .filter(|(_, args)| !args.is_empty())
+        .collect();
+    // Like other lists, the flags replace what ralphy.toml says
+    if !engine_arg.is_empty() {
+        engine_args.insert(ai_engine, engine_arg);
+    }
+    if let Some(engine) = engine_args
+        .keys()
+        .find(|engine| crate::ai::engine_binary(**engine).is_none())
+    {
+        anyhow::bail!(
+            "{} isn't run as a command, so it can't take extra arguments",
+            engine
+        );
+    }
+
+    if ab == Some(ai_engine) {
+        anyhow::bail!(
+            "--ab needs a different engine from the one already selected ({})",
+            ai_engine
+        );
+    }
+
+    // Only Claude takes one prompt after another on a running process
+    if reuse_session && ai_engine != AiEngine::Claude {
+        anyhow::bail!(
+            "--reuse-session only works with Claude; {} starts fresh for every task",
+            ai_engine
+        );
+    }
+    if reuse_session && backend == Backend::Kubernetes {
+        anyhow::bail!("--reuse-session cannot be combined with --backend kubernetes");
+    }
+    if engine_args.contains_key(&ai_engine) && backend == Backend::Kubernetes {
+        anyhow::bail!("Extra engine arguments cannot be passed to --backend kubernetes");
+    }
+
+    // Resolved now so tasks in worktrees and other repositories find it
+    let gate_script = match gate {
+        Some(path) => Some(
+            path.canonicalize()
+                .with_context(|| format!("Gate script not found: {}", path.display()))?,
+        ),
+        None => Path::new(GATE_FILE).canonicalize().ok(),
+    };
+    // Fail before any task runs if the script doesn't compile
+    if let Some(ref path) = gate_script {
+        Gate::load(path)?;
+    }
+
+    let kubernetes = if backend == Backend::Kubernetes {
+        if !parallel {
+            anyhow::bail!("--backend kubernetes runs tasks as parallel Jobs and needs --parallel");
         }
-        if self.create_pr {
-            mode_parts.push("create-pr".to_string());
+        if ai_engine == AiEngine::Mock {
+            anyhow::bail!("--backend kubernetes cannot run the mock engine");
         }
-        if self.max_iterations > 0 {
-            mode_parts.push(format!("max:{}", self.max_iterations));
+        if crate::ai::is_http_engine(ai_engine) {
+            anyhow::bail!("--backend kubernetes cannot run the {} engine", ai_engine);
         }
+        Some(settings.kubernetes.with_context(|| {
+            format!(
+                "--backend kubernetes needs a [kubernetes] section with at least an image in {}",
+                SETTINGS_FILE
+            )
+        })?)
+    } else {
+        None
+    };
 
-        if !mode_parts.is_empty() {
-            println!("Mode: {}", mode_parts.join(" ").bright_yellow());
-        }
+    Ok(RunConfig {
+        ai_engine,
+        model,
+        pricing: Pricing::new(settings.pricing),
+        prd_source,
+        skip_tests,
+        skip_lint,
+        skip_commits,
+        repo_map,
+        repo_map_tokens,
+        max_prompt_tokens,
+        context_files,
+        context,
+        progress_limit_kb: progress_limit,
+        max_iterations,
+        max_retries,
+        retry_backoff,
+        task_timeout,
+        timeout_grace,
+        rate_limit,
+        max_replans,
+        completion_marker,
+        prompt_template,
+        prompt_vars: settings.prompt_vars,
+        engine_args,
+        reuse_session,
+        resume,
+        dry_run,
+        interactive,
+        backend,
+        kubernetes,
+        budgets,
+        max_cost,
+        max_tokens,
+        parallel,
+        max_parallel,
+        merge_queue,
+        push_branches,
+        tui,
+        verify_cmd: if verify_cmd.is_empty() {
+            defaults.verify_cmd.unwrap_or_default()
+        } else {
+            verify_cmd
+        },
+        ab,
+        review,
+        // The default only matters when reviewing; preflight would
+        // otherwise require its binary on every run
+        review_engine: review_engine.or(defaults.review_engine.filter(|_| review)),
+        review_mode,
+        gate_script,
+        security: settings.security.filter(|s| !s.scanners.is_empty()),
+        diff_scan: if no_diff_scan {
+            None
+        } else {
+            Some(settings.diff_scan)
+        },
+        rewrite_commit_messages,
+        auto_commit,
+        conventional_commits,
+        commit_scope,
+        branch_per_task,
+        base_branch,
+        merge_strategy,
+        merge_into,
+        create_pr,
+        draft_pr,
+        pull_request,
+        file_followups,
+        verbose,
+        no_color,
+        no_notify,
+        log_json,
+        webhook,
+        reporting: if no_report { None } else { settings.reporting },
+        triage: settings.triage,
+        email: settings.email,
+        notifications: settings.notifications,
+    })
+}
+
+/// Print the run's banner: engine, task source and the modes it runs in.
+pub fn show_banner(config: &RunConfig) {
+    if config.no_color {
+        colored::control::set_override(false);
+    }
 
-        println!("{}", "=".repeat(60).bright_black());
+    println!("{}", "=".repeat(60).bright_black());
+    println!(
+        "{} - Running until PRD is complete",
+        "Ralphy".bright_cyan().bold()
+    );
+    println!(
+        "Engine: {}",
+        format!("{}", config.ai_engine).bright_magenta()
+    );
+    println!(
+        "Source: {} ({})",
+        "PRD".bright_cyan(),
+        config.prd_source.display_name().bright_black()
+    );
+
+    let mut mode_parts: Vec<String> = Vec::new();
+    if config.skip_tests {
+        mode_parts.push("no-tests".to_string());
+    }
+    if config.skip_lint {
+        mode_parts.push("no-lint".to_string());
+    }
+    if config.skip_commits {
+        mode_parts.push("no-commits".to_string());
+    }
+    if config.dry_run {
+        mode_parts.push("dry-run".to_string());
+    }
+    if config.backend == Backend::Devcontainer {
+        mode_parts.push("devcontainer".to_string());
+    }
+    if config.backend == Backend::Kubernetes {
+        mode_parts.push("kubernetes".to_string());
     }
+    if config.parallel {
+        mode_parts.push(format!("parallel:{}", config.max_parallel));
+    }
+    if config.merge_queue {
+        mode_parts.push("merge-queue".to_string());
+    }
+    if config.push_branches {
+        mode_parts.push("push-branches".to_string());
+    }
+    if config.reuse_session {
+        mode_parts.push("reuse-session".to_string());
+    }
+    if config.resume {
+        mode_parts.push("resume".to_string());
+    }
+    if let Some(comparison) = config.ab {
+        mode_parts.push(format!("ab:{}", comparison));
+    }
+    if config.review {
+        let engine = config.review_engine.unwrap_or(config.ai_engine);
+        mode_parts.push(match config.review_mode {
+            ReviewMode::Fix => format!("review:{}", engine),
+            ReviewMode::Comment => format!("review-comment:{}", engine),
+        });
+    }
+    if config.gate_script.is_some() {
+        mode_parts.push("gate".to_string());
+    }
+    if config.security.is_some() {
+        mode_parts.push("security-scan".to_string());
+    }
+    if config.rewrite_commit_messages {
+        mode_parts.push("ai-commit-messages".to_string());
+    }
+    if config.auto_commit {
+        mode_parts.push("auto-commit".to_string());
+    }
+    if config.conventional_commits {
+        mode_parts.push("conventional-commits".to_string());
+    }
+    if config.branch_per_task {
+        mode_parts.push("branch-per-task".to_string());
+    }
+    if config.merge_strategy != MergeStrategy::None {
+        mode_parts.push(format!(
+            "merge-back:{}",
+            config
+                .merge_strategy
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default()
+        ));
+    }
+    if config.create_pr {
+        mode_parts.push("create-pr".to_string());
+    }
+    if config.triage.is_some() {
+        mode_parts.push("triage".to_string());
+    }
+    if config.max_iterations > 0 {
+        mode_parts.push(format!("max:{}", config.max_iterations));
+    }
+    if let Some(secs) = config.task_timeout {
+        mode_parts.push(format!("timeout:{}s", secs));
+    }
+    if let Some(rpm) = config.rate_limit.rpm {
+        mode_parts.push(format!("rpm:{}", rpm));
+    }
+    if !config.rate_limit.min_delay.is_zero() {
+        mode_parts.push(format!(
+            "min-delay:{}s",
+            config.rate_limit.min_delay.as_secs()
+        ));
+    }
+    if let Some(max) = config.max_cost {
+        mode_parts.push(format!("max-cost:${:.2}", max));
+    }
+    if let Some(max) = config.max_tokens {
+        mode_parts.push(format!("max-tokens:{}", max));
+    }
+    for (label, amount) in &config.budgets {
+        mode_parts.push(format!("budget:{}=${:.2}", label, amount));
+    }
+
+    if !mode_parts.is_empty() {
+        println!("Mode: {}", mode_parts.join(" ").bright_yellow());
+    }
+
+    println!("{}", "=".repeat(60).bright_black());
 }
@@ -0,0 +1,246 @@
+use crate::cli::AiEngine;
+use crate::config::RunConfig;
+use crate::events::{self, Level, PrdProgress, RunEvent, Subscription};
+use crate::pricing::Pricing;
+use crate::stats::{self, RunStats};
+use crate::text;
+use colored::*;
+
+/// What the summary at the end of a run needs from its config.
+struct Summary {
+    engine: AiEngine,
+    pricing: Pricing,
+    parallel: bool,
+}
+
+/// Print the run's events the way `ralphy` shows them, until the returned
+/// subscription is dropped.
+pub fn subscribe(config: &RunConfig) -> Subscription {
+    let summary = Summary {
+        engine: config.ai_engine,
+        pricing: config.pricing.clone(),
+        parallel: config.parallel,
+    };
+    events::subscribe(move |event| match event {
+        RunEvent::Notice { level, message } => show_notice(*level, message),
+        RunEvent::TaskStarted {
+            iteration,
+            prd: Some(prd),
+            ..
+        } => show_task_header(*iteration, prd),
+        RunEvent::BranchCreated { branch, .. } => {
+            println!(
+                "{} On branch {}",
+                "[INFO]".blue().bold(),
+                branch.bright_cyan()
+            )
+        }
+        RunEvent::PullRequestOpened { url, .. } => {
+            println!("{} Opened {}", "[SUCCESS]".green().bold(), url)
+        }
+        RunEvent::RunFinished {
+            stats,
+            interrupted: false,
+            ..
+        } => show_summary(stats, &summary),
+        _ => {}
+    })
+}
+
+fn show_notice(level: Level, message: &str) {
+    match level {
+        Level::Plain => println!("{}", message),
+        Level::Info => println!("{} {}", "[INFO]".blue().bold(), message),
+        Level::Success => println!("{} {}", "[SUCCESS]".green().bold(), message),
+        Level::Warn => eprintln!("{} {}", "[WARN]".yellow().bold(), message),
+        Level::Error => eprintln!("{} {}", "[ERROR]".red().bold(), message),
+        Level::Passed => println!("  {} {}", "✓".green().bold(), message),
+        Level::Failed => eprintln!("  {} {}", "✗".red().bold(), message),
+    }
+}
+
+fn show_task_header(iteration: usize, prd: &PrdProgress) {
+    println!("\n{}", "─".repeat(60).bright_black());
+    println!("{} Task {}", ">>>".bright_cyan().bold(), iteration);
+    println!(
+        "    Completed: {} | Remaining: {}",
+        prd.completed.to_string().bright_green(),
+        prd.remaining.to_string().bright_yellow()
+    );
+    println!("{}", "─".repeat(60).bright_black());
+}
+
+fn show_summary(stats: &RunStats, summary: &Summary) {
+    println!("\n{}", "=".repeat(60).bright_black());
+    if let Some(ref reason) = stats.limit_reached {
+        println!(
+            "{} Stopped after {} task(s): {} limit",
+            "$".yellow().bold(),
+            stats.agents.len(),
+            reason
+        );
+    } else if stats.left_unchecked > 0 {
+        println!(
+            "{} Stopped after {} task(s) with {} still unchecked: the engine declared the PRD complete",
+            "✗".red().bold(),
+            stats.iterations,
+            stats.left_unchecked
+        );
+    } else if stats.failed.is_empty() && stats.over_budget.is_empty() {
+        println!(
+            "{} PRD complete! Finished {} task(s).",
+            "✓".green().bold(),
+            stats.iterations
+        );
+    }
+    if !stats.failed.is_empty() || !stats.over_budget.is_empty() {
+        println!(
+            "{} Finished {} task(s), {} failed:",
+            "✗".red().bold(),
+            stats.completed(),
+            stats.failed.len()
+        );
+        for task in &stats.failed {
+            println!("    {} {}", "✗".red(), text::truncate(task, 56));
+        }
+        if !stats.over_budget.is_empty() {
+            println!("  Skipped over budget:");
+        }
+        for task in &stats.over_budget {
+            println!("    {} {}", "$".yellow(), text::truncate(task, 56));
+        }
+    }
+    println!("{}", "=".repeat(60).bright_black());
+    println!("\n{} Cost Summary", ">>>".bright_cyan().bold());
+
+    match summary.engine {
+        AiEngine::Cursor if stats.input_tokens + stats.output_tokens == 0 => {
+            println!(
+                "{}",
+                "Token usage not available (this Cursor CLI version doesn't report it)"
+                    .bright_black()
+            );
+        }
+        _ => {
+            println!("Input tokens:  {}", stats.input_tokens);
+            println!("Output tokens: {}", stats.output_tokens);
+            println!(
+                "Total tokens:  {}",
+                stats.input_tokens + stats.output_tokens
+            );
+
+            // A reported cost of nothing (a local model) is still the actual cost
+            if stats.actual_cost > 0.0 || stats.usage().any(|a| a.actual_cost.is_some()) {
+                println!("Actual cost:   ${:.4}", stats.actual_cost);
+            } else {
+                let est_cost = crate::run_cost(&summary.pricing, stats);
+                println!("Est. cost:     ${:.4}", est_cost);
+            }
+        }
+    }
+
+    if stats.duration_ms > 0 {
+        println!(
+            "Total API time: {}",
+            stats::format_duration(stats.duration_ms)
+        );
+    }
+    if stats.wall_ms > 0 {
+        println!("Wall time:      {}", stats::format_duration(stats.wall_ms));
+    }
+
+    if let Some(timing) = stats.task_timing() {
+        println!("\n{} Task timing", ">>>".bright_cyan().bold());
+        for agent in &stats.agents {
+            let api = agent
+                .duration_ms
+                .map(stats::format_duration)
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "  {} │ {:>7} wall │ {:>7} API",
+                text::truncate_padded(&agent.task, 50),
+                stats::format_duration(agent.wall_ms),
+                api
+            );
+        }
+        println!(
+            "  Fastest {} │ slowest {} │ average {}",
+            stats::format_duration(timing.min_ms),
+            stats::format_duration(timing.max_ms),
+            stats::format_duration(timing.avg_ms)
+        );
+    }
+
+    let engines = stats.by_engine();
+    if engines.len() > 1 {
+        println!("\n{} Per-engine breakdown", ">>>".bright_cyan().bold());
+        for usage in &engines {
+            let cost = match usage.actual_cost {
+                Some(cost) => format!("${:.4}", cost),
+                None => format!(
+                    "~${:.4}",
+                    summary.pricing.cost(
+                        usage.engine,
+                        usage.model.as_deref(),
+                        usage.input_tokens,
+                        usage.output_tokens
+                    )
+                ),
+            };
+            println!(
+                "  {} │ {:>3} task(s) │ {:>7} in │ {:>7} out │ {:>9} │ {:>7}",
+                text::truncate_padded(&usage.label(), 30),
+                usage.tasks,
+                usage.input_tokens,
+                usage.output_tokens,
+                cost,
+                stats::format_duration(usage.duration_ms)
+            );
+        }
+    }
+
+    let repos = stats.by_repo();
+    if !repos.is_empty() {
+        println!("\n{} Per-repository breakdown", ">>>".bright_cyan().bold());
+        for outcome in &repos {
+            println!(
+                "  {} │ {:>3} done │ {:>3} failed",
+                text::truncate_padded(&outcome.repo, 40),
+                outcome.completed,
+                outcome.failed
+            );
+        }
+    }
+
+    if summary.parallel && !stats.agents.is_empty() {
+        println!("\n{} Per-agent breakdown", ">>>".bright_cyan().bold());
+        for agent in &stats.agents {
+            let cost = match agent.actual_cost {
+                Some(cost) => format!("${:.4}", cost),
+                None => format!(
+                    "~${:.4}",
+                    summary.pricing.cost(
+                        agent.engine,
+                        agent.model.as_deref(),
+                        agent.input_tokens,
+                        agent.output_tokens
+                    )
+                ),
+            };
+            let duration = agent
+                .duration_ms
+                .map(stats::format_duration)
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "  {} │ {:>7} in │ {:>7} out │ {:>9} │ {:>7}",
+                text::truncate_padded(&agent.task, 50),
+                agent.input_tokens,
+                agent.output_tokens,
+                cost,
+                duration
+            );
+        }
+    }
+
+    println!("{}", "=".repeat(60).bright_black());
+}
@@ -0,0 +1,60 @@
+use crate::settings::{EmailSettings, SmtpSecurity};
+use anyhow::{Context, Result};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::time::Duration;
+
+/// Environment variable holding the SMTP password for `username`.
+pub const PASSWORD_VAR: &str = "RALPHY_SMTP_PASSWORD";
+
+/// A server that doesn't answer must not hold up the end of a run.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Email `body` to everyone `settings` lists.
+pub async fn send(settings: &EmailSettings, subject: &str, body: &str) -> Result<()> {
+    let from = settings
+        .from
+        .as_deref()
+        .or(settings.username.as_deref())
+        .context("[email] needs a from address or a username")?;
+    let mut message = Message::builder()
+        .from(parse_mailbox(from)?)
+        .subject(subject);
+    for to in &settings.to {
+        message = message.to(parse_mailbox(to)?);
+    }
+    let message = message.body(body.to_string())?;
+
+    let mut transport = match settings.security {
+        SmtpSecurity::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+        }
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        SmtpSecurity::None => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+        }
+    }
+    .timeout(Some(SEND_TIMEOUT));
+    if let Some(port) = settings.port {
+        transport = transport.port(port);
+    }
+    if let Some(ref username) = settings.username {
+        let password =
+            std::env::var(PASSWORD_VAR).with_context(|| format!("{} is not set", PASSWORD_VAR))?;
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .with_context(|| format!("Failed to send through {}", settings.host))?;
+    Ok(())
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .with_context(|| format!("Invalid email address: {}", address))
+}
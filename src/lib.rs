@@ -2,418 +2,249 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
-pub mod ai;
+pub mod backlog;
 pub mod cli;
 pub mod config;
-pub mod git;
+pub mod console;
+pub mod email;
 pub mod monitor;
 pub mod notifications;
-pub mod prd;
-pub mod prompt;
+pub mod plan;
+pub mod report;
+pub mod run_log;
+pub mod runner;
+pub mod self_update;
+pub mod telemetry;
+pub mod templates;
+pub mod webhook;
+
+pub use ralphy_core::{
+    ab, ai, approval, backend, backoff, budget, checkpoint, commit_message, contract, diff_scan,
+    events, followups, gate, git, github, jira, kubernetes, linear, merge_back, merge_queue, prd,
+    preflight, pricing, process, progress, progress_summary, prompt, prompt_budget, pull_request,
+    relevance, repair, replan, repo_map, repos, retry, review, run_cost, schedule, security,
+    session, settings, shutdown, stats, text, triage, verify, workspace, Rejected, RunOutcome,
+};
 
 use anyhow::{Context, Result};
 use colored::*;
-use config::Config;
-use futures::future::join_all;
+use config::RunConfig;
+use events::RunEvent;
 use prd::PrdManager;
-use std::sync::Arc;
-use tokio::time::{sleep, Duration};
-
-pub async fn run_autonomous_loop(config: Config) -> Result<()> {
-    // Pre-flight checks
-    preflight_checks(&config).await?;
-
-    // Create managers
-    let prd_manager = Arc::new(PrdManager::new(config.prd_source.clone()));
-
-    if config.parallel {
-        run_parallel_loop(config, prd_manager).await
-    } else {
-        run_sequential_loop(config, prd_manager).await
-    }
-}
-
-async fn preflight_checks(config: &Config) -> Result<()> {
-    // Check AI CLI availability
-    ai::check_ai_availability(config.ai_engine)?;
-
-    // Check for jq
-    if std::process::Command::new("which")
-        .arg("jq")
-        .stdout(std::process::Stdio::null())
-        .status()?
-        .success()
-        == false
-    {
-        eprintln!(
-            "{} jq is required but not installed. Install with: apt-get install jq (Debian/Ubuntu) or brew install jq (macOS)",
-            "[ERROR]".red().bold()
-        );
-        anyhow::bail!("jq not found");
-    }
-
-    // Check for gh if PR creation is requested
-    if config.create_pr {
-        if std::process::Command::new("which")
-            .arg("gh")
-            .stdout(std::process::Stdio::null())
-            .status()?
-            .success()
-            == false
-        {
-            anyhow::bail!(
-                "GitHub CLI (gh) is required for --create-pr. Install from https://cli.github.com/"
-            );
+use ralphy_core::run::{detect_workspace, run_autonomous_loop};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Run the command `cli` describes, with `fallback` filling in defaults
+/// that neither flags nor ralphy.toml set. Shared by `ralphy` and
+/// `cargo ralphy`.
+pub async fn run_cli(mut cli: cli::Cli, fallback: settings::DefaultSettings) -> Result<RunOutcome> {
+    use cli::Command;
+
+    ralphy_core::log::set_sink(publish_log);
+    let command = cli.command.take();
+
+    // These don't depend on a PRD or engine
+    match command {
+        Some(Command::Telemetry { action }) => {
+            telemetry::command(action)?;
+            return Ok(RunOutcome::Complete);
         }
-    }
-
-    // Check for git
-    if !git::is_git_repo()? {
-        anyhow::bail!("Not a git repository. Ralphy requires a git repository to track changes.");
-    }
-
-    // Create progress.txt if missing
-    if !std::path::Path::new("progress.txt").exists() {
-        eprintln!(
-            "{} progress.txt not found, creating it...",
-            "[WARN]".yellow().bold()
-        );
-        tokio::fs::write("progress.txt", "").await?;
-    }
-
-    Ok(())
-}
-
-async fn run_sequential_loop(config: Config, prd_manager: Arc<PrdManager>) -> Result<()> {
-    let mut iteration = 0;
-    let mut total_input_tokens = 0;
-    let mut total_output_tokens = 0;
-    let mut total_cost = 0.0;
-    let mut total_duration_ms = 0u64;
-
-    loop {
-        iteration += 1;
-
-        // Check if we've hit max iterations
-        if config.max_iterations > 0 && iteration > config.max_iterations {
-            println!(
-                "\n{} Reached max iterations ({})",
-                "[WARN]".yellow().bold(),
-                config.max_iterations
-            );
-            break;
+        Some(Command::SelfUpdate { check }) => {
+            telemetry::send(&telemetry::Event::new("self-update"), false).await;
+            self_update::run(check).await?;
+            return Ok(RunOutcome::Complete);
         }
-
-        // Get next task
-        let task = match prd_manager.get_next_task().await? {
-            Some(t) => t,
-            None => {
-                println!("\n{} All tasks complete!", "[SUCCESS]".green().bold());
-                break;
-            }
-        };
-
-        // Show task info
-        let remaining = prd_manager.count_remaining().await?;
-        let completed = prd_manager.count_completed().await?;
-
-        println!("\n{}", "─".repeat(60).bright_black());
-        println!("{} Task {}", ">>>".bright_cyan().bold(), iteration);
-        println!(
-            "    Completed: {} | Remaining: {}",
-            completed.to_string().bright_green(),
-            remaining.to_string().bright_yellow()
-        );
-        println!("{}", "─".repeat(60).bright_black());
-
-        // Execute task with retries
-        let mut retry_count = 0;
-        let response = loop {
-            match execute_task(&config, &task, iteration).await {
-                Ok(resp) => break resp,
-                Err(e) => {
-                    retry_count += 1;
-                    if retry_count >= config.max_retries {
-                        eprintln!(
-                            "{} Task failed after {} attempts: {}",
-                            "[ERROR]".red().bold(),
-                            config.max_retries,
-                            e
-                        );
-                        // Continue to next task instead of failing entirely
-                        break ai::AiResponse {
-                            text: String::new(),
-                            input_tokens: 0,
-                            output_tokens: 0,
-                            actual_cost: None,
-                            duration_ms: None,
-                        };
-                    }
-                    eprintln!(
-                        "{} Attempt {}/{} failed: {}. Retrying in {}s...",
-                        "[WARN]".yellow().bold(),
-                        retry_count,
-                        config.max_retries,
-                        e,
-                        config.retry_delay
-                    );
-                    sleep(Duration::from_secs(config.retry_delay)).await;
-                }
-            }
-        };
-
-        // Update totals
-        total_input_tokens += response.input_tokens;
-        total_output_tokens += response.output_tokens;
-        if let Some(cost) = response.actual_cost {
-            total_cost += cost;
+        Some(Command::Progress { all }) => {
+            telemetry::send(&telemetry::Event::new("progress"), false).await;
+            print_progress(all)?;
+            return Ok(RunOutcome::Complete);
         }
-        if let Some(dur) = response.duration_ms {
-            total_duration_ms += dur;
+        Some(Command::Init {
+            yaml,
+            config,
+            force,
+        }) => {
+            telemetry::send(&telemetry::Event::new("init"), false).await;
+            templates::init(Path::new("."), yaml, config, force)?;
+            return Ok(RunOutcome::Complete);
         }
-
-        // Mark task complete
-        prd_manager.mark_complete(&task).await?;
-
-        // Show completion
-        println!(
-            "  {} Done │ {}",
-            "✓".green().bold(),
-            task.chars().take(50).collect::<String>()
-        );
-
-        if !response.text.is_empty() {
-            println!("\n{}", response.text);
+        Some(Command::New { template, force }) => {
+            telemetry::send(&telemetry::Event::new("new"), false).await;
+            templates::scaffold(template, Path::new("."), force)?;
+            return Ok(RunOutcome::Complete);
         }
+        _ => {}
     }
 
-    // Show summary
-    show_summary(
-        iteration,
-        total_input_tokens,
-        total_output_tokens,
-        total_cost,
-        total_duration_ms,
-        &config,
-    );
-
-    // Send notification
-    if !config.no_notify {
-        notifications::notify_done("Ralphy has completed all tasks!");
+    // The spec stands in for the PRD, which planning is there to write
+    if let Some(Command::Plan { ref spec, .. }) = command {
+        (
+            cli.prd,
+            cli.yaml,
+            cli.github,
+            cli.jira_project,
+            cli.linear_team,
+        ) = (Some(spec.clone()), None, None, None, None);
     }
 
-    Ok(())
-}
-
-async fn run_parallel_loop(config: Config, prd_manager: Arc<PrdManager>) -> Result<()> {
-    println!(
-        "\n{} Running {} parallel agents (each in isolated worktree)...",
-        "[INFO]".blue().bold(),
-        config.max_parallel.to_string().bright_cyan().bold()
-    );
+    // Convert CLI to RunConfig
+    let config = config::from_cli_with_defaults(cli, fallback)?;
+    let _console = console::subscribe(&config);
 
-    let all_tasks = prd_manager.get_tasks().await?;
-    if all_tasks.is_empty() {
-        println!("{} No tasks to run", "[INFO]".blue().bold());
-        return Ok(());
+    match command {
+        Some(Command::Prompt { task }) => {
+            telemetry::send(&telemetry::Event::new("prompt"), false).await;
+            print_prompt(&config, task.as_deref()).await?;
+            return Ok(RunOutcome::Complete);
+        }
+        Some(Command::Add {
+            title,
+            priority,
+            group,
+        }) => {
+            telemetry::send(&telemetry::Event::new("add"), false).await;
+            backlog::add(&config.prd_source, &title, priority, group).await?;
+            return Ok(RunOutcome::Complete);
+        }
+        Some(Command::Plan {
+            spec,
+            output,
+            force,
+        }) => {
+            telemetry::send(&telemetry::Event::new("plan"), false).await;
+            plan::run(&config, &spec, &output, force).await?;
+            return Ok(RunOutcome::Complete);
+        }
+        Some(Command::Done { pattern, all }) => {
+            telemetry::send(&telemetry::Event::new("done"), false).await;
+            backlog::done(&config.prd_source, &pattern, all).await?;
+            return Ok(RunOutcome::Complete);
+        }
+        _ => {}
     }
 
-    println!(
-        "{} Found {} tasks to process",
-        "[INFO]".blue().bold(),
-        all_tasks.len()
-    );
+    let _log = config.log_json.as_deref().map(run_log::open).transpose()?;
 
-    let mut total_input_tokens = 0;
-    let mut total_output_tokens = 0;
-    let mut iteration = 0;
+    // Show banner
+    config::show_banner(&config);
 
-    // Process tasks in batches
-    for chunk in all_tasks.chunks(config.max_parallel) {
-        let batch_num = iteration / config.max_parallel + 1;
-        println!(
-            "\n{} Batch {}: Spawning {} parallel agents",
-            "━━━".bright_black(),
-            batch_num,
-            chunk.len()
-        );
+    // Stop gracefully on SIGINT/SIGTERM
+    shutdown::install_handlers();
 
-        let mut handles = vec![];
-
-        for task in chunk {
-            iteration += 1;
-            let config_clone = config.clone();
-            let task_clone = task.clone();
-            let prd_manager_clone = prd_manager.clone();
-
-            let handle = tokio::spawn(async move {
-                let result = execute_task(&config_clone, &task_clone, iteration).await;
-                (task_clone, result)
-            });
-
-            handles.push(handle);
-        }
-
-        // Wait for all parallel tasks
-        let results = join_all(handles).await;
-
-        // Process results
-        for result in results {
-            match result {
-                Ok((task, Ok(response))) => {
-                    total_input_tokens += response.input_tokens;
-                    total_output_tokens += response.output_tokens;
-
-                    // Mark complete
-                    prd_manager.mark_complete(&task).await?;
+    // Run the autonomous loop
+    run_observed(config).await
+}
 
-                    println!(
-                        "  {} Agent completed: {}",
-                        "✓".green().bold(),
-                        task.chars().take(50).collect::<String>()
-                    );
-                }
-                Ok((task, Err(e))) => {
-                    eprintln!(
-                        "  {} Agent failed: {} - {}",
-                        "✗".red().bold(),
-                        task.chars().take(50).collect::<String>(),
-                        e
-                    );
-                }
-                Err(e) => {
-                    eprintln!("  {} Task join error: {}", "✗".red().bold(), e);
-                }
+/// Run the loop with notifications and the `--webhook` following its
+/// events, and deliver what they queued before returning. The console and
+/// the JSON log are up to the caller.
+pub async fn run_observed(config: RunConfig) -> Result<RunOutcome> {
+    ralphy_core::log::set_sink(publish_log);
+    let webhook = config.webhook.as_deref().map(webhook::start).transpose()?;
+    let notifications = notifications::start(&config);
+
+    // The usage report and telemetry cover runs that finished, not ones
+    // that were interrupted
+    let finished = Arc::new(Mutex::new(None));
+    let _finished = {
+        let finished = finished.clone();
+        events::subscribe(move |event| {
+            if let RunEvent::RunFinished {
+                stats,
+                interrupted: false,
+                ..
+            } = event
+            {
+                *finished.lock().unwrap() = Some(stats.clone());
             }
-        }
+        })
+    };
 
-        if config.max_iterations > 0 && iteration >= config.max_iterations {
-            println!(
-                "\n{} Reached max iterations ({})",
-                "[WARN]".yellow().bold(),
-                config.max_iterations
-            );
-            break;
-        }
+    let outcome = run_autonomous_loop(config.clone(), Arc::new(monitor::TerminalMonitor)).await;
+    if let Err(ref e) = outcome {
+        events::publish(RunEvent::run_error(e));
     }
-
-    show_summary(
-        iteration,
-        total_input_tokens,
-        total_output_tokens,
-        0.0,
-        0,
-        &config,
-    );
-
-    if !config.no_notify {
-        notifications::notify_done("Ralphy has completed all tasks!");
+    let stats = finished.lock().unwrap().take();
+    if let Some(stats) = stats {
+        report::submit(&config, &stats).await;
+        telemetry::send(&telemetry::Event::run(&config, &stats), config.verbose > 0).await;
     }
-
-    Ok(())
-}
-
-async fn execute_task(config: &Config, task: &str, iteration: usize) -> Result<ai::AiResponse> {
-    if config.dry_run {
-        println!("{} DRY RUN - Would execute:", "[INFO]".blue().bold());
-        let prompt = prompt::build_prompt(config, Some(task));
-        println!("{}", prompt.bright_black());
-        return Ok(ai::AiResponse {
-            text: "Dry run".to_string(),
-            input_tokens: 0,
-            output_tokens: 0,
-            actual_cost: None,
-            duration_ms: None,
-        });
+    // Deliver whatever the run left queued before the process exits
+    if let Some(notifications) = notifications {
+        notifications.finish().await;
     }
-
-    // Create branch if needed
-    if config.branch_per_task {
-        git::create_task_branch(task, config.base_branch.as_deref())?;
+    if let Some(webhook) = webhook {
+        webhook.finish().await;
     }
+    outcome
+}
 
-    // Build prompt
-    let prompt = prompt::build_prompt(config, Some(task));
-
-    // Execute AI
-    let executor = ai::AiExecutor::new(config.ai_engine);
+/// Publish engine messages as notices, like the rest of the run's output.
+fn publish_log(level: ralphy_core::log::Level, message: &str) {
+    match level {
+        ralphy_core::log::Level::Info => events::info(message),
+        ralphy_core::log::Level::Warn => events::warn(message),
+    }
+}
 
-    // Start progress monitor
-    let monitor_handle = if !config.parallel {
-        Some(tokio::spawn(monitor::monitor_progress(
-            task.to_string(),
-            config.ai_engine,
-        )))
+/// Print the progress log rendered as text, with archived entries first
+/// when `all` is set.
+pub fn print_progress(all: bool) -> Result<()> {
+    let log = progress::ProgressLog::default();
+    let entries = if all {
+        log.all_entries()?
     } else {
-        None
+        log.entries()?
     };
-
-    let response = executor.execute(&prompt).await?;
-
-    // Stop monitor
-    if let Some(handle) = monitor_handle {
-        handle.abort();
-    }
-
-    // Create PR if needed
-    if config.create_pr && config.branch_per_task {
-        git::create_pull_request(task, config.draft_pr)?;
+    if entries.is_empty() {
+        println!("{} No progress recorded yet", "[INFO]".blue().bold());
+        return Ok(());
     }
-
-    Ok(response)
+    print!("{}", progress::render(&entries));
+    Ok(())
 }
 
-fn show_summary(
-    iterations: usize,
-    input_tokens: usize,
-    output_tokens: usize,
-    actual_cost: f64,
-    duration_ms: u64,
-    config: &Config,
-) {
-    println!("\n{}", "=".repeat(60).bright_black());
+/// Print the prompt the sequential loop would send for `task`, or for the
+/// next incomplete task when none is named.
+pub async fn print_prompt(config: &RunConfig, task: Option<&str>) -> Result<()> {
+    let prd_manager = PrdManager::new(config.prd_source.clone());
+    let snapshot = prd_manager.refresh().await?;
+
+    let task = match task {
+        Some(name) => snapshot.find_task(name).with_context(|| {
+            format!(
+                "No single incomplete task matches '{}'. Incomplete tasks:\n  {}",
+                name,
+                snapshot.names().join("\n  ")
+            )
+        })?,
+        None => snapshot
+            .next_task()
+            .context("No incomplete tasks in the PRD")?,
+    };
+
+    let name = task.name();
+    let tags = task.budget_labels();
+    let details = task.details();
+    let workspace = detect_workspace(Path::new("."));
+    let scope = match snapshot.repo_of(&name) {
+        Some(_) => prompt::TaskScope {
+            other_repo: true,
+            details: (!details.is_empty()).then_some(&details),
+            ..Default::default()
+        },
+        None => prompt::TaskScope {
+            other_repo: false,
+            package: workspace.package_for(&tags),
+            tags: &tags,
+            details: (!details.is_empty()).then_some(&details),
+            previous_failure: None,
+        },
+    };
     println!(
-        "{} PRD complete! Finished {} task(s).",
-        "✓".green().bold(),
-        iterations
+        "{}",
+        prompt::build_scoped_prompt(config, &name, progress::PROGRESS_FILE, scope)
     );
-    println!("{}", "=".repeat(60).bright_black());
-    println!("\n{} Cost Summary", ">>>".bright_cyan().bold());
-
-    match config.ai_engine {
-        cli::AiEngine::Cursor => {
-            println!(
-                "{}",
-                "Token usage not available (Cursor CLI doesn't expose this data)".bright_black()
-            );
-            if duration_ms > 0 {
-                let dur_sec = duration_ms / 1000;
-                let dur_min = dur_sec / 60;
-                let dur_sec_rem = dur_sec % 60;
-                if dur_min > 0 {
-                    println!("Total API time: {}m {}s", dur_min, dur_sec_rem);
-                } else {
-                    println!("Total API time: {}s", dur_sec);
-                }
-            }
-        }
-        _ => {
-            println!("Input tokens:  {}", input_tokens);
-            println!("Output tokens: {}", output_tokens);
-            println!("Total tokens:  {}", input_tokens + output_tokens);
-
-            if actual_cost > 0.0 {
-                println!("Actual cost:   ${:.4}", actual_cost);
-            } else {
-                let est_cost = calculate_cost(input_tokens, output_tokens);
-                println!("Est. cost:     ${:.4}", est_cost);
-            }
-        }
-    }
-
-    println!("{}", "=".repeat(60).bright_black());
-}
-
-fn calculate_cost(input_tokens: usize, output_tokens: usize) -> f64 {
-    (input_tokens as f64 * 0.000003) + (output_tokens as f64 * 0.000015)
+    Ok(())
 }
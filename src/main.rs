@@ -1,6 +1,6 @@
 use anyhow::Result;
 use clap::Parser;
-use ralphy_rs::{cli::Cli, config::Config, run_autonomous_loop};
+use ralphy_rs::{cli::Cli, run_cli, settings::DefaultSettings, RunOutcome};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 #[tokio::main]
@@ -14,14 +14,11 @@ async fn main() -> Result<()> {
     // Parse CLI arguments
     let cli = Cli::parse();
 
-    // Convert CLI to Config
-    let config = Config::from_cli(cli)?;
+    let outcome = run_cli(cli, DefaultSettings::default()).await?;
 
-    // Show banner
-    config.show_banner();
-
-    // Run the autonomous loop
-    run_autonomous_loop(config).await?;
+    if outcome != RunOutcome::Complete {
+        std::process::exit(outcome.exit_code());
+    }
 
     Ok(())
 }
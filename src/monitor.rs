@@ -1,14 +1,78 @@
-use crate::cli::AiEngine;
+use crate::ai::{AiEngine, AiResponse, TokenCount};
+use anyhow::Result;
 use colored::*;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Position};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table};
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
-pub async fn monitor_progress(task: String, engine: AiEngine) {
+pub use ralphy_core::ai::{step_channel, usage_channel, StepSender, UsageSender};
+pub use ralphy_core::monitor::{
+    AgentMonitor, BatchMonitor, Finished, Monitor, NoMonitor, TaskMonitor,
+};
+
+/// A running progress monitor that can be stopped cleanly.
+pub struct MonitorHandle {
+    stop: Option<oneshot::Sender<bool>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MonitorHandle {
+    /// Start the spinner for `task` on the current line.
+    pub fn spawn(task: String, engine: AiEngine, steps: watch::Receiver<String>) -> Self {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let handle = tokio::spawn(monitor_progress(task, engine, steps, stop_rx));
+        Self {
+            stop: Some(stop_tx),
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the spinner, replacing it with a final status line.
+    pub async fn finish(mut self, success: bool) {
+        if let Some(stop) = self.stop.take() {
+            stop.send(success).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.await.ok();
+        }
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        // Dropped without `finish` (error or cancellation): don't leave a
+        // half-drawn spinner behind
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            clear_line();
+        }
+    }
+}
+
+fn clear_line() {
+    print!("\r\x1b[K");
+    std::io::stdout().flush().ok();
+}
+
+pub async fn monitor_progress(
+    task: String,
+    engine: AiEngine,
+    steps: watch::Receiver<String>,
+    mut stop: oneshot::Receiver<bool>,
+) {
     let start = Instant::now();
     let spinner_chars = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
     let mut spin_idx = 0;
 
-    let task_display: String = task.chars().take(40).collect();
+    let task_display = crate::text::truncate(&task, 40);
 
     loop {
         let elapsed = start.elapsed();
@@ -16,19 +80,406 @@ pub async fn monitor_progress(task: String, engine: AiEngine) {
         let secs = elapsed.as_secs() % 60;
 
         let spinner = spinner_chars[spin_idx];
-        let step = "Processing";
+        let step = steps.borrow().clone();
 
         print!(
-            "\r  {} {} │ {} {}",
+            "\r\x1b[K  {} {} │ {} {}",
             spinner.to_string().cyan(),
             format!("{:16}", step).bright_cyan(),
             task_display,
             format!("[{:02}:{:02}]", mins, secs).bright_black()
         );
-        use std::io::Write;
         std::io::stdout().flush().ok();
 
         spin_idx = (spin_idx + 1) % spinner_chars.len();
-        sleep(Duration::from_millis(120)).await;
+        tokio::select! {
+            _ = sleep(Duration::from_millis(120)) => {}
+            outcome = &mut stop => {
+                let success = outcome.unwrap_or(false);
+                clear_line();
+                let elapsed = start.elapsed();
+                let timing = format!(
+                    "[{:02}:{:02}]",
+                    elapsed.as_secs() / 60,
+                    elapsed.as_secs() % 60
+                );
+                if success {
+                    println!(
+                        "  {} Done │ {} {}",
+                        "✓".green().bold(),
+                        task_display,
+                        timing.bright_black()
+                    );
+                } else {
+                    println!(
+                        "  {} Failed │ {} {}",
+                        "✗".red().bold(),
+                        task_display,
+                        timing.bright_black()
+                    );
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Lines the dashboard takes besides one per agent: borders, header and
+/// totals.
+const DASHBOARD_CHROME: u16 = 4;
+
+/// How often the dashboard is redrawn.
+const DASHBOARD_REFRESH: Duration = Duration::from_millis(250);
+
+/// What one agent on the dashboard is doing.
+struct AgentState {
+    task: String,
+    started: Instant,
+    steps: watch::Receiver<String>,
+    usage: watch::Receiver<TokenCount>,
+    /// How long it ran and whether it succeeded, once it has finished
+    finished: Option<(Duration, bool)>,
+}
+
+/// A `--tui` table of the agents in a parallel batch, one row each, drawn in
+/// place below the batch header and left there when the batch is over.
+pub struct Dashboard {
+    agents: Arc<Mutex<Vec<AgentState>>>,
+    stop: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Start drawing a dashboard with room for `slots` agents. Fails when
+    /// standard output isn't a terminal.
+    pub fn start(engine: AiEngine, slots: usize) -> Result<Self> {
+        if !std::io::stdout().is_terminal() {
+            anyhow::bail!("--tui needs a terminal; showing plain output instead");
+        }
+        let height = u16::try_from(slots)
+            .unwrap_or(u16::MAX)
+            .saturating_add(DASHBOARD_CHROME);
+        let terminal = Terminal::with_options(
+            CrosstermBackend::new(std::io::stdout()),
+            TerminalOptions {
+                viewport: Viewport::Inline(height),
+            },
+        )?;
+
+        let agents = Arc::new(Mutex::new(Vec::new()));
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let handle = tokio::spawn(draw_dashboard(terminal, engine, agents.clone(), stop_rx));
+        Ok(Self {
+            agents,
+            stop: Some(stop_tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// Add a row for `task`, starting its clock.
+    pub fn add(&self, task: &str) -> AgentRow {
+        let (steps, steps_rx) = step_channel();
+        let (usage, usage_rx) = usage_channel();
+        let mut agents = self.agents.lock().unwrap();
+        agents.push(AgentState {
+            task: task.to_string(),
+            started: Instant::now(),
+            steps: steps_rx,
+            usage: usage_rx,
+            finished: None,
+        });
+        AgentRow {
+            index: agents.len() - 1,
+            agents: self.agents.clone(),
+            steps,
+            usage,
+        }
+    }
+
+    /// Draw the final state of every row and stop.
+    pub async fn finish(mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop.send(()).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.await.ok();
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            print!("\x1b[?25h");
+            std::io::stdout().flush().ok();
+        }
+    }
+}
+
+/// One agent's handle on its dashboard row.
+#[derive(Clone)]
+pub struct AgentRow {
+    index: usize,
+    agents: Arc<Mutex<Vec<AgentState>>>,
+    steps: StepSender,
+    usage: UsageSender,
+}
+
+impl AgentRow {
+    /// Where the agent's executor reports its current step
+    pub fn steps(&self) -> StepSender {
+        self.steps.clone()
+    }
+
+    /// Where the agent's executor reports tokens as they stream
+    pub fn usage(&self) -> UsageSender {
+        self.usage.clone()
+    }
+
+    /// Stop the row's clock, showing the response's final usage when the
+    /// agent succeeded.
+    pub fn finish(&self, response: Option<&AiResponse>) {
+        if let Some(response) = response {
+            self.usage.send_replace(TokenCount {
+                input_tokens: response.input_tokens,
+                output_tokens: response.output_tokens,
+            });
+        }
+        let mut agents = self.agents.lock().unwrap();
+        let agent = &mut agents[self.index];
+        agent.finished = Some((agent.started.elapsed(), response.is_some()));
+    }
+}
+
+/// Draws spinners and `--tui` dashboards on this terminal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalMonitor;
+
+impl Monitor for TerminalMonitor {
+    fn task(
+        &self,
+        task: &str,
+        engine: AiEngine,
+        steps: watch::Receiver<String>,
+    ) -> Box<dyn TaskMonitor> {
+        Box::new(MonitorHandle::spawn(task.to_string(), engine, steps))
+    }
+
+    fn batch(&self, engine: AiEngine, slots: usize) -> Result<Box<dyn BatchMonitor>> {
+        Ok(Box::new(Dashboard::start(engine, slots)?))
+    }
+}
+
+impl TaskMonitor for MonitorHandle {
+    fn finish(self: Box<Self>, success: bool) -> Finished {
+        Box::pin(MonitorHandle::finish(*self, success))
+    }
+}
+
+impl BatchMonitor for Dashboard {
+    fn add(&self, task: &str) -> Box<dyn AgentMonitor> {
+        Box::new(Dashboard::add(self, task))
+    }
+
+    fn finish(self: Box<Self>) -> Finished {
+        Box::pin(Dashboard::finish(*self))
+    }
+}
+
+impl AgentMonitor for AgentRow {
+    fn steps(&self) -> StepSender {
+        AgentRow::steps(self)
+    }
+
+    fn usage(&self) -> UsageSender {
+        AgentRow::usage(self)
+    }
+
+    fn finish(&self, response: Option<&AiResponse>) {
+        AgentRow::finish(self, response)
+    }
+}
+
+/// One dashboard row as drawn.
+#[derive(Debug, Clone)]
+struct AgentView {
+    task: String,
+    elapsed: Duration,
+    step: String,
+    tokens: TokenCount,
+    /// Whether it succeeded, once it has finished
+    outcome: Option<bool>,
+}
+
+impl AgentState {
+    fn view(&self) -> AgentView {
+        let (elapsed, outcome) = match self.finished {
+            Some((elapsed, success)) => (elapsed, Some(success)),
+            None => (self.started.elapsed(), None),
+        };
+        AgentView {
+            task: self.task.clone(),
+            elapsed,
+            step: self.steps.borrow().clone(),
+            tokens: *self.usage.borrow(),
+            outcome,
+        }
+    }
+}
+
+async fn draw_dashboard(
+    mut terminal: Terminal<CrosstermBackend<std::io::Stdout>>,
+    engine: AiEngine,
+    agents: Arc<Mutex<Vec<AgentState>>>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let start = Instant::now();
+    loop {
+        let stopping = tokio::select! {
+            _ = sleep(DASHBOARD_REFRESH) => false,
+            _ = &mut stop => true,
+        };
+        let views: Vec<AgentView> = agents
+            .lock()
+            .unwrap()
+            .iter()
+            .map(AgentState::view)
+            .collect();
+        let mut bottom = 0;
+        terminal
+            .draw(|frame| {
+                bottom = frame.area().bottom();
+                render(frame, engine, &views, start.elapsed());
+            })
+            .ok();
+        if stopping {
+            // Leave the table behind and carry on printing below it
+            terminal
+                .set_cursor_position(Position::new(0, bottom.saturating_sub(1)))
+                .ok();
+            terminal.show_cursor().ok();
+            println!();
+            return;
+        }
+    }
+}
+
+fn render(frame: &mut Frame, engine: AiEngine, agents: &[AgentView], elapsed: Duration) {
+    let running = agents.iter().filter(|a| a.outcome.is_none()).count();
+    let failed = agents.iter().filter(|a| a.outcome == Some(false)).count();
+    let done = agents.len() - running - failed;
+    let input: usize = agents.iter().map(|a| a.tokens.input_tokens).sum();
+    let output: usize = agents.iter().map(|a| a.tokens.output_tokens).sum();
+
+    let rows = agents.iter().enumerate().map(|(i, agent)| {
+        let (doing, style) = match agent.outcome {
+            None => (agent.step.clone(), Style::default().fg(Color::Cyan)),
+            Some(true) => ("✓ Done".to_string(), Style::default().fg(Color::Green)),
+            Some(false) => ("✗ Failed".to_string(), Style::default().fg(Color::Red)),
+        };
+        Row::new(vec![
+            (i + 1).to_string(),
+            agent.task.clone(),
+            clock(agent.elapsed),
+            tokens(agent.tokens.input_tokens, agent.tokens.output_tokens),
+            doing,
+        ])
+        .style(style)
+    });
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Length(18),
+            Constraint::Length(16),
+        ],
+    )
+    .header(Row::new(vec!["#", "Task", "Time", "Tokens in/out", "Doing"]).style(bold))
+    .footer(
+        Row::new(vec![
+            String::new(),
+            format!("{} running, {} done, {} failed", running, done, failed),
+            clock(elapsed),
+            tokens(input, output),
+            String::new(),
+        ])
+        .style(bold),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} agents ", engine)),
+    );
+    frame.render_widget(table, frame.area());
+}
+
+/// `mm:ss`
+fn clock(elapsed: Duration) -> String {
+    format!(
+        "{:02}:{:02}",
+        elapsed.as_secs() / 60,
+        elapsed.as_secs() % 60
+    )
+}
+
+/// Token counts in thousands once they get there, e.g. `12.3k / 850`
+fn tokens(input: usize, output: usize) -> String {
+    let short = |count: usize| match count {
+        0..=999 => count.to_string(),
+        _ => format!("{:.1}k", count as f64 / 1000.0),
+    };
+    format!("{} / {}", short(input), short(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_render() {
+        let agents = vec![
+            AgentView {
+                task: "Add login".to_string(),
+                elapsed: Duration::from_secs(65),
+                step: "Editing files".to_string(),
+                tokens: TokenCount {
+                    input_tokens: 12_345,
+                    output_tokens: 850,
+                },
+                outcome: None,
+            },
+            AgentView {
+                task: "Fix logout".to_string(),
+                elapsed: Duration::from_secs(7),
+                step: "Processing".to_string(),
+                tokens: TokenCount::default(),
+                outcome: Some(false),
+            },
+        ];
+        let mut terminal = Terminal::new(TestBackend::new(80, 6)).unwrap();
+        terminal
+            .draw(|frame| render(frame, AiEngine::Claude, &agents, Duration::from_secs(70)))
+            .unwrap();
+
+        let lines: Vec<String> = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(80)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        assert!(lines[0].contains("Claude Code agents"));
+        assert!(lines[2].contains("Add login"));
+        assert!(lines[2].contains("01:05"));
+        assert!(lines[2].contains("12.3k / 850"));
+        assert!(lines[2].contains("Editing files"));
+        assert!(lines[3].contains("✗ Failed"));
+        assert!(lines[4].contains("1 running, 0 done, 1 failed"));
+        assert!(lines[4].contains("01:10"));
     }
 }
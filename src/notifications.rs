@@ -1,36 +1,530 @@
-use notify_rust::Notification;
-
-pub fn notify_done(message: &str) {
-    #[cfg(target_os = "macos")]
-    {
-        // macOS sound
-        std::process::Command::new("afplay")
-            .arg("/System/Library/Sounds/Glass.aiff")
-            .spawn()
-            .ok();
-    }
-
-    #[cfg(target_os = "linux")]
-    {
-        // Linux sound
-        std::process::Command::new("paplay")
-            .arg("/usr/share/sounds/freedesktop/stereo/complete.oga")
-            .spawn()
-            .ok();
-    }
-
-    // Send notification
-    Notification::new()
-        .summary("Ralphy")
-        .body(message)
-        .show()
-        .ok();
-}
-
-pub fn notify_error(message: &str) {
-    Notification::new()
-        .summary("Ralphy - Error")
-        .body(message)
-        .show()
-        .ok();
+use crate::config::RunConfig;
+use crate::events::{self, RunEvent, Subscription};
+use crate::settings::{EmailSettings, NotifyEvent, SinkSettings, UrlSinkSettings};
+use crate::stats::{self, RunStats};
+use crate::{email, RunOutcome};
+use anyhow::Result;
+use colored::*;
+use futures::future::{join_all, BoxFuture};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A slow endpoint must not hold up the run.
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events a sink gets unless ralphy.toml picks others.
+const DEFAULT_EVENTS: &[NotifyEvent] = &[
+    NotifyEvent::RunCompleted,
+    NotifyEvent::RunFailed,
+    NotifyEvent::Error,
+];
+
+/// Something worth telling the user about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    /// One line, e.g. an email subject
+    pub title: String,
+    /// A sentence for a desktop popup
+    pub message: String,
+    /// The full text, for sinks with room for it
+    pub details: Option<String>,
+}
+
+impl Notification {
+    pub fn new(event: NotifyEvent, title: String, message: String) -> Self {
+        Self {
+            event,
+            title,
+            message,
+            details: None,
+        }
+    }
+
+    /// How a finished run went, with its summary as details.
+    pub fn run_finished(project: &str, stats: &RunStats, cost: f64) -> Self {
+        let (event, title, message) = match stats.outcome() {
+            RunOutcome::Complete => (
+                NotifyEvent::RunCompleted,
+                format!("Ralphy finished {}", project),
+                "Ralphy has completed all tasks!".to_string(),
+            ),
+            RunOutcome::LimitReached => {
+                let limit = stats.limit_reached.as_deref().unwrap_or("run");
+                (
+                    NotifyEvent::RunFailed,
+                    format!("Ralphy stopped at the {} limit in {}", limit, project),
+                    format!("Ralphy stopped: {} limit", limit),
+                )
+            }
+            RunOutcome::WorkRemaining if !stats.failed.is_empty() => (
+                NotifyEvent::RunFailed,
+                format!(
+                    "Ralphy finished {} with {} failed task(s)",
+                    project,
+                    stats.failed.len()
+                ),
+                format!("Ralphy finished with {} failed task(s)", stats.failed.len()),
+            ),
+            RunOutcome::WorkRemaining => (
+                NotifyEvent::RunFailed,
+                format!("Ralphy finished {} with tasks left", project),
+                if stats.over_budget.is_empty() {
+                    format!("Ralphy skipped {} task(s)", stats.skipped.len())
+                } else {
+                    format!(
+                        "Ralphy skipped {} task(s) over budget",
+                        stats.over_budget.len()
+                    )
+                },
+            ),
+        };
+        Self {
+            details: Some(run_summary(stats, cost)),
+            ..Self::new(event, title, message)
+        }
+    }
+
+    /// A task that gave up.
+    pub fn task_failed(project: &str, task: &str) -> Self {
+        Self::new(
+            NotifyEvent::TaskFailed,
+            format!("Ralphy gave up on a task in {}", project),
+            format!("Task failed: {}", task),
+        )
+    }
+
+    /// A parallel batch that finished with `completed` tasks done and
+    /// `failed` given up on.
+    pub fn batch_finished(project: &str, batch: usize, completed: usize, failed: usize) -> Self {
+        Self::new(
+            NotifyEvent::BatchFinished,
+            format!("Ralphy finished batch {} in {}", batch, project),
+            format!(
+                "Batch {}: {} task(s) completed, {} failed",
+                batch, completed, failed
+            ),
+        )
+    }
+
+    /// The error that stopped a run.
+    pub fn error(project: &str, error: &str) -> Self {
+        Self {
+            details: Some(format!("{}\n", error)),
+            ..Self::new(
+                NotifyEvent::Error,
+                format!("Ralphy stopped with an error in {}", project),
+                format!("Ralphy stopped: {}", error),
+            )
+        }
+    }
+
+    /// What, if anything, to tell the user about `event`. Dry runs and
+    /// interrupted ones end without a summary.
+    pub fn for_event(project: &str, event: &RunEvent, dry_run: bool) -> Option<Self> {
+        match event {
+            RunEvent::TaskFailed { task, .. } => Some(Self::task_failed(project, task)),
+            RunEvent::BatchFinished {
+                batch,
+                completed,
+                failed,
+            } => Some(Self::batch_finished(project, *batch, *completed, *failed)),
+            RunEvent::RunFinished {
+                stats,
+                cost,
+                interrupted: false,
+                ..
+            } if !dry_run => Some(Self::run_finished(project, stats, *cost)),
+            RunEvent::RunError { error } => Some(Self::error(project, error)),
+            _ => None,
+        }
+    }
+
+    /// The full text, or the message when there is nothing more to say.
+    pub fn body(&self) -> &str {
+        self.details.as_deref().unwrap_or(&self.message)
+    }
+}
+
+/// Completed, failed and skipped tasks with the run's usage.
+fn run_summary(stats: &RunStats, cost: f64) -> String {
+    let mut body = String::new();
+    body.push_str(&format!("Tasks completed: {}\n", stats.agents.len()));
+    for (label, tasks) in [
+        ("Tasks failed", &stats.failed),
+        ("Skipped over budget", &stats.over_budget),
+        ("Skipped", &stats.skipped),
+    ] {
+        if !tasks.is_empty() {
+            body.push_str(&format!("{}: {}\n", label, tasks.len()));
+            for task in tasks {
+                body.push_str(&format!("  - {}\n", task));
+            }
+        }
+    }
+    body.push_str(&format!(
+        "\nTokens: {} in, {} out\nCost: ${:.4}\n",
+        stats.input_tokens, stats.output_tokens, cost
+    ));
+    if stats.wall_ms > 0 {
+        body.push_str(&format!(
+            "Wall time: {}\n",
+            stats::format_duration(stats.wall_ms)
+        ));
+    }
+    body
+}
+
+/// Somewhere notifications can be sent.
+pub trait Notifier: Send + Sync {
+    /// Name used in warnings, e.g. "Slack"
+    fn name(&self) -> &'static str;
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>>;
+}
+
+/// A popup from the desktop's notification service.
+pub struct Desktop;
+
+impl Notifier for Desktop {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        let summary = match notification.event {
+            NotifyEvent::RunCompleted | NotifyEvent::BatchFinished => "Ralphy",
+            NotifyEvent::RunFailed | NotifyEvent::TaskFailed | NotifyEvent::Error => {
+                "Ralphy - Error"
+            }
+        };
+        let result = notify_rust::Notification::new()
+            .summary(summary)
+            .body(&notification.message)
+            .show()
+            .map(|_| ());
+        Box::pin(async move { result.map_err(Into::into) })
+    }
+}
+
+/// The system's "complete" sound.
+pub struct Sound;
+
+impl Notifier for Sound {
+    fn name(&self) -> &'static str {
+        "sound"
+    }
+
+    fn send<'a>(&'a self, _notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        #[cfg(target_os = "macos")]
+        {
+            std::process::Command::new("afplay")
+                .arg("/System/Library/Sounds/Glass.aiff")
+                .spawn()
+                .ok();
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            std::process::Command::new("paplay")
+                .arg("/usr/share/sounds/freedesktop/stereo/complete.oga")
+                .spawn()
+                .ok();
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            std::process::Command::new("powershell")
+                .args([
+                    "-NoProfile",
+                    "-Command",
+                    "(New-Object Media.SoundPlayer \"$env:WINDIR\\Media\\tada.wav\").PlaySync()",
+                ])
+                .creation_flags(CREATE_NO_WINDOW)
+                .spawn()
+                .ok();
+        }
+
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A message to a Slack incoming webhook.
+pub struct Slack {
+    pub url: String,
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        let text = format!("*{}*\n{}", notification.title, notification.body());
+        Box::pin(post(&self.url, json!({ "text": text })))
+    }
+}
+
+/// The notification as JSON, POSTed to a URL.
+pub struct Webhook {
+    pub url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        let body = json!({
+            "event": notification.event,
+            "title": notification.title,
+            "message": notification.message,
+            "details": notification.details,
+        });
+        Box::pin(post(&self.url, body))
+    }
+}
+
+/// An email through the `[email]` server.
+pub struct Email {
+    pub settings: EmailSettings,
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(email::send(
+            &self.settings,
+            &notification.title,
+            notification.body(),
+        ))
+    }
+}
+
+async fn post(url: &str, body: serde_json::Value) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(POST_TIMEOUT).build()?;
+    client
+        .post(url)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Every sink the run notifies, each with the events it wants.
+#[derive(Default)]
+pub struct NotificationManager {
+    sinks: Vec<(Box<dyn Notifier>, Vec<NotifyEvent>)>,
+}
+
+impl NotificationManager {
+    /// The sinks `config` sets up. Desktop popups and sound are on unless
+    /// `--no-notify` is given; email, Slack and webhooks once configured.
+    pub fn new(config: &RunConfig) -> Self {
+        let settings = config.notifications.clone();
+        let mut manager = Self::default();
+        if !config.no_notify {
+            manager.add_with(Box::new(Desktop), settings.desktop, DEFAULT_EVENTS);
+            manager.add_with(
+                Box::new(Sound),
+                settings.sound,
+                &[NotifyEvent::RunCompleted],
+            );
+        }
+        if let Some(ref email) = config.email {
+            let sink = Email {
+                settings: email.clone(),
+            };
+            manager.add_with(Box::new(sink), settings.email, DEFAULT_EVENTS);
+        }
+        if let Some(UrlSinkSettings { url, on }) = settings.slack {
+            manager.add(Box::new(Slack { url }), on_or_default(on, DEFAULT_EVENTS));
+        }
+        if let Some(UrlSinkSettings { url, on }) = settings.webhook {
+            manager.add(Box::new(Webhook { url }), on_or_default(on, DEFAULT_EVENTS));
+        }
+        manager
+    }
+
+    /// Send `events` to `sink`; none turns it off.
+    pub fn add(&mut self, sink: Box<dyn Notifier>, events: Vec<NotifyEvent>) {
+        if !events.is_empty() {
+            self.sinks.push((sink, events));
+        }
+    }
+
+    fn add_with(
+        &mut self,
+        sink: Box<dyn Notifier>,
+        settings: Option<SinkSettings>,
+        default: &[NotifyEvent],
+    ) {
+        let events = on_or_default(settings.and_then(|settings| settings.on), default);
+        self.add(sink, events);
+    }
+
+    /// Send `notification` to every sink that wants its event, all at once.
+    /// Failures are reported but never fail the run.
+    pub async fn notify(&self, notification: &Notification) {
+        let sends = self
+            .sinks
+            .iter()
+            .filter(|(_, events)| events.contains(&notification.event))
+            .map(|(sink, _)| async move { (sink.name(), sink.send(notification).await) });
+        for (name, result) in join_all(sends).await {
+            if let Err(e) = result {
+                events::warn(format!("Could not send {} notification: {:#}", name, e));
+            }
+        }
+    }
+}
+
+/// Notifications being sent for a run; [`Delivery::finish`] waits for the
+/// last of them.
+pub struct Delivery {
+    subscription: Subscription,
+    worker: JoinHandle<()>,
+}
+
+/// Send what the run's events call for to the sinks `config` sets up, one
+/// notification at a time so the run never waits on them. None when no
+/// sink is set up.
+pub fn start(config: &RunConfig) -> Option<Delivery> {
+    let manager = NotificationManager::new(config);
+    if manager.sinks.is_empty() {
+        return None;
+    }
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Notification>();
+    let worker = events::spawn(async move {
+        while let Some(notification) = receiver.recv().await {
+            manager.notify(&notification).await;
+        }
+    });
+    let project = project_name();
+    let dry_run = config.dry_run;
+    let subscription = events::subscribe(move |event| {
+        if let Some(notification) = Notification::for_event(&project, event, dry_run) {
+            sender.send(notification).ok();
+        }
+    });
+    Some(Delivery {
+        subscription,
+        worker,
+    })
+}
+
+impl Delivery {
+    /// Wait for every queued notification to be sent, and stop sending more.
+    pub async fn finish(self) {
+        // The worker stops once the queue it reads is dropped with the handler
+        drop(self.subscription);
+        self.worker.await.ok();
+    }
+}
+
+/// Name of the directory the run is in, to tell runs apart in an inbox or
+/// channel.
+pub fn project_name() -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "the project".to_string())
+}
+
+fn on_or_default(on: Option<Vec<NotifyEvent>>, default: &[NotifyEvent]) -> Vec<NotifyEvent> {
+    on.unwrap_or_else(|| default.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+            self.0.lock().unwrap().push(notification.title.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manager_filters_by_event() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut manager = NotificationManager::default();
+        manager.add(
+            Box::new(Recorder(sent.clone())),
+            vec![NotifyEvent::RunFailed],
+        );
+        manager.add(Box::new(Recorder(sent.clone())), Vec::new());
+
+        let note = |event, title: &str| Notification::new(event, title.to_string(), String::new());
+        manager
+            .notify(&note(NotifyEvent::RunCompleted, "done"))
+            .await;
+        manager
+            .notify(&note(NotifyEvent::RunFailed, "failed"))
+            .await;
+        assert_eq!(*sent.lock().unwrap(), ["failed"]);
+    }
+
+    #[test]
+    fn test_run_finished() {
+        let mut stats = RunStats::new();
+        stats.record_failure("Fix the flaky test");
+        stats.input_tokens = 1200;
+        stats.output_tokens = 300;
+        stats.wall_ms = 65_000;
+
+        let notification = Notification::run_finished("shop", &stats, 0.5);
+        assert_eq!(notification.event, NotifyEvent::RunFailed);
+        assert_eq!(
+            notification.title,
+            "Ralphy finished shop with 1 failed task(s)"
+        );
+        assert_eq!(
+            notification.body(),
+            "Tasks completed: 0\nTasks failed: 1\n  - Fix the flaky test\n\n\
+             Tokens: 1200 in, 300 out\nCost: $0.5000\nWall time: 1m 5s\n"
+        );
+
+        let notification = Notification::run_finished("shop", &RunStats::new(), 0.0);
+        assert_eq!(notification.event, NotifyEvent::RunCompleted);
+        assert_eq!(notification.message, "Ralphy has completed all tasks!");
+    }
+
+    #[test]
+    fn test_for_event() {
+        let pricing = crate::pricing::Pricing::default();
+        let stats = RunStats::new();
+        let finished = RunEvent::run_finished(&stats, &pricing, RunOutcome::Complete);
+        let event = |event: &RunEvent, dry_run| {
+            Notification::for_event("shop", event, dry_run).map(|n| n.event)
+        };
+        assert_eq!(event(&finished, false), Some(NotifyEvent::RunCompleted));
+        assert_eq!(event(&finished, true), None);
+        assert_eq!(
+            event(&RunEvent::run_interrupted(&stats, &pricing), false),
+            None
+        );
+        let failed = RunEvent::task_failed("Add login", &anyhow::anyhow!("boom"), 3, None);
+        assert_eq!(event(&failed, true), Some(NotifyEvent::TaskFailed));
+        let error = RunEvent::run_error(&anyhow::anyhow!("boom"));
+        assert_eq!(event(&error, false), Some(NotifyEvent::Error));
+    }
 }
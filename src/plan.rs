@@ -0,0 +1,184 @@
+use crate::ai::AiExecutor;
+use crate::config::RunConfig;
+use crate::prd::{PrdManager, PrdSource, YamlTasks};
+use anyhow::{Context, Result};
+use colored::*;
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// Specs are cut to this many characters so the prompt fits every engine.
+const MAX_SPEC_CHARS: usize = 20_000;
+
+/// Ask the engine to break the free-form spec at `spec` into a YAML task
+/// file at `output`, ready for `ralphy --yaml`. An existing `output` is only
+/// replaced with `force`.
+pub async fn run(config: &RunConfig, spec: &Path, output: &Path, force: bool) -> Result<()> {
+    if output.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; pass --force to overwrite",
+            output.display()
+        );
+    }
+    let text = std::fs::read_to_string(spec)
+        .with_context(|| format!("Failed to read {}", spec.display()))?;
+    if text.trim().is_empty() {
+        anyhow::bail!("{} is empty", spec.display());
+    }
+
+    println!(
+        "{} Planning {} with {}...",
+        ">>>".bright_cyan().bold(),
+        spec.display(),
+        config.ai_engine
+    );
+    if config.dry_run {
+        println!(
+            "{} DRY RUN - Would ask {} to plan {}",
+            "[INFO]".blue().bold(),
+            config.ai_engine,
+            output.display()
+        );
+        return Ok(());
+    }
+
+    let executor = AiExecutor::new(config.ai_engine)
+        .with_backend(config.backend)
+        .with_rate_limit(config.rate_limit)
+        .with_model(config.model.as_deref())
+        .with_extra_args(config.engine_args(config.ai_engine));
+    let response = executor.execute(&plan_prompt(&text)).await?;
+    let plan = parse_plan(&response.text)?;
+    write_plan(output, &plan).await?;
+
+    let groups: HashSet<usize> = plan.tasks.iter().map(|t| t.parallel_group).collect();
+    println!(
+        "{} Wrote {} task(s) in {} parallel group(s) to {}:",
+        "[SUCCESS]".green().bold(),
+        plan.tasks.len(),
+        groups.len(),
+        output.display()
+    );
+    for task in &plan.tasks {
+        println!("    - {}", task.title);
+    }
+    println!(
+        "\nReview the plan, then run {} to start.",
+        format!("ralphy --yaml {}", output.display()).bright_cyan()
+    );
+    Ok(())
+}
+
+/// Prompt asking the engine to turn `spec` into a task list.
+pub fn plan_prompt(spec: &str) -> String {
+    let spec = crate::text::truncate(spec, MAX_SPEC_CHARS);
+    format!(
+        "You are planning the implementation of a project for coding agents that work through one task at a time. \
+         Do not edit any files.\n\n\
+         Specification:\n{}\n\n\
+         Inspect the repository as it is now, then break the work the specification still needs into tasks \
+         that are each small enough for one agent session. Reply with the plan as a single YAML block:\n\n\
+         ```yaml\n\
+         tasks:\n  \
+           - title: Add the user model\n    \
+             description: What the task is about, beyond its title\n    \
+             acceptance_criteria:\n      \
+               - A condition the work has to meet\n    \
+             priority: high\n    \
+             parallel_group: 1\n  \
+           - title: Add the signup endpoint\n    \
+             depends_on:\n      \
+               - Add the user model\n    \
+             parallel_group: 2\n\
+         ```\n\n\
+         Titles must be unique. `depends_on` lists the titles of tasks that must be done first. \
+         Tasks with the same `parallel_group` run at the same time, so they must not depend on each other \
+         or edit the same files; number the groups in the order they should run. \
+         `priority` is one of low, medium, high or critical.",
+        spec.trim()
+    )
+}
+
+/// The task list in the engine's response, with every task open and
+/// dependencies on tasks the plan doesn't have dropped.
+pub fn parse_plan(text: &str) -> Result<YamlTasks> {
+    let fence = Regex::new(r"(?s)```(?:ya?ml)?[ \t]*\n(.*?)```").unwrap();
+    let yaml = fence
+        .captures(text)
+        .map_or(text, |cap| cap.get(1).unwrap().as_str());
+    let mut plan: YamlTasks =
+        serde_yaml::from_str(yaml).context("The engine's plan is not a YAML task list")?;
+
+    let mut seen = HashSet::new();
+    plan.tasks.retain_mut(|task| {
+        task.title = task.title.split_whitespace().collect::<Vec<_>>().join(" ");
+        !task.title.is_empty() && seen.insert(task.title.clone())
+    });
+    if plan.tasks.is_empty() {
+        anyhow::bail!("The engine's plan has no tasks");
+    }
+    for task in &mut plan.tasks {
+        task.completed = false;
+        task.in_progress = false;
+        let title = task.title.clone();
+        task.depends_on
+            .retain(|dep| *dep != title && seen.contains(dep));
+    }
+    Ok(plan)
+}
+
+/// Write `plan` to `output` once it loads as a task file, so a plan with a
+/// dependency cycle never replaces anything.
+async fn write_plan(output: &Path, plan: &YamlTasks) -> Result<()> {
+    let content = serde_yaml::to_string(plan).context("Failed to serialize YAML")?;
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut file = tempfile::Builder::new().suffix(".yaml").tempfile_in(dir)?;
+    file.write_all(content.as_bytes())?;
+
+    PrdManager::new(PrdSource::Yaml {
+        path: file.path().to_path_buf(),
+    })
+    .refresh()
+    .await
+    .context("The engine's plan can't be run")?;
+
+    file.persist(output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prd::Priority;
+
+    #[test]
+    fn test_parse_plan() {
+        let response = "Here is the plan:\n\n```yaml\ntasks:\n  \
+                        - title: Add  user model\n    completed: true\n    priority: high\n    parallel_group: 1\n  \
+                        - title: Add signup\n    depends_on: [Add user model, Add billing, Add signup]\n    parallel_group: 2\n  \
+                        - title: Add signup\n```\nLet me know.";
+        let plan = parse_plan(response).unwrap();
+        let titles: Vec<&str> = plan.tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Add user model", "Add signup"]);
+        assert!(!plan.tasks[0].completed);
+        assert_eq!(plan.tasks[0].priority, Some(Priority::High));
+        assert_eq!(plan.tasks[1].depends_on, ["Add user model"]);
+        assert_eq!(plan.tasks[1].parallel_group, 2);
+
+        assert!(parse_plan("tasks:\n  - title: Bare YAML\n").is_ok());
+        assert!(parse_plan("I couldn't make a plan.").is_err());
+        assert!(parse_plan("tasks: []").is_err());
+    }
+
+    #[test]
+    fn test_plan_prompt() {
+        let prompt = plan_prompt("  Build a URL shortener.\n");
+        assert!(prompt.contains("Specification:\nBuild a URL shortener.\n\nInspect"));
+        assert!(prompt.contains("```yaml\ntasks:\n  - title: Add the user model\n"));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::config::RunConfig;
+use crate::events;
+use crate::pricing::Pricing;
+use crate::stats::RunStats;
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+use std::time::Duration;
+
+/// A slow or unreachable endpoint must not hold up the end of a run.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Anonymized usage for one run: counts and spend only, no task titles,
+/// paths, remotes or user names.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub ralphy_version: &'static str,
+    pub timestamp: String,
+    pub repo_tag: Option<String>,
+    pub mode: &'static str,
+    pub tasks_completed: usize,
+    pub tasks_failed: usize,
+    pub tasks_over_budget: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cost_usd: f64,
+    /// Whether any of the cost is our per-token estimate rather than a
+    /// figure the engine reported
+    pub cost_estimated: bool,
+    pub engines: Vec<EngineReport>,
+}
+
+/// Usage for one engine and model within a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineReport {
+    pub engine: String,
+    pub model: Option<String>,
+    pub tasks: usize,
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cost_usd: f64,
+    pub cost_estimated: bool,
+}
+
+impl UsageReport {
+    pub fn new(
+        stats: &RunStats,
+        pricing: &Pricing,
+        parallel: bool,
+        repo_tag: Option<String>,
+    ) -> Self {
+        let engines: Vec<EngineReport> = stats
+            .by_engine()
+            .into_iter()
+            .map(|usage| EngineReport {
+                engine: usage
+                    .engine
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_string())
+                    .unwrap_or_default(),
+                cost_usd: usage.actual_cost.unwrap_or_else(|| {
+                    pricing.cost(
+                        usage.engine,
+                        usage.model.as_deref(),
+                        usage.input_tokens,
+                        usage.output_tokens,
+                    )
+                }),
+                cost_estimated: usage.actual_cost.is_none(),
+                model: usage.model,
+                tasks: usage.tasks,
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            })
+            .collect();
+
+        Self {
+            ralphy_version: env!("CARGO_PKG_VERSION"),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            repo_tag,
+            mode: if parallel { "parallel" } else { "sequential" },
+            tasks_completed: stats.completed(),
+            tasks_failed: stats.failed.len(),
+            tasks_over_budget: stats.over_budget.len(),
+            input_tokens: stats.input_tokens,
+            output_tokens: stats.output_tokens,
+            cost_usd: engines.iter().map(|e| e.cost_usd).sum(),
+            cost_estimated: engines.iter().any(|e| e.cost_estimated),
+            engines,
+        }
+    }
+}
+
+/// POST the run's usage to the `[reporting]` endpoint, if one is configured.
+/// Failures are reported but never fail the run.
+pub async fn submit(config: &RunConfig, stats: &RunStats) {
+    let Some(ref reporting) = config.reporting else {
+        return;
+    };
+    if config.dry_run || stats.iterations == 0 {
+        return;
+    }
+
+    let report = UsageReport::new(
+        stats,
+        &config.pricing,
+        config.parallel,
+        reporting.repo_tag.clone(),
+    );
+    match post(&reporting.endpoint, &report).await {
+        Ok(()) if config.verbose > 0 => {
+            events::info(format!("Sent usage report to {}", reporting.endpoint));
+        }
+        Ok(()) => {}
+        Err(e) => {
+            events::warn(format!(
+                "Could not send usage report to {}: {}",
+                reporting.endpoint, e
+            ));
+        }
+    }
+}
+
+async fn post(endpoint: &str, report: &UsageReport) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(REPORT_TIMEOUT).build()?;
+    client
+        .post(endpoint)
+        .json(report)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AiResponse;
+    use crate::cli::AiEngine;
+
+    #[test]
+    fn test_report_is_anonymized() {
+        let mut stats = RunStats::new();
+        stats.record(
+            "Add secret billing feature",
+            AiEngine::Claude,
+            &AiResponse {
+                text: String::new(),
+                input_tokens: 1000,
+                output_tokens: 100,
+                actual_cost: Some(0.5),
+                duration_ms: None,
+                model: Some("claude-sonnet-4".to_string()),
+            },
+            std::time::Duration::from_secs(30),
+        );
+        stats.record_failure("Fix private bug");
+        stats.iterations = 2;
+
+        let report = UsageReport::new(
+            &stats,
+            &Pricing::default(),
+            false,
+            Some("payments".to_string()),
+        );
+        assert_eq!(report.tasks_completed, 1);
+        assert_eq!(report.tasks_failed, 1);
+        assert_eq!(report.cost_usd, 0.5);
+        assert!(!report.cost_estimated);
+        assert_eq!(report.engines[0].engine, "claude");
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"repo_tag\":\"payments\""));
+        assert!(!json.contains("billing"));
+        assert!(!json.contains("private"));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::events::{self, RunEvent, Subscription};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// `--log-json` target that means standard output.
+pub const STDOUT: &str = "-";
+
+/// Format of what a run prints on standard output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored progress for people
+    #[default]
+    Text,
+    /// Run log events as JSON Lines, with the progress moved to stderr
+    Json,
+}
+
+/// One line of the log: the event and when it happened.
+#[derive(Serialize)]
+pub(crate) struct Line<'a> {
+    /// RFC 3339 timestamp
+    time: String,
+    #[serde(flatten)]
+    event: &'a RunEvent,
+}
+
+impl Line<'_> {
+    /// `event` as a line of JSON, without the newline.
+    pub(crate) fn render(event: &RunEvent) -> String {
+        let line = Line {
+            time: chrono::Utc::now().to_rfc3339(),
+            event,
+        };
+        serde_json::to_string(&line).unwrap_or_default()
+    }
+}
+
+/// Start writing events to `target`, appending to a file or, for
+/// [`STDOUT`], to standard output, until the returned subscription is
+/// dropped. Everything else printed on standard output goes to stderr from
+/// then on, so the stream stays parseable.
+pub fn open(target: &Path) -> Result<Subscription> {
+    let writer: Box<dyn Write + Send> = if target == Path::new(STDOUT) {
+        Box::new(take_stdout()?)
+    } else {
+        Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(target)
+                .with_context(|| format!("Failed to open {}", target.display()))?,
+        )
+    };
+    // A log that can't be written never stops the run
+    let writer = Mutex::new(writer);
+    Ok(events::subscribe(move |event| {
+        if matches!(event, RunEvent::Notice { .. }) {
+            return;
+        }
+        if let Ok(mut writer) = writer.lock() {
+            writeln!(writer, "{}", Line::render(event)).ok();
+            writer.flush().ok();
+        }
+    }))
+}
+
+/// Standard output for the log alone, with fd 1 pointed at stderr.
+#[cfg(unix)]
+fn take_stdout() -> Result<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    std::io::stdout().flush().ok();
+    // SAFETY: plain descriptor duplication; the duplicate is owned by the
+    // returned file alone
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to redirect stdout");
+        }
+        Ok(std::fs::File::from_raw_fd(fd))
+    }
+}
+
+#[cfg(not(unix))]
+fn take_stdout() -> Result<std::fs::File> {
+    anyhow::bail!("--output json is only supported on Unix; use --log-json FILE instead")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AiResponse;
+    use crate::pricing::Pricing;
+    use crate::stats::RunStats;
+    use crate::RunOutcome;
+    use std::time::Duration;
+
+    #[test]
+    fn test_events_render_as_flat_lines() {
+        let response = AiResponse {
+            text: String::new(),
+            input_tokens: 1200,
+            output_tokens: 300,
+            actual_cost: None,
+            duration_ms: Some(4200),
+            model: None,
+        };
+        let event = RunEvent::task_completed(
+            "Add login",
+            &response,
+            0.0081,
+            Duration::from_millis(6500),
+            Some("ralphy/add-login".into()),
+        );
+        let line: serde_json::Value = serde_json::from_str(&Line::render(&event)).unwrap();
+        assert_eq!(line["event"], "task_completed");
+        assert_eq!(line["event"], event.name());
+        assert_eq!(line["task"], "Add login");
+        assert_eq!(line["input_tokens"], 1200);
+        assert_eq!(line["cost"], 0.0081);
+        assert_eq!(line["cost_estimated"], true);
+        assert_eq!(line["duration_ms"], 4200);
+        assert_eq!(line["wall_ms"], 6500);
+        assert_eq!(line["branch"], "ralphy/add-login");
+        assert!(line.get("model").is_none());
+        assert!(chrono::DateTime::parse_from_rfc3339(line["time"].as_str().unwrap()).is_ok());
+
+        let finished = RunEvent::run_finished(
+            &RunStats::new(),
+            &Pricing::default(),
+            RunOutcome::LimitReached,
+        );
+        let line: serde_json::Value = serde_json::from_str(&Line::render(&finished)).unwrap();
+        assert_eq!(line["event"], "run_finished");
+        assert_eq!(line["outcome"], "limit_reached");
+        assert!(line.get("task_timing").is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::cli::{AiEngine, Cli};
+use crate::config::{self, RunConfig};
+use crate::console;
+use crate::events::{self, RunEvent};
+use crate::run_log;
+use crate::settings::DefaultSettings;
+use crate::{run_observed, RunOutcome};
+use anyhow::{Context as _, Result};
+use clap::Parser;
+use futures::channel::mpsc;
+use futures::Stream;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::task::JoinHandle;
+
+type Handler = Arc<dyn Fn(&RunEvent) + Send + Sync>;
+
+/// The autonomous loop, for embedding in other tools.
+///
+/// It works on the repository in the current directory, as `ralphy` does.
+/// Its events go to its own handlers alone, even with other runners in the
+/// process, and it prints them as `ralphy` would unless built
+/// [`quiet`](RalphyRunnerBuilder::quiet).
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use ralphy_rs::cli::AiEngine;
+/// use ralphy_rs::runner::RalphyRunner;
+///
+/// let outcome = RalphyRunner::builder()
+///     .engine(AiEngine::Codex)
+///     .prd("tasks.yaml")
+///     .args(["--fast", "--max-iterations", "3"])
+///     .on_event(|event| eprintln!("{}", event.name()))
+///     .quiet()
+///     .build()?
+///     .run()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RalphyRunner {
+    config: RunConfig,
+    handlers: Vec<Handler>,
+    console: bool,
+}
+
+impl RalphyRunner {
+    pub fn builder() -> RalphyRunnerBuilder {
+        RalphyRunnerBuilder::default()
+    }
+
+    /// A runner for an already built `config`.
+    pub fn from_config(config: RunConfig) -> Self {
+        Self {
+            config,
+            handlers: Vec::new(),
+            console: true,
+        }
+    }
+
+    pub fn config(&self) -> &RunConfig {
+        &self.config
+    }
+
+    /// Run the loop until the PRD is done or the run stops, calling the
+    /// `on_event` handlers as it goes.
+    pub async fn run(self) -> Result<RunOutcome> {
+        events::scope(Arc::default(), async move {
+            let _subscriptions: Vec<_> = self
+                .handlers
+                .into_iter()
+                .map(|handler| events::subscribe(move |event| handler(event)))
+                .collect();
+            let _console = self.console.then(|| console::subscribe(&self.config));
+            let _log = self
+                .config
+                .log_json
+                .as_deref()
+                .map(run_log::open)
+                .transpose()?;
+            run_observed(self.config).await
+        })
+        .await
+    }
+
+    /// Start the loop on the Tokio runtime, returning its events as a
+    /// stream that ends with the run.
+    pub fn spawn(mut self) -> RunEvents {
+        let (sender, receiver) = mpsc::unbounded();
+        self.handlers.push(Arc::new(move |event: &RunEvent| {
+            sender.unbounded_send(event.clone()).ok();
+        }));
+        RunEvents {
+            events: receiver,
+            run: tokio::spawn(self.run()),
+        }
+    }
+}
+
+/// Events of a [`RalphyRunner::spawn`]ed run.
+pub struct RunEvents {
+    events: mpsc::UnboundedReceiver<RunEvent>,
+    run: JoinHandle<Result<RunOutcome>>,
+}
+
+impl RunEvents {
+    /// Wait for the run to end, dropping events not yet taken.
+    pub async fn outcome(self) -> Result<RunOutcome> {
+        self.run.await.context("The run panicked")?
+    }
+}
+
+impl Stream for RunEvents {
+    type Item = RunEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<RunEvent>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+/// Settings for a [`RalphyRunner`]. Anything without a method of its own
+/// can be given as `ralphy` flags through [`RalphyRunnerBuilder::args`];
+/// ralphy.toml applies as usual.
+#[derive(Default)]
+pub struct RalphyRunnerBuilder {
+    args: Vec<String>,
+    engine: Option<AiEngine>,
+    prd: Option<PathBuf>,
+    model: Option<String>,
+    defaults: DefaultSettings,
+    handlers: Vec<Handler>,
+    quiet: bool,
+}
+
+impl RalphyRunnerBuilder {
+    pub fn engine(mut self, engine: AiEngine) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    /// Markdown PRD or, for `.yaml` and `.yml` files, YAML task file to
+    /// work through.
+    pub fn prd(mut self, path: impl Into<PathBuf>) -> Self {
+        self.prd = Some(path.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// `ralphy` flags, e.g. `["--parallel", "--max-parallel", "2"]`.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fallbacks for what neither the flags nor ralphy.toml set, as
+    /// `cargo ralphy` uses for its verify command.
+    pub fn defaults(mut self, defaults: DefaultSettings) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Call `handler` with each event of the run.
+    pub fn on_event(mut self, handler: impl Fn(&RunEvent) + Send + Sync + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// Leave out what `ralphy` prints for the events, for embedders that
+    /// show the run their own way.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    pub fn build(self) -> Result<RalphyRunner> {
+        let mut cli = Cli::try_parse_from(std::iter::once("ralphy".to_string()).chain(self.args))?;
+        if cli.command.is_some() {
+            anyhow::bail!("A runner only runs the loop; subcommands are for the CLI");
+        }
+        if let Some(engine) = self.engine {
+            cli.set_engine(engine);
+        }
+        if let Some(path) = self.prd {
+            cli.github = None;
+            cli.jira_project = None;
+            cli.linear_team = None;
+            let yaml = matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yaml" | "yml")
+            );
+            (cli.prd, cli.yaml) = if yaml {
+                (None, Some(path))
+            } else {
+                (Some(path), None)
+            };
+        }
+        if self.model.is_some() {
+            cli.model = self.model;
+        }
+        Ok(RalphyRunner {
+            config: config::from_cli_with_defaults(cli, self.defaults)?,
+            handlers: self.handlers,
+            console: !self.quiet,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prd::PrdSource;
+
+    #[test]
+    fn test_builder_overrides_flags() {
+        let runner = RalphyRunner::builder()
+            .args(["--codex", "--github", "owner/repo", "--max-iterations", "3"])
+            .engine(AiEngine::Mock)
+            .prd("examples/tasks.yaml")
+            .model("mock-1")
+            .build()
+            .unwrap();
+        let config = runner.config();
+        assert_eq!(config.ai_engine, AiEngine::Mock);
+        assert!(
+            matches!(config.prd_source, PrdSource::Yaml { ref path } if path.ends_with("examples/tasks.yaml"))
+        );
+        assert_eq!(config.model.as_deref(), Some("mock-1"));
+        assert_eq!(config.max_iterations, 3);
+
+        assert!(RalphyRunner::builder().args(["progress"]).build().is_err());
+        assert!(RalphyRunner::builder().args(["--nope"]).build().is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{Context, Result};
+use colored::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+/// Repository whose GitHub releases Ralphy updates from.
+pub const RELEASE_REPO: &str = "MikeTeddyOmondi/ralphy-rs";
+
+/// Checksums of every archive in a release, one `<sha256>  <file>` per line.
+pub const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// Minisign public key release checksums are signed with, baked in by the
+/// release workflow. Builds without one can't update themselves.
+pub const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("RALPHY_RELEASE_PUBLIC_KEY");
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// The release's version, from a tag like `v1.2.0`.
+    pub fn version(&self) -> Result<semver::Version> {
+        let tag = self.tag_name.trim_start_matches('v');
+        semver::Version::parse(tag)
+            .with_context(|| format!("Release tag {} is not a version", self.tag_name))
+    }
+
+    pub fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .with_context(|| format!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+/// Archive holding the binary for this platform, e.g.
+/// `ralphy-x86_64-linux.tar.gz`.
+pub fn archive_name() -> String {
+    format!(
+        "ralphy-{}-{}.tar.gz",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+fn binary_name() -> &'static str {
+    if cfg!(windows) {
+        "ralphy.exe"
+    } else {
+        "ralphy"
+    }
+}
+
+/// The checksum `checksums` lists for `file`.
+pub fn expected_checksum(checksums: &str, file: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (sum, name) = line.split_once(char::is_whitespace)?;
+        // `sha256sum -b` marks names with a leading `*`
+        let name = name.trim().trim_start_matches('*');
+        (name == file).then(|| sum.to_lowercase())
+    })
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Check `checksums` was signed with the release key.
+pub fn verify_signature(public_key: &str, checksums: &[u8], signature: &str) -> Result<()> {
+    let key = minisign_verify::PublicKey::from_base64(public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid release public key: {}", e))?;
+    let signature = minisign_verify::Signature::decode(signature)
+        .map_err(|e| anyhow::anyhow!("Invalid signature file: {}", e))?;
+    key.verify(checksums, &signature, false)
+        .map_err(|e| anyhow::anyhow!("{} signature does not verify: {}", CHECKSUMS_ASSET, e))
+}
+
+/// The Ralphy binary inside a `.tar.gz` release archive.
+pub fn extract_binary(archive: &[u8]) -> Result<Vec<u8>> {
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in tar.entries().context("Failed to read release archive")? {
+        let mut entry = entry.context("Failed to read release archive")?;
+        let path = entry.path()?.into_owned();
+        if path.file_name().and_then(|n| n.to_str()) == Some(binary_name()) {
+            let mut binary = Vec::new();
+            entry.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
+    }
+    anyhow::bail!("Release archive has no {}", binary_name())
+}
+
+fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("ralphy/", env!("CARGO_PKG_VERSION")))
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?)
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to download {}", url))?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+async fn latest_release(client: &reqwest::Client) -> Result<Release> {
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        RELEASE_REPO
+    );
+    client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .context("Failed to reach GitHub")?
+        .error_for_status()
+        .context("Failed to look up the latest release")?
+        .json()
+        .await
+        .context("Failed to parse the release")
+}
+
+/// `ralphy self-update`: replace this binary with the latest release once its
+/// signature and checksum verify. With `check`, only report whether there is
+/// a newer release.
+pub async fn run(check: bool) -> Result<()> {
+    let client = client()?;
+    let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    let release = latest_release(&client).await?;
+    let latest = release.version()?;
+
+    if latest <= current {
+        println!(
+            "{} ralphy {} is the latest release",
+            "✓".green().bold(),
+            current
+        );
+        return Ok(());
+    }
+    if check {
+        println!(
+            "{} ralphy {} is available (you have {}); run {} to install it",
+            "[INFO]".blue().bold(),
+            latest,
+            current,
+            "ralphy self-update".bright_cyan()
+        );
+        return Ok(());
+    }
+
+    let key = RELEASE_PUBLIC_KEY.with_context(|| {
+        format!(
+            "This build has no release key to verify updates with; download {} from https://github.com/{}/releases instead",
+            archive_name(),
+            RELEASE_REPO
+        )
+    })?;
+
+    println!(
+        "{} Updating ralphy {} → {}...",
+        ">>>".bright_cyan().bold(),
+        current,
+        latest
+    );
+    let checksums = download(
+        &client,
+        &release.asset(CHECKSUMS_ASSET)?.browser_download_url,
+    )
+    .await?;
+    let signature_asset = format!("{}.minisig", CHECKSUMS_ASSET);
+    let signature = download(
+        &client,
+        &release.asset(&signature_asset)?.browser_download_url,
+    )
+    .await?;
+    verify_signature(key, &checksums, &String::from_utf8_lossy(&signature))?;
+    println!("  {} Signature verified", "✓".green().bold());
+
+    let name = archive_name();
+    let expected = expected_checksum(&String::from_utf8_lossy(&checksums), &name)
+        .with_context(|| format!("{} has no entry for {}", CHECKSUMS_ASSET, name))?;
+    let archive = download(&client, &release.asset(&name)?.browser_download_url).await?;
+    let actual = sha256_hex(&archive);
+    if actual != expected {
+        anyhow::bail!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            name,
+            expected,
+            actual
+        );
+    }
+    println!("  {} Checksum verified", "✓".green().bold());
+
+    install(&extract_binary(&archive)?)?;
+    println!("{} Updated to ralphy {}", "✓".green().bold(), latest);
+    Ok(())
+}
+
+/// Swap the running executable for `binary`.
+fn install(binary: &[u8]) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the running executable")?;
+    let dir = exe.parent().unwrap_or(Path::new("."));
+    // Stage next to the executable so the final rename stays on one filesystem
+    let staged = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Can't write to {}; try again with sudo", dir.display()))?;
+    std::fs::write(staged.path(), binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(staged.path(), std::fs::Permissions::from_mode(0o755))?;
+    }
+    self_replace::self_replace(staged.path())
+        .with_context(|| format!("Failed to replace {}", exe.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_checksum() {
+        let sums = "abc123  ralphy-x86_64-linux.tar.gz\nDEF456 *ralphy-aarch64-macos.tar.gz\n";
+        assert_eq!(
+            expected_checksum(sums, "ralphy-aarch64-macos.tar.gz").as_deref(),
+            Some("def456")
+        );
+        assert_eq!(
+            expected_checksum(sums, "ralphy-x86_64-windows.tar.gz"),
+            None
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_release_version() {
+        let release: Release = serde_json::from_str(
+            r#"{"tag_name": "v1.2.0", "assets": [{"name": "SHA256SUMS", "browser_download_url": "https://example.com/SHA256SUMS"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(release.version().unwrap(), semver::Version::new(1, 2, 0));
+        assert!(release.asset(CHECKSUMS_ASSET).is_ok());
+        assert!(release.asset("ralphy-x86_64-linux.tar.gz").is_err());
+    }
+
+    #[test]
+    fn test_extract_binary() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, content) in [
+            ("ralphy-1.2.0/README.md", b"readme".as_slice()),
+            (
+                &format!("ralphy-1.2.0/{}", binary_name()),
+                b"binary".as_slice(),
+            ),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+        assert_eq!(extract_binary(&archive).unwrap(), b"binary");
+        assert!(extract_binary(b"not an archive").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_tampering() {
+        // Test vector from the minisign-verify crate
+        let key = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+        let signature = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+        assert!(verify_signature(key, b"test", signature).is_ok());
+        assert!(verify_signature(key, b"tampered", signature).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::RunConfig;
+use crate::stats::RunStats;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where events go unless `RALPHY_TELEMETRY_URL` says otherwise.
+pub const TELEMETRY_ENDPOINT: &str = "https://telemetry.ralphy.dev/v1/events";
+
+/// Telemetry must never make a command noticeably slower.
+const TELEMETRY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What `ralphy telemetry` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TelemetryAction {
+    /// Start sending anonymous usage events
+    On,
+    /// Stop sending them
+    Off,
+    /// Show whether telemetry is on and what it sends
+    Status,
+}
+
+/// The user's choice, kept outside any project so it covers every run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TelemetrySettings {
+    enabled: bool,
+}
+
+/// One anonymous event: which command ran, with which engine, and how it
+/// went. Never task titles, code, prompts, paths or anything identifying.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub ralphy_version: &'static str,
+    pub os: &'static str,
+    pub command: &'static str,
+    pub engine: Option<String>,
+    pub mode: Option<&'static str>,
+    pub tasks_completed: usize,
+    pub tasks_failed: usize,
+    pub error_categories: Vec<String>,
+}
+
+impl Event {
+    pub fn new(command: &'static str) -> Self {
+        Self {
+            ralphy_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            command,
+            engine: None,
+            mode: None,
+            tasks_completed: 0,
+            tasks_failed: 0,
+            error_categories: Vec::new(),
+        }
+    }
+
+    /// Event for a finished run.
+    pub fn run(config: &RunConfig, stats: &RunStats) -> Self {
+        Self {
+            engine: config
+                .ai_engine
+                .to_possible_value()
+                .map(|value| value.get_name().to_string()),
+            mode: Some(if config.parallel {
+                "parallel"
+            } else {
+                "sequential"
+            }),
+            tasks_completed: stats.completed(),
+            tasks_failed: stats.failed.len(),
+            error_categories: stats.error_categories.clone(),
+            ..Self::new("run")
+        }
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ralphy").join("telemetry.toml"))
+}
+
+fn load() -> TelemetrySettings {
+    settings_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Whether events are sent: only after `ralphy telemetry on`, and never
+/// with `DO_NOT_TRACK` or `RALPHY_TELEMETRY=0` set.
+pub fn enabled() -> bool {
+    let opted_out = std::env::var("DO_NOT_TRACK").is_ok_and(|v| !v.is_empty() && v != "0")
+        || std::env::var("RALPHY_TELEMETRY").is_ok_and(|v| v == "0" || v == "off");
+    !opted_out && load().enabled
+}
+
+/// Where events are sent.
+fn endpoint() -> String {
+    std::env::var("RALPHY_TELEMETRY_URL").unwrap_or_else(|_| TELEMETRY_ENDPOINT.to_string())
+}
+
+/// Send `event` if telemetry is on. Failures are silent unless `verbose`:
+/// telemetry must never get in the way.
+pub async fn send(event: &Event, verbose: bool) {
+    if !enabled() {
+        return;
+    }
+    if let Err(e) = post(&endpoint(), event).await {
+        if verbose {
+            eprintln!(
+                "{} Could not send telemetry: {}",
+                "[WARN]".yellow().bold(),
+                e
+            );
+        }
+    }
+}
+
+async fn post(endpoint: &str, event: &Event) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(TELEMETRY_TIMEOUT)
+        .build()?;
+    client
+        .post(endpoint)
+        .json(event)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// `ralphy telemetry on|off|status`
+pub fn command(action: TelemetryAction) -> Result<()> {
+    let path = settings_path().context("Can't find a config directory: HOME is not set")?;
+    match action {
+        TelemetryAction::On | TelemetryAction::Off => {
+            let settings = TelemetrySettings {
+                enabled: action == TelemetryAction::On,
+            };
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            std::fs::write(&path, toml::to_string(&settings)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "{} Telemetry is now {}",
+                "✓".green().bold(),
+                if settings.enabled { "on" } else { "off" }
+            );
+        }
+        TelemetryAction::Status => {
+            let state = if enabled() {
+                "on".green().bold()
+            } else if load().enabled {
+                "off (disabled by DO_NOT_TRACK or RALPHY_TELEMETRY)"
+                    .yellow()
+                    .bold()
+            } else {
+                "off".yellow().bold()
+            };
+            println!("Telemetry is {}", state);
+            println!("Setting: {}", path.display().to_string().bright_black());
+        }
+    }
+    println!(
+        "\nWhen on, each command sends its name, the Ralphy version and OS, the engine, \
+         task counts and\nkinds of errors to {}. Never code, prompts, task titles or paths.",
+        endpoint()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use crate::config;
+    use clap::Parser;
+
+    #[test]
+    fn test_event_has_no_task_titles() {
+        let mut stats = RunStats::new();
+        stats.record_failure("Fix private bug");
+        stats.error_categories.push("engine".to_string());
+        stats.iterations = 1;
+        let config = config::from_cli(Cli::parse_from([
+            "ralphy",
+            "--parallel",
+            "--github",
+            "acme/app",
+        ]))
+        .unwrap();
+        let event = Event::run(&config, &stats);
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"command\":\"run\""));
+        assert!(json.contains("\"mode\":\"parallel\""));
+        assert!(json.contains("\"tasks_completed\":0"));
+        assert!(json.contains("\"error_categories\":[\"engine\"]"));
+        assert!(!json.contains("private"));
+    }
+}
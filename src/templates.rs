@@ -0,0 +1,209 @@
+use crate::progress::{PROGRESS_FILE, STATE_DIR};
+use crate::settings::SETTINGS_FILE;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::*;
+use std::path::Path;
+
+/// Starting points for `ralphy new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// A Rust command-line tool
+    RustCli,
+    /// A TypeScript and React browser app
+    Webapp,
+    /// A JSON HTTP service backed by a SQL database
+    Api,
+}
+
+impl Template {
+    /// The PRD and ralphy.toml the template scaffolds.
+    pub fn files(self) -> [(&'static str, &'static str); 2] {
+        match self {
+            Template::RustCli => [
+                ("PRD.md", include_str!("../templates/rust-cli/PRD.md")),
+                (
+                    SETTINGS_FILE,
+                    include_str!("../templates/rust-cli/ralphy.toml"),
+                ),
+            ],
+            Template::Webapp => [
+                ("PRD.md", include_str!("../templates/webapp/PRD.md")),
+                (
+                    SETTINGS_FILE,
+                    include_str!("../templates/webapp/ralphy.toml"),
+                ),
+            ],
+            Template::Api => [
+                ("PRD.md", include_str!("../templates/api/PRD.md")),
+                (SETTINGS_FILE, include_str!("../templates/api/ralphy.toml")),
+            ],
+        }
+    }
+}
+
+/// Write `template`'s files into `dir`, refusing to overwrite existing ones
+/// unless `force` is set.
+pub fn scaffold(template: Template, dir: &Path, force: bool) -> Result<()> {
+    write_files(dir, &template.files(), force)?;
+    println!(
+        "\nEdit the tasks in PRD.md, then run {} to start.",
+        "ralphy".bright_cyan()
+    );
+    Ok(())
+}
+
+/// Starter files for `ralphy init`.
+const INIT_PRD: &str = include_str!("../templates/init/PRD.md");
+const INIT_YAML: &str = include_str!("../templates/init/tasks.yaml");
+const INIT_SETTINGS: &str = include_str!("../templates/init/ralphy.toml");
+
+/// Set `dir` up for a first run: a PRD.md with example tasks, or a
+/// tasks.yaml with `yaml`, a commented ralphy.toml with `with_config`, an
+/// empty progress.txt and `.ralphy/` in .gitignore. Existing task and
+/// settings files are only overwritten with `force`; progress.txt and
+/// .gitignore are only ever added to.
+pub fn init(dir: &Path, yaml: bool, with_config: bool, force: bool) -> Result<()> {
+    let tasks = if yaml {
+        ("tasks.yaml", INIT_YAML)
+    } else {
+        ("PRD.md", INIT_PRD)
+    };
+    let mut files = vec![tasks];
+    if with_config {
+        files.push((SETTINGS_FILE, INIT_SETTINGS));
+    }
+    write_files(dir, &files, force)?;
+
+    let progress = dir.join(PROGRESS_FILE);
+    if !progress.exists() {
+        std::fs::write(&progress, "")
+            .with_context(|| format!("Failed to write {}", progress.display()))?;
+        println!("  {} {}", "✓".green().bold(), PROGRESS_FILE);
+    }
+    if ignore_state_dir(dir)? {
+        println!("  {} .gitignore ({}/)", "✓".green().bold(), STATE_DIR);
+    }
+
+    println!(
+        "\nEdit the tasks in {}, then run {} to start.",
+        tasks.0,
+        if yaml {
+            "ralphy --yaml tasks.yaml"
+        } else {
+            "ralphy"
+        }
+        .bright_cyan()
+    );
+    Ok(())
+}
+
+/// Add the state directory to `dir`'s .gitignore, creating it if need be.
+/// Returns false when it was already ignored.
+fn ignore_state_dir(dir: &Path) -> Result<bool> {
+    let path = dir.join(".gitignore");
+    let existing = std::fs::read_to_string(&path).unwrap_or_default();
+    let ignored = existing
+        .lines()
+        .map(|line| line.trim().trim_start_matches('/').trim_end_matches('/'))
+        .any(|line| line == STATE_DIR);
+    if ignored {
+        return Ok(false);
+    }
+
+    let mut content = existing;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("{}/\n", STATE_DIR));
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Write `files` into `dir`, refusing to overwrite existing ones unless
+/// `force` is set.
+fn write_files(dir: &Path, files: &[(&str, &str)], force: bool) -> Result<()> {
+    if !force {
+        let existing: Vec<&str> = files
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| dir.join(name).exists())
+            .collect();
+        if !existing.is_empty() {
+            anyhow::bail!(
+                "{} already exist{}; pass --force to overwrite",
+                existing.join(" and "),
+                if existing.len() == 1 { "s" } else { "" }
+            );
+        }
+    }
+
+    for (name, content) in files {
+        let path = dir.join(name);
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("  {} {}", "✓".green().bold(), name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_templates_parse() {
+        for template in Template::value_variants() {
+            let [(_, prd), (_, settings)] = template.files();
+            assert!(prd.contains("- [ ] "), "{:?}", template);
+            let settings: Settings = toml::from_str(settings).unwrap();
+            assert!(settings.defaults.engine.is_some(), "{:?}", template);
+            assert!(settings.defaults.verify_cmd.is_some(), "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn test_scaffold_refuses_to_overwrite() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("PRD.md"), "mine").unwrap();
+
+        let err = scaffold(Template::Api, dir.path(), false).unwrap_err();
+        assert!(err.to_string().contains("PRD.md already exists"));
+        assert!(!dir.path().join(SETTINGS_FILE).exists());
+
+        scaffold(Template::Api, dir.path(), true).unwrap();
+        let prd = std::fs::read_to_string(dir.path().join("PRD.md")).unwrap();
+        assert!(prd.starts_with("# HTTP API"));
+    }
+
+    #[test]
+    fn test_init() {
+        let settings: Settings = toml::from_str(INIT_SETTINGS).unwrap();
+        assert!(settings.defaults.engine.is_none());
+        let tasks: crate::prd::YamlTasks = serde_yaml::from_str(INIT_YAML).unwrap();
+        assert_eq!(tasks.tasks.len(), 3);
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target").unwrap();
+        init(dir.path(), false, true, false).unwrap();
+        assert!(std::fs::read_to_string(dir.path().join("PRD.md"))
+            .unwrap()
+            .contains("- [ ] "));
+        assert!(dir.path().join(SETTINGS_FILE).exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(PROGRESS_FILE)).unwrap(),
+            ""
+        );
+        let gitignore = || std::fs::read_to_string(dir.path().join(".gitignore")).unwrap();
+        assert_eq!(gitignore(), "target\n.ralphy/\n");
+
+        let err = init(dir.path(), false, false, false).unwrap_err();
+        assert!(err.to_string().contains("PRD.md already exists"));
+        init(dir.path(), true, false, false).unwrap();
+        assert!(dir.path().join("tasks.yaml").exists());
+        assert_eq!(gitignore(), "target\n.ralphy/\n");
+    }
+}
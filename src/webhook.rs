@@ -0,0 +1,164 @@
+use crate::events::{self, RunEvent, Subscription};
+use crate::run_log::Line;
+use anyhow::{Context, Result};
+use colored::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Environment variable holding the key payloads are signed with.
+pub const SECRET_VAR: &str = "RALPHY_WEBHOOK_SECRET";
+
+/// Header carrying `sha256=<hex HMAC of "<timestamp>.<body>">` when a
+/// secret is set.
+pub const SIGNATURE_HEADER: &str = "X-Ralphy-Signature";
+
+/// Header carrying the Unix time a delivery was signed at, so a receiver can
+/// turn away old deliveries replayed at it.
+pub const TIMESTAMP_HEADER: &str = "X-Ralphy-Timestamp";
+
+/// Header naming the event, e.g. `task_completed`.
+pub const EVENT_HEADER: &str = "X-Ralphy-Event";
+
+/// Deliveries tried per event before it is dropped.
+const ATTEMPTS: usize = 3;
+
+/// Delay before the first retry; doubled on each later one.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// A slow endpoint holds up later events, never the run.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An event waiting to be delivered: its name and JSON body.
+type Payload = (String, String);
+
+/// Events being POSTed for a run; [`Webhook::finish`] waits for the last of
+/// them.
+pub struct Webhook {
+    subscription: Subscription,
+    worker: JoinHandle<()>,
+}
+
+/// Start POSTing events to `url`, one at a time and in order, signed with
+/// `$RALPHY_WEBHOOK_SECRET` if it is set.
+pub fn start(url: &str) -> Result<Webhook> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("Failed to set up the webhook client")?;
+    let secret = std::env::var(SECRET_VAR)
+        .ok()
+        .filter(|secret| !secret.is_empty());
+    let url = url.to_string();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Payload>();
+    let worker = events::spawn(async move {
+        while let Some((event, body)) = receiver.recv().await {
+            if let Err(e) = deliver(&client, &url, &event, &body, secret.as_deref()).await {
+                events::warn(format!("Could not send {} to the webhook: {:#}", event, e));
+            }
+        }
+    });
+    let subscription = events::subscribe(move |event| {
+        if matches!(event, RunEvent::Notice { .. }) {
+            return;
+        }
+        sender
+            .send((event.name().to_string(), Line::render(event)))
+            .ok();
+    });
+    Ok(Webhook {
+        subscription,
+        worker,
+    })
+}
+
+impl Webhook {
+    /// Wait for every queued event to be delivered or given up on, and stop
+    /// sending more.
+    pub async fn finish(self) {
+        // The worker stops once the queue it reads is dropped with the handler
+        drop(self.subscription);
+        self.worker.await.ok();
+    }
+}
+
+/// POST one event, retrying connection failures, 429s and server errors
+/// with backoff. Each attempt is signed afresh, with the time it was sent.
+async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    event: &str,
+    body: &str,
+    secret: Option<&str>,
+) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .body(body.to_string());
+        if let Some(secret) = secret {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string();
+            request = request
+                .header(TIMESTAMP_HEADER, &timestamp)
+                .header(SIGNATURE_HEADER, sign(secret, &timestamp, body));
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                let error = anyhow::anyhow!("the endpoint returned {}", status);
+                if !status.is_server_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    return Err(error);
+                }
+                error
+            }
+            Err(e) => anyhow::Error::new(e),
+        };
+        if attempt >= ATTEMPTS {
+            return Err(error.context(format!("gave up after {} attempts", ATTEMPTS)));
+        }
+        tokio::time::sleep(BASE_DELAY * 2u32.pow(attempt as u32 - 1)).await;
+        attempt += 1;
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `<timestamp>.<body>` under `secret`.
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign(
+                "key",
+                "1700000000",
+                "The quick brown fox jumps over the lazy dog"
+            ),
+            "sha256=2f658d6aef4f246e91cd741bbcded7479e9605f9d41c9e248122a117e0e1765b"
+        );
+    }
+}
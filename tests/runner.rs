@@ -0,0 +1,64 @@
+// A runner works on the current directory, which every test in a binary
+// shares, so it gets a binary of its own.
+
+use futures::StreamExt;
+use ralphy_rs::cli::AiEngine;
+use ralphy_rs::runner::RalphyRunner;
+use ralphy_rs::RunOutcome;
+use tempfile::TempDir;
+
+fn git(dir: &TempDir, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(args)
+        .current_dir(dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[tokio::test]
+async fn test_runner_streams_events() {
+    let dir = TempDir::new().unwrap();
+    git(&dir, &["init", "-q"]);
+    git(&dir, &["config", "user.name", "Test"]);
+    git(&dir, &["config", "user.email", "test@example.com"]);
+    std::fs::write(
+        dir.path().join("PRD.md"),
+        "# Tasks\n\n- [ ] First task\n- [ ] Second task\n",
+    )
+    .unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    std::env::set_var("RALPHY_MOCK_DELAY_MS", "0");
+
+    let mut run = RalphyRunner::builder()
+        .engine(AiEngine::Mock)
+        .args(["--no-notify", "--no-color"])
+        .build()
+        .unwrap()
+        .spawn();
+    let mut events = Vec::new();
+    let mut notices = 0;
+    while let Some(event) = run.next().await {
+        match event.name() {
+            // What the console would have printed
+            "notice" => notices += 1,
+            name => events.push(name),
+        }
+    }
+    assert!(notices > 0);
+    assert_eq!(
+        events,
+        [
+            "run_started",
+            "task_started",
+            "task_completed",
+            "task_started",
+            "task_completed",
+            "run_finished"
+        ]
+    );
+    assert_eq!(run.outcome().await.unwrap(), RunOutcome::Complete);
+
+    let prd = std::fs::read_to_string(dir.path().join("PRD.md")).unwrap();
+    assert_eq!(prd, "# Tasks\n\n- [x] First task\n- [x] Second task\n");
+}